@@ -2,16 +2,21 @@ use bt_hci::AsHciBytes;
 #[cfg(feature = "defmt")]
 use defmt::{Debug2Format, error, info};
 
-use embassy_futures::{join::join, select::select};
+use embassy_futures::{
+    join::join,
+    select::{Either, select},
+};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::{Duration, Timer};
 use heapless::Vec;
 use static_cell::StaticCell;
 use trouble_audio::{
-    MAX_SERVICES,
-    ascs::{Ase, AseType},
-    generic_audio::AudioLocation,
-    pacs::{AudioContexts, PAC, PACRecord},
+    GattStorage, MIN_ATTRIBUTES,
+    ascs::{AscsEvent, AscsEventChannel, AscsStorage, Ase, AseId, AseType, ascs_attributes},
+    codec_ids,
+    generic_audio::{AudioLocation, ContextType},
+    pacs::{AudioContexts, MAX_PAC_SIZE, PAC, PACRecord, PacsServer},
+    tmap::{TMAP_ATTRIBUTES, TmapRole, TmapStorage},
 };
 use trouble_host::prelude::*;
 
@@ -21,6 +26,12 @@ const CONNECTIONS_MAX: usize = 1;
 /// Max number of L2CAP channels.
 const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
 
+/// Attributes of the Gap, Gatt, Pacs, Ascs and Tmap services
+const ATTRIBUTES: usize = MIN_ATTRIBUTES + ascs_attributes(1, CONNECTIONS_MAX) + TMAP_ATTRIBUTES;
+
+/// Operations performed by the client on the Ases
+static ASCS_EVENTS: StaticCell<AscsEventChannel> = StaticCell::new();
+
 pub async fn run<C, const L2CAP_MTU: usize>(mut controller: C) -> !
 where
     C: Controller,
@@ -52,48 +63,90 @@ where
         },
     };
 
-    let sink_pac = PAC::default();
+    let mut sink_records = Vec::new();
+    let _ = sink_records.push(PACRecord {
+        codec_id: Vec::from_slice(&[codec_ids::LC3]).unwrap(),
+        ..Default::default()
+    });
+    let sink_pac = PAC::new(sink_records).unwrap();
+    let mut sink_pac_store = [0; MAX_PAC_SIZE];
     let sink_audio_locations = AudioLocation::all();
-    static sink_audio_locations_store: StaticCell<[u8; 90]> = StaticCell::new();
-    let supported_audio_contexts = AudioContexts::default();
-    let available_audio_contexts = AudioContexts::default();
+    let mut sink_audio_locations_store = [0; 90];
+    let supported_audio_contexts = AudioContexts::sink_only_default();
+    let available_audio_contexts = AudioContexts::sink_only_default();
+    let mut available_audio_contexts_store = [0; 4];
+    let mut ascs_storage = AscsStorage::<1, 1>::new();
+    let mut tmap_storage = TmapStorage::new();
+    let mut gatt_storage = GattStorage::new();
+    let ascs_events = ASCS_EVENTS.init(AscsEventChannel::new());
 
     loop {
         select(runner.run(), async {
             loop {
                 let mut ases = Vec::new();
-                ases.push(AseType::Sink(Ase::new(0)));
+                ases.push(AseType::Sink(Ase::new(AseId::new(1).unwrap())));
 
                 match advertise::<C>("Ble Audio Sink", &mut peripheral).await {
                     Ok(conn) => {
                         #[cfg(feature = "defmt")]
                         info!("[adv] connection established");
-                        let server =
-                            trouble_audio::ServerBuilder::<L2CAP_MTU, 1, 1, NoopRawMutex>::new(
-                                b"Ble Audio Sink Example",
-                                &appearance::audio_sink::GENERIC_AUDIO_SINK,
-                            )
-                            .add_pacs(
-                                Some(&sink_pac),
-                                Some((
-                                    &sink_audio_locations,
-                                    sink_audio_locations_store.init([0; 90]),
-                                )),
-                                None,
-                                None,
+                        let mut server = trouble_audio::ServerBuilder::<
+                            L2CAP_MTU,
+                            1,
+                            1,
+                            ATTRIBUTES,
+                            NoopRawMutex,
+                        >::new(
+                            b"Ble Audio Sink Example",
+                            &appearance::audio_sink::GENERIC_AUDIO_SINK,
+                            &mut gatt_storage,
+                        )
+                        .with_pacs(
+                            PacsServer::<L2CAP_MTU>::builder(
                                 &supported_audio_contexts,
-                                &available_audio_contexts,
+                                (
+                                    &available_audio_contexts,
+                                    &mut available_audio_contexts_store,
+                                ),
                             )
-                            .add_ascs(ases)
-                            .build();
+                            .with_sink_pac(&sink_pac, &mut sink_pac_store)
+                            .with_sink_audio_locations(
+                                &sink_audio_locations,
+                                &mut sink_audio_locations_store,
+                            ),
+                        )
+                        .add_ascs(ases, &mut ascs_storage)
+                        .with_ascs_event_channel(ascs_events)
+                        .add_tmap(TmapRole::UnicastMediaReceiver, &mut tmap_storage)
+                        .build()
+                        .expect("invalid Pacs configuration");
+                        server.handle_connect(&conn);
                         loop {
-                            match conn.next().await {
-                                ConnectionEvent::Disconnected { reason: _reason } => {
+                            match select(conn.next(), ascs_events.receive()).await {
+                                Either::First(ConnectionEvent::Disconnected {
+                                    reason: _reason,
+                                }) => {
                                     #[cfg(feature = "defmt")]
                                     info!("[gatt] disconnected: {:?}", _reason);
+                                    server.handle_disconnect(&conn);
                                     break;
                                 }
-                                ConnectionEvent::Gatt { data } => server.process(data).await,
+                                Either::First(ConnectionEvent::Gatt { data }) => {
+                                    server.process(&conn, data).await
+                                }
+                                // The sink is busy while streaming, and available again once idle
+                                Either::Second(AscsEvent::Streaming { .. }) => {
+                                    let busy = AudioContexts {
+                                        sink_contexts: ContextType::Prohibited,
+                                        source_contexts: ContextType::Prohibited,
+                                    };
+                                    update_available_contexts(&server, &conn, busy).await;
+                                }
+                                Either::Second(AscsEvent::Released { .. }) => {
+                                    let idle = available_audio_contexts.clone();
+                                    update_available_contexts(&server, &conn, idle).await;
+                                }
+                                Either::Second(_) => {}
                             }
                         }
                     }
@@ -112,6 +165,18 @@ where
     }
 }
 
+/// Tell the client which contexts it can start a stream for
+async fn update_available_contexts<const L2CAP_MTU: usize>(
+    server: &trouble_audio::Server<'_, L2CAP_MTU, 1, 1, ATTRIBUTES, NoopRawMutex>,
+    conn: &Connection<'_>,
+    contexts: AudioContexts,
+) {
+    if let Err(_e) = server.update_available_contexts(conn, contexts).await {
+        #[cfg(feature = "defmt")]
+        error!("[pacs] error notifying available contexts: {:?}", _e);
+    }
+}
+
 /// Create an advertiser
 async fn advertise<'a, C: Controller>(
     name: &'a str,
@@ -124,6 +189,7 @@ async fn advertise<'a, C: Controller>(
             AdStructure::ServiceUuids16(&[
                 service::PUBLISHED_AUDIO_CAPABILITIES.into(),
                 service::AUDIO_STREAM_CONTROL.into(),
+                service::TELEPHONY_AND_MEDIA_AUDIO.into(),
             ]),
             AdStructure::CompleteLocalName(name.as_bytes()),
         ],
@@ -0,0 +1,321 @@
+#[cfg(feature = "defmt")]
+use defmt::{Debug2Format, error, info};
+
+use embassy_futures::select::{select, select4};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, signal::Signal};
+use embassy_time::{Duration, Ticker};
+use heapless::Vec;
+use static_cell::StaticCell;
+use trouble_audio::{
+    GattStorage, MIN_ATTRIBUTES,
+    ascs::{AscsEventChannel, AscsStorage, Ase, AseId, AseState, AseType, ascs_attributes},
+    bap::{BapUnicastServer, CisHandle, DataPathDirection, IsoDataPathController, StreamHandle},
+    codec_ids,
+    generic_audio::{
+        AudioLocation, CodecSpecificCapabilities, OctetsPerCodecFrame, SamplingFrequency,
+        SupportedAudioChannelCounts, SupportedFrameDurations, SupportedSamplingFrequencies,
+    },
+    pacs::{AudioContexts, MAX_PAC_SIZE, PAC, PACRecord, PacsServer},
+    tmap::{TMAP_ATTRIBUTES, TmapRole, TmapStorage},
+};
+use trouble_host::prelude::*;
+
+/// Max number of connections
+const CONNECTIONS_MAX: usize = 1;
+
+/// Max number of L2CAP channels.
+const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
+
+/// Attributes of the Gap, Gatt, Pacs, Ascs and Tmap services
+const ATTRIBUTES: usize = MIN_ATTRIBUTES + ascs_attributes(1, CONNECTIONS_MAX) + TMAP_ATTRIBUTES;
+
+/// The Ase of the microphone
+const SOURCE_ASE_ID: u8 = 1;
+
+/// The handle the stubbed controller gives the CIS of the stream
+const CIS_HANDLE: CisHandle = CisHandle(0x0060);
+
+/// Samples of a 10 ms frame of mono audio at 16 kHz
+const SAMPLES_PER_FRAME: usize = 160;
+
+/// Operations performed by the client on the Ases
+static ASCS_EVENTS: StaticCell<AscsEventChannel> = StaticCell::new();
+
+/// Routes the audio of a stream over HCI
+///
+/// trouble_host does not expose isochronous channels yet, the application
+/// sends LE Setup ISO Data Path to its controller here.
+struct IsoDataPath;
+
+impl IsoDataPathController for IsoDataPath {
+    type Error = ();
+
+    async fn setup_iso_data_path(
+        &mut self,
+        _cis: CisHandle,
+        _direction: DataPathDirection,
+    ) -> Result<(), Self::Error> {
+        #[cfg(feature = "defmt")]
+        info!("[iso] data path of cis {} set up", _cis);
+        Ok(())
+    }
+}
+
+pub async fn run<C, const L2CAP_MTU: usize>(mut controller: C) -> !
+where
+    C: Controller,
+{
+    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
+    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
+    let address: Address = Address::random([0xff, 0x8f, 0x1b, 0x05, 0xe4, 0xfe]);
+    #[cfg(feature = "defmt")]
+    info!("Our address = {:?}", address);
+
+    let mut resources: HostResources<CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU> =
+        HostResources::new();
+    let stack = trouble_host::new(controller, &mut resources).set_random_address(address);
+    let Host {
+        mut peripheral,
+        mut runner,
+        ..
+    } = stack.build();
+
+    // A mono microphone, LC3 at 16 kHz with 10 ms frames of 40 octets
+    let mut capabilities = Vec::new();
+    let _ = capabilities.push(CodecSpecificCapabilities::SupportedSamplingFrequencies(
+        SupportedSamplingFrequencies::new(&[SamplingFrequency::Hz16000]),
+    ));
+    let _ = capabilities.push(CodecSpecificCapabilities::SupportedFrameDurations(
+        SupportedFrameDurations::default(),
+    ));
+    let _ = capabilities.push(CodecSpecificCapabilities::SupportedAudioChannelCounts(
+        SupportedAudioChannelCounts::new(&[1]),
+    ));
+    let _ = capabilities.push(CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(
+        OctetsPerCodecFrame::from_min_max(40, 40),
+    ));
+    let mut source_records = Vec::new();
+    let _ = source_records.push(PACRecord {
+        codec_id: Vec::from_slice(&[codec_ids::LC3]).unwrap(),
+        codec_specific_capabilities: capabilities,
+        ..Default::default()
+    });
+    let source_pac = PAC::new(source_records).unwrap();
+    let mut source_pac_store = [0; MAX_PAC_SIZE];
+    let source_audio_locations = AudioLocation::FrontLeft;
+    let mut source_audio_locations_store = [0; 90];
+    let supported_audio_contexts = AudioContexts::source_only_default();
+    let available_audio_contexts = AudioContexts::source_only_default();
+    let mut available_audio_contexts_store = [0; 4];
+    let mut ascs_storage = AscsStorage::<1, 1>::new();
+    let mut tmap_storage = TmapStorage::new();
+    let mut gatt_storage = GattStorage::new();
+    let ascs_events = ASCS_EVENTS.init(AscsEventChannel::new());
+
+    // Started by the unicast server once the client starts the stream
+    let stream_ready: Signal<NoopRawMutex, StreamHandle> = Signal::new();
+
+    loop {
+        select(runner.run(), async {
+            loop {
+                let mut ases = Vec::new();
+                let _ = ases.push(AseType::Source(Ase::new(
+                    AseId::new(SOURCE_ASE_ID).unwrap(),
+                )));
+
+                match advertise::<C>("Ble Audio Source", &mut peripheral).await {
+                    Ok(conn) => {
+                        let mut server = trouble_audio::ServerBuilder::<
+                            L2CAP_MTU,
+                            1,
+                            1,
+                            ATTRIBUTES,
+                            NoopRawMutex,
+                        >::new(
+                            b"Ble Audio Source Example",
+                            &appearance::audio_source::MICROPHONE,
+                            &mut gatt_storage,
+                        )
+                        .with_pacs(
+                            PacsServer::<L2CAP_MTU>::builder(
+                                &supported_audio_contexts,
+                                (
+                                    &available_audio_contexts,
+                                    &mut available_audio_contexts_store,
+                                ),
+                            )
+                            .with_source_pac(&source_pac, &mut source_pac_store)
+                            .with_source_audio_locations(
+                                &source_audio_locations,
+                                &mut source_audio_locations_store,
+                            ),
+                        )
+                        .add_ascs(ases, &mut ascs_storage)
+                        .with_ascs_event_channel(ascs_events)
+                        .add_tmap(TmapRole::UnicastMediaSender, &mut tmap_storage)
+                        .build()
+                        .expect("invalid Pacs configuration");
+                        server.handle_connect(&conn);
+
+                        stream_ready.reset();
+                        {
+                            let bap = BapUnicastServer::new(
+                                server.ascs().expect("Ascs was added"),
+                                ascs_events,
+                                IsoDataPath,
+                            );
+                            select4(
+                                gatt_events(&server, &conn),
+                                cis_events(&server, &bap, &conn),
+                                streams(&bap, &stream_ready),
+                                produce_audio(&stream_ready),
+                            )
+                            .await;
+                        }
+                        server.handle_disconnect(&conn);
+                    }
+                    Err(e) => {
+                        #[cfg(feature = "defmt")]
+                        let e = Debug2Format(&e);
+                        #[cfg(feature = "defmt")]
+                        error!("[adv] error: {:?}", e);
+                    }
+                }
+            }
+        })
+        .await;
+        #[cfg(feature = "defmt")]
+        info!("Exiting Bluetooth");
+    }
+}
+
+/// Process the Gatt events of `conn` until it disconnects
+async fn gatt_events<const L2CAP_MTU: usize>(
+    server: &trouble_audio::Server<'_, L2CAP_MTU, 1, 1, ATTRIBUTES, NoopRawMutex>,
+    conn: &Connection<'_>,
+) {
+    loop {
+        match conn.next().await {
+            ConnectionEvent::Disconnected { reason: _reason } => {
+                #[cfg(feature = "defmt")]
+                info!("[gatt] disconnected: {:?}", _reason);
+                break;
+            }
+            ConnectionEvent::Gatt { data } => server.process(conn, data).await,
+        }
+    }
+}
+
+/// Forward the LE CIS Established events of the controller to the unicast server
+///
+/// trouble_host does not report isochronous events yet, so this stands in for
+/// the controller: it connects the CIS once the client enabled the source Ase
+/// with the CIG and CIS it configured.
+async fn cis_events<const L2CAP_MTU: usize, H: IsoDataPathController>(
+    server: &trouble_audio::Server<'_, L2CAP_MTU, 1, 1, ATTRIBUTES, NoopRawMutex>,
+    bap: &BapUnicastServer<'_, H, 1, 1>,
+    conn: &Connection<'_>,
+) {
+    let ascs = server.ascs().expect("Ascs was added");
+    let ase_id = AseId::new(SOURCE_ASE_ID).unwrap();
+    let conn_handle = conn.handle().raw();
+    let mut ticker = Ticker::every(Duration::from_millis(10));
+    let mut configured = None;
+    let mut established = false;
+    loop {
+        ticker.next().await;
+        let enabling = match ascs.ase_state(conn_handle, ase_id).as_deref() {
+            Some(AseState::QosConfigured(qos)) => {
+                configured = Some((qos.cig_id, qos.cis_id));
+                false
+            }
+            Some(AseState::Enabling(_)) => true,
+            Some(AseState::Streaming(_) | AseState::Disabling(_)) => continue,
+            _ => {
+                configured = None;
+                false
+            }
+        };
+        if !enabling {
+            established = false;
+        } else if let (false, Some((cig_id, cis_id))) = (established, configured) {
+            bap.cis_established(conn_handle, cig_id, cis_id, CIS_HANDLE);
+            established = true;
+        }
+    }
+}
+
+/// Hand every stream the client starts to the audio task
+async fn streams<H: IsoDataPathController>(
+    bap: &BapUnicastServer<'_, H, 1, 1>,
+    stream_ready: &Signal<NoopRawMutex, StreamHandle>,
+) {
+    loop {
+        match bap.wait_for_stream_ready().await {
+            Ok(stream) => stream_ready.signal(stream),
+            Err(_e) => {
+                #[cfg(feature = "defmt")]
+                error!("[bap] error starting stream: {:?}", _e);
+            }
+        }
+    }
+}
+
+/// Produce a frame of dummy samples every 10 ms once a stream is ready
+///
+/// A real microphone would fill the frames from its I2S or PDM peripheral,
+/// then encode them with LC3 before sending them on the CIS.
+async fn produce_audio(stream_ready: &Signal<NoopRawMutex, StreamHandle>) {
+    let _stream = stream_ready.wait().await;
+    #[cfg(feature = "defmt")]
+    info!("[audio] producing audio for ase {}", _stream.ase_id);
+
+    let mut ticker = Ticker::every(Duration::from_millis(10));
+    let mut frame = [0i16; SAMPLES_PER_FRAME];
+    let mut phase: i16 = 0;
+    loop {
+        // A sawtooth, the dummy samples of the simulated microphone
+        for sample in frame.iter_mut() {
+            *sample = phase;
+            phase = phase.wrapping_add(512);
+        }
+        ticker.next().await;
+        #[cfg(feature = "defmt")]
+        info!("[audio] produced {} samples", frame.len());
+    }
+}
+
+/// Create an advertiser
+async fn advertise<'a, C: Controller>(
+    name: &'a str,
+    peripheral: &mut Peripheral<'a, C>,
+) -> Result<Connection<'a>, BleHostError<C::Error>> {
+    let mut advertiser_data = [0; 31];
+    AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ServiceUuids16(&[
+                service::PUBLISHED_AUDIO_CAPABILITIES.into(),
+                service::AUDIO_STREAM_CONTROL.into(),
+                service::TELEPHONY_AND_MEDIA_AUDIO.into(),
+            ]),
+            AdStructure::CompleteLocalName(name.as_bytes()),
+        ],
+        &mut advertiser_data[..],
+    )?;
+    let advertiser = peripheral
+        .advertise(
+            &Default::default(),
+            Advertisement::ConnectableScannableUndirected {
+                adv_data: &advertiser_data[..],
+                scan_data: &[],
+            },
+        )
+        .await?;
+    #[cfg(feature = "defmt")]
+    info!("[adv] advertising");
+    let conn = advertiser.accept().await?;
+    #[cfg(feature = "defmt")]
+    info!("[adv] connection established");
+    Ok(conn)
+}
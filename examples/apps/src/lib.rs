@@ -3,3 +3,5 @@
 #![feature(generic_const_exprs)]
 
 pub mod basic_audio_sink;
+pub mod basic_audio_source;
+pub mod pacs_browser;
@@ -0,0 +1,108 @@
+#[cfg(feature = "defmt")]
+use defmt::{Debug2Format, error, info, warn};
+
+use embassy_futures::join::join;
+use embassy_futures::select::select;
+use embassy_time::{Duration, Timer};
+use trouble_audio::{generic_audio::AudioLocation, pacs::PacsClient};
+use trouble_host::prelude::*;
+
+/// Max number of connections
+const CONNECTIONS_MAX: usize = 1;
+
+/// Max number of L2CAP channels.
+const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
+
+pub async fn run<C, const L2CAP_MTU: usize>(controller: C) -> !
+where
+    C: Controller,
+{
+    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
+    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
+    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
+    #[cfg(feature = "defmt")]
+    info!("Our address = {:?}", address);
+
+    let mut resources: HostResources<CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU> =
+        HostResources::new();
+    let stack = trouble_host::new(controller, &mut resources).set_random_address(address);
+    let Host {
+        mut central,
+        mut runner,
+        ..
+    } = stack.build();
+
+    // NOTE: Modify this to match the address of the peripheral you want to connect to.
+    // Currently, it matches the address used by the basic_audio_sink example
+    let target: Address = Address::random([0xff, 0x8f, 0x1b, 0x05, 0xe4, 0xff]);
+
+    let config = ConnectConfig {
+        connect_params: Default::default(),
+        scan_config: ScanConfig {
+            filter_accept_list: &[(target.kind, &target.addr)],
+            ..Default::default()
+        },
+    };
+
+    let _ = join(runner.run(), async {
+        loop {
+            #[cfg(feature = "defmt")]
+            info!("[central] connecting");
+            let conn = match central.connect(&config).await {
+                Ok(conn) => conn,
+                Err(_e) => {
+                    #[cfg(feature = "defmt")]
+                    error!("[central] error: {:?}", Debug2Format(&_e));
+                    Timer::after(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            #[cfg(feature = "defmt")]
+            info!("[central] connected, discovering services");
+
+            let client = match GattClient::<C, 10, L2CAP_MTU>::new(&stack, &conn).await {
+                Ok(client) => client,
+                Err(_e) => {
+                    #[cfg(feature = "defmt")]
+                    error!("[gatt] error creating client: {:?}", Debug2Format(&_e));
+                    continue;
+                }
+            };
+
+            select(client.task(), async {
+                let pacs = match PacsClient::new(&client).await {
+                    Ok(pacs) => pacs,
+                    Err(_e) => {
+                        #[cfg(feature = "defmt")]
+                        error!("[pacs] unable to discover the service: {:?}", _e);
+                        return;
+                    }
+                };
+
+                // Route the peripheral's audio to the front left speaker
+                match pacs
+                    .write_sink_audio_locations(&client, AudioLocation::FrontLeft)
+                    .await
+                {
+                    Ok(()) => {
+                        #[cfg(feature = "defmt")]
+                        info!("[pacs] sink audio locations updated");
+                    }
+                    Err(_e) => {
+                        #[cfg(feature = "defmt")]
+                        warn!("[pacs] unable to set sink audio locations: {:?}", _e);
+                    }
+                }
+
+                // Keep the connection open so the server can be inspected
+                Timer::after(Duration::from_secs(10)).await;
+            })
+            .await;
+        }
+    })
+    .await;
+
+    loop {
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
@@ -0,0 +1,639 @@
+//! Audio Input Control Service
+//!
+//! This service exposes the gain, mute state and description of an audio
+//! input, usually included by the Volume Control or Microphone Control service.
+
+use bt_hci::uuid::{characteristic, service};
+use core::{
+    cell::{Cell, RefCell},
+    slice,
+};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::info;
+
+use crate::{generic_audio::AudioInputType, LeAudioError, LeAudioServerService, Utf8Text};
+
+/// Attributes added to the attribute table by the Aics service
+pub const AICS_ATTRIBUTES: usize = 16;
+
+/// Max length of the UTF-8 audio input description
+pub const MAX_AUDIO_INPUT_DESCRIPTION_SIZE: usize = 32;
+
+/// Application errors of the Aics service
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AicsError {
+    /// A write named a stale change counter
+    InvalidChangeCounter,
+    /// The opcode belongs to a later version of the service
+    OpcodeNotSupported,
+    /// The input is disabled and can not be muted or unmuted
+    MuteDisabled,
+    /// The gain setting is outside of the gain setting properties
+    ValueOutOfRange,
+    /// The gain mode can not be changed by the client
+    GainModeChangeNotAllowed,
+}
+
+impl From<AicsError> for AttErrorCode {
+    fn from(err: AicsError) -> Self {
+        match err {
+            AicsError::InvalidChangeCounter => AttErrorCode::APPLICATION_ERROR_0x80,
+            AicsError::OpcodeNotSupported => AttErrorCode::APPLICATION_ERROR_0x81,
+            AicsError::MuteDisabled => AttErrorCode::APPLICATION_ERROR_0x82,
+            AicsError::ValueOutOfRange => AttErrorCode::APPLICATION_ERROR_0x83,
+            AicsError::GainModeChangeNotAllowed => AttErrorCode::APPLICATION_ERROR_0x84,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of an [`AicsServer`]
+pub struct AicsStorage {
+    audio_input_state: [u8; AudioInputState::SIZE],
+    gain_setting_properties: [u8; GainSettingProperties::SIZE],
+    audio_input_type: [u8; AudioInputType::SIZE],
+    audio_input_status: [u8; AudioInputStatus::SIZE],
+    audio_input_control_point: [u8; 3],
+    audio_input_description: [u8; MAX_AUDIO_INPUT_DESCRIPTION_SIZE],
+}
+
+impl AicsStorage {
+    pub const fn new() -> Self {
+        Self {
+            audio_input_state: [0; AudioInputState::SIZE],
+            gain_setting_properties: [0; GainSettingProperties::SIZE],
+            audio_input_type: [0; AudioInputType::SIZE],
+            audio_input_status: [0; AudioInputStatus::SIZE],
+            audio_input_control_point: [0; 3],
+            audio_input_description: [0; MAX_AUDIO_INPUT_DESCRIPTION_SIZE],
+        }
+    }
+}
+
+impl Default for AicsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the gain and mute state of an audio input
+pub struct AicsServer {
+    handle: u16,
+    audio_input_state: Characteristic<AudioInputState>,
+    gain_setting_properties: Characteristic<GainSettingProperties>,
+    audio_input_type: Characteristic<AudioInputType>,
+    audio_input_status: Characteristic<AudioInputStatus>,
+    audio_input_control_point: Characteristic<AudioInputControlOpcode>,
+    audio_input_description: Characteristic<AudioInputDescription>,
+    state: Cell<AudioInputState>,
+    properties: GainSettingProperties,
+    status: Cell<AudioInputStatus>,
+    description: RefCell<AudioInputDescription>,
+    state_changed: Cell<bool>,
+    status_changed: Cell<bool>,
+    description_changed: Cell<bool>,
+}
+
+impl AicsServer {
+    /// Create a new Aics Gatt Service
+    ///
+    /// The gain setting of `audio_input_state` must be within `properties`.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        audio_input_state: AudioInputState,
+        properties: GainSettingProperties,
+        audio_input_type: AudioInputType,
+        description: AudioInputDescription,
+        storage: &'a mut AicsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::AUDIO_INPUT_CONTROL));
+
+        let audio_input_state_char = service
+            .add_characteristic(
+                characteristic::AUDIO_INPUT_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                audio_input_state,
+                &mut storage.audio_input_state,
+            )
+            .build();
+
+        let gain_setting_properties_char = service
+            .add_characteristic(
+                characteristic::GAIN_SETTINGS_ATTRIBUTE,
+                &[CharacteristicProp::Read],
+                properties,
+                &mut storage.gain_setting_properties,
+            )
+            .build();
+
+        let audio_input_type_char = service
+            .add_characteristic(
+                characteristic::AUDIO_INPUT_TYPE,
+                &[CharacteristicProp::Read],
+                audio_input_type,
+                &mut storage.audio_input_type,
+            )
+            .build();
+
+        let audio_input_status_char = service
+            .add_characteristic(
+                characteristic::AUDIO_INPUT_STATUS,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                AudioInputStatus::Active,
+                &mut storage.audio_input_status,
+            )
+            .build();
+
+        let audio_input_control_point_char = service
+            .add_characteristic(
+                characteristic::AUDIO_INPUT_CONTROL_POINT,
+                &[CharacteristicProp::Write],
+                AudioInputControlOpcode::Unmute,
+                &mut storage.audio_input_control_point,
+            )
+            .build();
+
+        let audio_input_description_char = service
+            .add_characteristic(
+                characteristic::AUDIO_INPUT_DESCRIPTION,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                description.clone(),
+                &mut storage.audio_input_description,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            audio_input_state: audio_input_state_char,
+            gain_setting_properties: gain_setting_properties_char,
+            audio_input_type: audio_input_type_char,
+            audio_input_status: audio_input_status_char,
+            audio_input_control_point: audio_input_control_point_char,
+            audio_input_description: audio_input_description_char,
+            state: Cell::new(audio_input_state),
+            properties,
+            status: Cell::new(AudioInputStatus::Active),
+            description: RefCell::new(description),
+            state_changed: Cell::new(false),
+            status_changed: Cell::new(false),
+            description_changed: Cell::new(false),
+        }
+    }
+
+    /// The handle of the service, to be included by another service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Audio Input State characteristic
+    pub fn audio_input_state_characteristic(&self) -> &Characteristic<AudioInputState> {
+        &self.audio_input_state
+    }
+
+    /// The Audio Input Status characteristic
+    pub fn audio_input_status_characteristic(&self) -> &Characteristic<AudioInputStatus> {
+        &self.audio_input_status
+    }
+
+    /// The Audio Input Description characteristic
+    pub fn audio_input_description_characteristic(&self) -> &Characteristic<AudioInputDescription> {
+        &self.audio_input_description
+    }
+
+    /// The current gain, mute state and gain mode
+    pub fn audio_input_state(&self) -> AudioInputState {
+        self.state.get()
+    }
+
+    /// Change whether the audio input is currently available
+    pub fn set_status(&self, status: AudioInputStatus) {
+        if self.status.replace(status) != status {
+            self.status_changed.set(true);
+        }
+    }
+
+    /// The audio input state to notify to clients, if it changed since the last call
+    pub fn take_audio_input_state_change(&self) -> Option<AudioInputState> {
+        self.state_changed.replace(false).then(|| self.state.get())
+    }
+
+    /// The audio input status to notify to clients, if it changed since the last call
+    pub fn take_audio_input_status_change(&self) -> Option<AudioInputStatus> {
+        self.status_changed
+            .replace(false)
+            .then(|| self.status.get())
+    }
+
+    /// The audio input description to notify to clients, if a client changed it
+    pub fn take_audio_input_description_change(&self) -> Option<AudioInputDescription> {
+        self.description_changed
+            .replace(false)
+            .then(|| self.description.borrow().clone())
+    }
+
+    /// Store a new audio input state, counting the change if any field differs
+    fn update(&self, new: AudioInputState) {
+        let current = self.state.get();
+        if new.gain_setting != current.gain_setting
+            || new.mute != current.mute
+            || new.gain_mode != current.gain_mode
+        {
+            self.state.set(AudioInputState {
+                change_counter: current.change_counter.wrapping_add(1),
+                ..new
+            });
+            self.state_changed.set(true);
+        }
+    }
+
+    /// Apply an audio input control point operation
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, change_counter, operand @ ..] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        let opcode = AudioInputControlOpcode::try_from(*opcode)
+            .map_err(|_| AicsError::OpcodeNotSupported)?;
+        let expected_len = match opcode {
+            AudioInputControlOpcode::SetGainSetting => 1,
+            _ => 0,
+        };
+        if operand.len() != expected_len {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        }
+
+        let state = self.state.get();
+        if *change_counter != state.change_counter {
+            return Err(AicsError::InvalidChangeCounter.into());
+        }
+
+        #[cfg(feature = "defmt")]
+        info!("[aics] {:?}", opcode);
+        let new = match opcode {
+            AudioInputControlOpcode::SetGainSetting => {
+                let gain_setting = operand[0] as i8;
+                if !(self.properties.minimum..=self.properties.maximum).contains(&gain_setting) {
+                    return Err(AicsError::ValueOutOfRange.into());
+                }
+                match state.gain_mode {
+                    // The gain is only set by the server in the automatic modes
+                    GainMode::Automatic | GainMode::AutomaticOnly => state,
+                    GainMode::Manual | GainMode::ManualOnly => AudioInputState {
+                        gain_setting,
+                        ..state
+                    },
+                }
+            }
+            AudioInputControlOpcode::Unmute | AudioInputControlOpcode::Mute => {
+                if state.mute == MuteState::Disabled {
+                    return Err(AicsError::MuteDisabled.into());
+                }
+                let mute = if opcode == AudioInputControlOpcode::Mute {
+                    MuteState::Muted
+                } else {
+                    MuteState::NotMuted
+                };
+                AudioInputState { mute, ..state }
+            }
+            AudioInputControlOpcode::SetManualGainMode
+            | AudioInputControlOpcode::SetAutomaticGainMode => {
+                if matches!(
+                    state.gain_mode,
+                    GainMode::ManualOnly | GainMode::AutomaticOnly
+                ) {
+                    return Err(AicsError::GainModeChangeNotAllowed.into());
+                }
+                let gain_mode = if opcode == AudioInputControlOpcode::SetManualGainMode {
+                    GainMode::Manual
+                } else {
+                    GainMode::Automatic
+                };
+                AudioInputState { gain_mode, ..state }
+            }
+        };
+        self.update(new);
+        Ok(())
+    }
+
+    /// Store a description written by a client
+    fn write_description(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let description = AudioInputDescription::from_gatt(data)
+            .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+        *self.description.borrow_mut() = description;
+        self.description_changed.set(true);
+        Ok(())
+    }
+}
+
+impl LeAudioServerService for AicsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.audio_input_state.handle
+            || handle == self.gain_setting_properties.handle
+            || handle == self.audio_input_type.handle
+            || handle == self.audio_input_status.handle
+            || handle == self.audio_input_description.handle
+        {
+            Some(Ok(()))
+        } else if handle == self.audio_input_control_point.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.audio_input_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.audio_input_description.handle {
+            Some(self.write_description(data))
+        } else if handle == self.audio_input_state.handle
+            || handle == self.gain_setting_properties.handle
+            || handle == self.audio_input_type.handle
+            || handle == self.audio_input_status.handle
+        {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The gain setting, mute state, gain mode and the number of changes made to them
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct AudioInputState {
+    /// Gain in units of [`GainSettingProperties::unit`]
+    pub gain_setting: i8,
+    pub mute: MuteState,
+    pub gain_mode: GainMode,
+    /// Incremented on every change, a client must name it to change the state
+    pub change_counter: u8,
+}
+
+impl FixedGattValue for AudioInputState {
+    const SIZE: usize = 4;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [gain_setting, mute, gain_mode, change_counter] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            gain_setting: *gain_setting as i8,
+            mute: MuteState::try_from(*mute).map_err(|_| FromGattError::InvalidLength)?,
+            gain_mode: GainMode::try_from(*gain_mode).map_err(|_| FromGattError::InvalidLength)?,
+            change_counter: *change_counter,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Mute state of an audio input
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MuteState {
+    #[default]
+    NotMuted = 0,
+    Muted = 1,
+    /// Muting is not supported by the input, clients can not change it
+    Disabled = 2,
+}
+
+impl TryFrom<u8> for MuteState {
+    type Error = u8;
+
+    fn try_from(mute: u8) -> Result<Self, Self::Error> {
+        match mute {
+            0 => Ok(Self::NotMuted),
+            1 => Ok(Self::Muted),
+            2 => Ok(Self::Disabled),
+            _ => Err(mute),
+        }
+    }
+}
+
+/// Who controls the gain of an audio input
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GainMode {
+    /// The client sets the gain, and can not switch to automatic
+    ManualOnly = 0,
+    /// The server sets the gain, and clients can not switch to manual
+    AutomaticOnly = 1,
+    #[default]
+    Manual = 2,
+    Automatic = 3,
+}
+
+impl TryFrom<u8> for GainMode {
+    type Error = u8;
+
+    fn try_from(mode: u8) -> Result<Self, Self::Error> {
+        match mode {
+            0 => Ok(Self::ManualOnly),
+            1 => Ok(Self::AutomaticOnly),
+            2 => Ok(Self::Manual),
+            3 => Ok(Self::Automatic),
+            _ => Err(mode),
+        }
+    }
+}
+
+/// Unit and range of the gain setting
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct GainSettingProperties {
+    /// Size of a gain setting step, in 0.1 dB
+    pub unit: u8,
+    pub minimum: i8,
+    pub maximum: i8,
+}
+
+impl FixedGattValue for GainSettingProperties {
+    const SIZE: usize = 3;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [unit, minimum, maximum] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            unit: *unit,
+            minimum: *minimum as i8,
+            maximum: *maximum as i8,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Whether an audio input is currently available
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AudioInputStatus {
+    Inactive = 0,
+    Active = 1,
+}
+
+impl FixedGattValue for AudioInputStatus {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [0] => Ok(Self::Inactive),
+            [1] => Ok(Self::Active),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Operations of the Audio Input Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AudioInputControlOpcode {
+    SetGainSetting = 0x01,
+    Unmute = 0x02,
+    Mute = 0x03,
+    SetManualGainMode = 0x04,
+    SetAutomaticGainMode = 0x05,
+}
+
+impl TryFrom<u8> for AudioInputControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x01 => Self::SetGainSetting,
+            0x02 => Self::Unmute,
+            0x03 => Self::Mute,
+            0x04 => Self::SetManualGainMode,
+            0x05 => Self::SetAutomaticGainMode,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+impl FixedGattValue for AudioInputControlOpcode {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [opcode] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*opcode).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// UTF-8 description of an audio input, like "Bluetooth" or "Microphone"
+pub type AudioInputDescription = Utf8Text<MAX_AUDIO_INPUT_DESCRIPTION_SIZE>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use trouble_host::attribute::AttributeTable;
+
+    /// Gain from -10 to 10, in manual mode
+    const PROPERTIES: GainSettingProperties = GainSettingProperties {
+        unit: 10,
+        minimum: -10,
+        maximum: 10,
+    };
+
+    const STATE: AudioInputState = AudioInputState {
+        gain_setting: 0,
+        mute: MuteState::NotMuted,
+        gain_mode: GainMode::Manual,
+        change_counter: 0,
+    };
+
+    fn write(aics: &AicsServer, data: &[u8]) -> Result<(), AttErrorCode> {
+        aics.handle_write(aics.audio_input_control_point.handle, data)
+            .unwrap()
+            .map_err(AttErrorCode::from)
+    }
+
+    #[test]
+    fn stale_change_counter_is_rejected() {
+        let mut storage = AicsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, AICS_ATTRIBUTES> = AttributeTable::new();
+        let aics = AicsServer::new(
+            &mut table,
+            STATE,
+            PROPERTIES,
+            AudioInputType::Microphone,
+            AudioInputDescription::default(),
+            &mut storage,
+        );
+
+        write(&aics, &[AudioInputControlOpcode::Mute as u8, 0]).unwrap();
+        assert_eq!(
+            write(&aics, &[AudioInputControlOpcode::Unmute as u8, 0]),
+            Err(AttErrorCode::APPLICATION_ERROR_0x80)
+        );
+        let state = aics.audio_input_state();
+        assert_eq!(state.mute, MuteState::Muted);
+        assert_eq!(state.change_counter, 1);
+    }
+
+    #[test]
+    fn gain_setting_is_limited_by_the_properties() {
+        let mut storage = AicsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, AICS_ATTRIBUTES> = AttributeTable::new();
+        let aics = AicsServer::new(
+            &mut table,
+            STATE,
+            PROPERTIES,
+            AudioInputType::Microphone,
+            AudioInputDescription::default(),
+            &mut storage,
+        );
+
+        for gain_setting in [PROPERTIES.minimum - 1, PROPERTIES.maximum + 1] {
+            assert_eq!(
+                write(
+                    &aics,
+                    &[
+                        AudioInputControlOpcode::SetGainSetting as u8,
+                        0,
+                        gain_setting as u8
+                    ]
+                ),
+                Err(AttErrorCode::APPLICATION_ERROR_0x83)
+            );
+        }
+        write(
+            &aics,
+            &[
+                AudioInputControlOpcode::SetGainSetting as u8,
+                0,
+                PROPERTIES.minimum as u8,
+            ],
+        )
+        .unwrap();
+        assert_eq!(aics.audio_input_state().gain_setting, PROPERTIES.minimum);
+    }
+}
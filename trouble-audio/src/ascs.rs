@@ -4,87 +4,588 @@
 //! which enables clients to discover, configure, establish,and
 //! control the ASEs and their associated unicast Audio Streams.
 
-use core::{mem::size_of, slice};
-use embassy_sync::blocking_mutex::raw::RawMutex;
-use heapless::Vec;
-use static_cell::StaticCell;
+use core::{
+    cell::{Cell, Ref, RefCell},
+    pin::Pin,
+    slice,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use embassy_futures::select::{select, select_slice, Either};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    channel::Channel,
+};
+use heapless::{LinearMap, Vec};
 use trouble_host::{connection::PhySet, prelude::*, types::gatt_traits::*};
 
 #[cfg(feature = "defmt")]
-use defmt::{assert, info, warn};
+use defmt::warn;
 
-use crate::{CodecId, LeAudioServerService, MAX_SERVICES};
+use crate::{
+    generic_audio::{supports_codec_config, CodecSpecificConfiguration, LtvIterator, Metadata},
+    pacs::{AudioContexts, PACRecord, MAX_NUMBER_PAC_RECORDS, PAC},
+    CodecId, LeAudioError, LeAudioServerService, NotifyError,
+};
 
 /// A Gatt service client for reading exposed Capabilities of an audio server
 pub struct AscsClient<const MAX_ASES: usize> {
     handle: ServiceHandle,
-    ase_control_point: Characteristic<AseControlOpcode>,
-    // pub ases: Vec<Characteristic<AseType>, MAX_ASES>,
-    pub source_ase: Option<Characteristic<AseType>>,
-    pub sink_ase: Option<Characteristic<AseType>>,
+    ase_control_point: Characteristic<AseControlPointResponse>,
+    pub sink_ases: Vec<Characteristic<AseValue>, MAX_ASES>,
+    pub source_ases: Vec<Characteristic<AseValue>, MAX_ASES>,
 }
 
 impl<const MAX_ASES: usize> AscsClient<MAX_ASES> {
-    pub async fn new<'a, T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
-        client: &'a mut GattClient<'a, T, MAX_SERVICES, L2CAP_MTU>,
+    pub async fn new<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
     ) -> Self {
+        match Self::discover(client).await {
+            Ok(ascs) => ascs,
+            // One of the sink or source ases must be implemented on the server
+            Err(AscsDiscoverError::NoAses) => panic!("The server must expose an Ase"),
+            Err(_) => panic!("Ase Control point must exist on the server"),
+        }
+    }
+
+    /// Discover the Ascs service and every Ase characteristic it exposes
+    ///
+    /// The number of Ases on the server does not need to be known in advance,
+    /// Ases beyond `MAX_ASES` of either direction are ignored.
+    pub async fn discover<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<Self, AscsDiscoverError> {
         let services = client
             .services_by_uuid(&Uuid::new_short(service::AUDIO_STREAM_CONTROL.into()))
             .await
-            .unwrap();
-        let handle = services.first().unwrap();
+            .map_err(|_| AscsDiscoverError::ServiceNotFound)?;
+        let handle = services.first().ok_or(AscsDiscoverError::ServiceNotFound)?;
 
         let ase_control_point = client
             .characteristic_by_uuid(
-                &handle,
+                handle,
                 &Uuid::new_short(characteristic::ASE_CONTROL_POINT.into()),
             )
             .await
-            .expect("Ase Control point must exist on the server");
+            .map_err(|_| AscsDiscoverError::ControlPointMissing)?;
+
+        let sink_ases = Self::discover_ases(
+            client,
+            handle,
+            Uuid::new_short(characteristic::SINK_ASE.into()),
+        )
+        .await;
+        let source_ases = Self::discover_ases(
+            client,
+            handle,
+            Uuid::new_short(characteristic::SOURCE_ASE.into()),
+        )
+        .await;
+
+        if sink_ases.is_empty() && source_ases.is_empty() {
+            return Err(AscsDiscoverError::NoAses);
+        }
+
+        Ok(Self {
+            handle: handle.clone(),
+            ase_control_point,
+            sink_ases,
+            source_ases,
+        })
+    }
+
+    /// Discover up to `MAX_ASES` Ase characteristics of `uuid` in the service
+    ///
+    /// The lookup only resolves the first characteristic of a uuid, so it is
+    /// repeated on the handles following each Ase found.
+    async fn discover_ases<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        service: &ServiceHandle,
+        uuid: Uuid,
+    ) -> Vec<Characteristic<AseValue>, MAX_ASES> {
+        let mut ases = Vec::new();
+        let mut range = service.clone();
+        while !ases.is_full() && range.start <= range.end {
+            let Ok(ase) = client
+                .characteristic_by_uuid::<AseValue>(&range, &uuid)
+                .await
+            else {
+                break;
+            };
+            range = ServiceHandle {
+                start: ase.handle + 1,
+                ..range
+            };
+            let _ = ases.push(ase);
+        }
+        ases
+    }
+
+    /// Read the id the server assigned to an Ase
+    pub async fn read_ase_id<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        ase: &Characteristic<AseValue>,
+    ) -> Result<AseId, AscsClientError> {
+        let mut buf = [0; ASE_STORE_SIZE];
+        let len = client
+            .read_characteristic(ase, &mut buf)
+            .await
+            .map_err(|_| AscsClientError::ReadFailed)?;
+        // ASE_ID is the first field of the Ase characteristic value
+        buf[..len]
+            .first()
+            .and_then(|id| AseId::new(*id).ok())
+            .ok_or(AscsClientError::InvalidResponse)
+    }
+
+    /// Read the id and the current state of an Ase
+    pub async fn read_ase_state<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        ase: &Characteristic<AseValue>,
+    ) -> Result<(AseId, AseState), AscsClientError> {
+        let mut buf = [0; ASE_STORE_SIZE];
+        let len = client
+            .read_characteristic(ase, &mut buf)
+            .await
+            .map_err(|_| AscsClientError::ReadFailed)?;
+        let value =
+            AseValue::from_gatt(&buf[..len]).map_err(|_| AscsClientError::InvalidResponse)?;
+        let ase_id = value.ase_id().ok_or(AscsClientError::InvalidResponse)?;
+        let state = value
+            .state()
+            .map_err(|_| AscsClientError::InvalidResponse)?;
+        Ok((ase_id, state))
+    }
+
+    /// Subscribe to the responses notified on the Ase Control Point
+    ///
+    /// The listener is passed to every control point operation, which waits on it
+    /// for the server to confirm the operation.
+    pub async fn subscribe<'c, T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<NotificationListener<'c, L2CAP_MTU>, AscsClientError> {
+        client
+            .subscribe(&self.ase_control_point, false)
+            .await
+            .map_err(|_| AscsClientError::SubscribeFailed)
+    }
+
+    /// Subscribe to the state notified by the server on every Ase
+    ///
+    /// Each Ase discovered is subscribed to, the listener yields their state
+    /// changes, like the server completing a control point operation.
+    pub async fn subscribe_ase_state_changes<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<AseStateListener<'c, MAX_ASES, L2CAP_MTU>, AscsClientError> {
+        let mut listener = AseStateListener {
+            sink_listeners: Vec::new(),
+            source_listeners: Vec::new(),
+        };
+        for ase in self.sink_ases.iter() {
+            let sink = client
+                .subscribe(ase, false)
+                .await
+                .map_err(|_| AscsClientError::SubscribeFailed)?;
+            // Cannot fail, there are at most MAX_ASES sink Ases
+            let _ = listener.sink_listeners.push(sink);
+        }
+        for ase in self.source_ases.iter() {
+            let source = client
+                .subscribe(ase, false)
+                .await
+                .map_err(|_| AscsClientError::SubscribeFailed)?;
+            let _ = listener.source_listeners.push(source);
+        }
+        Ok(listener)
+    }
+
+    /// Select the codec and its configuration for an Ase
+    pub async fn configure_codec<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        listener: &mut NotificationListener<'_, L2CAP_MTU>,
+        ase_id: AseId,
+        codec_id: CodecId,
+        config: &[CodecSpecificConfiguration],
+    ) -> Result<(), AscsClientError> {
+        // Target latency of a balanced latency and reliability, on the 2M PHY
+        let mut operation = [0; MAX_CONTROL_POINT_WRITE_SIZE];
+        operation[..5].copy_from_slice(&[0x01, 1, ase_id.get(), 0x02, 0x02]);
+        operation[5..10].copy_from_slice(&codec_id.to_bytes());
+        let mut len = 11;
+        for entry in config {
+            len += entry
+                .encode_ltv(&mut operation[len..])
+                .map_err(|_| AscsClientError::OperationTooLarge)?;
+        }
+        operation[10] = (len - 11) as u8;
+
+        self.control(client, listener, ase_id, &operation[..len])
+            .await
+    }
+
+    /// Select the QoS of an Ase, once its codec is configured
+    pub async fn configure_qos<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        listener: &mut NotificationListener<'_, L2CAP_MTU>,
+        ase_id: AseId,
+        qos: &AseParamsQoSConfigured,
+    ) -> Result<(), AscsClientError> {
+        let [sdu_low, sdu_mid, sdu_high] = qos.sdu_interval;
+        let [max_sdu_low, max_sdu_high] = qos.max_sdu.to_le_bytes();
+        let [latency_low, latency_high] = qos.max_transport_latency.to_le_bytes();
+        let [delay_low, delay_mid, delay_high] = qos.presentation_delay;
+        let operation = [
+            0x02,
+            1,
+            ase_id.get(),
+            qos.cig_id,
+            qos.cis_id,
+            sdu_low,
+            sdu_mid,
+            sdu_high,
+            qos.framing,
+            phy_to_bits(&qos.phy),
+            max_sdu_low,
+            max_sdu_high,
+            qos.retransmission_number,
+            latency_low,
+            latency_high,
+            delay_low,
+            delay_mid,
+            delay_high,
+        ];
+
+        self.control(client, listener, ase_id, &operation).await
+    }
+
+    /// Start the stream of an Ase, once its QoS is configured
+    pub async fn enable<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        listener: &mut NotificationListener<'_, L2CAP_MTU>,
+        ase_id: AseId,
+        metadata: &[Metadata],
+    ) -> Result<(), AscsClientError> {
+        let mut operation = [0; MAX_CONTROL_POINT_WRITE_SIZE];
+        operation[..3].copy_from_slice(&[0x03, 1, ase_id.get()]);
+        let mut len = 4;
+        for entry in metadata {
+            match entry.encode_ltv(&mut operation[len..]) {
+                0 => return Err(AscsClientError::OperationTooLarge),
+                written => len += written,
+            }
+        }
+        operation[3] = (len - 4) as u8;
+
+        self.control(client, listener, ase_id, &operation[..len])
+            .await
+    }
 
-        let sink_ase = client
-            .characteristic_by_uuid(&handle, &Uuid::new_short(characteristic::SINK_ASE.into()))
+    /// Release the resources of an Ase, returning it to idle
+    pub async fn release<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        listener: &mut NotificationListener<'_, L2CAP_MTU>,
+        ase_id: AseId,
+    ) -> Result<(), AscsClientError> {
+        self.control(client, listener, ase_id, &[0x08, 1, ase_id.get()])
             .await
-            .ok();
+    }
 
-        let source_ase = client
-            .characteristic_by_uuid(&handle, &Uuid::new_short(characteristic::SOURCE_ASE.into()))
+    /// Write an operation on a single Ase and wait for the server to respond to it
+    async fn control<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        listener: &mut NotificationListener<'_, L2CAP_MTU>,
+        ase_id: AseId,
+        operation: &[u8],
+    ) -> Result<(), AscsClientError> {
+        client
+            .write_characteristic(&self.ase_control_point, operation)
             .await
-            .ok();
+            .map_err(|_| AscsClientError::WriteFailed)?;
+
+        loop {
+            let notification = listener.next().await;
+            let response = AseControlPointResponse::from_gatt(notification.as_ref())
+                .map_err(|_| AscsClientError::InvalidResponse)?;
+            if response.opcode() != Some(operation[0]) {
+                continue;
+            }
+
+            // A rejected operation is reported once for every Ase it addresses
+            let Some((_, code, reason)) = response
+                .entries()
+                .find(|(id, _, _)| *id == ase_id.get() || response.is_operation_rejected())
+            else {
+                return Err(AscsClientError::InvalidResponse);
+            };
+            return match AseResponseCode::try_from(code) {
+                Ok(AseResponseCode::Success) => Ok(()),
+                Ok(code) => match AseResponseReason::decode(code, reason) {
+                    Ok(reason) => Err(AscsClientError::Rejected(AseRejection { code, reason })),
+                    Err(_) => Err(AscsClientError::InvalidResponse),
+                },
+                Err(_) => Err(AscsClientError::InvalidResponse),
+            };
+        }
+    }
+}
 
-        // One of the following must me implemented on the servver
-        assert!(sink_ase.is_some() || source_ase.is_some());
+/// A state notified by the server on one of its Ases
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone)]
+pub struct AseStateEvent {
+    pub ase_id: AseId,
+    pub new_state: AseState,
+}
 
+/// The states notified by the server on every Ase, see
+/// [`AscsClient::subscribe_ase_state_changes`]
+///
+/// The notifications are only received while the `GattClient` task runs.
+pub struct AseStateListener<'c, const MAX_ASES: usize, const L2CAP_MTU: usize> {
+    sink_listeners: Vec<NotificationListener<'c, L2CAP_MTU>, MAX_ASES>,
+    source_listeners: Vec<NotificationListener<'c, L2CAP_MTU>, MAX_ASES>,
+}
+
+impl<const MAX_ASES: usize, const L2CAP_MTU: usize> AseStateListener<'_, MAX_ASES, L2CAP_MTU> {
+    /// Wait for the next state notified by the server
+    ///
+    /// Cancel safe, a notification is only taken from its listener when this
+    /// completes, so it can be raced with other futures in `select`.
+    pub async fn next(&mut self) -> Result<AseStateEvent, AscsClientError> {
+        let mut sinks: Vec<_, MAX_ASES> = self
+            .sink_listeners
+            .iter_mut()
+            .map(|listener| listener.next())
+            .collect();
+        let mut sources: Vec<_, MAX_ASES> = self
+            .source_listeners
+            .iter_mut()
+            .map(|listener| listener.next())
+            .collect();
+        // SAFETY: the futures are not moved out of the vecs before they are dropped
+        let (sinks, sources) = unsafe {
+            (
+                Pin::new_unchecked(sinks.as_mut_slice()),
+                Pin::new_unchecked(sources.as_mut_slice()),
+            )
+        };
+        let notification = match select(select_slice(sinks), select_slice(sources)).await {
+            Either::First((notification, _)) | Either::Second((notification, _)) => notification,
+        };
+
+        let value = AseValue::from_gatt(notification.as_ref())
+            .map_err(|_| AscsClientError::InvalidResponse)?;
+        Ok(AseStateEvent {
+            ase_id: value.ase_id().ok_or(AscsClientError::InvalidResponse)?,
+            new_state: value
+                .state()
+                .map_err(|_| AscsClientError::InvalidResponse)?,
+        })
+    }
+}
+
+/// Largest control point operation written by the [`AscsClient`]
+const MAX_CONTROL_POINT_WRITE_SIZE: usize = 64;
+
+/// Errors returned by the [`AscsClient`] control point operations
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AscsClientError {
+    /// Reading an Ase characteristic failed
+    ReadFailed,
+    /// Subscribing to the control point notifications failed
+    SubscribeFailed,
+    /// The configuration or metadata does not fit in a single operation
+    OperationTooLarge,
+    /// Writing the operation to the control point failed
+    WriteFailed,
+    /// The server notified a malformed response to the operation
+    InvalidResponse,
+    /// The server rejected the operation
+    Rejected(AseRejection),
+}
+
+/// Errors returned while discovering the Ascs service on a server
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AscsDiscoverError {
+    /// The server does not expose the Audio Stream Control service
+    ServiceNotFound,
+    /// The mandatory Ase Control Point characteristic was not found
+    ControlPointMissing,
+    /// The server exposes neither a sink nor a source Ase
+    NoAses,
+}
+
+/// Attributes added to the attribute table by an Ascs service exposing `ases`
+/// Ases to `max_connections` connections
+///
+/// Each Ase has a characteristic for every connection.
+pub const fn ascs_attributes(ases: usize, max_connections: usize) -> usize {
+    // The service, the control point and the Ase characteristics, with their CCCD
+    1 + 3 + 3 * ases * max_connections
+}
+
+/// Size of the backing store of the ASE Control Point characteristic
+pub const ASCS_STORE_SIZE: usize = 90;
+
+/// Max length of a Codec_Specific_Configuration
+pub const MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE: usize = 255;
+
+/// Max length of the metadata of an enabled Ase
+pub const MAX_ASE_METADATA_SIZE: usize = 32;
+
+/// Size of the backing store of an Ase characteristic
+///
+/// Fits the largest value, an Ase in the `CodecConfigured` state.
+pub const ASE_STORE_SIZE: usize =
+    2 + CODEC_CONFIGURED_PARAMS_SIZE + MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE;
+
+/// Size of the `CodecConfigured` parameters without the Codec_Specific_Configuration
+const CODEC_CONFIGURED_PARAMS_SIZE: usize = 23;
+
+/// Size of the `QosConfigured` parameters
+const QOS_CONFIGURED_PARAMS_SIZE: usize = 15;
+
+/// Backing storage for the characteristics of an [`AscsServer`]
+///
+/// The storage is owned by the caller, so a new server can be created
+/// each time a connection is established.
+pub struct AscsStorage<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> {
+    control_point: [u8; ASCS_STORE_SIZE],
+    ases: [[[u8; ASE_STORE_SIZE]; MAX_CONNECTIONS]; MAX_ASES],
+}
+
+impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> AscsStorage<MAX_ASES, MAX_CONNECTIONS> {
+    pub const fn new() -> Self {
         Self {
-            handle: handle.clone(),
-            ase_control_point,
-            source_ase,
-            sink_ase,
+            control_point: [0; ASCS_STORE_SIZE],
+            ases: [[[0; ASE_STORE_SIZE]; MAX_CONNECTIONS]; MAX_ASES],
         }
     }
 }
 
+impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> Default
+    for AscsStorage<MAX_ASES, MAX_CONNECTIONS>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Codec configurations of a client kept across its connections
+///
+/// The server may keep the Ases of a client `CodecConfigured` when it
+/// reconnects, so the client can skip Config Codec. Place a cache for each
+/// bonded client in a `static`, see [`AscsServer::cache_state`].
+pub struct AscsStateCache<const MAX_ASES: usize> {
+    codec_configs: Vec<(AseId, AseParamsCodecConfigured), MAX_ASES>,
+}
+
+impl<const MAX_ASES: usize> AscsStateCache<MAX_ASES> {
+    pub const fn new() -> Self {
+        Self {
+            codec_configs: Vec::new(),
+        }
+    }
+
+    /// Whether no Ase codec configuration is cached
+    pub fn is_empty(&self) -> bool {
+        self.codec_configs.is_empty()
+    }
+
+    /// Forget the cached configurations, for example once the client's bond is removed
+    pub fn clear(&mut self) {
+        self.codec_configs.clear();
+    }
+}
+
+impl<const MAX_ASES: usize> Default for AscsStateCache<MAX_ASES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A Gatt service for controlling unicast audio streams
 ///
 /// MAX_ASES is the max number of sink ases and source ases the device supports
 /// MAX_CONNECTIONS is the max number clients each ase can handle
 pub struct AscsServer<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> {
     handle: u16,
-    ase_control_point: Characteristic<AseControlOpcode>,
-    ases: Vec<Vec<Characteristic<AseType>, MAX_CONNECTIONS>, MAX_ASES>,
+    ase_control_point: Characteristic<AseControlPointResponse>,
+    ases: Vec<Vec<Characteristic<AseValue>, MAX_CONNECTIONS>, MAX_ASES>,
+    ase_types: Vec<AseType, MAX_ASES>,
+    /// State of every Ase, for each connection slot
+    states: RefCell<Vec<Vec<AseState, MAX_ASES>, MAX_CONNECTIONS>>,
+    codec_configs: RefCell<Vec<Vec<Option<AseParamsCodecConfigured>, MAX_ASES>, MAX_CONNECTIONS>>,
+    qos_configs: RefCell<Vec<Vec<Option<AseParamsQoSConfigured>, MAX_ASES>, MAX_CONNECTIONS>>,
+    /// Connections using each Ase, see [`AscsServer::on_connection_opened`]
+    connection_count: [AtomicU8; MAX_ASES],
+    connections: RefCell<ConnectionMap<MAX_CONNECTIONS>>,
+    /// Slot of the connection whose event is being handled
+    active_slot: Cell<Option<usize>>,
+    qos_proposals: Vec<Option<AseParamsQoSConfigured>, MAX_ASES>,
+    /// QoS preferences reported by each Ase once codec configured
+    preferred_qos: Vec<ServerPreferredQos, MAX_ASES>,
+    /// PAC records Config Codec is checked against, for sink and source Ases
+    sink_capabilities: Option<Vec<PACRecord, MAX_NUMBER_PAC_RECORDS>>,
+    source_capabilities: Option<Vec<PACRecord, MAX_NUMBER_PAC_RECORDS>>,
+    /// Contexts Enable and Update Metadata may stream, unchecked when `None`
+    available_contexts: RefCell<Option<AudioContexts>>,
+    events: Option<&'static AscsEventChannel>,
+    /// CIS carrying the audio of every Ase, for each connection slot
+    cis_handles: RefCell<Vec<Vec<Option<u16>, MAX_ASES>, MAX_CONNECTIONS>>,
+    stream_events: Option<&'static StreamEventChannel>,
+    pending_response: RefCell<Option<AseControlPointResponse>>,
+    changed_ases: RefCell<Vec<Vec<AseId, MAX_ASES>, MAX_CONNECTIONS>>,
 }
 
 impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> AscsServer<MAX_ASES, MAX_CONNECTIONS> {
+    const MAX_ASES_CHECK: () = assert!(MAX_ASES <= 254, "Ase ids go from 1 to 254");
+
     /// Create a new Ascs Gatt Service
     ///
     /// MAX_ASES is the number of audio stream endpoints you wish to support PER client/connection
-    pub fn new<'a, M: RawMutex>(
-        table: &mut trouble_host::attribute::AttributeTable<'a, M, MAX_SERVICES>,
-        ases: Vec<AseType, MAX_ASES>,
+    ///
+    /// The Ases are given the ids 1, 2, 3... in the order of `ases`, replacing
+    /// the ids they were created with, so several sink or source Ases can be
+    /// told apart by the client.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        mut ases: Vec<AseType, MAX_ASES>,
+        stores: &'a mut AscsStorage<MAX_ASES, MAX_CONNECTIONS>,
     ) -> Self {
+        let () = Self::MAX_ASES_CHECK;
+        for (index, ase) in ases.iter_mut().enumerate() {
+            // Cannot fail, there are at most 254 Ases
+            if let Ok(id) = AseId::new(index as u8 + 1) {
+                ase.ase_mut().id = id;
+            }
+        }
+
         let mut service = table.add_service(Service::new(service::AUDIO_STREAM_CONTROL));
 
-        static CONTROL_STORE: StaticCell<[u8; 90]> = StaticCell::new();
         let ase_control_point_char = service
             .add_characteristic(
                 characteristic::ASE_CONTROL_POINT,
@@ -93,38 +594,37 @@ impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> AscsServer<MAX_ASES, M
                     CharacteristicProp::WriteWithoutResponse,
                     CharacteristicProp::Notify,
                 ],
-                AseControlOpcode::Disable,
-                CONTROL_STORE.init([0; 90]),
+                AseControlPointResponse::default(),
+                &mut stores.control_point,
             )
             .build();
 
         let mut ase_chars = Vec::new();
-        for ase in ases.iter() {
+        for (ase, ase_stores) in ases.iter().zip(stores.ases.iter_mut()) {
             let mut ases_handles = Vec::new();
-            for _ in 0..MAX_CONNECTIONS {
-                static ASE_STORE: StaticCell<[u8; 90]> = StaticCell::new();
+            for store in ase_stores {
                 ases_handles.push(match ase {
                     AseType::Source(_) => service
                         .add_characteristic(
                             characteristic::SOURCE_ASE,
                             &[CharacteristicProp::Read, CharacteristicProp::Notify],
-                            ase.clone(),
-                            ASE_STORE.init([0; 90]),
+                            AseValue::new(ase.id(), &AseState::Idle),
+                            store,
                         )
                         .build(),
                     AseType::Sink(_) => service
                         .add_characteristic(
                             characteristic::SINK_ASE,
                             &[CharacteristicProp::Read, CharacteristicProp::Notify],
-                            ase.clone(),
-                            ASE_STORE.init([0; 90]),
+                            AseValue::new(ase.id(), &AseState::Idle),
+                            store,
                         )
                         .build(),
                 });
             }
             ase_chars
                 .push(ases_handles)
-                .map_err(|_| "Adding ASE endpoint exceeded MAX_SERVICES")
+                .map_err(|_| "Adding ASE endpoint exceeded the attribute table")
                 .unwrap()
         }
 
@@ -132,56 +632,1561 @@ impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> AscsServer<MAX_ASES, M
             handle: service.build(),
             ase_control_point: ase_control_point_char,
             ases: ase_chars,
+            states: RefCell::new(
+                (0..MAX_CONNECTIONS)
+                    .map(|_| ases.iter().map(|_| AseState::Idle).collect())
+                    .collect(),
+            ),
+            codec_configs: RefCell::new(
+                (0..MAX_CONNECTIONS)
+                    .map(|_| ases.iter().map(|_| None).collect())
+                    .collect(),
+            ),
+            qos_configs: RefCell::new(
+                (0..MAX_CONNECTIONS)
+                    .map(|_| ases.iter().map(|_| None).collect())
+                    .collect(),
+            ),
+            connection_count: [const { AtomicU8::new(0) }; MAX_ASES],
+            connections: RefCell::new(ConnectionMap::new()),
+            active_slot: Cell::new(None),
+            qos_proposals: ases.iter().map(|_| None).collect(),
+            preferred_qos: ases.iter().map(|_| ServerPreferredQos::default()).collect(),
+            sink_capabilities: None,
+            source_capabilities: None,
+            available_contexts: RefCell::new(None),
+            cis_handles: RefCell::new(
+                (0..MAX_CONNECTIONS)
+                    .map(|_| ases.iter().map(|_| None).collect())
+                    .collect(),
+            ),
+            ase_types: ases,
+            events: None,
+            stream_events: None,
+            pending_response: RefCell::new(None),
+            changed_ases: RefCell::new((0..MAX_CONNECTIONS).map(|_| Vec::new()).collect()),
+        }
+    }
+
+    /// The connection slot of a connected client
+    fn slot_of(&self, conn_handle: u16) -> Result<usize, AscsError> {
+        self.connections
+            .borrow()
+            .slot(conn_handle)
+            .ok_or(AscsError::UnknownConnection(conn_handle))
+    }
+
+    /// The connection handle of the client given a connection slot
+    fn conn_handle_of(&self, slot: usize) -> u16 {
+        self.connections.borrow().handle(slot).unwrap_or_default()
+    }
+
+    fn index_of(&self, ase_id: AseId) -> Result<usize, AscsError> {
+        self.ase_types
+            .iter()
+            .position(|ase| ase.id() == ase_id)
+            .ok_or(AscsError::UnknownAseId(ase_id.get()))
+    }
+
+    /// The index and id of the Ase a control point operation addresses
+    fn resolve(&self, ase_id: u8) -> Result<(usize, AseId), AseRejection> {
+        let ase_id = AseId::new(ase_id).map_err(|_| AseResponseCode::InvalidAseId)?;
+        let index = self
+            .index_of(ase_id)
+            .map_err(|_| AseResponseCode::InvalidAseId)?;
+        Ok((index, ase_id))
+    }
+
+    /// Assign a connection slot to a newly connected client
+    pub fn handle_connect(&mut self, conn_handle: u16) -> Result<(), AscsError> {
+        self.connections.get_mut().connect(conn_handle).map(|_| ())
+    }
+
+    /// Clean up the Ases of a client whose connection dropped
+    ///
+    /// Application code should call this from the `ConnectionEvent::Disconnected`
+    /// arm of the connection event loop, a client that disconnects without
+    /// releasing its Ases would otherwise leave them enabled or streaming.
+    pub fn handle_disconnect(&mut self, conn_handle: u16) {
+        if let Some(slot) = self.connections.get_mut().disconnect(conn_handle) {
+            #[cfg(feature = "defmt")]
+            warn!(
+                "[ascs] connection {} dropped, returning its ases to idle",
+                conn_handle
+            );
+            self.force_idle_all(slot, conn_handle);
+        }
+    }
+
+    /// Return every Ase of a connection slot to the idle state
+    ///
+    /// The Ases are marked changed, so the next client given the slot is
+    /// notified of their idle state.
+    fn force_idle_all(&mut self, slot: usize, conn_handle: u16) {
+        for index in 0..self.ase_types.len() {
+            let state = &mut self.states.get_mut()[slot][index];
+            if matches!(state, AseState::Idle) {
+                continue;
+            }
+            let was_streaming = matches!(state, AseState::Streaming(_));
+            *state = AseState::Idle;
+            let ase_id = self.ase_types[index].id();
+            self.mark_changed(slot, ase_id);
+            self.emit(AscsEvent::Released {
+                conn_handle,
+                ase_id,
+            });
+            if was_streaming {
+                self.emit_stream(StreamEvent::StreamTerminated { ase_id });
+            }
+        }
+        for cis in self.cis_handles.get_mut()[slot].iter_mut() {
+            *cis = None;
+        }
+        for config in self.codec_configs.get_mut()[slot].iter_mut() {
+            *config = None;
+        }
+        for config in self.qos_configs.get_mut()[slot].iter_mut() {
+            *config = None;
+        }
+    }
+
+    /// The connection slot of the Ase characteristic with `handle`
+    fn ase_slot(&self, handle: u16) -> Option<usize> {
+        self.ases.iter().find_map(|ase| {
+            ase.iter()
+                .position(|client_ase| client_ase.handle == handle)
+        })
+    }
+
+    /// Select the client whose Gatt event is handled next
+    ///
+    /// The Gatt events do not carry their connection, so this must be called
+    /// before [`LeAudioServerService::handle_read_event`] and
+    /// [`LeAudioServerService::handle_write_event`]. Clients only access the
+    /// Ase characteristics of their own connection slot.
+    pub fn set_active_connection(&self, conn_handle: u16) {
+        self.active_slot.set(self.slot_of(conn_handle).ok());
+    }
+
+    /// Register a connection using an Ase
+    ///
+    /// Fails with [`AscsError::InsufficientResources`] once `MAX_CONNECTIONS`
+    /// connections use the Ase. Pair with [`Self::on_connection_closed`].
+    pub fn on_connection_opened(&self, ase_id: AseId) -> Result<(), AscsError> {
+        let count = &self.connection_count[self.index_of(ase_id)?];
+        // Load and store rather than fetch_add, which thumbv6m does not support
+        let current = count.load(Ordering::Relaxed);
+        if current as usize >= MAX_CONNECTIONS {
+            return Err(AscsError::InsufficientResources);
+        }
+        count.store(current + 1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Release the slot taken by [`Self::on_connection_opened`]
+    pub fn on_connection_closed(&self, ase_id: AseId) -> Result<(), AscsError> {
+        let count = &self.connection_count[self.index_of(ase_id)?];
+        count.store(
+            count.load(Ordering::Relaxed).saturating_sub(1),
+            Ordering::Relaxed,
+        );
+        Ok(())
+    }
+
+    /// The number of additional connections an Ase can serve
+    pub fn available_slots_for_ase(&self, ase_id: AseId) -> usize {
+        match self.index_of(ase_id) {
+            Ok(index) => MAX_CONNECTIONS
+                .saturating_sub(self.connection_count[index].load(Ordering::Relaxed) as usize),
+            Err(_) => 0,
+        }
+    }
+
+    /// Propose the QoS of an Ase instead of waiting for the client's Config QoS
+    ///
+    /// Once a client configures the codec of the Ase and `CodecConfigured` is
+    /// notified, the server moves it on to `QosConfigured` using `proposal`.
+    /// Selecting the QoS on the server is implementation defined, the client
+    /// can still override it with Config QoS.
+    pub fn enable_server_qos_proposal(
+        &mut self,
+        ase_id: AseId,
+        proposal: AseParamsQoSConfigured,
+    ) -> Result<(), AscsError> {
+        let index = self.index_of(ase_id)?;
+        self.qos_proposals[index] = Some(proposal);
+        Ok(())
+    }
+
+    /// Leave the QoS of an Ase to the client's Config QoS again
+    ///
+    /// Ases codec configured but not notified yet stay `CodecConfigured`.
+    pub fn disable_server_qos_proposal(&mut self, ase_id: AseId) -> Result<(), AscsError> {
+        let index = self.index_of(ase_id)?;
+        self.qos_proposals[index] = None;
+        let states = self.states.get_mut();
+        for (slot, configs) in self.qos_configs.get_mut().iter_mut().enumerate() {
+            if matches!(states[slot][index], AseState::CodecConfigured(_)) {
+                configs[index] = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// The QoS the server proposes for an Ase, if any
+    pub fn qos_proposal_for_ase(&self, ase_id: AseId) -> Option<&AseParamsQoSConfigured> {
+        let index = self.index_of(ase_id).ok()?;
+        self.qos_proposals[index].as_ref()
+    }
+
+    /// Check a CIG and CIS pair is not already used by another Ase of the same direction
+    ///
+    /// A sink and a source Ase may share the pair to form a bidirectional stream.
+    fn validate_cig_cis_uniqueness(
+        &self,
+        slot: usize,
+        new_cig: u8,
+        new_cis: u8,
+        new_ase_id: AseId,
+    ) -> Result<(), QosConflictError> {
+        let Ok(new_index) = self.index_of(new_ase_id) else {
+            return Ok(());
+        };
+        let new_is_sink = self.ase_types[new_index].is_sink();
+
+        for (index, qos) in self.qos_configs.borrow()[slot].iter().enumerate() {
+            let Some(qos) = qos else {
+                continue;
+            };
+            if index != new_index
+                && qos.cig_id == new_cig
+                && qos.cis_id == new_cis
+                && self.ase_types[index].is_sink() == new_is_sink
+            {
+                return Err(QosConflictError::DuplicateCigCis);
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the codec configuration a client selected for an Ase
+    ///
+    /// The configuration is kept to validate a later Config QoS and
+    /// to report the Ase state.
+    pub fn cache_codec_config(
+        &mut self,
+        conn_handle: u16,
+        ase_id: AseId,
+        config: ConfigCodecOperand,
+    ) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        let index = self.index_of(ase_id)?;
+        self.codec_configs.get_mut()[slot][index] = Some(config.into());
+        Ok(())
+    }
+
+    /// The codec configuration last selected for an Ase by the client of `conn_handle`, if any
+    pub fn codec_config_for_ase(
+        &self,
+        conn_handle: u16,
+        ase_id: AseId,
+    ) -> Option<Ref<'_, AseParamsCodecConfigured>> {
+        let slot = self.slot_of(conn_handle).ok()?;
+        let index = self.index_of(ase_id).ok()?;
+        Ref::filter_map(self.codec_configs.borrow(), |configs| {
+            configs[slot][index].as_ref()
+        })
+        .ok()
+    }
+
+    /// Save the codec configuration of each Ase of the client of `conn_handle` into `cache`
+    ///
+    /// Call this before [`Self::handle_disconnect`], which returns the Ases to
+    /// idle. Ases past `CodecConfigured` keep the codec configuration they
+    /// were configured with.
+    pub fn cache_state(
+        &self,
+        conn_handle: u16,
+        cache: &mut AscsStateCache<MAX_ASES>,
+    ) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        cache.clear();
+        for (ase, config) in self
+            .ase_types
+            .iter()
+            .zip(&self.codec_configs.borrow()[slot])
+        {
+            if let Some(config) = config {
+                // Holds at most one configuration for each of the MAX_ASES Ases
+                let _ = cache.codec_configs.push((ase.id(), config.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the Ases of a reconnected client to the codec configurations of `cache`
+    ///
+    /// Call this after [`Self::handle_connect`]. The restored Ases are
+    /// `CodecConfigured` and marked changed, so the client is notified of them
+    /// and reads them back from the Ase characteristics.
+    pub fn restore_state(
+        &mut self,
+        conn_handle: u16,
+        cache: &AscsStateCache<MAX_ASES>,
+    ) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        for (ase_id, config) in &cache.codec_configs {
+            let Ok(index) = self.index_of(*ase_id) else {
+                #[cfg(feature = "defmt")]
+                warn!("[ascs] cached ase {} does not exist, skipping it", ase_id);
+                continue;
+            };
+            if !matches!(self.states.get_mut()[slot][index], AseState::Idle) {
+                continue;
+            }
+            self.codec_configs.get_mut()[slot][index] = Some(config.clone());
+            self.states.get_mut()[slot][index] = AseState::CodecConfigured(config.clone());
+            self.mark_changed(slot, *ase_id);
+        }
+        Ok(())
+    }
+
+    /// Surface control point operations written by clients to the application
+    ///
+    /// The application task should receive from the channel, for example to
+    /// create the CIS once an Ase is `QosConfigured`.
+    pub fn with_event_channel(mut self, channel: &'static AscsEventChannel) -> Self {
+        self.events = Some(channel);
+        self
+    }
+
+    /// Publish the streams entering and leaving the streaming state on `channel`
+    ///
+    /// The application should set up the ISO data path of each established
+    /// stream with LE Setup ISO Data Path, and remove it once terminated.
+    pub fn with_stream_event_channel(mut self, channel: &'static StreamEventChannel) -> Self {
+        self.stream_events = Some(channel);
+        self
+    }
+
+    /// Reject codec configurations the records of `sink_pac` and `source_pac` do not support
+    ///
+    /// Config Codec of a sink Ase is checked against the sink PAC, of a source
+    /// Ase against the source PAC. Without a PAC any configuration is accepted.
+    pub fn with_capabilities(mut self, sink_pac: Option<&PAC>, source_pac: Option<&PAC>) -> Self {
+        self.sink_capabilities = sink_pac.map(|pac| pac.records().iter().cloned().collect());
+        self.source_capabilities = source_pac.map(|pac| pac.records().iter().cloned().collect());
+        self
+    }
+
+    /// Set the available audio contexts of Pacs
+    ///
+    /// Enable and Update Metadata operations streaming another context are
+    /// rejected. [`Server`](crate::Server) sets them before handling each write.
+    pub fn set_available_contexts(&self, contexts: AudioContexts) {
+        *self.available_contexts.borrow_mut() = Some(contexts);
+    }
+
+    /// Set the QoS preferences an Ase reports once a client configures its codec
+    ///
+    /// Config QoS operations outside of these preferences are rejected.
+    pub fn set_preferred_qos(
+        &mut self,
+        ase_id: AseId,
+        preferred: ServerPreferredQos,
+    ) -> Result<(), AscsError> {
+        let index = self.index_of(ase_id)?;
+        self.preferred_qos[index] = preferred;
+        Ok(())
+    }
+
+    fn emit_stream(&self, event: StreamEvent) {
+        if let Some(events) = self.stream_events {
+            if events.try_send(event).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[ascs] stream event channel is full, dropping event");
+            }
+        }
+    }
+
+    /// Record the CIS the client of `conn_handle` connected for `cig_id` and `cis_id`
+    ///
+    /// Call this on the LE CIS Established event. The Ases configured with this
+    /// CIS report their stream as established once they are streaming.
+    pub fn set_cis_handle(
+        &self,
+        conn_handle: u16,
+        cig_id: u8,
+        cis_id: u8,
+        cis_handle: u16,
+    ) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        for index in 0..self.ase_types.len() {
+            let configured = self.qos_configs.borrow()[slot][index]
+                .as_ref()
+                .is_some_and(|qos| qos.cig_id == cig_id && qos.cis_id == cis_id);
+            if !configured {
+                continue;
+            }
+            self.cis_handles.borrow_mut()[slot][index] = Some(cis_handle);
+            if matches!(self.states.borrow()[slot][index], AseState::Streaming(_)) {
+                self.stream_established(slot, index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget a CIS of the client of `conn_handle` once it is disconnected
+    ///
+    /// Call this on the HCI Disconnection Complete event of the CIS. `Releasing`
+    /// Ases using the CIS return to `Idle`, to be notified with
+    /// [`Server::notify_ase_state_changes`](crate::Server::notify_ase_state_changes).
+    pub fn clear_cis_handle(&self, conn_handle: u16, cis_handle: u16) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        for index in 0..self.ase_types.len() {
+            let mut cis_handles = self.cis_handles.borrow_mut();
+            if cis_handles[slot][index] != Some(cis_handle) {
+                continue;
+            }
+            cis_handles[slot][index] = None;
+            drop(cis_handles);
+            if matches!(self.states.borrow()[slot][index], AseState::Streaming(_)) {
+                let ase_id = self.ase_types[index].id();
+                self.emit_stream(StreamEvent::StreamTerminated { ase_id });
+            }
+            self.complete_release(slot, index);
+        }
+        Ok(())
+    }
+
+    /// Report the stream of a streaming Ase, once its CIS is known
+    fn stream_established(&self, slot: usize, index: usize) {
+        let Some(cis_handle) = self.cis_handles.borrow()[slot][index] else {
+            return;
+        };
+        let Some(codec_config) = self.codec_configs.borrow()[slot][index].clone() else {
+            return;
+        };
+        self.emit_stream(StreamEvent::StreamEstablished {
+            ase_id: self.ase_types[index].id(),
+            cis_handle,
+            codec_config,
+        });
+    }
+
+    fn emit(&self, event: AscsEvent) {
+        if let Some(events) = self.events {
+            if events.try_send(event).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[ascs] event channel is full, dropping event");
+            }
+        }
+    }
+
+    /// The current state of an Ase for the client of `conn_handle`
+    pub fn ase_state(&self, conn_handle: u16, ase_id: AseId) -> Option<Ref<'_, AseState>> {
+        let slot = self.slot_of(conn_handle).ok()?;
+        let index = self.index_of(ase_id).ok()?;
+        Some(Ref::map(self.states.borrow(), |states| {
+            &states[slot][index]
+        }))
+    }
+
+    /// The number of Ases the server exposes to each client
+    pub fn ase_count(&self) -> usize {
+        self.ase_types.len()
+    }
+
+    /// Whether the server exposes at least one sink Ase
+    pub fn has_sink_ases(&self) -> bool {
+        self.ase_types.iter().any(AseType::is_sink)
+    }
+
+    /// Whether the server exposes at least one source Ase
+    pub fn has_source_ases(&self) -> bool {
+        self.ase_types.iter().any(|ase| !ase.is_sink())
+    }
+
+    /// The ids of the Ases the server exposes, sink and source
+    pub fn ase_ids(&self) -> Vec<AseId, MAX_ASES> {
+        self.ase_types.iter().map(AseType::id).collect()
+    }
+
+    /// Whether `ase_id` is a sink Ase, `None` if the server has no such Ase
+    pub fn is_sink(&self, ase_id: AseId) -> Option<bool> {
+        let index = self.index_of(ase_id).ok()?;
+        Some(self.ase_types[index].is_sink())
+    }
+
+    /// Signal the server is ready to receive audio on a sink Ase
+    ///
+    /// Moves the Ase from `Enabling` to `Streaming`, the client performs this
+    /// operation for source Ases with the Receiver Start Ready operation.
+    pub fn start_streaming(&self, conn_handle: u16, ase_id: AseId) -> Result<(), AscsError> {
+        let slot = self.slot_of(conn_handle)?;
+        let index = self.index_of(ase_id)?;
+        if !self.ase_types[index].is_sink() {
+            return Err(AscsError::InvalidTransition);
+        }
+        self.receiver_start_ready(slot, index, ase_id)
+            .map_err(|_| AscsError::InvalidTransition)
+    }
+
+    /// The ASE Control Point characteristic
+    pub fn control_point(&self) -> &Characteristic<AseControlPointResponse> {
+        &self.ase_control_point
+    }
+
+    /// The response to the last control point write, to be notified to the client
+    pub fn take_control_point_response(&self) -> Option<AseControlPointResponse> {
+        self.pending_response.borrow_mut().take()
+    }
+
+    /// The ids of the Ases of the client of `conn_handle` whose state changed since the last call
+    ///
+    /// Each of them must be notified with [`Self::notify_ase_state_change`].
+    pub fn take_changed_ases(&self, conn_handle: u16) -> Vec<AseId, MAX_ASES> {
+        match self.slot_of(conn_handle) {
+            Ok(slot) => core::mem::take(&mut self.changed_ases.borrow_mut()[slot]),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Notify the current state of an Ase to the client of `conn`
+    pub async fn notify_ase_state_change<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        ase_id: AseId,
+    ) -> Result<(), NotifyError> {
+        let index = self.index_of(ase_id).map_err(NotifyError::Ascs)?;
+        let slot = self
+            .slot_of(conn.handle().raw())
+            .map_err(NotifyError::Ascs)?;
+
+        let value = AseValue::new(ase_id, &self.states.borrow()[slot][index]);
+        self.ases[index][slot]
+            .notify(server, conn, &value)
+            .await
+            .map_err(NotifyError::Host)?;
+        self.state_notified(slot, index);
+        Ok(())
+    }
+
+    /// Move an Ase on from the transient states once they are notified
+    fn state_notified(&self, slot: usize, index: usize) {
+        self.complete_release(slot, index);
+        self.apply_qos_proposal(slot, index);
+    }
+
+    /// Return a `Releasing` Ase to `Idle` once its CIS is disconnected
+    ///
+    /// Called once the `Releasing` state is notified and when a CIS is
+    /// cleared. The Ase is marked changed so `Idle` is notified too.
+    fn complete_release(&self, slot: usize, index: usize) {
+        if self.cis_handles.borrow()[slot][index].is_some() {
+            return;
+        }
+        let mut states = self.states.borrow_mut();
+        if !matches!(states[slot][index], AseState::Releasing) {
+            return;
+        }
+        states[slot][index] = AseState::Idle;
+        self.mark_changed(slot, self.ase_types[index].id());
+    }
+
+    /// Record a state change of an Ase, to be notified to the client of `slot`
+    fn mark_changed(&self, slot: usize, ase_id: AseId) {
+        let mut changed = self.changed_ases.borrow_mut();
+        let changed = &mut changed[slot];
+        if !changed.contains(&ase_id) {
+            // Holds every Ase id at most once, so it can not overflow
+            let _ = changed.push(ase_id);
+        }
+    }
+
+    /// Parse a control point write and apply the operation to every Ase it addresses
+    ///
+    /// The outcome for each Ase is collected into the response returned by
+    /// [`Self::take_control_point_response`], which is notified after the
+    /// write response. Operations that can not be parsed, with an unsupported
+    /// opcode, an invalid length or more Ases or metadata than the server
+    /// holds, are accepted too and rejected in that notification.
+    fn process_control_point(&self, slot: usize, data: &[u8]) -> Result<(), LeAudioError> {
+        let command = match parse_ase_control_point::<MAX_ASES>(data) {
+            Ok(command) => command,
+            // The write is accepted and the rejection notified on the control point
+            Err(err) => {
+                let code = match err {
+                    AscsError::InvalidLength => AseResponseCode::InvalidLength,
+                    AscsError::UnsupportedOpcode(_) => AseResponseCode::UnsupportedOpcode,
+                    AscsError::InsufficientResources => AseResponseCode::InsufficientResources,
+                    _ => AseResponseCode::UnspecifiedError,
+                };
+                #[cfg(feature = "defmt")]
+                warn!("[ascs] control point write rejected: {:?}", err);
+                let opcode = data.first().copied().unwrap_or_default();
+                *self.pending_response.borrow_mut() =
+                    Some(AseControlPointResponse::rejected(opcode, code));
+                return Ok(());
+            }
+        };
+        // Checked before applying the operation to any Ase, so a rejection leaves every Ase as is
+        if command.num_ases() > MAX_CONTROL_POINT_RESPONSE_ASES {
+            #[cfg(feature = "defmt")]
+            warn!(
+                "[ascs] control point write addresses {} ases, more than a response holds",
+                command.num_ases()
+            );
+            *self.pending_response.borrow_mut() = Some(AseControlPointResponse::rejected(
+                command.opcode() as u8,
+                AseResponseCode::InsufficientResources,
+            ));
+            return Ok(());
+        }
+        let mut response = AseControlPointResponse::new(command.opcode() as u8);
+        let mut respond = |ase_id: u8, result: Result<(), AseRejection>| {
+            let rejection = result.err().unwrap_or(AseRejection {
+                code: AseResponseCode::Success,
+                reason: AseResponseReason::None,
+            });
+            #[cfg(feature = "defmt")]
+            if rejection.code != AseResponseCode::Success {
+                warn!(
+                    "[ascs] opcode {} rejected for ase {}: {:?}",
+                    command.opcode() as u8,
+                    ase_id,
+                    rejection
+                );
+            }
+            response
+                .push(ase_id, rejection)
+                .map_err(|_| AttErrorCode::INSUFFICIENT_RESOURCES)
+        };
+
+        match &command {
+            AseControlPointCommand::ConfigCodec(configs) => {
+                for config in configs {
+                    let result = self.resolve(config.ase_id).and_then(|(index, ase_id)| {
+                        self.config_codec(slot, index, ase_id, config.operand.clone())
+                    });
+                    respond(config.ase_id, result)?;
+                }
+            }
+            AseControlPointCommand::ConfigQos(configs) => {
+                for config in configs {
+                    let result = self.resolve(config.ase_id).and_then(|(index, ase_id)| {
+                        self.config_qos(slot, index, ase_id, config.qos.clone())
+                    });
+                    respond(config.ase_id, result)?;
+                }
+            }
+            AseControlPointCommand::Enable(configs)
+            | AseControlPointCommand::UpdateMetadata(configs) => {
+                let enable = matches!(command, AseControlPointCommand::Enable(_));
+                for config in configs {
+                    let result = self.resolve(config.ase_id).and_then(|(index, ase_id)| {
+                        if enable {
+                            self.enable(slot, index, ase_id, &config.metadata)
+                        } else {
+                            self.update_metadata(slot, index, ase_id, &config.metadata)
+                        }
+                    });
+                    respond(config.ase_id, result)?;
+                }
+            }
+            AseControlPointCommand::ReceiverStartReady(ase_ids)
+            | AseControlPointCommand::Disable(ase_ids)
+            | AseControlPointCommand::ReceiverStopReady(ase_ids)
+            | AseControlPointCommand::Release(ase_ids) => {
+                for &id in ase_ids {
+                    let result = self.resolve(id).and_then(|(index, ase_id)| match command {
+                        AseControlPointCommand::ReceiverStartReady(_) => {
+                            self.client_receiver_start_ready(slot, index, ase_id)
+                        }
+                        AseControlPointCommand::Disable(_) => self.disable(slot, index, ase_id),
+                        AseControlPointCommand::ReceiverStopReady(_) => {
+                            self.receiver_stop_ready(slot, index, ase_id)
+                        }
+                        _ => self.release(slot, index, ase_id),
+                    });
+                    respond(id, result)?;
+                }
+            }
+        }
+
+        *self.pending_response.borrow_mut() = Some(response);
+        Ok(())
+    }
+
+    /// Config Codec, valid from `Idle`, `CodecConfigured` and `QosConfigured`
+    fn config_codec(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+        operand: ConfigCodecOperand,
+    ) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        if !matches!(
+            states[index],
+            AseState::Idle | AseState::CodecConfigured(_) | AseState::QosConfigured(_)
+        ) {
+            return Err(AseResponseCode::InvalidTransition.into());
+        }
+        self.check_capabilities(index, &operand)?;
+        let proposal = self.qos_proposals[index].clone();
+        if let Some(proposal) = &proposal {
+            self.validate_cig_cis_uniqueness(slot, proposal.cig_id, proposal.cis_id, ase_id)
+                .map_err(|_| AseRejection {
+                    code: AseResponseCode::InvalidConfigurationParameterValue,
+                    reason: AseResponseReason::InvalidAseCisMapping,
+                })?;
+        }
+
+        let params = AseParamsCodecConfigured::new(operand, &self.preferred_qos[index]);
+        self.codec_configs.borrow_mut()[slot][index] = Some(params.clone());
+        // A proposal holds its CIG and CIS until applied by `apply_qos_proposal`
+        self.qos_configs.borrow_mut()[slot][index] = proposal;
+        states[index] = AseState::CodecConfigured(params.clone());
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::CodecConfigured {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+            params,
+        });
+        Ok(())
+    }
+
+    /// Move a `CodecConfigured` Ase on to the QoS proposed by the server
+    ///
+    /// Called once `CodecConfigured` is notified, so the client sees both
+    /// states. The Ase is marked changed so `QosConfigured` is notified too.
+    fn apply_qos_proposal(&self, slot: usize, index: usize) {
+        let mut states = self.states.borrow_mut();
+        if !matches!(states[slot][index], AseState::CodecConfigured(_)) {
+            return;
+        }
+        let Some(params) = self.qos_configs.borrow()[slot][index].clone() else {
+            return;
+        };
+        let ase_id = self.ase_types[index].id();
+        states[slot][index] = AseState::QosConfigured(params.clone());
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::QosConfigured {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+            params,
+        });
+    }
+
+    /// Check the PAC records of the direction of an Ase support a codec configuration
+    fn check_capabilities(
+        &self,
+        index: usize,
+        operand: &ConfigCodecOperand,
+    ) -> Result<(), AseRejection> {
+        let capabilities = if self.ase_types[index].is_sink() {
+            &self.sink_capabilities
+        } else {
+            &self.source_capabilities
+        };
+        let Some(records) = capabilities else {
+            return Ok(());
+        };
+        let config =
+            CodecSpecificConfiguration::decode_all_ltv(&operand.codec_specific_configuration)
+                .map_err(|_| AseRejection {
+                    code: AseResponseCode::InvalidConfigurationParameterValue,
+                    reason: AseResponseReason::CodecSpecificConfiguration,
+                })?;
+        if !records
+            .iter()
+            .any(|record| record.codec_id.contains(&operand.codec_id))
+        {
+            return Err(AseRejection {
+                code: AseResponseCode::UnsupportedAudioCapabilities,
+                reason: AseResponseReason::CodecId,
+            });
+        }
+        if !records
+            .iter()
+            .any(|record| supports_codec_config(record, &operand.codec_id, &config))
+        {
+            return Err(AseRejection {
+                code: AseResponseCode::UnsupportedAudioCapabilities,
+                reason: AseResponseReason::CodecSpecificConfiguration,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a Config QoS is within the preferences the Ase reported once codec configured
+    fn check_qos(
+        preferred: &AseParamsCodecConfigured,
+        params: &AseParamsQoSConfigured,
+    ) -> Result<(), AseRejection> {
+        let rejected = |reason| AseRejection {
+            code: AseResponseCode::RejectedConfigurationParameterValue,
+            reason,
+        };
+        // Framing 0x01 means the server does not support unframed ISOAL PDUs
+        if preferred.framing == 0x01 && params.framing == 0x00 {
+            return Err(rejected(AseResponseReason::Framing));
+        }
+        if phy_to_bits(&preferred.preferred_phy) & phy_to_bits(&params.phy) == 0 {
+            return Err(rejected(AseResponseReason::Phy));
+        }
+        if params.max_transport_latency > preferred.max_transport_latency {
+            return Err(rejected(AseResponseReason::MaxTransportLatency));
+        }
+        let [low, mid, high] = params.presentation_delay;
+        let presentation_delay = u32::from_le_bytes([low, mid, high, 0]);
+        if !(preferred.presentation_delay_min..=preferred.presentation_delay_max)
+            .contains(&presentation_delay)
+        {
+            return Err(rejected(AseResponseReason::PresentationDelay));
+        }
+        Ok(())
+    }
+
+    /// Config QoS, valid from `CodecConfigured` and `QosConfigured`
+    fn config_qos(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+        params: AseParamsQoSConfigured,
+    ) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        if !matches!(
+            states[index],
+            AseState::CodecConfigured(_) | AseState::QosConfigured(_)
+        ) {
+            return Err(AseResponseCode::InvalidTransition.into());
+        }
+        if let Some(preferred) = &self.codec_configs.borrow()[slot][index] {
+            Self::check_qos(preferred, &params)?;
+        }
+        self.validate_cig_cis_uniqueness(slot, params.cig_id, params.cis_id, ase_id)
+            .map_err(|_| AseRejection {
+                code: AseResponseCode::InvalidConfigurationParameterValue,
+                reason: AseResponseReason::InvalidAseCisMapping,
+            })?;
+
+        self.qos_configs.borrow_mut()[slot][index] = Some(params.clone());
+        self.cis_handles.borrow_mut()[slot][index] = None;
+        states[index] = AseState::QosConfigured(params.clone());
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::QosConfigured {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+            params,
+        });
+        Ok(())
+    }
+
+    /// Check the Streaming_Audio_Contexts metadata only holds available contexts
+    ///
+    /// Metadata without streaming contexts is accepted.
+    fn check_streaming_contexts(&self, index: usize, metadata: &[u8]) -> Result<(), AseRejection> {
+        let rejection = |code| AseRejection {
+            code,
+            reason: AseResponseReason::Metadata(STREAMING_AUDIO_CONTEXTS),
+        };
+        let mut ltvs = LtvIterator::new(metadata);
+        let mut streaming = None;
+        for (type_id, value) in ltvs.by_ref() {
+            if type_id != STREAMING_AUDIO_CONTEXTS {
+                continue;
+            }
+            match Metadata::decode_value(type_id, value) {
+                Ok(Metadata::StreamingAudioContexts(contexts)) if !contexts.is_empty() => {
+                    streaming = Some(contexts)
+                }
+                _ => return Err(rejection(AseResponseCode::InvalidMetadata)),
+            }
+        }
+        if !ltvs.remaining().is_empty() {
+            return Err(AseResponseCode::InvalidMetadata.into());
+        }
+        let (Some(streaming), Some(available)) = (streaming, &*self.available_contexts.borrow())
+        else {
+            return Ok(());
+        };
+        let available = if self.ase_types[index].is_sink() {
+            available.sink_contexts
+        } else {
+            available.source_contexts
+        };
+        if !available.contains(streaming) {
+            return Err(rejection(AseResponseCode::RejectedMetadata));
+        }
+        Ok(())
+    }
+
+    /// Enable, valid from `QosConfigured`
+    fn enable(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+        metadata: &Vec<u8, MAX_ASE_METADATA_SIZE>,
+    ) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        let AseState::QosConfigured(qos) = &states[index] else {
+            return Err(AseResponseCode::InvalidTransition.into());
+        };
+        self.check_streaming_contexts(index, metadata)?;
+
+        states[index] = AseState::Enabling(AseParamsOther {
+            cig_id: qos.cig_id,
+            cis_id: qos.cis_id,
+            metadata: metadata.clone(),
+        });
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::Enabled {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+        });
+        Ok(())
+    }
+
+    /// Receiver Start Ready written by the client, only valid for source Ases
+    fn client_receiver_start_ready(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+    ) -> Result<(), AseRejection> {
+        if self.ase_types[index].is_sink() {
+            return Err(AseResponseCode::InvalidAseDirection.into());
+        }
+        self.receiver_start_ready(slot, index, ase_id)
+    }
+
+    /// Receiver Start Ready, valid from `Enabling`
+    fn receiver_start_ready(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+    ) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        let AseState::Enabling(params) = &states[index] else {
+            return Err(AseResponseCode::InvalidTransition.into());
+        };
+
+        states[index] = AseState::Streaming(params.clone());
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::Streaming {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+        });
+        self.stream_established(slot, index);
+        Ok(())
+    }
+
+    /// Disable, valid from `Enabling` and `Streaming`
+    ///
+    /// Sink Ases go straight back to `QosConfigured`, source Ases wait in
+    /// `Disabling` for the client's Receiver Stop Ready.
+    fn disable(&self, slot: usize, index: usize, ase_id: AseId) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        let (AseState::Enabling(params) | AseState::Streaming(params)) = &states[index] else {
+            return Err(AseResponseCode::InvalidTransition.into());
+        };
+        let was_streaming = matches!(states[index], AseState::Streaming(_));
+
+        states[index] = if self.ase_types[index].is_sink() {
+            AseState::QosConfigured(
+                self.qos_configs.borrow()[slot][index]
+                    .clone()
+                    .unwrap_or_default(),
+            )
+        } else {
+            AseState::Disabling(params.clone())
+        };
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::Disabled {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+        });
+        if was_streaming {
+            self.emit_stream(StreamEvent::StreamTerminated { ase_id });
+        }
+        Ok(())
+    }
+
+    /// Receiver Stop Ready, valid from `Disabling` and only for source Ases
+    fn receiver_stop_ready(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+    ) -> Result<(), AseRejection> {
+        if self.ase_types[index].is_sink() {
+            return Err(AseResponseCode::InvalidAseDirection.into());
+        }
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        if !matches!(states[index], AseState::Disabling(_)) {
+            return Err(AseResponseCode::InvalidTransition.into());
+        }
+
+        states[index] = AseState::QosConfigured(
+            self.qos_configs.borrow()[slot][index]
+                .clone()
+                .unwrap_or_default(),
+        );
+        self.mark_changed(slot, ase_id);
+        Ok(())
+    }
+
+    /// Update Metadata, valid from `Enabling` and `Streaming`
+    fn update_metadata(
+        &self,
+        slot: usize,
+        index: usize,
+        ase_id: AseId,
+        metadata: &Vec<u8, MAX_ASE_METADATA_SIZE>,
+    ) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let (AseState::Enabling(params) | AseState::Streaming(params)) = &mut states[slot][index]
+        else {
+            return Err(AseResponseCode::InvalidTransition.into());
+        };
+        self.check_streaming_contexts(index, metadata)?;
+
+        params.metadata = metadata.clone();
+        self.mark_changed(slot, ase_id);
+
+        self.emit(AscsEvent::MetadataUpdated {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+        });
+        Ok(())
+    }
+
+    /// Release, valid from any state but `Idle` and `Releasing`
+    ///
+    /// The Ase waits in `Releasing` until that state is notified and its CIS,
+    /// if any, is disconnected, see [`Self::complete_release`].
+    fn release(&self, slot: usize, index: usize, ase_id: AseId) -> Result<(), AseRejection> {
+        let mut states = self.states.borrow_mut();
+        let states = &mut states[slot];
+        if matches!(states[index], AseState::Idle | AseState::Releasing) {
+            return Err(AseResponseCode::InvalidTransition.into());
+        }
+
+        let was_streaming = matches!(states[index], AseState::Streaming(_));
+        self.qos_configs.borrow_mut()[slot][index] = None;
+        states[index] = AseState::Releasing;
+        self.mark_changed(slot, ase_id);
+        self.emit(AscsEvent::Released {
+            conn_handle: self.conn_handle_of(slot),
+            ase_id,
+        });
+        if was_streaming {
+            self.emit_stream(StreamEvent::StreamTerminated { ase_id });
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned by the Ascs server
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AscsError {
+    /// No Ase with this id is exposed by the server
+    UnknownAseId(u8),
+    /// The id is 0 or 255, which no Ase can have
+    InvalidAseId(u8),
+    /// Every connection slot of the Ase is in use
+    InsufficientResources,
+    /// The connection was not given a slot with [`AscsServer::handle_connect`]
+    UnknownConnection(u16),
+    /// The Ase is not in a state allowing the operation
+    InvalidTransition,
+    /// An Ase characteristic value does not hold a valid state
+    InvalidAseState,
+    /// A control point write ends before the parameters of its operation
+    InvalidLength,
+    /// A control point write has an opcode outside 0x01 to 0x08
+    UnsupportedOpcode(u8),
+}
+
+impl From<AscsError> for AttErrorCode {
+    fn from(err: AscsError) -> Self {
+        match err {
+            AscsError::UnknownAseId(_) => AttErrorCode::WRITE_REQUEST_REJECTED,
+            AscsError::InvalidAseId(_) => AttErrorCode::WRITE_REQUEST_REJECTED,
+            AscsError::InsufficientResources => AttErrorCode::INSUFFICIENT_RESOURCES,
+            AscsError::UnknownConnection(_) => AttErrorCode::INSUFFICIENT_RESOURCES,
+            AscsError::InvalidTransition => AttErrorCode::WRITE_REQUEST_REJECTED,
+            AscsError::InvalidAseState => AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH,
+            AscsError::InvalidLength => AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH,
+            AscsError::UnsupportedOpcode(_) => AttErrorCode::REQUEST_NOT_SUPPORTED,
+        }
+    }
+}
+
+/// Maps the connection handle of each client to its connection slot
+struct ConnectionMap<const MAX_CONNECTIONS: usize>(LinearMap<u16, usize, MAX_CONNECTIONS>);
+
+impl<const MAX_CONNECTIONS: usize> ConnectionMap<MAX_CONNECTIONS> {
+    fn new() -> Self {
+        Self(LinearMap::new())
+    }
+
+    /// The slot of a connection, assigning the first free slot to new connections
+    fn connect(&mut self, conn_handle: u16) -> Result<usize, AscsError> {
+        if let Some(slot) = self.0.get(&conn_handle) {
+            return Ok(*slot);
+        }
+        let slot = (0..MAX_CONNECTIONS)
+            .find(|slot| !self.0.values().any(|used| used == slot))
+            .ok_or(AscsError::InsufficientResources)?;
+        self.0
+            .insert(conn_handle, slot)
+            .map_err(|_| AscsError::InsufficientResources)?;
+        Ok(slot)
+    }
+
+    /// The slot of a connected client
+    fn slot(&self, conn_handle: u16) -> Option<usize> {
+        self.0.get(&conn_handle).copied()
+    }
+
+    /// The connection handle of the client given `slot`
+    fn handle(&self, slot: usize) -> Option<u16> {
+        self.0
+            .iter()
+            .find(|(_, used)| **used == slot)
+            .map(|(conn_handle, _)| *conn_handle)
+    }
+
+    /// Free the slot of a connection
+    fn disconnect(&mut self, conn_handle: u16) -> Option<usize> {
+        self.0.remove(&conn_handle)
+    }
+}
+
+/// Conflicts between the QoS of two Ases
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosConflictError {
+    /// The CIG and CIS pair is already used by an Ase of the same direction
+    DuplicateCigCis,
+}
+
+/// The parameters of a Config Codec operation for a single Ase
+#[derive(Clone)]
+pub struct ConfigCodecOperand {
+    /// Target latency requested by the client
+    pub target_latency: u8,
+    /// PHY the client intends to use
+    pub target_phy: u8,
+    /// Codec ID
+    pub codec_id: CodecId,
+    /// Codec specific configuration, LTV encoded
+    pub codec_specific_configuration: Vec<u8, MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE>,
+}
+
+impl From<ConfigCodecOperand> for AseParamsCodecConfigured {
+    /// The configuration with the default [`ServerPreferredQos`]
+    fn from(operand: ConfigCodecOperand) -> Self {
+        Self::new(operand, &ServerPreferredQos::default())
+    }
+}
+
+/// Number of events the Ascs event channel can queue
+pub const ASCS_EVENT_QUEUE_SIZE: usize = 4;
+
+/// Channel the Ascs server publishes [`AscsEvent`]s on
+pub type AscsEventChannel = Channel<NoopRawMutex, AscsEvent, ASCS_EVENT_QUEUE_SIZE>;
+
+/// Control point operations a client performed on an Ase
+///
+/// `conn_handle` is the connection of the client owning the Ase.
+///
+/// `CodecConfigured` carries the whole codec configuration by value, there is
+/// no allocator to box it. The budget is 320 bytes an event, 1.25 kB for the
+/// [`ASCS_EVENT_QUEUE_SIZE`] events of an [`AscsEventChannel`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum AscsEvent {
+    CodecConfigured {
+        conn_handle: u16,
+        ase_id: AseId,
+        params: AseParamsCodecConfigured,
+    },
+    QosConfigured {
+        conn_handle: u16,
+        ase_id: AseId,
+        params: AseParamsQoSConfigured,
+    },
+    Enabled {
+        conn_handle: u16,
+        ase_id: AseId,
+    },
+    Streaming {
+        conn_handle: u16,
+        ase_id: AseId,
+    },
+    Disabled {
+        conn_handle: u16,
+        ase_id: AseId,
+    },
+    MetadataUpdated {
+        conn_handle: u16,
+        ase_id: AseId,
+    },
+    Released {
+        conn_handle: u16,
+        ase_id: AseId,
+    },
+}
+
+/// Number of events the stream event channel can queue
+pub const STREAM_EVENT_QUEUE_SIZE: usize = 4;
+
+/// Channel the Ascs server publishes [`StreamEvent`]s on
+pub type StreamEventChannel = Channel<NoopRawMutex, StreamEvent, STREAM_EVENT_QUEUE_SIZE>;
+
+/// Streams entering and leaving the streaming state, to drive the ISO data paths
+///
+/// `StreamEstablished` carries the codec configuration the data path is set
+/// up with by value, within the same 320 bytes budget as [`AscsEvent`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum StreamEvent {
+    /// The Ase is streaming over the CIS `cis_handle`, set up its ISO data path
+    StreamEstablished {
+        ase_id: AseId,
+        cis_handle: u16,
+        codec_config: AseParamsCodecConfigured,
+    },
+    /// The Ase stopped streaming, remove its ISO data path
+    StreamTerminated { ase_id: AseId },
+}
+
+/// Max number of Ases reported in a single control point response
+///
+/// Writes addressing more Ases are rejected as a whole with insufficient resources.
+pub const MAX_CONTROL_POINT_RESPONSE_ASES: usize = 8;
+
+/// Metadata type of Streaming_Audio_Contexts, the reason of its rejections
+const STREAMING_AUDIO_CONTEXTS: u8 = 0x02;
+
+/// Size of the largest control point response
+const ASE_CONTROL_POINT_RESPONSE_SIZE: usize = 2 + 3 * MAX_CONTROL_POINT_RESPONSE_ASES;
+
+/// Notified on the ASE Control Point after each write, reporting the outcome for every Ase
+///
+/// Encoded as Opcode, Number_of_ASEs, then ASE_ID, Response_Code and Reason for each Ase.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AseControlPointResponse(Vec<u8, ASE_CONTROL_POINT_RESPONSE_SIZE>);
+
+impl AseControlPointResponse {
+    fn new(opcode: u8) -> Self {
+        let mut response = Vec::new();
+        // Cannot fail, the capacity is larger than the header
+        let _ = response.extend_from_slice(&[opcode, 0]);
+        Self(response)
+    }
+
+    /// The response to an operation rejected as a whole
+    ///
+    /// Unsupported opcodes and writes of an invalid length are not attributed
+    /// to any Ase, the response has a Number_of_ASEs of 0xFF and an ASE_ID of 0.
+    fn rejected(opcode: u8, code: AseResponseCode) -> Self {
+        let mut response = Vec::new();
+        // Cannot fail, the capacity is larger than a single entry
+        let _ = response.extend_from_slice(&[opcode, 0xFF, 0, code as u8, 0]);
+        Self(response)
+    }
+
+    /// The operation was rejected as a whole, see [`Self::rejected`]
+    pub fn is_operation_rejected(&self) -> bool {
+        self.0.get(1) == Some(&0xFF)
+    }
+
+    fn push(&mut self, ase_id: u8, rejection: AseRejection) -> Result<(), ResponseFull> {
+        self.0
+            .extend_from_slice(&[ase_id, rejection.code as u8, rejection.reason.into()])
+            .map_err(|_| ResponseFull)?;
+        self.0[1] += 1;
+        Ok(())
+    }
+
+    /// The opcode of the operation this responds to
+    pub fn opcode(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    /// The ASE_ID, Response_Code and Reason of every Ase in the response
+    pub fn entries(&self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        self.0
+            .get(2..)
+            .unwrap_or(&[])
+            .chunks_exact(3)
+            .map(|entry| (entry[0], entry[1], entry[2]))
+    }
+}
+
+/// The response already holds [`MAX_CONTROL_POINT_RESPONSE_ASES`] Ases
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseFull;
+
+impl AsGatt for AseControlPointResponse {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = ASE_CONTROL_POINT_RESPONSE_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for AseControlPointResponse {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+/// Response_Code values of the ASE Control Point notification
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AseResponseCode {
+    Success = 0x00,
+    UnsupportedOpcode = 0x01,
+    InvalidLength = 0x02,
+    InvalidAseId = 0x03,
+    InvalidTransition = 0x04,
+    InvalidAseDirection = 0x05,
+    UnsupportedAudioCapabilities = 0x06,
+    UnsupportedConfigurationParameterValue = 0x07,
+    RejectedConfigurationParameterValue = 0x08,
+    InvalidConfigurationParameterValue = 0x09,
+    UnsupportedMetadata = 0x0A,
+    RejectedMetadata = 0x0B,
+    InvalidMetadata = 0x0C,
+    InsufficientResources = 0x0D,
+    UnspecifiedError = 0x0E,
+}
+
+/// Reason values of the ASE Control Point notification, naming the rejected parameter
+///
+/// Rejected metadata is named by its metadata type instead.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AseResponseReason {
+    None,
+    CodecId,
+    CodecSpecificConfiguration,
+    SduInterval,
+    Framing,
+    Phy,
+    MaxSdu,
+    RetransmissionNumber,
+    MaxTransportLatency,
+    PresentationDelay,
+    InvalidAseCisMapping,
+    /// The type of the rejected metadata, reason of the metadata response codes
+    Metadata(u8),
+}
+
+impl AseResponseReason {
+    /// Decode the Reason of a response, a metadata type for the metadata response codes
+    pub fn decode(code: AseResponseCode, reason: u8) -> Result<Self, u8> {
+        match code {
+            AseResponseCode::UnsupportedMetadata
+            | AseResponseCode::RejectedMetadata
+            | AseResponseCode::InvalidMetadata => Ok(Self::Metadata(reason)),
+            _ => Self::try_from(reason),
+        }
+    }
+}
+
+impl From<AseResponseReason> for u8 {
+    fn from(reason: AseResponseReason) -> Self {
+        match reason {
+            AseResponseReason::None => 0x00,
+            AseResponseReason::CodecId => 0x01,
+            AseResponseReason::CodecSpecificConfiguration => 0x02,
+            AseResponseReason::SduInterval => 0x03,
+            AseResponseReason::Framing => 0x04,
+            AseResponseReason::Phy => 0x05,
+            AseResponseReason::MaxSdu => 0x06,
+            AseResponseReason::RetransmissionNumber => 0x07,
+            AseResponseReason::MaxTransportLatency => 0x08,
+            AseResponseReason::PresentationDelay => 0x09,
+            AseResponseReason::InvalidAseCisMapping => 0x0A,
+            AseResponseReason::Metadata(type_id) => type_id,
+        }
+    }
+}
+
+/// Why an operation was rejected for an Ase
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AseRejection {
+    pub code: AseResponseCode,
+    pub reason: AseResponseReason,
+}
+
+impl TryFrom<u8> for AseResponseCode {
+    type Error = u8;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        Ok(match code {
+            0x00 => Self::Success,
+            0x01 => Self::UnsupportedOpcode,
+            0x02 => Self::InvalidLength,
+            0x03 => Self::InvalidAseId,
+            0x04 => Self::InvalidTransition,
+            0x05 => Self::InvalidAseDirection,
+            0x06 => Self::UnsupportedAudioCapabilities,
+            0x07 => Self::UnsupportedConfigurationParameterValue,
+            0x08 => Self::RejectedConfigurationParameterValue,
+            0x09 => Self::InvalidConfigurationParameterValue,
+            0x0A => Self::UnsupportedMetadata,
+            0x0B => Self::RejectedMetadata,
+            0x0C => Self::InvalidMetadata,
+            0x0D => Self::InsufficientResources,
+            0x0E => Self::UnspecifiedError,
+            _ => return Err(code),
+        })
+    }
+}
+
+impl TryFrom<u8> for AseResponseReason {
+    type Error = u8;
+
+    fn try_from(reason: u8) -> Result<Self, Self::Error> {
+        Ok(match reason {
+            0x00 => Self::None,
+            0x01 => Self::CodecId,
+            0x02 => Self::CodecSpecificConfiguration,
+            0x03 => Self::SduInterval,
+            0x04 => Self::Framing,
+            0x05 => Self::Phy,
+            0x06 => Self::MaxSdu,
+            0x07 => Self::RetransmissionNumber,
+            0x08 => Self::MaxTransportLatency,
+            0x09 => Self::PresentationDelay,
+            0x0A => Self::InvalidAseCisMapping,
+            _ => return Err(reason),
+        })
+    }
+}
+
+impl From<AseResponseCode> for AseRejection {
+    fn from(code: AseResponseCode) -> Self {
+        Self {
+            code,
+            reason: AseResponseReason::None,
         }
     }
 }
 
+/// Convert a [`PhySet`] into the PHY bitfield used by ASCS
+fn phy_to_bits(phy: &PhySet) -> u8 {
+    match phy {
+        PhySet::M1 => 0b001,
+        PhySet::M2 => 0b010,
+        PhySet::M1M2 => 0b011,
+        PhySet::Coded => 0b100,
+        PhySet::M1Coded => 0b101,
+        PhySet::M2Coded => 0b110,
+        PhySet::M1M2Coded => 0b111,
+    }
+}
+
+/// Convert the PHY bitfield used by ASCS into a [`PhySet`]
+fn phy_from_bits(bits: u8) -> PhySet {
+    match bits & 0b111 {
+        0b001 => PhySet::M1,
+        0b011 => PhySet::M1M2,
+        0b100 => PhySet::Coded,
+        0b101 => PhySet::M1Coded,
+        0b110 => PhySet::M2Coded,
+        0b111 => PhySet::M1M2Coded,
+        _ => PhySet::M2,
+    }
+}
+
 impl<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> LeAudioServerService
     for AscsServer<MAX_ASES, MAX_CONNECTIONS>
 {
-    fn handle_read_event(&self, event: &ReadEvent) -> Option<Result<(), AttErrorCode>> {
-        if event.handle() == self.ase_control_point.handle {
-            return Some(Err(AttErrorCode::WRITE_REQUEST_REJECTED));
-        }
-        for ase in self.ases.iter() {
-            // TODO: need to retrieve which ase belongs to each client
-            for client_ase in ase {
-                if event.handle() == client_ase.handle {
-                    return Some(Ok(()));
-                }
-            }
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.ase_control_point.handle {
+            return Some(Err(AttErrorCode::WRITE_REQUEST_REJECTED.into()));
         }
-
-        None
+        let slot = self.ase_slot(handle)?;
+        Some(match self.active_slot.get() {
+            Some(active) if active == slot => Ok(()),
+            // The Ase characteristic of another client
+            Some(_) => Err(AttErrorCode::READ_NOT_PERMITTED.into()),
+            // Every connection slot was taken when the client connected
+            None => Err(AttErrorCode::INSUFFICIENT_RESOURCES.into()),
+        })
     }
 
-    fn handle_write_event(&self, event: &WriteEvent) -> Option<Result<(), AttErrorCode>> {
-        if event.handle() == self.ase_control_point.handle {
-            return Some(Ok(()));
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.ase_control_point.handle {
+            return Some(match self.active_slot.get() {
+                Some(slot) => self.process_control_point(slot, data),
+                None => Err(AttErrorCode::INSUFFICIENT_RESOURCES.into()),
+            });
         }
-        for ase in self.ases.iter() {
-            for client_ase in ase {
-                if event.handle() == client_ase.handle {
-                    return Some(Err(AttErrorCode::WRITE_REQUEST_REJECTED));
-                }
-            }
+        self.ase_slot(handle)
+            .map(|_| Err(AttErrorCode::WRITE_REQUEST_REJECTED.into()))
+    }
+}
+
+/// The id of an Ase, from 1 to 254
+///
+/// The server assigns the ids, 0 is reserved and 255 is not a valid id.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AseId(u8);
+
+impl AseId {
+    pub const fn new(id: u8) -> Result<Self, AscsError> {
+        match id {
+            0 | 255 => Err(AscsError::InvalidAseId(id)),
+            id => Ok(Self(id)),
         }
+    }
 
-        None
+    /// The id as sent in the Ase characteristic and control point
+    pub const fn get(&self) -> u8 {
+        self.0
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct Ase {
     /// Identifier of this ASE, assigned by the server.
-    pub id: u8,
+    pub id: AseId,
     state_id: u8,
     /// State of the ASE with respect to the ASE state machine
     pub state: AseState,
 }
 
 impl Ase {
-    pub fn new(id: u8) -> Self {
+    pub fn new(id: AseId) -> Self {
         Self {
             id,
             state_id: 0,
@@ -198,14 +2203,37 @@ pub enum AseControlOperation {
     ConfigQos = 2,
     Enable = 3,
     ReceiverStartReady = 4,
-    ReceiverStopReady = 5,
-    Disable = 6,
+    Disable = 5,
+    ReceiverStopReady = 6,
     UpdateMetadata = 7,
     Release = 8,
-    Released,
+}
+
+impl AseControlOperation {
+    /// The name of the operation, for logging
+    pub fn name(&self) -> &'static str {
+        match self {
+            AseControlOperation::ConfigCodec => "ConfigCodec",
+            AseControlOperation::ConfigQos => "ConfigQos",
+            AseControlOperation::Enable => "Enable",
+            AseControlOperation::ReceiverStartReady => "ReceiverStartReady",
+            AseControlOperation::Disable => "Disable",
+            AseControlOperation::ReceiverStopReady => "ReceiverStopReady",
+            AseControlOperation::UpdateMetadata => "UpdateMetadata",
+            AseControlOperation::Release => "Release",
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for AseControlOperation {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.name())
+    }
 }
 
 /// Represents the device initiating the operation.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InitiatingDevice {
     Client,
@@ -220,22 +2248,395 @@ pub enum AseType {
     Sink(Ase),
 }
 
-impl FixedGattValue for AseType {
-    const SIZE: usize = size_of::<Ase>();
+impl AseType {
+    fn ase(&self) -> &Ase {
+        match self {
+            AseType::Source(ase) | AseType::Sink(ase) => ase,
+        }
+    }
 
-    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        if data.len() != Self::SIZE {
-            Err(FromGattError::InvalidLength)
-        } else {
-            unsafe { Ok((data.as_ptr() as *const Self).read_unaligned()) }
+    fn ase_mut(&mut self) -> &mut Ase {
+        match self {
+            AseType::Source(ase) | AseType::Sink(ase) => ase,
         }
     }
 
+    fn is_sink(&self) -> bool {
+        matches!(self, AseType::Sink(_))
+    }
+
+    /// The id of the Ase, whichever its direction
+    pub fn id(&self) -> AseId {
+        self.ase().id
+    }
+}
+
+/// The value of an Ase characteristic
+///
+/// Encoded as ASE_ID, ASE_State, then the parameters of the state.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AseValue(Vec<u8, ASE_STORE_SIZE>);
+
+impl AseValue {
+    pub fn new(ase_id: AseId, state: &AseState) -> Self {
+        let mut buf = [0; ASE_STORE_SIZE];
+        buf[0] = ase_id.get();
+        let len = 1 + encode_ase_state(state, &mut buf[1..]);
+        // Cannot fail, the buffer has the capacity of the value
+        Self(Vec::from_slice(&buf[..len]).unwrap_or_default())
+    }
+
+    /// The id of the Ase, `None` if it is missing or not a valid id
+    pub fn ase_id(&self) -> Option<AseId> {
+        self.0.first().and_then(|id| AseId::new(*id).ok())
+    }
+
+    /// The state of the Ase and its parameters
+    pub fn state(&self) -> Result<AseState, AscsError> {
+        decode_ase_state(self.0.get(1..).unwrap_or(&[]))
+    }
+}
+
+impl AsGatt for AseValue {
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: usize = ASE_STORE_SIZE;
+
     fn as_gatt(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+        &self.0
+    }
+}
+
+impl FromGatt for AseValue {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+/// The Config Codec parameters of a single Ase
+#[derive(Clone)]
+pub struct AseCodecConfig {
+    pub ase_id: u8,
+    pub operand: ConfigCodecOperand,
+}
+
+/// The Config QoS parameters of a single Ase
+#[derive(Clone)]
+pub struct AseQosConfig {
+    pub ase_id: u8,
+    pub qos: AseParamsQoSConfigured,
+}
+
+/// The Enable or Update Metadata parameters of a single Ase
+#[derive(Clone)]
+pub struct AseMetadataConfig {
+    pub ase_id: u8,
+    /// LTV encoded metadata
+    pub metadata: Vec<u8, MAX_ASE_METADATA_SIZE>,
+}
+
+/// An ASE Control Point write, with the parameters of every Ase it addresses
+#[derive(Clone)]
+pub enum AseControlPointCommand<const MAX_ASES: usize> {
+    ConfigCodec(Vec<AseCodecConfig, MAX_ASES>),
+    ConfigQos(Vec<AseQosConfig, MAX_ASES>),
+    Enable(Vec<AseMetadataConfig, MAX_ASES>),
+    ReceiverStartReady(Vec<u8, MAX_ASES>),
+    Disable(Vec<u8, MAX_ASES>),
+    ReceiverStopReady(Vec<u8, MAX_ASES>),
+    UpdateMetadata(Vec<AseMetadataConfig, MAX_ASES>),
+    Release(Vec<u8, MAX_ASES>),
+}
+
+impl<const MAX_ASES: usize> AseControlPointCommand<MAX_ASES> {
+    pub fn opcode(&self) -> AseControlOpcode {
+        match self {
+            Self::ConfigCodec(_) => AseControlOpcode::ConfigCodec,
+            Self::ConfigQos(_) => AseControlOpcode::ConfigQoS,
+            Self::Enable(_) => AseControlOpcode::Enable,
+            Self::ReceiverStartReady(_) => AseControlOpcode::ReceiverStartReady,
+            Self::Disable(_) => AseControlOpcode::Disable,
+            Self::ReceiverStopReady(_) => AseControlOpcode::ReceiverStopReady,
+            Self::UpdateMetadata(_) => AseControlOpcode::UpdateMetadata,
+            Self::Release(_) => AseControlOpcode::Release,
+        }
+    }
+
+    /// Parse a whole ASE Control Point write, see [`parse_ase_control_point`]
+    pub fn from_gatt(data: &[u8]) -> Result<Self, AscsError> {
+        parse_ase_control_point(data)
+    }
+
+    /// Number of Ases addressed by the operation
+    pub fn num_ases(&self) -> usize {
+        match self {
+            Self::ConfigCodec(configs) => configs.len(),
+            Self::ConfigQos(configs) => configs.len(),
+            Self::Enable(configs) | Self::UpdateMetadata(configs) => configs.len(),
+            Self::ReceiverStartReady(ase_ids)
+            | Self::Disable(ase_ids)
+            | Self::ReceiverStopReady(ase_ids)
+            | Self::Release(ase_ids) => ase_ids.len(),
+        }
+    }
+}
+
+/// Parse an ASE Control Point write, laid out as in ASCS table 5.2
+///
+/// The opcode and Number_of_ASEs are followed by the parameters of each Ase.
+/// Fails with [`AscsError::InsufficientResources`] when it addresses more
+/// than `MAX_ASES` Ases or carries more metadata than an Ase can hold.
+pub fn parse_ase_control_point<const MAX_ASES: usize>(
+    data: &[u8],
+) -> Result<AseControlPointCommand<MAX_ASES>, AscsError> {
+    let [opcode, num_ases, params @ ..] = data else {
+        return Err(AscsError::InvalidLength);
+    };
+    let opcode = match AseControlOpcode::try_from(*opcode) {
+        Ok(AseControlOpcode::Released | AseControlOpcode::Rfu) | Err(_) => {
+            return Err(AscsError::UnsupportedOpcode(*opcode))
+        }
+        Ok(opcode) => opcode,
+    };
+
+    let mut params = params;
+    /// Split the next `len` bytes off the parameters
+    fn take<'d>(params: &mut &'d [u8], len: usize) -> Result<&'d [u8], AscsError> {
+        if params.len() < len {
+            return Err(AscsError::InvalidLength);
+        }
+        let (field, rest) = params.split_at(len);
+        *params = rest;
+        Ok(field)
+    }
+
+    macro_rules! collect {
+        ($parse:expr) => {{
+            let mut entries = Vec::new();
+            for _ in 0..*num_ases {
+                entries
+                    .push($parse)
+                    .map_err(|_| AscsError::InsufficientResources)?;
+            }
+            entries
+        }};
     }
+
+    let command = match opcode {
+        AseControlOpcode::ConfigCodec => AseControlPointCommand::ConfigCodec(collect!({
+            // ASE_ID, Target_Latency, Target_PHY, Codec_ID, Codec_Specific_Configuration
+            let header = take(&mut params, 9)?;
+            let config = take(&mut params, header[8] as usize)?;
+            let mut codec_id = [0; CodecId::SIZE];
+            codec_id.copy_from_slice(&header[3..8]);
+            AseCodecConfig {
+                ase_id: header[0],
+                operand: ConfigCodecOperand {
+                    target_latency: header[1],
+                    target_phy: header[2],
+                    codec_id: CodecId::decode(&codec_id),
+                    codec_specific_configuration: Vec::from_slice(config)
+                        .map_err(|_| AscsError::InsufficientResources)?,
+                },
+            }
+        })),
+        AseControlOpcode::ConfigQoS => AseControlPointCommand::ConfigQos(collect!({
+            // ASE_ID, CIG_ID, CIS_ID, SDU_Interval, Framing, PHY, Max_SDU,
+            // Retransmission_Number, Max_Transport_Latency, Presentation_Delay
+            let qos = take(&mut params, 16)?;
+            AseQosConfig {
+                ase_id: qos[0],
+                qos: AseParamsQoSConfigured {
+                    cig_id: qos[1],
+                    cis_id: qos[2],
+                    sdu_interval: [qos[3], qos[4], qos[5]],
+                    framing: qos[6],
+                    phy: phy_from_bits(qos[7]),
+                    max_sdu: u16::from_le_bytes([qos[8], qos[9]]),
+                    retransmission_number: qos[10],
+                    max_transport_latency: u16::from_le_bytes([qos[11], qos[12]]),
+                    presentation_delay: [qos[13], qos[14], qos[15]],
+                },
+            }
+        })),
+        AseControlOpcode::Enable | AseControlOpcode::UpdateMetadata => {
+            let configs = collect!({
+                // ASE_ID, Metadata_Length, Metadata
+                let header = take(&mut params, 2)?;
+                let metadata = take(&mut params, header[1] as usize)?;
+                AseMetadataConfig {
+                    ase_id: header[0],
+                    metadata: Vec::from_slice(metadata)
+                        .map_err(|_| AscsError::InsufficientResources)?,
+                }
+            });
+            if opcode == AseControlOpcode::Enable {
+                AseControlPointCommand::Enable(configs)
+            } else {
+                AseControlPointCommand::UpdateMetadata(configs)
+            }
+        }
+        _ => {
+            let ase_ids = collect!(take(&mut params, 1)?[0]);
+            match opcode {
+                AseControlOpcode::ReceiverStartReady => {
+                    AseControlPointCommand::ReceiverStartReady(ase_ids)
+                }
+                AseControlOpcode::Disable => AseControlPointCommand::Disable(ase_ids),
+                AseControlOpcode::ReceiverStopReady => {
+                    AseControlPointCommand::ReceiverStopReady(ase_ids)
+                }
+                _ => AseControlPointCommand::Release(ase_ids),
+            }
+        }
+    };
+    Ok(command)
+}
+
+/// Write the ASE_State of `state` followed by the parameters of the state into `buf`
+///
+/// Returns the number of bytes written, 0 if `buf` is too small or the state is `RFU`.
+pub fn encode_ase_state(state: &AseState, buf: &mut [u8]) -> usize {
+    let mut params = [0; CODEC_CONFIGURED_PARAMS_SIZE + MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE];
+    let (state_id, len) = match state {
+        AseState::Idle => (0, 0),
+        AseState::CodecConfigured(codec) => {
+            let config = &codec.codec_specific_configuration;
+            params[0] = codec.framing;
+            params[1] = phy_to_bits(&codec.preferred_phy);
+            params[2] = codec.preferred_retransmission_number;
+            params[3..5].copy_from_slice(&codec.max_transport_latency.to_le_bytes());
+            params[5..8].copy_from_slice(&codec.presentation_delay_min.to_le_bytes()[..3]);
+            params[8..11].copy_from_slice(&codec.presentation_delay_max.to_le_bytes()[..3]);
+            params[11..14]
+                .copy_from_slice(&codec.preferred_presentation_delay_min.to_le_bytes()[..3]);
+            params[14..17]
+                .copy_from_slice(&codec.preferred_presentation_delay_max.to_le_bytes()[..3]);
+            params[17..22].copy_from_slice(&codec.codec_id.to_bytes());
+            params[22] = config.len() as u8;
+            params[23..23 + config.len()].copy_from_slice(config);
+            (1, CODEC_CONFIGURED_PARAMS_SIZE + config.len())
+        }
+        AseState::QosConfigured(qos) => {
+            params[0] = qos.cig_id;
+            params[1] = qos.cis_id;
+            params[2..5].copy_from_slice(&qos.sdu_interval);
+            params[5] = qos.framing;
+            params[6] = phy_to_bits(&qos.phy);
+            params[7..9].copy_from_slice(&qos.max_sdu.to_le_bytes());
+            params[9] = qos.retransmission_number;
+            params[10..12].copy_from_slice(&qos.max_transport_latency.to_le_bytes());
+            params[12..15].copy_from_slice(&qos.presentation_delay);
+            (2, QOS_CONFIGURED_PARAMS_SIZE)
+        }
+        AseState::Enabling(other) | AseState::Streaming(other) | AseState::Disabling(other) => {
+            let state_id = match state {
+                AseState::Enabling(_) => 3,
+                AseState::Streaming(_) => 4,
+                _ => 5,
+            };
+            params[0] = other.cig_id;
+            params[1] = other.cis_id;
+            params[2] = other.metadata.len() as u8;
+            params[3..3 + other.metadata.len()].copy_from_slice(&other.metadata);
+            (state_id, 3 + other.metadata.len())
+        }
+        AseState::Releasing => (6, 0),
+        AseState::RFU => return 0,
+    };
+
+    let Some(buf) = buf.get_mut(..1 + len) else {
+        return 0;
+    };
+    buf[0] = state_id;
+    buf[1..].copy_from_slice(&params[..len]);
+    buf.len()
+}
+
+/// Read an ASE_State followed by the parameters of the state
+pub fn decode_ase_state(data: &[u8]) -> Result<AseState, AscsError> {
+    let [state_id, params @ ..] = data else {
+        return Err(AscsError::InvalidAseState);
+    };
+    let u24 = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+
+    Ok(match *state_id {
+        0 => AseState::Idle,
+        1 => {
+            let fixed = params
+                .get(..CODEC_CONFIGURED_PARAMS_SIZE)
+                .ok_or(AscsError::InvalidAseState)?;
+            let mut codec_id = [0; CodecId::SIZE];
+            codec_id.copy_from_slice(&fixed[17..22]);
+            let config_len = fixed[22];
+            let config = params
+                .get(
+                    CODEC_CONFIGURED_PARAMS_SIZE
+                        ..CODEC_CONFIGURED_PARAMS_SIZE + config_len as usize,
+                )
+                .ok_or(AscsError::InvalidAseState)?;
+            AseState::CodecConfigured(AseParamsCodecConfigured {
+                framing: fixed[0],
+                preferred_phy: phy_from_bits(fixed[1]),
+                preferred_retransmission_number: fixed[2],
+                max_transport_latency: u16::from_le_bytes([fixed[3], fixed[4]]),
+                presentation_delay_min: u24(&fixed[5..8]),
+                presentation_delay_max: u24(&fixed[8..11]),
+                preferred_presentation_delay_min: u24(&fixed[11..14]),
+                preferred_presentation_delay_max: u24(&fixed[14..17]),
+                codec_id: CodecId::decode(&codec_id),
+                codec_specific_configuration_length: config_len,
+                codec_specific_configuration: Vec::from_slice(config)
+                    .map_err(|_| AscsError::InvalidAseState)?,
+            })
+        }
+        2 => {
+            let qos = params
+                .get(..QOS_CONFIGURED_PARAMS_SIZE)
+                .ok_or(AscsError::InvalidAseState)?;
+            AseState::QosConfigured(AseParamsQoSConfigured {
+                cig_id: qos[0],
+                cis_id: qos[1],
+                sdu_interval: [qos[2], qos[3], qos[4]],
+                framing: qos[5],
+                phy: phy_from_bits(qos[6]),
+                max_sdu: u16::from_le_bytes([qos[7], qos[8]]),
+                retransmission_number: qos[9],
+                max_transport_latency: u16::from_le_bytes([qos[10], qos[11]]),
+                presentation_delay: [qos[12], qos[13], qos[14]],
+            })
+        }
+        state_id @ 3..=5 => {
+            let [cig_id, cis_id, metadata_len, metadata @ ..] = params else {
+                return Err(AscsError::InvalidAseState);
+            };
+            let metadata = metadata
+                .get(..*metadata_len as usize)
+                .and_then(|metadata| Vec::from_slice(metadata).ok())
+                .ok_or(AscsError::InvalidAseState)?;
+            let other = AseParamsOther {
+                cig_id: *cig_id,
+                cis_id: *cis_id,
+                metadata,
+            };
+            match state_id {
+                3 => AseState::Enabling(other),
+                4 => AseState::Streaming(other),
+                _ => AseState::Disabling(other),
+            }
+        }
+        6 => AseState::Releasing,
+        _ => return Err(AscsError::InvalidAseState),
+    })
 }
 
+/// State of an Ase, with the parameters of the state
+///
+/// `CodecConfigured` holds the codec configuration by value, there is no
+/// allocator to box it. The budget is 320 bytes a state, the server keeps
+/// one for each Ase of each connection slot.
+#[allow(clippy::large_enum_variant)]
 #[derive(Default, Clone)]
 #[repr(u8)]
 pub enum AseState {
@@ -250,7 +2651,31 @@ pub enum AseState {
     RFU,
 }
 
+impl AseState {
+    /// The name of the state, for logging
+    pub fn state_name(&self) -> &'static str {
+        match self {
+            AseState::Idle => "Idle",
+            AseState::CodecConfigured(_) => "CodecConfigured",
+            AseState::QosConfigured(_) => "QosConfigured",
+            AseState::Enabling(_) => "Enabling",
+            AseState::Streaming(_) => "Streaming",
+            AseState::Disabling(_) => "Disabling",
+            AseState::Releasing => "Releasing",
+            AseState::RFU => "RFU",
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for AseState {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.state_name())
+    }
+}
+
 /// Additional Ase parameters for the State::CodedConfigured
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone)]
 pub struct AseParamsCodecConfigured {
     /// Server support for unframed ISOAL PDUs
@@ -274,7 +2699,7 @@ pub struct AseParamsCodecConfigured {
     /// Length of the Codec_Specific_Configuration field
     pub codec_specific_configuration_length: u8,
     /// Codec specific configuration for this ASE
-    pub codec_specific_configuration: Option<&'static [u8]>,
+    pub codec_specific_configuration: Vec<u8, MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE>,
 }
 
 impl Default for AseParamsCodecConfigured {
@@ -295,7 +2720,65 @@ impl Default for AseParamsCodecConfigured {
     }
 }
 
+impl AseParamsCodecConfigured {
+    /// The parameters of an Ase configured with `operand`, reporting the QoS `preferred` by the server
+    pub fn new(operand: ConfigCodecOperand, preferred: &ServerPreferredQos) -> Self {
+        Self {
+            framing: preferred.framing,
+            preferred_phy: preferred.preferred_phy,
+            preferred_retransmission_number: preferred.preferred_retransmission_number,
+            max_transport_latency: preferred.max_transport_latency,
+            presentation_delay_min: preferred.presentation_delay_min,
+            presentation_delay_max: preferred.presentation_delay_max,
+            preferred_presentation_delay_min: preferred.preferred_presentation_delay_min,
+            preferred_presentation_delay_max: preferred.preferred_presentation_delay_max,
+            codec_id: operand.codec_id,
+            codec_specific_configuration_length: operand.codec_specific_configuration.len() as u8,
+            codec_specific_configuration: operand.codec_specific_configuration,
+        }
+    }
+}
+
+/// The QoS a server supports and prefers for an Ase, reported in the `CodecConfigured` state
+///
+/// The default supports unframed PDUs, the 2M PHY, a max transport latency of
+/// 100 ms and presentation delays from 0 to the 40 ms BAP requires, without
+/// preferred presentation delays.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone)]
+pub struct ServerPreferredQos {
+    /// 0x01 if the server does not support unframed ISOAL PDUs
+    pub framing: u8,
+    /// PHYs the server supports
+    pub preferred_phy: PhySet,
+    pub preferred_retransmission_number: u8,
+    /// Max transport latency in milliseconds
+    pub max_transport_latency: u16,
+    /// Range of supported presentation delays, in microseconds
+    pub presentation_delay_min: u32,
+    pub presentation_delay_max: u32,
+    /// Range of preferred presentation delays in microseconds, 0 for no preference
+    pub preferred_presentation_delay_min: u32,
+    pub preferred_presentation_delay_max: u32,
+}
+
+impl Default for ServerPreferredQos {
+    fn default() -> Self {
+        Self {
+            framing: 0x00,
+            preferred_phy: PhySet::M2,
+            preferred_retransmission_number: 2,
+            max_transport_latency: 100,
+            presentation_delay_min: 0,
+            presentation_delay_max: 40_000,
+            preferred_presentation_delay_min: 0,
+            preferred_presentation_delay_max: 0,
+        }
+    }
+}
+
 /// Additional Ase parameters for the State::QoSConfigured
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone)]
 pub struct AseParamsQoSConfigured {
     pub cig_id: u8,
@@ -326,11 +2809,13 @@ impl Default for AseParamsQoSConfigured {
 }
 
 /// Additional Ase parameters for the State::Enabling, State::Steaming, or State::Disabled
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Default, Clone)]
 pub struct AseParamsOther {
     pub cig_id: u8,
     pub cis_id: u8,
-    pub metadata: Option<u64>,
+    /// LTV encoded metadata
+    pub metadata: Vec<u8, MAX_ASE_METADATA_SIZE>,
 }
 
 #[repr(u8)]
@@ -348,18 +2833,784 @@ pub enum AseControlOpcode {
     Rfu = 0xFF,                // Reserved for future use
 }
 
-impl FixedGattValue for AseControlOpcode {
-    const SIZE: usize = 1;
+impl TryFrom<u8> for AseControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x01 => Self::ConfigCodec,
+            0x02 => Self::ConfigQoS,
+            0x03 => Self::Enable,
+            0x04 => Self::ReceiverStartReady,
+            0x05 => Self::Disable,
+            0x06 => Self::ReceiverStopReady,
+            0x07 => Self::UpdateMetadata,
+            0x08 => Self::Release,
+            0x09 => Self::Released,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+impl AsGatt for AseControlOpcode {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1;
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::MAX_SIZE) }
+    }
+}
 
+impl FromGatt for AseControlOpcode {
+    /// Read the opcode of a whole ASE Control Point write
+    ///
+    /// The parameters following the opcode are ignored, see
+    /// [`AseControlPointCommand::from_gatt`] to parse them.
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        if data.len() != Self::SIZE {
-            Err(FromGattError::InvalidLength)
-        } else {
-            unsafe { Ok((data.as_ptr() as *const Self).read_unaligned()) }
+        let [opcode, ..] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::try_from(*opcode).unwrap_or(Self::Rfu))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codec_ids,
+        generic_audio::{
+            serialize_codec_config, CodecSpecificCapabilities, ContextType, FrameDuration,
+            OctetsPerCodecFrame, SamplingFrequency, SupportedFrameDurations,
+            SupportedSamplingFrequencies,
+        },
+    };
+    use trouble_host::attribute::AttributeTable;
+
+    const ATTRIBUTES: usize = ascs_attributes(2, 1);
+    const CONN: u16 = 1;
+    const SINK: u8 = 1;
+    const SOURCE: u8 = 2;
+
+    /// LC3 at 48 kHz with 10 ms frames of 100 to 120 octets, mono
+    fn pac() -> PAC {
+        let mut record = PACRecord {
+            codec_id: Vec::from_slice(&[codec_ids::LC3]).unwrap(),
+            ..Default::default()
+        };
+        for capability in [
+            CodecSpecificCapabilities::SupportedSamplingFrequencies(
+                SupportedSamplingFrequencies::new(&[SamplingFrequency::Hz48000]),
+            ),
+            CodecSpecificCapabilities::SupportedFrameDurations(SupportedFrameDurations::new(
+                false, true, false, false,
+            )),
+            CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(
+                OctetsPerCodecFrame::new(100, 120).unwrap(),
+            ),
+        ] {
+            record.codec_specific_capabilities.push(capability).unwrap();
         }
+        let mut records = Vec::new();
+        records.push(record).unwrap();
+        PAC::new(records).unwrap()
     }
 
-    fn as_gatt(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    /// A sink and a source Ase of a connected client, checked against [`pac`]
+    fn ascs<'a>(
+        table: &mut AttributeTable<'a, NoopRawMutex, ATTRIBUTES>,
+        stores: &'a mut AscsStorage<2, 1>,
+    ) -> AscsServer<2, 1> {
+        ascs_with(
+            table,
+            stores,
+            AseType::Source(Ase::new(AseId::new(SOURCE).unwrap())),
+        )
+    }
+
+    /// A sink Ase and `second` with the id 2, of a connected client
+    fn ascs_with<'a>(
+        table: &mut AttributeTable<'a, NoopRawMutex, ATTRIBUTES>,
+        stores: &'a mut AscsStorage<2, 1>,
+        second: AseType,
+    ) -> AscsServer<2, 1> {
+        let mut ases = Vec::new();
+        let _ = ases.push(AseType::Sink(Ase::new(AseId::new(SINK).unwrap())));
+        let _ = ases.push(second);
+        let pac = pac();
+        let mut ascs =
+            AscsServer::new(table, ases, stores).with_capabilities(Some(&pac), Some(&pac));
+        ascs.handle_connect(CONN).unwrap();
+        ascs.set_active_connection(CONN);
+        ascs
+    }
+
+    fn write(ascs: &AscsServer<2, 1>, data: &[u8]) -> Vec<(u8, u8, u8), 4> {
+        let handle = ascs.control_point().handle;
+        assert!(matches!(ascs.handle_write(handle, data), Some(Ok(()))));
+        ascs.take_control_point_response()
+            .unwrap()
+            .entries()
+            .collect()
+    }
+
+    fn state(ascs: &AscsServer<2, 1>, ase_id: u8) -> &'static str {
+        ascs.ase_state(CONN, AseId::new(ase_id).unwrap())
+            .unwrap()
+            .state_name()
+    }
+
+    fn success(ase_id: u8) -> (u8, u8, u8) {
+        (
+            ase_id,
+            AseResponseCode::Success as u8,
+            u8::from(AseResponseReason::None),
+        )
+    }
+
+    fn config_codec(ase_id: u8, codec_id: CodecId, frequency: SamplingFrequency) -> Vec<u8, 32> {
+        let mut config = Vec::new();
+        serialize_codec_config(
+            &[
+                CodecSpecificConfiguration::SamplingFrequency(frequency),
+                CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+                CodecSpecificConfiguration::OctetsPerCodecFrame(
+                    OctetsPerCodecFrame::new(100, 100).unwrap(),
+                ),
+            ],
+            &mut config,
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        data.extend_from_slice(&[0x01, 1, ase_id, 0x02, 0x02])
+            .unwrap();
+        data.extend_from_slice(&codec_id.to_bytes()).unwrap();
+        data.push(config.len() as u8).unwrap();
+        data.extend_from_slice(&config).unwrap();
+        data
+    }
+
+    /// Config QoS of CIG 1 and CIS `ase_id`, unframed on the 2M PHY
+    fn config_qos(ase_id: u8, max_transport_latency: u16, presentation_delay: u32) -> Vec<u8, 18> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&[0x02, 1, ase_id, 1, ase_id])
+            .unwrap();
+        // SDU interval of 10 ms, unframed, 2M PHY, Max_SDU of 100, 2 retransmissions
+        data.extend_from_slice(&[0x10, 0x27, 0x00, 0x00, 0b010, 100, 0, 2])
+            .unwrap();
+        data.extend_from_slice(&max_transport_latency.to_le_bytes())
+            .unwrap();
+        data.extend_from_slice(&presentation_delay.to_le_bytes()[..3])
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn sink_ase_streams_after_codec_qos_and_enable() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+
+        let config = config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000);
+        assert_eq!(write(&ascs, &config)[..], [success(SINK)]);
+        assert_eq!(state(&ascs, SINK), "CodecConfigured");
+
+        assert_eq!(
+            write(&ascs, &config_qos(SINK, 10, 40_000))[..],
+            [success(SINK)]
+        );
+        assert_eq!(state(&ascs, SINK), "QosConfigured");
+
+        assert_eq!(write(&ascs, &[0x03, 1, SINK, 0])[..], [success(SINK)]);
+        assert_eq!(state(&ascs, SINK), "Enabling");
+
+        ascs.start_streaming(CONN, AseId::new(SINK).unwrap())
+            .unwrap();
+        assert_eq!(state(&ascs, SINK), "Streaming");
+        assert_eq!(
+            ascs.take_changed_ases(CONN)[..],
+            [AseId::new(SINK).unwrap()]
+        );
+    }
+
+    #[test]
+    fn source_ase_streams_on_receiver_start_ready() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+
+        write(
+            &ascs,
+            &config_codec(SOURCE, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        write(&ascs, &config_qos(SOURCE, 10, 40_000));
+        write(&ascs, &[0x03, 1, SOURCE, 0]);
+        assert_eq!(write(&ascs, &[0x04, 1, SOURCE])[..], [success(SOURCE)]);
+        assert_eq!(state(&ascs, SOURCE), "Streaming");
+    }
+
+    #[test]
+    fn release_goes_through_releasing() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+        let sink = AseId::new(SINK).unwrap();
+
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        ascs.take_changed_ases(CONN);
+        assert_eq!(write(&ascs, &[0x08, 1, SINK])[..], [success(SINK)]);
+        assert_eq!(state(&ascs, SINK), "Releasing");
+        assert_eq!(ascs.take_changed_ases(CONN)[..], [sink]);
+
+        // Without a CIS the Ase is idle once Releasing is notified
+        ascs.complete_release(0, 0);
+        assert_eq!(state(&ascs, SINK), "Idle");
+        assert_eq!(ascs.take_changed_ases(CONN)[..], [sink]);
+        assert_eq!(
+            write(&ascs, &[0x08, 1, SINK])[..],
+            [(
+                SINK,
+                AseResponseCode::InvalidTransition as u8,
+                u8::from(AseResponseReason::None)
+            )]
+        );
+    }
+
+    #[test]
+    fn releasing_waits_for_the_cis_to_disconnect() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        write(&ascs, &config_qos(SINK, 10, 40_000));
+        write(&ascs, &[0x03, 1, SINK, 0]);
+        ascs.set_cis_handle(CONN, 1, SINK, 0x60).unwrap();
+        write(&ascs, &[0x08, 1, SINK]);
+
+        ascs.complete_release(0, 0);
+        assert_eq!(state(&ascs, SINK), "Releasing");
+        ascs.clear_cis_handle(CONN, 0x60).unwrap();
+        assert_eq!(state(&ascs, SINK), "Idle");
+    }
+
+    #[test]
+    fn unsupported_codec_configurations_are_rejected() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+
+        let config = config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz44100);
+        assert_eq!(
+            write(&ascs, &config)[..],
+            [(
+                SINK,
+                AseResponseCode::UnsupportedAudioCapabilities as u8,
+                u8::from(AseResponseReason::CodecSpecificConfiguration)
+            )]
+        );
+        let config = config_codec(SINK, codec_ids::CVSD, SamplingFrequency::Hz48000);
+        assert_eq!(
+            write(&ascs, &config)[..],
+            [(
+                SINK,
+                AseResponseCode::UnsupportedAudioCapabilities as u8,
+                u8::from(AseResponseReason::CodecId)
+            )]
+        );
+        assert_eq!(state(&ascs, SINK), "Idle");
+    }
+
+    #[test]
+    fn qos_outside_the_preferences_is_rejected() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let mut ascs = ascs(&mut table, &mut stores);
+        ascs.set_preferred_qos(
+            AseId::new(SINK).unwrap(),
+            ServerPreferredQos {
+                framing: 0x01,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        write(
+            &ascs,
+            &config_codec(SOURCE, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+
+        let rejected = |ase_id, reason: AseResponseReason| {
+            [(
+                ase_id,
+                AseResponseCode::RejectedConfigurationParameterValue as u8,
+                u8::from(reason),
+            )]
+        };
+        assert_eq!(
+            write(&ascs, &config_qos(SINK, 10, 40_000))[..],
+            rejected(SINK, AseResponseReason::Framing)
+        );
+        assert_eq!(
+            write(&ascs, &config_qos(SOURCE, 200, 40_000))[..],
+            rejected(SOURCE, AseResponseReason::MaxTransportLatency)
+        );
+        assert_eq!(
+            write(&ascs, &config_qos(SOURCE, 10, 50_000))[..],
+            rejected(SOURCE, AseResponseReason::PresentationDelay)
+        );
+        let mut one_m = config_qos(SOURCE, 10, 40_000);
+        one_m[9] = 0b001;
+        assert_eq!(
+            write(&ascs, &one_m)[..],
+            rejected(SOURCE, AseResponseReason::Phy)
+        );
+        assert_eq!(state(&ascs, SOURCE), "CodecConfigured");
+    }
+
+    #[test]
+    fn codec_configured_reports_the_preferred_qos() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let mut ascs = ascs(&mut table, &mut stores);
+        ascs.set_preferred_qos(
+            AseId::new(SINK).unwrap(),
+            ServerPreferredQos {
+                max_transport_latency: 20,
+                presentation_delay_min: 10_000,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+
+        let state = ascs.ase_state(CONN, AseId::new(SINK).unwrap()).unwrap();
+        let AseState::CodecConfigured(params) = &*state else {
+            panic!("the sink ase is not codec configured");
+        };
+        assert_eq!(params.max_transport_latency, 20);
+        assert_eq!(params.presentation_delay_min, 10_000);
+        assert_eq!(params.presentation_delay_max, 40_000);
+    }
+
+    #[test]
+    fn unparsable_writes_are_rejected_in_the_notification() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+        let handle = ascs.control_point().handle;
+
+        // Three Ases, the server only has two
+        assert!(matches!(
+            ascs.handle_write(handle, &[0x08, 3, 1, 2, 3]),
+            Some(Ok(()))
+        ));
+        let response = ascs.take_control_point_response().unwrap();
+        assert!(response.is_operation_rejected());
+        assert_eq!(
+            response.entries().next(),
+            Some((0, AseResponseCode::InsufficientResources as u8, 0))
+        );
+
+        // More metadata than an Ase holds
+        let mut enable = Vec::<u8, 64>::from_slice(&[0x03, 1, SINK, 40]).unwrap();
+        enable.extend_from_slice(&[0; 40]).unwrap();
+        assert!(matches!(ascs.handle_write(handle, &enable), Some(Ok(()))));
+        let response = ascs.take_control_point_response().unwrap();
+        assert_eq!(response.opcode(), Some(0x03));
+        assert!(response.is_operation_rejected());
+    }
+
+    #[test]
+    fn server_qos_proposals_must_not_share_a_cis() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let mut ascs = ascs_with(
+            &mut table,
+            &mut stores,
+            AseType::Sink(Ase::new(AseId::new(2).unwrap())),
+        );
+        let proposal = AseParamsQoSConfigured {
+            cig_id: 1,
+            cis_id: 1,
+            ..Default::default()
+        };
+        for ase_id in [1, 2] {
+            ascs.enable_server_qos_proposal(AseId::new(ase_id).unwrap(), proposal.clone())
+                .unwrap();
+        }
+
+        let config = config_codec(1, codec_ids::LC3, SamplingFrequency::Hz48000);
+        assert_eq!(write(&ascs, &config)[..], [success(1)]);
+        ascs.state_notified(0, 0);
+        assert_eq!(state(&ascs, 1), "QosConfigured");
+        let config = config_codec(2, codec_ids::LC3, SamplingFrequency::Hz48000);
+        assert_eq!(
+            write(&ascs, &config)[..],
+            [(
+                2,
+                AseResponseCode::InvalidConfigurationParameterValue as u8,
+                u8::from(AseResponseReason::InvalidAseCisMapping)
+            )]
+        );
+        assert_eq!(state(&ascs, 2), "Idle");
+    }
+
+    #[test]
+    fn enabling_an_unavailable_context_is_rejected() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+        ascs.set_available_contexts(AudioContexts {
+            sink_contexts: ContextType::Unspecified | ContextType::Media,
+            source_contexts: ContextType::Prohibited,
+        });
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        write(&ascs, &config_qos(SINK, 10, 40_000));
+
+        // Streaming_Audio_Contexts of Conversational
+        assert_eq!(
+            write(&ascs, &[0x03, 1, SINK, 4, 3, 0x02, 0x02, 0x00])[..],
+            [(
+                SINK,
+                AseResponseCode::RejectedMetadata as u8,
+                u8::from(AseResponseReason::Metadata(STREAMING_AUDIO_CONTEXTS))
+            )]
+        );
+        assert_eq!(
+            write(&ascs, &[0x03, 1, SINK, 4, 3, 0x02, 0x00, 0x00])[..],
+            [(
+                SINK,
+                AseResponseCode::InvalidMetadata as u8,
+                u8::from(AseResponseReason::Metadata(STREAMING_AUDIO_CONTEXTS))
+            )]
+        );
+        assert_eq!(state(&ascs, SINK), "QosConfigured");
+        // Streaming_Audio_Contexts of Media
+        assert_eq!(
+            write(&ascs, &[0x03, 1, SINK, 4, 3, 0x02, 0x04, 0x00])[..],
+            [success(SINK)]
+        );
+        assert_eq!(state(&ascs, SINK), "Enabling");
+    }
+
+    #[test]
+    fn a_single_connection_slot_serves_one_client_at_a_time() {
+        let mut stores = AscsStorage::<1, 1>::new();
+        let mut table = AttributeTable::<NoopRawMutex, { ascs_attributes(1, 1) }>::new();
+        let mut ases = Vec::new();
+        let _ = ases.push(AseType::Sink(Ase::new(AseId::new(SINK).unwrap())));
+        let mut ascs = AscsServer::<1, 1>::new(&mut table, ases, &mut stores);
+        let handle = ascs.control_point().handle;
+
+        ascs.handle_connect(1).unwrap();
+        assert_eq!(
+            ascs.handle_connect(2),
+            Err(AscsError::InsufficientResources)
+        );
+        // The client without a slot can not operate the Ases
+        ascs.set_active_connection(2);
+        assert!(matches!(
+            ascs.handle_write(handle, &[0x08, 1, SINK]),
+            Some(Err(_))
+        ));
+
+        ascs.handle_disconnect(1);
+        ascs.handle_connect(2).unwrap();
+        ascs.set_active_connection(2);
+        assert!(matches!(
+            ascs.handle_write(handle, &[0x08, 1, SINK]),
+            Some(Ok(()))
+        ));
+    }
+
+    /// Config QoS of `ase_id` on CIG 1 and CIS 1
+    fn config_qos_on_cis_1(ase_id: u8) -> Vec<u8, 18> {
+        let mut data = config_qos(ase_id, 10, 40_000);
+        data[4] = 1;
+        data
+    }
+
+    #[test]
+    fn two_sinks_must_not_share_a_cis() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs_with(
+            &mut table,
+            &mut stores,
+            AseType::Sink(Ase::new(AseId::new(2).unwrap())),
+        );
+
+        for ase_id in [SINK, 2] {
+            write(
+                &ascs,
+                &config_codec(ase_id, codec_ids::LC3, SamplingFrequency::Hz48000),
+            );
+        }
+        assert_eq!(
+            write(&ascs, &config_qos_on_cis_1(SINK))[..],
+            [success(SINK)]
+        );
+        assert_eq!(
+            write(&ascs, &config_qos_on_cis_1(2))[..],
+            [(
+                2,
+                AseResponseCode::InvalidConfigurationParameterValue as u8,
+                u8::from(AseResponseReason::InvalidAseCisMapping),
+            )]
+        );
+        assert_eq!(state(&ascs, 2), "CodecConfigured");
+
+        // The Ase already on the CIS may configure it again
+        assert_eq!(
+            write(&ascs, &config_qos_on_cis_1(SINK))[..],
+            [success(SINK)]
+        );
+    }
+
+    #[test]
+    fn a_sink_and_a_source_may_share_a_cis() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+
+        for ase_id in [SINK, SOURCE] {
+            write(
+                &ascs,
+                &config_codec(ase_id, codec_ids::LC3, SamplingFrequency::Hz48000),
+            );
+        }
+        assert_eq!(
+            write(&ascs, &config_qos_on_cis_1(SINK))[..],
+            [success(SINK)]
+        );
+        assert_eq!(
+            write(&ascs, &config_qos_on_cis_1(SOURCE))[..],
+            [success(SOURCE)]
+        );
+        assert_eq!(state(&ascs, SINK), "QosConfigured");
+        assert_eq!(state(&ascs, SOURCE), "QosConfigured");
+    }
+
+    #[test]
+    fn operations_are_logged_by_name() {
+        assert_eq!(AseControlOperation::Enable.name(), "Enable");
+        let names = [
+            (AseControlOperation::ConfigCodec, "ConfigCodec"),
+            (AseControlOperation::ConfigQos, "ConfigQos"),
+            (AseControlOperation::Enable, "Enable"),
+            (
+                AseControlOperation::ReceiverStartReady,
+                "ReceiverStartReady",
+            ),
+            (AseControlOperation::ReceiverStopReady, "ReceiverStopReady"),
+            (AseControlOperation::Disable, "Disable"),
+            (AseControlOperation::UpdateMetadata, "UpdateMetadata"),
+            (AseControlOperation::Release, "Release"),
+        ];
+        for (operation, name) in names {
+            assert_eq!(operation.name(), name);
+        }
+    }
+
+    #[test]
+    fn states_are_logged_by_name() {
+        assert_eq!(AseState::Idle.state_name(), "Idle");
+        assert_eq!(AseState::Releasing.state_name(), "Releasing");
+        assert_eq!(AseState::RFU.state_name(), "RFU");
+
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let ascs = ascs(&mut table, &mut stores);
+        write(
+            &ascs,
+            &config_codec(SOURCE, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        assert_eq!(state(&ascs, SOURCE), "CodecConfigured");
+        write(&ascs, &config_qos(SOURCE, 10, 40_000));
+        assert_eq!(state(&ascs, SOURCE), "QosConfigured");
+        write(&ascs, &[0x03, 1, SOURCE, 0]);
+        assert_eq!(state(&ascs, SOURCE), "Enabling");
+        write(&ascs, &[0x04, 1, SOURCE]);
+        assert_eq!(state(&ascs, SOURCE), "Streaming");
+        write(&ascs, &[0x05, 1, SOURCE]);
+        assert_eq!(state(&ascs, SOURCE), "Disabling");
+    }
+
+    #[test]
+    fn disconnecting_returns_the_ases_to_idle_and_frees_the_slot() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let mut ascs = ascs(&mut table, &mut stores);
+        let sink = AseId::new(SINK).unwrap();
+        write(
+            &ascs,
+            &config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000),
+        );
+        write(&ascs, &config_qos(SINK, 10, 40_000));
+        write(&ascs, &[0x03, 1, SINK, 0]);
+        ascs.start_streaming(CONN, sink).unwrap();
+        ascs.take_changed_ases(CONN);
+
+        ascs.handle_disconnect(CONN);
+        assert!(ascs.ase_state(CONN, sink).is_none());
+
+        // The slot of the dropped client is given to the next one
+        ascs.handle_connect(2).unwrap();
+        assert_eq!(ascs.ase_state(2, sink).unwrap().state_name(), "Idle");
+        assert_eq!(ascs.take_changed_ases(2)[..], [sink]);
+    }
+
+    #[test]
+    fn writes_addressing_more_ases_than_a_response_holds_are_rejected() {
+        const ASES: usize = MAX_CONTROL_POINT_RESPONSE_ASES + 1;
+        let mut stores = AscsStorage::<ASES, 1>::new();
+        let mut table = AttributeTable::<NoopRawMutex, { ascs_attributes(ASES, 1) }>::new();
+        let mut ases = Vec::new();
+        for id in 1..=ASES as u8 {
+            let _ = ases.push(AseType::Sink(Ase::new(AseId::new(id).unwrap())));
+        }
+        let pac = pac();
+        let mut ascs = AscsServer::<ASES, 1>::new(&mut table, ases, &mut stores)
+            .with_capabilities(Some(&pac), None);
+        ascs.handle_connect(CONN).unwrap();
+        ascs.set_active_connection(CONN);
+
+        let mut data = Vec::<u8, 256>::new();
+        data.extend_from_slice(&[0x01, ASES as u8]).unwrap();
+        for id in 1..=ASES as u8 {
+            let config = config_codec(id, codec_ids::LC3, SamplingFrequency::Hz48000);
+            data.extend_from_slice(&config[2..]).unwrap();
+        }
+        let handle = ascs.control_point().handle;
+        assert!(matches!(ascs.handle_write(handle, &data), Some(Ok(()))));
+        let response = ascs.take_control_point_response().unwrap();
+        assert!(response.is_operation_rejected());
+        assert_eq!(
+            response.entries().collect::<Vec<_, 1>>()[..],
+            [(0, AseResponseCode::InsufficientResources as u8, 0)]
+        );
+        for id in 1..=ASES as u8 {
+            let state = ascs.ase_state(CONN, AseId::new(id).unwrap()).unwrap();
+            assert_eq!(state.state_name(), "Idle");
+        }
+    }
+
+    #[test]
+    fn responses_hold_at_most_max_control_point_response_ases() {
+        let mut response = AseControlPointResponse::new(0x08);
+        for id in 1..=MAX_CONTROL_POINT_RESPONSE_ASES as u8 {
+            response.push(id, AseResponseCode::Success.into()).unwrap();
+        }
+        assert_eq!(
+            response.push(0xFF, AseResponseCode::Success.into()),
+            Err(ResponseFull)
+        );
+        assert_eq!(response.entries().count(), MAX_CONTROL_POINT_RESPONSE_ASES);
+    }
+
+    #[test]
+    fn operations_have_the_value_of_their_opcode() {
+        let opcodes = [
+            (
+                AseControlOperation::ConfigCodec,
+                AseControlOpcode::ConfigCodec,
+            ),
+            (AseControlOperation::ConfigQos, AseControlOpcode::ConfigQoS),
+            (AseControlOperation::Enable, AseControlOpcode::Enable),
+            (
+                AseControlOperation::ReceiverStartReady,
+                AseControlOpcode::ReceiverStartReady,
+            ),
+            (AseControlOperation::Disable, AseControlOpcode::Disable),
+            (
+                AseControlOperation::ReceiverStopReady,
+                AseControlOpcode::ReceiverStopReady,
+            ),
+            (
+                AseControlOperation::UpdateMetadata,
+                AseControlOpcode::UpdateMetadata,
+            ),
+            (AseControlOperation::Release, AseControlOpcode::Release),
+        ];
+        for (operation, opcode) in opcodes {
+            assert_eq!(operation as u8, opcode as u8);
+        }
+        assert_eq!(AseControlOperation::Disable as u8, 0x05);
+        assert_eq!(AseControlOperation::ReceiverStopReady as u8, 0x06);
+    }
+
+    #[test]
+    fn server_qos_proposals_apply_once_codec_configured_is_notified() {
+        let mut stores = AscsStorage::new();
+        let mut table = AttributeTable::new();
+        let mut ascs = ascs(&mut table, &mut stores);
+        let sink = AseId::new(SINK).unwrap();
+        let proposal = AseParamsQoSConfigured {
+            cig_id: 1,
+            cis_id: 1,
+            ..Default::default()
+        };
+        ascs.enable_server_qos_proposal(sink, proposal).unwrap();
+
+        let config = config_codec(SINK, codec_ids::LC3, SamplingFrequency::Hz48000);
+        assert_eq!(write(&ascs, &config)[..], [success(SINK)]);
+        assert_eq!(state(&ascs, SINK), "CodecConfigured");
+        assert_eq!(ascs.take_changed_ases(CONN)[..], [sink]);
+
+        // CodecConfigured was notified
+        ascs.state_notified(0, 0);
+        assert_eq!(state(&ascs, SINK), "QosConfigured");
+        assert_eq!(ascs.take_changed_ases(CONN)[..], [sink]);
+
+        ascs.disable_server_qos_proposal(sink).unwrap();
+        assert_eq!(write(&ascs, &config)[..], [success(SINK)]);
+        ascs.state_notified(0, 0);
+        assert_eq!(state(&ascs, SINK), "CodecConfigured");
+    }
+
+    #[test]
+    fn an_ase_serves_at_most_max_connections() {
+        let mut stores = AscsStorage::<1, 1>::new();
+        let mut table = AttributeTable::<NoopRawMutex, { ascs_attributes(1, 1) }>::new();
+        let mut ases = Vec::new();
+        let _ = ases.push(AseType::Sink(Ase::new(AseId::new(SINK).unwrap())));
+        let ascs = AscsServer::<1, 1>::new(&mut table, ases, &mut stores);
+        let sink = AseId::new(SINK).unwrap();
+
+        assert_eq!(ascs.available_slots_for_ase(sink), 1);
+        ascs.on_connection_opened(sink).unwrap();
+        assert_eq!(ascs.available_slots_for_ase(sink), 0);
+        assert_eq!(
+            ascs.on_connection_opened(sink),
+            Err(AscsError::InsufficientResources)
+        );
+
+        ascs.on_connection_closed(sink).unwrap();
+        assert_eq!(ascs.available_slots_for_ase(sink), 1);
+        ascs.on_connection_opened(sink).unwrap();
+        assert_eq!(ascs.available_slots_for_ase(AseId::new(2).unwrap()), 0);
+    }
+
+    #[test]
+    fn events_fit_their_size_budget() {
+        assert!(core::mem::size_of::<AscsEvent>() <= 320);
+        assert!(core::mem::size_of::<StreamEvent>() <= 320);
+        assert!(core::mem::size_of::<AseState>() <= 320);
     }
 }
@@ -1,4 +1,1950 @@
-// Basic Audio Profile 1.0.2
-//
-// This profile defines how devices can distribute and/or
-// consume audio using Bluetooth Low Energy (LE) wireless communications.
+//! Basic Audio Profile 1.0.2
+//!
+//! This profile defines how devices can distribute and/or
+//! consume audio using Bluetooth Low Energy (LE) wireless communications.
+
+use core::cell::RefCell;
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel, mutex::Mutex};
+use heapless::{LinearMap, Vec};
+use trouble_host::{connection::PhySet, prelude::*};
+
+#[cfg(feature = "defmt")]
+use defmt::{info, warn};
+
+use crate::{
+    ascs::{
+        AscsClient, AscsClientError, AscsDiscoverError, AscsEvent, AscsEventChannel, AscsServer,
+        AseId, AseParamsQoSConfigured,
+    },
+    codec_ids,
+    generic_audio::{
+        compatible_codec_configs, compute_max_sdu, AudioLocation, CodecSpecificCapabilities,
+        CodecSpecificConfiguration, ComputeError, ContextType, FrameDuration, FrameDurationMask,
+        LtvIterator, Metadata, OctetsPerCodecFrame, RecordCapabilities, SamplingFrequency,
+        SamplingFrequencyMask, SupportedAudioChannelCounts, SupportedFrameDurations,
+        SupportedSamplingFrequencies, MAX_CODEC_SPECIFIC_CONFIGURATIONS,
+    },
+    pacs::{PACRecord, PacsClient, PacsDiscoveryError, PacsReadError, MAX_PAC_SIZE},
+    CodecId,
+};
+
+/// The audio stream an application would like to establish
+#[derive(Debug, Clone)]
+pub struct StreamPreferences {
+    pub codec: CodecId,
+    pub sampling_frequency: SamplingFrequency,
+    /// Sampling frequencies tried in order when the server does not support `sampling_frequency`
+    pub fallback_sampling_frequencies: Vec<SamplingFrequency, 4>,
+    /// Preferred frame duration, the other one is used if the server only supports it
+    pub frame_duration: FrameDuration,
+    /// Octets of each codec frame, which sets the bitrate, clamped to the range of the server
+    pub octets_per_codec_frame: u16,
+    /// Audio channels carried by the stream
+    pub channel_count: u8,
+    /// Location the stream is rendered at
+    pub audio_location: AudioLocation,
+    /// Context of the audio, announced to the server when the stream is enabled
+    pub context: ContextType,
+    pub retransmission_number: u8,
+    /// Max transport latency in milliseconds
+    pub max_transport_latency: u16,
+    /// Presentation delay in microseconds
+    pub presentation_delay: u32,
+}
+
+impl Default for StreamPreferences {
+    /// The mandatory 48_2_1 configuration, 48 kHz at 80 kbps with low latency
+    fn default() -> Self {
+        Self {
+            codec: codec_ids::LC3,
+            sampling_frequency: SamplingFrequency::Hz48000,
+            fallback_sampling_frequencies: Vec::new(),
+            frame_duration: FrameDuration::Duration10MS,
+            octets_per_codec_frame: 100,
+            channel_count: 1,
+            audio_location: AudioLocation::FrontLeft,
+            context: ContextType::Unspecified,
+            retransmission_number: 2,
+            max_transport_latency: 10,
+            presentation_delay: 40_000,
+        }
+    }
+}
+
+/// Highest CIS_ID of LE Set CIG Parameters
+const MAX_CIS_ID: u8 = 0xEF;
+
+/// Handle of a connected isochronous stream
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CisHandle(pub u16);
+
+/// The HCI commands setting up a unicast stream
+///
+/// trouble_host does not expose isochronous channels yet, so the application
+/// issues these commands to its controller.
+#[allow(async_fn_in_trait)]
+pub trait CisController {
+    type Error;
+
+    /// Configure a CIG holding a single CIS, with LE Set CIG Parameters
+    async fn set_cig_parameters(
+        &mut self,
+        cig_id: u8,
+        cis_id: u8,
+        qos: &AseParamsQoSConfigured,
+    ) -> Result<CisHandle, Self::Error>;
+
+    /// Connect a CIS configured by [`Self::set_cig_parameters`], with LE Create CIS
+    async fn create_cis(&mut self, cis: CisHandle) -> Result<(), Self::Error>;
+}
+
+/// Errors returned while establishing a unicast stream
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BapError {
+    /// The server does not expose a valid Pacs service
+    PacsDiscovery(PacsDiscoveryError),
+    /// The server does not expose a valid Ascs service
+    AscsDiscovery(AscsDiscoverError),
+    /// The sink PAC of the server could not be read
+    Pacs(PacsReadError),
+    /// No PAC record of the server supports the preferred stream
+    Negotiation(NegotiationError),
+    /// The server exposes no sink Ase
+    NoSinkAse,
+    /// Every CIS id of the CIG is used by a stream
+    NoFreeCisId,
+    /// A control point operation failed
+    Ascs(AscsClientError),
+    /// The negotiated codec frames do not fit in an SDU
+    MaxSdu(ComputeError),
+    /// The controller failed to set up the CIG or CIS
+    CisSetupFailed,
+    /// The controller failed to set up the ISO data path of a stream
+    DataPathSetupFailed,
+    /// The BASE does not fit in the periodic advertising data
+    BaseTooLarge,
+    /// The BASE has no subgroup, a subgroup without BIS or an invalid BIS index
+    InvalidBase,
+    /// The controller failed to start the extended or periodic advertising
+    AdvertisingFailed,
+    /// The controller failed to create the BIG
+    BigSetupFailed,
+    /// Scanning or synchronizing to the periodic advertising failed
+    SyncFailed,
+    /// No BIS of the broadcast matches the preferences
+    NoMatchingBis,
+    /// The controller failed to synchronize to the BIG
+    BigSyncFailed,
+}
+
+/// The unicast client role, which configures and starts streams on a unicast server
+pub struct BapUnicastClient<
+    'a,
+    'c,
+    T: Controller,
+    H: CisController,
+    const MAX_SERVICES: usize,
+    const L2CAP_MTU: usize,
+    const MAX_ASES: usize,
+> {
+    client: &'a GattClient<'c, T, MAX_SERVICES, L2CAP_MTU>,
+    cis_controller: H,
+    pacs: PacsClient,
+    ascs: AscsClient<MAX_ASES>,
+    cig_id: u8,
+    next_cis_id: u8,
+    /// CIS ids of the established streams and their CIS
+    cis_ids: Vec<(u8, CisHandle), MAX_ASES>,
+}
+
+impl<
+        'a,
+        'c,
+        T: Controller,
+        H: CisController,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+        const MAX_ASES: usize,
+    > BapUnicastClient<'a, 'c, T, H, MAX_SERVICES, L2CAP_MTU, MAX_ASES>
+{
+    /// Discover the Pacs and Ascs services of the server
+    pub async fn new(
+        client: &'a GattClient<'c, T, MAX_SERVICES, L2CAP_MTU>,
+        cis_controller: H,
+    ) -> Result<Self, BapError> {
+        Ok(Self {
+            client,
+            cis_controller,
+            pacs: PacsClient::new(client)
+                .await
+                .map_err(BapError::PacsDiscovery)?,
+            ascs: AscsClient::discover(client)
+                .await
+                .map_err(BapError::AscsDiscovery)?,
+            cig_id: 0,
+            next_cis_id: 0,
+            cis_ids: Vec::new(),
+        })
+    }
+
+    /// Free the CIS id of a stream whose CIS disconnected, for a later stream
+    pub fn cis_disconnected(&mut self, cis: CisHandle) {
+        self.cis_ids.retain(|(_, handle)| *handle != cis);
+    }
+
+    /// Configure and start a stream to the first sink Ase of the server
+    ///
+    /// The stream is negotiated with the sink PAC of the server, see
+    /// [`negotiate_stream`]. Follows the order of BAP section 6: Config Codec,
+    /// then the CIG is set up before Config QoS and the CIS is created once
+    /// the Ase is enabled.
+    pub async fn establish_stream(
+        &mut self,
+        preferences: &StreamPreferences,
+    ) -> Result<CisHandle, BapError> {
+        let mut buf = [0; MAX_PAC_SIZE];
+        let pac = self
+            .pacs
+            .read_sink_pac_into(self.client, &mut buf)
+            .await
+            .map_err(BapError::Pacs)?;
+        let negotiated =
+            negotiate_stream(pac.records(), preferences).map_err(BapError::Negotiation)?;
+
+        let sink_ase = self.ascs.sink_ases.first().ok_or(BapError::NoSinkAse)?;
+        let ase_id = self
+            .ascs
+            .read_ase_id(self.client, sink_ase)
+            .await
+            .map_err(BapError::Ascs)?;
+        let mut listener = self
+            .ascs
+            .subscribe(self.client)
+            .await
+            .map_err(BapError::Ascs)?;
+
+        #[cfg(feature = "defmt")]
+        info!("[bap] configuring codec of ase {}", ase_id);
+        let config = [
+            CodecSpecificConfiguration::SamplingFrequency(negotiated.sampling_frequency),
+            CodecSpecificConfiguration::FrameDuration(negotiated.frame_duration.clone()),
+            CodecSpecificConfiguration::AudioChannelAllocation(preferences.audio_location),
+            CodecSpecificConfiguration::OctetsPerCodecFrame(OctetsPerCodecFrame::from_min_max(
+                negotiated.octets_per_codec_frame,
+                negotiated.octets_per_codec_frame,
+            )),
+        ];
+        self.ascs
+            .configure_codec(
+                self.client,
+                &mut listener,
+                ase_id,
+                negotiated.codec_id,
+                &config,
+            )
+            .await
+            .map_err(BapError::Ascs)?;
+
+        if self.cis_ids.is_full() {
+            return Err(BapError::NoFreeCisId);
+        }
+        let in_use = self.cis_ids.iter().map(|(id, _)| *id);
+        let cis_id = free_cis_id(in_use, self.next_cis_id).ok_or(BapError::NoFreeCisId)?;
+        let qos = qos_for(&negotiated, &config, preferences, self.cig_id, cis_id)
+            .map_err(BapError::MaxSdu)?;
+        let cis = self
+            .cis_controller
+            .set_cig_parameters(qos.cig_id, qos.cis_id, &qos)
+            .await
+            .map_err(|_| BapError::CisSetupFailed)?;
+        // Cannot fail, checked for room above
+        let _ = self.cis_ids.push((cis_id, cis));
+        self.next_cis_id = if cis_id == MAX_CIS_ID { 0 } else { cis_id + 1 };
+
+        #[cfg(feature = "defmt")]
+        info!("[bap] configuring qos of ase {}", ase_id);
+        self.ascs
+            .configure_qos(self.client, &mut listener, ase_id, &qos)
+            .await
+            .map_err(BapError::Ascs)?;
+
+        #[cfg(feature = "defmt")]
+        info!("[bap] enabling ase {}", ase_id);
+        self.ascs
+            .enable(
+                self.client,
+                &mut listener,
+                ase_id,
+                &[Metadata::StreamingAudioContexts(preferences.context)],
+            )
+            .await
+            .map_err(BapError::Ascs)?;
+
+        // The server moves a sink Ase to streaming once the CIS is connected
+        self.cis_controller
+            .create_cis(cis)
+            .await
+            .map_err(|_| BapError::CisSetupFailed)?;
+        Ok(cis)
+    }
+}
+
+/// The first CIS id from `start` not `in_use`, wrapping around after [`MAX_CIS_ID`]
+fn free_cis_id(in_use: impl Iterator<Item = u8> + Clone, start: u8) -> Option<u8> {
+    (start..=MAX_CIS_ID)
+        .chain(0..start)
+        .find(|id| !in_use.clone().any(|used| used == *id))
+}
+
+/// Negotiate the stream described by `preferences` with the PAC records of a server
+///
+/// The preferred sampling frequency is tried first, then each fallback in order.
+pub fn negotiate_stream(
+    server_records: &[PACRecord],
+    preferences: &StreamPreferences,
+) -> Result<NegotiatedQos, NegotiationError> {
+    let qos_preferences = QosPreferences {
+        sampling_frequency: None,
+        frame_duration: Some(preferences.frame_duration.clone()),
+        octets_per_codec_frame: Some(preferences.octets_per_codec_frame),
+        retransmission_number: preferences.retransmission_number,
+        max_transport_latency: preferences.max_transport_latency,
+    };
+    core::iter::once(&preferences.sampling_frequency)
+        .chain(preferences.fallback_sampling_frequencies.iter())
+        .find_map(|frequency| {
+            let client = client_record(preferences, *frequency);
+            negotiate_qos(server_records, &[client], &qos_preferences).ok()
+        })
+        .ok_or(NegotiationError::NoCommonCodec)
+}
+
+/// The capabilities of a client only accepting `frequency` for the stream of `preferences`
+fn client_record(preferences: &StreamPreferences, frequency: SamplingFrequency) -> PACRecord {
+    let mut record = PACRecord::default();
+    let _ = record.codec_id.push(preferences.codec);
+    for capability in [
+        CodecSpecificCapabilities::SupportedSamplingFrequencies(SupportedSamplingFrequencies::new(
+            &[frequency],
+        )),
+        CodecSpecificCapabilities::SupportedFrameDurations(SupportedFrameDurations::new(
+            true, true, false, false,
+        )),
+        CodecSpecificCapabilities::SupportedAudioChannelCounts(SupportedAudioChannelCounts::new(
+            &[preferences.channel_count],
+        )),
+    ] {
+        let _ = record.codec_specific_capabilities.push(capability);
+    }
+    record
+}
+
+/// The QoS of a stream carrying the codec frames of `config` in each SDU
+fn qos_for(
+    negotiated: &NegotiatedQos,
+    config: &[CodecSpecificConfiguration],
+    preferences: &StreamPreferences,
+    cig_id: u8,
+    cis_id: u8,
+) -> Result<AseParamsQoSConfigured, ComputeError> {
+    let [sdu_low, sdu_mid, sdu_high, _] = negotiated.sdu_interval.to_le_bytes();
+    let [delay_low, delay_mid, delay_high, _] = preferences.presentation_delay.to_le_bytes();
+    Ok(AseParamsQoSConfigured {
+        cig_id,
+        cis_id,
+        sdu_interval: [sdu_low, sdu_mid, sdu_high],
+        // Unframed
+        framing: 0,
+        phy: PhySet::M2,
+        max_sdu: compute_max_sdu(config)?,
+        retransmission_number: negotiated.retransmission_number,
+        max_transport_latency: negotiated.max_transport_latency,
+        presentation_delay: [delay_low, delay_mid, delay_high],
+    })
+}
+
+/// Preferences breaking the ties when negotiating the QoS of a stream
+///
+/// Values not supported by both ends are ignored.
+#[derive(Debug, Clone)]
+pub struct QosPreferences {
+    /// Preferred sampling frequency, otherwise the highest common one is picked
+    pub sampling_frequency: Option<SamplingFrequency>,
+    /// Preferred frame duration, otherwise the one preferred by the server, then 10 ms
+    pub frame_duration: Option<FrameDuration>,
+    /// Preferred octets per codec frame, clamped to the common range, otherwise its maximum
+    pub octets_per_codec_frame: Option<u16>,
+    pub retransmission_number: u8,
+    /// Max transport latency in milliseconds
+    pub max_transport_latency: u16,
+}
+
+impl Default for QosPreferences {
+    /// The low latency QoS of the mandatory 48_2_1 configuration
+    fn default() -> Self {
+        Self {
+            sampling_frequency: None,
+            frame_duration: None,
+            octets_per_codec_frame: None,
+            retransmission_number: 2,
+            max_transport_latency: 10,
+        }
+    }
+}
+
+/// The QoS of a stream supported by both the client and the server
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedQos {
+    pub codec_id: CodecId,
+    pub sampling_frequency: SamplingFrequency,
+    pub frame_duration: FrameDuration,
+    pub octets_per_codec_frame: u16,
+    /// SDU interval in microseconds, one codec frame per SDU
+    pub sdu_interval: u32,
+    /// Max transport latency in milliseconds
+    pub max_transport_latency: u16,
+    pub retransmission_number: u8,
+}
+
+/// Errors returned when negotiating the QoS of a stream
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegotiationError {
+    /// No pair of records shares a codec, sampling frequency, frame duration,
+    /// audio channel count and octets per codec frame
+    NoCommonCodec,
+}
+
+/// Pick the QoS of a stream from the PAC records of the server and the client
+///
+/// Each pair of records with the same codec is intersected, the pair matching
+/// the most `preferences`, then with the highest sampling frequency, is kept.
+pub fn negotiate_qos(
+    server_records: &[PACRecord],
+    client_records: &[PACRecord],
+    preferences: &QosPreferences,
+) -> Result<NegotiatedQos, NegotiationError> {
+    server_records
+        .iter()
+        .flat_map(|server| {
+            client_records
+                .iter()
+                .filter_map(move |client| negotiate_records(server, client, preferences))
+        })
+        .max_by_key(|(score, qos)| (*score, qos.sampling_frequency as u8))
+        .map(|(_, qos)| qos)
+        .ok_or(NegotiationError::NoCommonCodec)
+}
+
+/// The QoS of two records and how many preferences it matches
+fn negotiate_records(
+    server: &PACRecord,
+    client: &PACRecord,
+    preferences: &QosPreferences,
+) -> Option<(u8, NegotiatedQos)> {
+    if !compatible_codec_configs(client, server) {
+        return None;
+    }
+    let codec_id = *server
+        .codec_id
+        .iter()
+        .find(|id| client.codec_id.contains(id))?;
+    let server_caps = RecordCapabilities::of(server);
+    let client_caps = RecordCapabilities::of(client);
+    if server_caps.channel_counts & client_caps.channel_counts == 0 {
+        return None;
+    }
+
+    let frequencies = server_caps.frequencies & client_caps.frequencies;
+    let sampling_frequency = match preferences.sampling_frequency {
+        Some(preferred) if frequencies.intersects(preferred.into()) => preferred,
+        _ => {
+            SamplingFrequencyMask::highest_common(server_caps.frequencies, client_caps.frequencies)?
+        }
+    };
+
+    let durations = server_caps.durations & client_caps.durations;
+    let common_duration = |duration: &FrameDuration| match duration {
+        FrameDuration::Duration7_5MS => durations.contains(FrameDurationMask::Supports7_5MS),
+        FrameDuration::Duration10MS => durations.contains(FrameDurationMask::Supports10MS),
+    };
+    let frame_duration = [
+        preferences.frame_duration.clone(),
+        server_caps.durations.preferred(),
+        Some(FrameDuration::Duration10MS),
+        Some(FrameDuration::Duration7_5MS),
+    ]
+    .into_iter()
+    .flatten()
+    .find(common_duration)?;
+
+    let min_octets = server_caps.min_octets.max(client_caps.min_octets);
+    let max_octets = server_caps.max_octets.min(client_caps.max_octets);
+    if min_octets > max_octets {
+        return None;
+    }
+    let octets_per_codec_frame = preferences
+        .octets_per_codec_frame
+        .map_or(max_octets, |octets| octets.clamp(min_octets, max_octets));
+
+    let score = [
+        preferences.sampling_frequency == Some(sampling_frequency),
+        preferences.frame_duration.as_ref() == Some(&frame_duration),
+        preferences.octets_per_codec_frame == Some(octets_per_codec_frame),
+    ]
+    .into_iter()
+    .filter(|matched| *matched)
+    .count() as u8;
+
+    let sdu_interval = match frame_duration {
+        FrameDuration::Duration7_5MS => 7_500,
+        FrameDuration::Duration10MS => 10_000,
+    };
+    Some((
+        score,
+        NegotiatedQos {
+            codec_id,
+            sampling_frequency,
+            frame_duration,
+            octets_per_codec_frame,
+            sdu_interval,
+            max_transport_latency: preferences.max_transport_latency,
+            retransmission_number: preferences.retransmission_number,
+        },
+    ))
+}
+
+/// Direction of the audio routed by LE Setup ISO Data Path
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DataPathDirection {
+    /// Host to controller, the audio of a source Ase
+    Input = 0x00,
+    /// Controller to host, the audio of a sink Ase
+    Output = 0x01,
+}
+
+/// The HCI commands routing the audio of a connected stream to the host
+///
+/// trouble_host does not expose isochronous channels yet, so the application
+/// issues these commands to its controller.
+#[allow(async_fn_in_trait)]
+pub trait IsoDataPathController {
+    type Error;
+
+    /// Route the audio of `cis` over HCI, with LE Setup ISO Data Path
+    async fn setup_iso_data_path(
+        &mut self,
+        cis: CisHandle,
+        direction: DataPathDirection,
+    ) -> Result<(), Self::Error>;
+}
+
+/// A stream a client started on the unicast server, ready to carry audio
+#[derive(Clone)]
+pub struct StreamHandle {
+    pub ase_id: AseId,
+    pub cis: CisHandle,
+    pub direction: DataPathDirection,
+    /// The QoS the client configured the stream with
+    pub qos: AseParamsQoSConfigured,
+}
+
+/// Number of CIS establishments the unicast server can queue
+const CIS_EVENT_QUEUE_SIZE: usize = 2;
+
+/// A CIS connected by the controller, reported with LE CIS Established
+#[derive(Debug, Clone, Copy)]
+struct CisEstablished {
+    conn_handle: u16,
+    cig_id: u8,
+    cis_id: u8,
+    cis: CisHandle,
+}
+
+/// An Ase going through the unicast stream setup
+struct PendingStream {
+    ase_id: AseId,
+    qos: AseParamsQoSConfigured,
+    cis: Option<CisHandle>,
+    streaming: bool,
+}
+
+impl PendingStream {
+    /// Whether the client configured this Ase with `cig_id` and `cis_id`
+    fn is_on_cis(&self, cig_id: u8, cis_id: u8) -> bool {
+        self.qos.cig_id == cig_id && self.qos.cis_id == cis_id
+    }
+}
+
+/// The pending streams of each client, by connection handle
+type PendingStreams<const MAX_ASES: usize, const MAX_CONNECTIONS: usize> =
+    LinearMap<u16, Vec<PendingStream, MAX_ASES>, MAX_CONNECTIONS>;
+
+/// The unicast server role, which streams audio configured by a unicast client
+///
+/// Follows the Ascs events of the server and, once the client moved an Ase to
+/// streaming and its CIS is connected, sets up the ISO data path of the stream.
+pub struct BapUnicastServer<
+    'a,
+    H: IsoDataPathController,
+    const MAX_ASES: usize,
+    const MAX_CONNECTIONS: usize,
+> {
+    ascs: &'a AscsServer<MAX_ASES, MAX_CONNECTIONS>,
+    events: &'static AscsEventChannel,
+    controller: Mutex<NoopRawMutex, H>,
+    cis_events: Channel<NoopRawMutex, CisEstablished, CIS_EVENT_QUEUE_SIZE>,
+    streams: RefCell<PendingStreams<MAX_ASES, MAX_CONNECTIONS>>,
+}
+
+impl<'a, H: IsoDataPathController, const MAX_ASES: usize, const MAX_CONNECTIONS: usize>
+    BapUnicastServer<'a, H, MAX_ASES, MAX_CONNECTIONS>
+{
+    /// Drive the streams of `ascs`, whose events are published on `events`
+    ///
+    /// `events` must be the channel given to
+    /// [`ServerBuilder::with_ascs_event_channel`](crate::ServerBuilder::with_ascs_event_channel),
+    /// and no other task should receive from it.
+    pub fn new(
+        ascs: &'a AscsServer<MAX_ASES, MAX_CONNECTIONS>,
+        events: &'static AscsEventChannel,
+        controller: H,
+    ) -> Self {
+        Self {
+            ascs,
+            events,
+            controller: Mutex::new(controller),
+            cis_events: Channel::new(),
+            streams: RefCell::new(LinearMap::new()),
+        }
+    }
+
+    /// Report a CIS of the client of `conn_handle` connected by the controller
+    ///
+    /// Call this on the LE CIS Established event. A sink Ase waiting for this CIS
+    /// moves to streaming, notify it with
+    /// [`Server::notify_ase_state_changes`](crate::Server::notify_ase_state_changes).
+    pub fn cis_established(&self, conn_handle: u16, cig_id: u8, cis_id: u8, cis: CisHandle) {
+        let ase_id = self
+            .streams
+            .borrow()
+            .get(&conn_handle)
+            .and_then(|streams| streams.iter().find(|s| s.is_on_cis(cig_id, cis_id)))
+            .map(|stream| stream.ase_id);
+        if let Some(ase_id) = ase_id {
+            // Source Ases are started by the client with Receiver Start Ready
+            let _ = self.ascs.start_streaming(conn_handle, ase_id);
+        }
+        if self
+            .cis_events
+            .try_send(CisEstablished {
+                conn_handle,
+                cig_id,
+                cis_id,
+                cis,
+            })
+            .is_err()
+        {
+            #[cfg(feature = "defmt")]
+            warn!("[bap] cis event queue is full, dropping cis {}", cis);
+        }
+    }
+
+    /// Wait for the client to start a stream, then route its audio over HCI
+    ///
+    /// Resolves once an Ase went through Config Codec, Config QoS and Enable to
+    /// streaming, its CIS is connected and the ISO data path is set up.
+    pub async fn wait_for_stream_ready(&self) -> Result<StreamHandle, BapError> {
+        loop {
+            let ready = match select(self.events.receive(), self.cis_events.receive()).await {
+                Either::First(event) => self.on_ascs_event(event),
+                Either::Second(established) => self.on_cis_established(established),
+            };
+            if let Some(stream) = ready {
+                #[cfg(feature = "defmt")]
+                info!(
+                    "[bap] setting up data path of ase {} on cis {}",
+                    stream.ase_id, stream.cis
+                );
+                self.controller
+                    .lock()
+                    .await
+                    .setup_iso_data_path(stream.cis, stream.direction)
+                    .await
+                    .map_err(|_| BapError::DataPathSetupFailed)?;
+                return Ok(stream);
+            }
+        }
+    }
+
+    /// Track the Ase of `event`, returning its stream once it is ready
+    fn on_ascs_event(&self, event: AscsEvent) -> Option<StreamHandle> {
+        let mut streams = self.streams.borrow_mut();
+        match event {
+            AscsEvent::QosConfigured {
+                conn_handle,
+                ase_id,
+                params,
+            } => {
+                if streams.get(&conn_handle).is_none()
+                    && streams.insert(conn_handle, Vec::new()).is_err()
+                {
+                    #[cfg(feature = "defmt")]
+                    warn!("[bap] no stream slot left for connection {}", conn_handle);
+                    return None;
+                }
+                let streams = streams.get_mut(&conn_handle)?;
+                if let Some(stream) = streams.iter_mut().find(|s| s.ase_id == ase_id) {
+                    stream.qos = params;
+                    stream.cis = None;
+                } else if streams
+                    .push(PendingStream {
+                        ase_id,
+                        qos: params,
+                        cis: None,
+                        streaming: false,
+                    })
+                    .is_err()
+                {
+                    #[cfg(feature = "defmt")]
+                    warn!("[bap] no stream slot left for ase {}", ase_id);
+                }
+                None
+            }
+            AscsEvent::Streaming {
+                conn_handle,
+                ase_id,
+            } => {
+                let stream = streams
+                    .get_mut(&conn_handle)?
+                    .iter_mut()
+                    .find(|s| s.ase_id == ase_id)?;
+                stream.streaming = true;
+                self.stream_handle(stream)
+            }
+            AscsEvent::Disabled {
+                conn_handle,
+                ase_id,
+            } => {
+                if let Some(stream) = streams
+                    .get_mut(&conn_handle)
+                    .and_then(|streams| streams.iter_mut().find(|s| s.ase_id == ase_id))
+                {
+                    stream.streaming = false;
+                }
+                None
+            }
+            AscsEvent::Released {
+                conn_handle,
+                ase_id,
+            } => {
+                if let Some(pending) = streams.get_mut(&conn_handle) {
+                    pending.retain(|s| s.ase_id != ase_id);
+                    if pending.is_empty() {
+                        streams.remove(&conn_handle);
+                    }
+                }
+                None
+            }
+            AscsEvent::CodecConfigured { .. }
+            | AscsEvent::Enabled { .. }
+            | AscsEvent::MetadataUpdated { .. } => None,
+        }
+    }
+
+    /// Attach a connected CIS to its Ase, returning its stream once it is ready
+    fn on_cis_established(&self, established: CisEstablished) -> Option<StreamHandle> {
+        let mut streams = self.streams.borrow_mut();
+        let stream = streams
+            .get_mut(&established.conn_handle)?
+            .iter_mut()
+            .find(|s| s.is_on_cis(established.cig_id, established.cis_id))?;
+        stream.cis = Some(established.cis);
+        self.stream_handle(stream)
+    }
+
+    /// The stream of an Ase that is streaming over a connected CIS
+    fn stream_handle(&self, stream: &PendingStream) -> Option<StreamHandle> {
+        let cis = stream.cis.filter(|_| stream.streaming)?;
+        let direction = if self.ascs.is_sink(stream.ase_id)? {
+            DataPathDirection::Output
+        } else {
+            DataPathDirection::Input
+        };
+        Some(StreamHandle {
+            ase_id: stream.ase_id,
+            cis,
+            direction,
+            qos: stream.qos.clone(),
+        })
+    }
+}
+
+/// Basic Audio Announcement Service UUID, announcing the BASE in periodic advertising
+const BASIC_AUDIO_ANNOUNCEMENT: u16 = 0x1851;
+/// Broadcast Audio Announcement Service UUID, announcing the Broadcast_ID in extended advertising
+const BROADCAST_AUDIO_ANNOUNCEMENT: u16 = 0x1852;
+/// AD type of the service data of a 16-bit UUID
+const SERVICE_DATA_16: u8 = 0x16;
+
+/// Max size of the periodic advertising data set by a single HCI command
+pub const MAX_PERIODIC_ADVERTISING_DATA_SIZE: usize = 252;
+/// Max size of a BASE, once prefixed by its service data header
+pub const MAX_BASE_SIZE: usize = MAX_PERIODIC_ADVERTISING_DATA_SIZE - 4;
+/// Size of the extended advertising data announcing a broadcast
+pub const BROADCAST_ANNOUNCEMENT_SIZE: usize = 7;
+
+/// Max metadata of a subgroup
+pub const MAX_BROADCAST_METADATA: usize = 4;
+/// Max BIS of a subgroup
+pub const MAX_SUBGROUP_BISES: usize = 4;
+
+/// Handle of a broadcast isochronous group
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BigHandle(pub u8);
+
+/// Parameters of the LE Create BIG command
+#[derive(Debug, Clone)]
+pub struct BigParameters {
+    pub big_handle: u8,
+    /// Handle of the periodic advertising train the BIG is announced in
+    pub advertising_handle: u8,
+    /// SDU interval in microseconds
+    pub sdu_interval: u32,
+    pub max_sdu: u16,
+    /// Max transport latency in milliseconds
+    pub max_transport_latency: u16,
+    pub retransmission_number: u8,
+    pub phy: PhySet,
+    /// 0 for sequential, 1 for interleaved BIS arrangement
+    pub packing: u8,
+    /// 0 for unframed, 1 for framed
+    pub framing: u8,
+    /// Code encrypting the BIS, unencrypted when `None`
+    pub broadcast_code: Option<[u8; 16]>,
+}
+
+/// A BIS of a [`BroadcastSubgroup`]
+#[derive(Debug, Clone)]
+pub struct BroadcastBis {
+    /// Index of the BIS in the BIG, from 1
+    pub index: u8,
+    /// Configuration overriding the one of the subgroup for this BIS
+    pub codec_specific_configuration:
+        Vec<CodecSpecificConfiguration, MAX_CODEC_SPECIFIC_CONFIGURATIONS>,
+}
+
+/// BIS sharing a codec, its configuration and metadata
+#[derive(Debug, Clone)]
+pub struct BroadcastSubgroup {
+    pub codec_id: CodecId,
+    pub codec_specific_configuration:
+        Vec<CodecSpecificConfiguration, MAX_CODEC_SPECIFIC_CONFIGURATIONS>,
+    pub metadata: Vec<Metadata, MAX_BROADCAST_METADATA>,
+    pub bises: Vec<BroadcastBis, MAX_SUBGROUP_BISES>,
+}
+
+/// The HCI commands starting a broadcast
+///
+/// trouble_host does not expose periodic advertising nor isochronous channels
+/// yet, so the application issues these commands to its controller.
+#[allow(async_fn_in_trait)]
+pub trait BigController {
+    type Error;
+
+    /// Start extended advertising with `advertising_data`, and the periodic
+    /// advertising train it points to with `periodic_advertising_data`
+    async fn start_advertising(
+        &mut self,
+        advertising_handle: u8,
+        advertising_data: &[u8],
+        periodic_advertising_data: &[u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Create a BIG of `num_bis` BIS, with LE Create BIG
+    async fn create_big(
+        &mut self,
+        parameters: &BigParameters,
+        num_bis: u8,
+    ) -> Result<BigHandle, Self::Error>;
+}
+
+/// The broadcast source role, which streams audio to any number of broadcast sinks
+pub struct BapBroadcastSource<const MAX_SUBGROUPS: usize> {
+    /// Identifier of the broadcast, 24 bits
+    broadcast_id: u32,
+    /// Presentation delay in microseconds
+    presentation_delay: u32,
+    big: BigParameters,
+    subgroups: Vec<BroadcastSubgroup, MAX_SUBGROUPS>,
+}
+
+impl<const MAX_SUBGROUPS: usize> BapBroadcastSource<MAX_SUBGROUPS> {
+    pub fn new(
+        broadcast_id: u32,
+        presentation_delay: u32,
+        big: BigParameters,
+        subgroups: Vec<BroadcastSubgroup, MAX_SUBGROUPS>,
+    ) -> Self {
+        Self {
+            broadcast_id,
+            presentation_delay,
+            big,
+            subgroups,
+        }
+    }
+
+    pub fn big_parameters(&self) -> &BigParameters {
+        &self.big
+    }
+
+    pub fn subgroups(&self) -> &[BroadcastSubgroup] {
+        &self.subgroups
+    }
+
+    /// Number of BIS in every subgroup
+    pub fn num_bis(&self) -> usize {
+        self.subgroups
+            .iter()
+            .map(|subgroup| subgroup.bises.len())
+            .sum()
+    }
+
+    /// Announce the broadcast and create its BIG
+    ///
+    /// The BASE is advertised in the periodic advertising train before the
+    /// BIG is created, as sinks need it to synchronize to the BIS.
+    pub async fn start<C: BigController>(&self, controller: &mut C) -> Result<BigHandle, BapError> {
+        let mut periodic_data = [0; MAX_PERIODIC_ADVERTISING_DATA_SIZE];
+        let base_len = self.encode_base(&mut periodic_data[4..])?;
+        periodic_data[0] = 3 + base_len as u8;
+        periodic_data[1] = SERVICE_DATA_16;
+        periodic_data[2..4].copy_from_slice(&BASIC_AUDIO_ANNOUNCEMENT.to_le_bytes());
+
+        let mut advertising_data = [0; BROADCAST_ANNOUNCEMENT_SIZE];
+        advertising_data[0] = 6;
+        advertising_data[1] = SERVICE_DATA_16;
+        advertising_data[2..4].copy_from_slice(&BROADCAST_AUDIO_ANNOUNCEMENT.to_le_bytes());
+        advertising_data[4..].copy_from_slice(&self.broadcast_id.to_le_bytes()[..3]);
+
+        #[cfg(feature = "defmt")]
+        info!("[bap] announcing broadcast {:x}", self.broadcast_id);
+        controller
+            .start_advertising(
+                self.big.advertising_handle,
+                &advertising_data,
+                &periodic_data[..4 + base_len],
+            )
+            .await
+            .map_err(|_| BapError::AdvertisingFailed)?;
+
+        controller
+            .create_big(&self.big, self.num_bis() as u8)
+            .await
+            .map_err(|_| BapError::BigSetupFailed)
+    }
+
+    /// Write the Broadcast Audio Source Endpoint structure into `buf`
+    ///
+    /// Presentation delay, then every subgroup with its codec, configuration
+    /// and metadata, followed by the index and configuration of its BIS.
+    /// Returns the number of bytes written.
+    pub fn encode_base(&self, buf: &mut [u8]) -> Result<usize, BapError> {
+        if self.subgroups.is_empty() {
+            return Err(BapError::InvalidBase);
+        }
+        let len = MAX_BASE_SIZE.min(buf.len());
+        let mut writer = BaseWriter {
+            buf: &mut buf[..len],
+            len: 0,
+        };
+        writer.write(&self.presentation_delay.to_le_bytes()[..3])?;
+        writer.write(&[self.subgroups.len() as u8])?;
+
+        for subgroup in self.subgroups.iter() {
+            if subgroup.bises.is_empty() {
+                return Err(BapError::InvalidBase);
+            }
+            writer.write(&[subgroup.bises.len() as u8])?;
+            writer.write(&subgroup.codec_id.to_bytes())?;
+            writer.write_configuration(&subgroup.codec_specific_configuration)?;
+            writer.write_length_prefixed(|buf| {
+                let mut len = 0;
+                for entry in subgroup.metadata.iter() {
+                    match entry.encode_ltv(&mut buf[len..]) {
+                        0 => return Err(BapError::BaseTooLarge),
+                        written => len += written,
+                    }
+                }
+                Ok(len)
+            })?;
+
+            for bis in subgroup.bises.iter() {
+                // BIS indices are 5 bits, 0 is reserved
+                if !(1..=0x1F).contains(&bis.index) {
+                    return Err(BapError::InvalidBase);
+                }
+                writer.write(&[bis.index])?;
+                writer.write_configuration(&bis.codec_specific_configuration)?;
+            }
+        }
+        Ok(writer.len)
+    }
+}
+
+/// Appends the fields of a BASE to a buffer
+struct BaseWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl BaseWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<(), BapError> {
+        self.buf
+            .get_mut(self.len..self.len + data.len())
+            .ok_or(BapError::BaseTooLarge)?
+            .copy_from_slice(data);
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Write a length octet, then the bytes written by `encode`
+    fn write_length_prefixed(
+        &mut self,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize, BapError>,
+    ) -> Result<(), BapError> {
+        let start = self.len + 1;
+        let buf = self.buf.get_mut(start..).ok_or(BapError::BaseTooLarge)?;
+        let len = encode(buf)?;
+        self.buf[self.len] = u8::try_from(len).map_err(|_| BapError::BaseTooLarge)?;
+        self.len = start + len;
+        Ok(())
+    }
+
+    fn write_configuration(
+        &mut self,
+        configuration: &[CodecSpecificConfiguration],
+    ) -> Result<(), BapError> {
+        self.write_length_prefixed(|buf| {
+            let mut len = 0;
+            for entry in configuration {
+                len += entry
+                    .encode_ltv(&mut buf[len..])
+                    .map_err(|_| BapError::BaseTooLarge)?;
+            }
+            Ok(len)
+        })
+    }
+}
+
+/// Max size of the periodic advertising data reassembled by a broadcast sink
+pub const MAX_PERIODIC_ADVERTISING_SIZE: usize = 512;
+/// Max BIS a broadcast sink synchronizes to
+pub const MAX_SYNCED_BISES: usize = 4;
+
+/// Handle of a BIG a broadcast sink is synchronized to
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BigSyncHandle(pub u8);
+
+/// Handle of a periodic advertising train a broadcast sink is synchronized to
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeriodicSyncHandle(pub u16);
+
+/// The advertising set of a broadcast source
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BroadcastAdvertiser {
+    pub addr_kind: AddrKind,
+    pub addr: BdAddr,
+    /// Advertising SID of the set
+    pub sid: u8,
+}
+
+/// Data status of a periodic advertising report
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeriodicDataStatus {
+    Complete,
+    /// More reports follow with the rest of the data
+    Incomplete,
+    /// The rest of the data will not be received
+    Truncated,
+}
+
+/// The BIS a broadcast sink synchronizes to
+#[derive(Debug, Clone)]
+pub struct BisPreferences {
+    /// Only synchronize to this broadcast, any broadcast when `None`
+    pub broadcast_id: Option<u32>,
+    /// Locations rendered by the sink, the first BIS is used when empty
+    pub audio_location: AudioLocation,
+    pub big_handle: u8,
+    /// Code decrypting an encrypted broadcast
+    pub broadcast_code: Option<[u8; 16]>,
+    /// Time without receiving BIG PDUs before losing sync, in units of 10 ms
+    pub big_sync_timeout: u16,
+}
+
+impl Default for BisPreferences {
+    fn default() -> Self {
+        Self {
+            broadcast_id: None,
+            audio_location: AudioLocation::empty(),
+            big_handle: 0,
+            broadcast_code: None,
+            big_sync_timeout: 100,
+        }
+    }
+}
+
+/// The HCI commands and events synchronizing to a broadcast
+///
+/// trouble_host does not expose periodic advertising nor isochronous channels
+/// yet, so the application issues these commands to its controller.
+#[allow(async_fn_in_trait)]
+pub trait BroadcastScanner {
+    type Error;
+
+    /// Wait for the next extended advertising report, copying its data into `data`
+    ///
+    /// Returns the advertiser and the length of the data.
+    async fn next_advertisement(
+        &mut self,
+        data: &mut [u8],
+    ) -> Result<(BroadcastAdvertiser, usize), Self::Error>;
+
+    /// Synchronize to the periodic advertising train of `advertiser`, with LE Periodic Advertising Create Sync
+    async fn sync_periodic(
+        &mut self,
+        advertiser: &BroadcastAdvertiser,
+    ) -> Result<PeriodicSyncHandle, Self::Error>;
+
+    /// Wait for the next periodic advertising report, copying its data into `data`
+    ///
+    /// Returns the length of the data and whether more reports complete it.
+    async fn next_periodic_report(
+        &mut self,
+        sync: PeriodicSyncHandle,
+        data: &mut [u8],
+    ) -> Result<(usize, PeriodicDataStatus), Self::Error>;
+
+    /// Synchronize to the BIS with the indices `bises`, with LE BIG Create Sync
+    async fn big_create_sync(
+        &mut self,
+        sync: PeriodicSyncHandle,
+        preferences: &BisPreferences,
+        bises: &[u8],
+    ) -> Result<BigSyncHandle, Self::Error>;
+}
+
+/// A Broadcast Audio Source Endpoint, describing the BIS of a broadcast
+#[derive(Debug, Clone)]
+pub struct Base<const MAX_SUBGROUPS: usize> {
+    /// Presentation delay in microseconds
+    pub presentation_delay: u32,
+    pub subgroups: Vec<BroadcastSubgroup, MAX_SUBGROUPS>,
+}
+
+impl<const MAX_SUBGROUPS: usize> Base<MAX_SUBGROUPS> {
+    /// Parse a BASE, as encoded by [`BapBroadcastSource::encode_base`]
+    ///
+    /// Configurations of unknown types are skipped.
+    pub fn decode(data: &[u8]) -> Result<Self, BapError> {
+        let mut reader = BaseReader { data };
+        let delay = reader.read(3)?;
+        let presentation_delay = u32::from_le_bytes([delay[0], delay[1], delay[2], 0]);
+        let num_subgroups = reader.read(1)?[0];
+        if num_subgroups == 0 {
+            return Err(BapError::InvalidBase);
+        }
+
+        let mut subgroups = Vec::new();
+        for _ in 0..num_subgroups {
+            let num_bis = reader.read(1)?[0];
+            if num_bis == 0 {
+                return Err(BapError::InvalidBase);
+            }
+            let mut codec_id = [0; CodecId::SIZE];
+            codec_id.copy_from_slice(reader.read(CodecId::SIZE)?);
+            let codec_specific_configuration = reader.read_configuration()?;
+
+            let mut metadata = Vec::new();
+            let mut ltvs = LtvIterator::new(reader.read_length_prefixed()?);
+            for (type_id, value) in ltvs.by_ref() {
+                let entry =
+                    Metadata::decode_value(type_id, value).map_err(|_| BapError::InvalidBase)?;
+                metadata.push(entry).map_err(|_| BapError::InvalidBase)?;
+            }
+            if !ltvs.remaining().is_empty() {
+                return Err(BapError::InvalidBase);
+            }
+
+            let mut bises = Vec::new();
+            for _ in 0..num_bis {
+                let index = reader.read(1)?[0];
+                if !(1..=0x1F).contains(&index) {
+                    return Err(BapError::InvalidBase);
+                }
+                let bis = BroadcastBis {
+                    index,
+                    codec_specific_configuration: reader.read_configuration()?,
+                };
+                bises.push(bis).map_err(|_| BapError::InvalidBase)?;
+            }
+
+            let subgroup = BroadcastSubgroup {
+                codec_id: CodecId::decode(&codec_id),
+                codec_specific_configuration,
+                metadata,
+                bises,
+            };
+            subgroups
+                .push(subgroup)
+                .map_err(|_| BapError::InvalidBase)?;
+        }
+        Ok(Self {
+            presentation_delay,
+            subgroups,
+        })
+    }
+
+    /// The configuration of the BIS `index`
+    ///
+    /// The configuration of its subgroup, where the BIS configuration replaces
+    /// the entries of the same type.
+    pub fn bis_configuration(
+        &self,
+        index: u8,
+    ) -> Option<Vec<CodecSpecificConfiguration, MAX_CODEC_SPECIFIC_CONFIGURATIONS>> {
+        self.subgroups.iter().find_map(|subgroup| {
+            let bis = subgroup.bises.iter().find(|bis| bis.index == index)?;
+            let mut configuration = subgroup.codec_specific_configuration.clone();
+            for entry in bis.codec_specific_configuration.iter() {
+                let kind = core::mem::discriminant(entry);
+                match configuration
+                    .iter_mut()
+                    .find(|existing| core::mem::discriminant(&**existing) == kind)
+                {
+                    Some(existing) => *existing = entry.clone(),
+                    // One entry per type fits in the configuration
+                    None => {
+                        let _ = configuration.push(entry.clone());
+                    }
+                }
+            }
+            Some(configuration)
+        })
+    }
+
+    /// The audio location of the BIS `index`
+    fn bis_location(&self, index: u8) -> AudioLocation {
+        self.bis_configuration(index)
+            .and_then(|configuration| {
+                configuration.iter().find_map(|entry| match entry {
+                    CodecSpecificConfiguration::AudioChannelAllocation(location) => Some(*location),
+                    _ => None,
+                })
+            })
+            .unwrap_or(AudioLocation::empty())
+    }
+}
+
+/// Reads the fields of a BASE
+struct BaseReader<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> BaseReader<'d> {
+    fn read(&mut self, len: usize) -> Result<&'d [u8], BapError> {
+        if self.data.len() < len {
+            return Err(BapError::InvalidBase);
+        }
+        let (field, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(field)
+    }
+
+    /// Read a length octet, then as many bytes
+    fn read_length_prefixed(&mut self) -> Result<&'d [u8], BapError> {
+        let len = self.read(1)?[0];
+        self.read(len as usize)
+    }
+
+    fn read_configuration(
+        &mut self,
+    ) -> Result<Vec<CodecSpecificConfiguration, MAX_CODEC_SPECIFIC_CONFIGURATIONS>, BapError> {
+        let data = self.read_length_prefixed()?;
+        CodecSpecificConfiguration::decode_all_ltv(data).map_err(|_| BapError::InvalidBase)
+    }
+}
+
+/// The broadcast sink role, which receives the audio of a broadcast source
+pub struct BapBroadcastSink<const MAX_SUBGROUPS: usize> {
+    sync: Option<PeriodicSyncHandle>,
+    /// The periodic advertising data the BASE was last read from
+    base_data: Vec<u8, MAX_PERIODIC_ADVERTISING_SIZE>,
+    base: Option<Base<MAX_SUBGROUPS>>,
+    bises: Vec<u8, MAX_SYNCED_BISES>,
+}
+
+impl<const MAX_SUBGROUPS: usize> Default for BapBroadcastSink<MAX_SUBGROUPS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MAX_SUBGROUPS: usize> BapBroadcastSink<MAX_SUBGROUPS> {
+    pub const fn new() -> Self {
+        Self {
+            sync: None,
+            base_data: Vec::new(),
+            base: None,
+            bises: Vec::new(),
+        }
+    }
+
+    /// The BASE of the broadcast the sink is synchronized to
+    pub fn base(&self) -> Option<&Base<MAX_SUBGROUPS>> {
+        self.base.as_ref()
+    }
+
+    /// The indices of the BIS the sink is synchronized to
+    pub fn bises(&self) -> &[u8] {
+        &self.bises
+    }
+
+    /// Find a broadcast, read its BASE and synchronize to the BIS matching `preferences`
+    ///
+    /// Extended advertising is scanned for a Broadcast Audio Announcement, then
+    /// the BASE is read from the periodic advertising train it points to.
+    pub async fn scan_and_sync<S: BroadcastScanner>(
+        &mut self,
+        scanner: &mut S,
+        preferences: BisPreferences,
+    ) -> Result<BigSyncHandle, BapError> {
+        let mut data = [0; MAX_PERIODIC_ADVERTISING_SIZE];
+        let advertiser = loop {
+            let (advertiser, len) = scanner
+                .next_advertisement(&mut data)
+                .await
+                .map_err(|_| BapError::SyncFailed)?;
+            let Some(announcement) = service_data(&data[..len], BROADCAST_AUDIO_ANNOUNCEMENT)
+            else {
+                continue;
+            };
+            let [a, b, c, ..] = announcement else {
+                continue;
+            };
+            let broadcast_id = u32::from_le_bytes([*a, *b, *c, 0]);
+            if preferences.broadcast_id.is_none_or(|id| id == broadcast_id) {
+                #[cfg(feature = "defmt")]
+                info!("[bap] found broadcast {:x}", broadcast_id);
+                break advertiser;
+            }
+        };
+
+        let sync = scanner
+            .sync_periodic(&advertiser)
+            .await
+            .map_err(|_| BapError::SyncFailed)?;
+        self.sync = Some(sync);
+        self.base_data.clear();
+        self.base = None;
+        self.next_base(scanner).await?;
+
+        let base = self.base.as_ref().ok_or(BapError::InvalidBase)?;
+        let mut bises = Vec::new();
+        for index in base
+            .subgroups
+            .iter()
+            .flat_map(|subgroup| subgroup.bises.iter().map(|bis| bis.index))
+        {
+            let wanted = if preferences.audio_location.is_empty() {
+                bises.is_empty()
+            } else {
+                base.bis_location(index)
+                    .intersects(preferences.audio_location)
+            };
+            if wanted && bises.push(index).is_err() {
+                break;
+            }
+        }
+        if bises.is_empty() {
+            return Err(BapError::NoMatchingBis);
+        }
+
+        #[cfg(feature = "defmt")]
+        info!("[bap] synchronizing to bis {:?}", bises.as_slice());
+        let big = scanner
+            .big_create_sync(sync, &preferences, &bises)
+            .await
+            .map_err(|_| BapError::BigSyncFailed)?;
+        self.bises = bises;
+        Ok(big)
+    }
+
+    /// Wait until the source changes its BASE, returning the new one
+    ///
+    /// A source changes the BASE when its streams are reconfigured, the BIS
+    /// in use should then be checked against the new configuration.
+    pub async fn next_base<S: BroadcastScanner>(
+        &mut self,
+        scanner: &mut S,
+    ) -> Result<&Base<MAX_SUBGROUPS>, BapError> {
+        let sync = self.sync.ok_or(BapError::SyncFailed)?;
+        let mut report = [0; MAX_PERIODIC_ADVERTISING_SIZE];
+        let mut data: Vec<u8, MAX_PERIODIC_ADVERTISING_SIZE> = Vec::new();
+        loop {
+            let (len, status) = scanner
+                .next_periodic_report(sync, &mut report)
+                .await
+                .map_err(|_| BapError::SyncFailed)?;
+            // A report too large to reassemble is dropped like a truncated one
+            let appended = data.extend_from_slice(&report[..len]).is_ok();
+            match status {
+                PeriodicDataStatus::Incomplete if appended => continue,
+                PeriodicDataStatus::Complete if appended => {}
+                _ => {
+                    data.clear();
+                    continue;
+                }
+            }
+
+            if data != self.base_data {
+                if let Some(base) = service_data(&data, BASIC_AUDIO_ANNOUNCEMENT) {
+                    match Base::decode(base) {
+                        Ok(base) => {
+                            self.base = Some(base);
+                            self.base_data = data;
+                            break;
+                        }
+                        Err(_e) => {
+                            #[cfg(feature = "defmt")]
+                            info!("[bap] invalid base: {:?}", _e);
+                        }
+                    }
+                }
+            }
+            data.clear();
+        }
+        self.base.as_ref().ok_or(BapError::InvalidBase)
+    }
+}
+
+/// The data of the service data AD structure of `uuid` in advertising `data`
+fn service_data(data: &[u8], uuid: u16) -> Option<&[u8]> {
+    let mut data = data;
+    while let [len, rest @ ..] = data {
+        let structure = rest.get(..*len as usize)?;
+        if let [SERVICE_DATA_16, low, high, service_data @ ..] = structure {
+            if u16::from_le_bytes([*low, *high]) == uuid {
+                return Some(service_data);
+            }
+        }
+        data = &rest[*len as usize..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascs::{ascs_attributes, AscsStorage, Ase, AseType};
+    use trouble_host::attribute::AttributeTable;
+
+    const ATTRIBUTES: usize = ascs_attributes(1, 2);
+
+    struct NoDataPath;
+
+    impl IsoDataPathController for NoDataPath {
+        type Error = ();
+
+        async fn setup_iso_data_path(
+            &mut self,
+            _cis: CisHandle,
+            _direction: DataPathDirection,
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn qos_configured(conn_handle: u16) -> AscsEvent {
+        AscsEvent::QosConfigured {
+            conn_handle,
+            ase_id: AseId::new(1).unwrap(),
+            params: AseParamsQoSConfigured {
+                cig_id: 1,
+                cis_id: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn cis_established(conn_handle: u16) -> CisEstablished {
+        CisEstablished {
+            conn_handle,
+            cig_id: 1,
+            cis_id: 1,
+            cis: CisHandle(conn_handle + 0x60),
+        }
+    }
+
+    #[test]
+    fn streams_of_two_clients_with_the_same_ase_and_cis_are_kept_apart() {
+        let mut stores = AscsStorage::<1, 2>::new();
+        let mut table = AttributeTable::<NoopRawMutex, ATTRIBUTES>::new();
+        let mut ases = Vec::new();
+        let _ = ases.push(AseType::Sink(Ase::new(AseId::new(1).unwrap())));
+        let mut ascs = AscsServer::new(&mut table, ases, &mut stores);
+        ascs.handle_connect(1).unwrap();
+        ascs.handle_connect(2).unwrap();
+        let events: &'static AscsEventChannel = Box::leak(Box::new(AscsEventChannel::new()));
+        let bap = BapUnicastServer::new(&ascs, events, NoDataPath);
+
+        assert!(bap.on_ascs_event(qos_configured(1)).is_none());
+        assert!(bap.on_ascs_event(qos_configured(2)).is_none());
+        let streaming = AscsEvent::Streaming {
+            conn_handle: 2,
+            ase_id: AseId::new(1).unwrap(),
+        };
+        assert!(bap.on_ascs_event(streaming).is_none());
+
+        // The Ase of the first client is not streaming
+        assert!(bap.on_cis_established(cis_established(1)).is_none());
+        let stream = bap.on_cis_established(cis_established(2)).unwrap();
+        assert_eq!(stream.cis, CisHandle(0x62));
+        assert_eq!(stream.direction, DataPathDirection::Output);
+
+        let released = AscsEvent::Released {
+            conn_handle: 1,
+            ase_id: AseId::new(1).unwrap(),
+        };
+        assert!(bap.on_ascs_event(released).is_none());
+        assert!(bap.streams.borrow().get(&1).is_none());
+        assert_eq!(bap.streams.borrow()[&2].len(), 1);
+    }
+
+    #[test]
+    fn cis_ids_skip_those_in_use_and_wrap_around() {
+        assert_eq!(free_cis_id([0, 1, 3].into_iter(), 0), Some(2));
+        assert_eq!(free_cis_id([0, 2].into_iter(), 1), Some(1));
+        assert_eq!(
+            free_cis_id([MAX_CIS_ID, 0].into_iter(), MAX_CIS_ID),
+            Some(1)
+        );
+        // Ids above the range are never handed out
+        assert_eq!(free_cis_id(core::iter::empty(), MAX_CIS_ID + 1), Some(0));
+    }
+
+    #[test]
+    fn no_cis_id_is_free_once_all_are_used() {
+        assert_eq!(free_cis_id(0..=MAX_CIS_ID, 7), None);
+        assert_eq!(
+            free_cis_id((0..=MAX_CIS_ID).filter(|id| *id != 7), 8),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn qos_max_sdu_holds_a_codec_frame_per_channel() {
+        let negotiated = NegotiatedQos {
+            codec_id: codec_ids::LC3,
+            sampling_frequency: SamplingFrequency::Hz48000,
+            frame_duration: FrameDuration::Duration10MS,
+            octets_per_codec_frame: 100,
+            sdu_interval: 10_000,
+            max_transport_latency: 10,
+            retransmission_number: 2,
+        };
+        let mut config = [
+            CodecSpecificConfiguration::AudioChannelAllocation(
+                AudioLocation::FrontLeft | AudioLocation::FrontRight,
+            ),
+            CodecSpecificConfiguration::OctetsPerCodecFrame(OctetsPerCodecFrame::from_min_max(
+                100, 100,
+            )),
+        ];
+        let preferences = StreamPreferences::default();
+
+        let Ok(qos) = qos_for(&negotiated, &config, &preferences, 0, 3) else {
+            panic!("the SDU fits");
+        };
+        assert_eq!(qos.max_sdu, 200);
+        assert_eq!(qos.cis_id, 3);
+        assert_eq!(qos.sdu_interval, [0x10, 0x27, 0x00]);
+
+        config[1] = CodecSpecificConfiguration::OctetsPerCodecFrame(
+            OctetsPerCodecFrame::from_min_max(0x0900, 0x0900),
+        );
+        assert!(matches!(
+            qos_for(&negotiated, &config, &preferences, 0, 3),
+            Err(ComputeError::Overflow)
+        ));
+    }
+
+    /// An LC3 record supporting `frequencies` and `durations`, with 40 to 120 octets
+    fn record(frequencies: &[SamplingFrequency], durations: SupportedFrameDurations) -> PACRecord {
+        let mut record = PACRecord::default();
+        let _ = record.codec_id.push(codec_ids::LC3);
+        for capability in [
+            CodecSpecificCapabilities::SupportedSamplingFrequencies(
+                SupportedSamplingFrequencies::new(frequencies),
+            ),
+            CodecSpecificCapabilities::SupportedFrameDurations(durations),
+            CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(
+                OctetsPerCodecFrame::from_min_max(40, 120),
+            ),
+        ] {
+            let _ = record.codec_specific_capabilities.push(capability);
+        }
+        record
+    }
+
+    const ALL_FREQUENCIES: [SamplingFrequency; 3] = [
+        SamplingFrequency::Hz16000,
+        SamplingFrequency::Hz24000,
+        SamplingFrequency::Hz48000,
+    ];
+
+    #[test]
+    fn octets_preference_is_clamped_to_the_server_range() {
+        let durations = SupportedFrameDurations::new(false, true, false, false);
+        let server = [record(&ALL_FREQUENCIES, durations.clone())];
+        let client = [record(&ALL_FREQUENCIES, durations)];
+
+        for (preferred, negotiated) in [
+            (Some(100), 100),
+            (Some(200), 120),
+            (Some(10), 40),
+            (None, 120),
+        ] {
+            let preferences = QosPreferences {
+                octets_per_codec_frame: preferred,
+                ..Default::default()
+            };
+            let qos = negotiate_qos(&server, &client, &preferences).unwrap();
+            assert_eq!(qos.octets_per_codec_frame, negotiated);
+        }
+    }
+
+    #[test]
+    fn no_common_frequency_or_frame_duration_fails() {
+        let ten_ms = SupportedFrameDurations::new(false, true, false, false);
+        let seven_ms = SupportedFrameDurations::new(true, false, false, false);
+        let preferences = QosPreferences::default();
+
+        let server = [record(&[SamplingFrequency::Hz48000], ten_ms.clone())];
+        let client = [record(&[SamplingFrequency::Hz16000], ten_ms.clone())];
+        assert_eq!(
+            negotiate_qos(&server, &client, &preferences),
+            Err(NegotiationError::NoCommonCodec)
+        );
+
+        let client = [record(&[SamplingFrequency::Hz48000], seven_ms)];
+        assert_eq!(
+            negotiate_qos(&server, &client, &preferences),
+            Err(NegotiationError::NoCommonCodec)
+        );
+    }
+
+    #[test]
+    fn preferences_break_ties_between_common_values() {
+        // The server prefers 7.5 ms frames
+        let server = [record(
+            &ALL_FREQUENCIES,
+            SupportedFrameDurations::new(true, true, true, false),
+        )];
+        let client = [record(
+            &ALL_FREQUENCIES,
+            SupportedFrameDurations::new(true, true, false, false),
+        )];
+
+        let qos = negotiate_qos(&server, &client, &QosPreferences::default()).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz48000);
+        assert_eq!(qos.frame_duration, FrameDuration::Duration7_5MS);
+        assert_eq!(qos.sdu_interval, 7_500);
+
+        let preferences = QosPreferences {
+            sampling_frequency: Some(SamplingFrequency::Hz24000),
+            frame_duration: Some(FrameDuration::Duration10MS),
+            ..Default::default()
+        };
+        let qos = negotiate_qos(&server, &client, &preferences).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz24000);
+        assert_eq!(qos.frame_duration, FrameDuration::Duration10MS);
+
+        // A preference not supported by both ends is ignored
+        let preferences = QosPreferences {
+            sampling_frequency: Some(SamplingFrequency::Hz32000),
+            ..Default::default()
+        };
+        let qos = negotiate_qos(&server, &client, &preferences).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz48000);
+    }
+
+    #[test]
+    fn the_record_matching_the_most_preferences_wins() {
+        let durations = SupportedFrameDurations::new(false, true, false, false);
+        let server = [
+            record(&[SamplingFrequency::Hz48000], durations.clone()),
+            record(&[SamplingFrequency::Hz24000], durations.clone()),
+        ];
+        let client = [record(&ALL_FREQUENCIES, durations)];
+
+        let qos = negotiate_qos(&server, &client, &QosPreferences::default()).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz48000);
+
+        let preferences = QosPreferences {
+            sampling_frequency: Some(SamplingFrequency::Hz24000),
+            ..Default::default()
+        };
+        let qos = negotiate_qos(&server, &client, &preferences).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz24000);
+    }
+
+    #[test]
+    fn stream_falls_back_to_the_next_supported_frequency() {
+        let server = [record(
+            &[SamplingFrequency::Hz16000, SamplingFrequency::Hz24000],
+            SupportedFrameDurations::new(true, true, false, false),
+        )];
+        let preferences = StreamPreferences {
+            sampling_frequency: SamplingFrequency::Hz48000,
+            fallback_sampling_frequencies: Vec::from_slice(&[
+                SamplingFrequency::Hz32000,
+                SamplingFrequency::Hz24000,
+                SamplingFrequency::Hz16000,
+            ])
+            .unwrap(),
+            ..Default::default()
+        };
+
+        let qos = negotiate_stream(&server, &preferences).unwrap();
+        assert_eq!(qos.sampling_frequency, SamplingFrequency::Hz24000);
+        assert_eq!(qos.frame_duration, FrameDuration::Duration10MS);
+        assert_eq!(qos.octets_per_codec_frame, 100);
+    }
+
+    #[test]
+    fn stream_uses_the_only_frame_duration_of_the_server() {
+        let server = [record(
+            &[SamplingFrequency::Hz48000],
+            SupportedFrameDurations::new(true, false, false, false),
+        )];
+        let preferences = StreamPreferences {
+            octets_per_codec_frame: 155,
+            ..Default::default()
+        };
+
+        let qos = negotiate_stream(&server, &preferences).unwrap();
+        assert_eq!(qos.frame_duration, FrameDuration::Duration7_5MS);
+        assert_eq!(qos.octets_per_codec_frame, 120);
+    }
+
+    #[test]
+    fn stream_fails_when_no_frequency_is_supported() {
+        let server = [record(
+            &[SamplingFrequency::Hz16000],
+            SupportedFrameDurations::new(false, true, false, false),
+        )];
+        let preferences = StreamPreferences {
+            fallback_sampling_frequencies: Vec::from_slice(&[SamplingFrequency::Hz24000]).unwrap(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            negotiate_stream(&server, &preferences),
+            Err(NegotiationError::NoCommonCodec)
+        );
+    }
+
+    fn bis(index: u8, location: AudioLocation) -> BroadcastBis {
+        BroadcastBis {
+            index,
+            codec_specific_configuration: Vec::from_slice(&[
+                CodecSpecificConfiguration::AudioChannelAllocation(location),
+            ])
+            .unwrap(),
+        }
+    }
+
+    fn subgroup(frequency: SamplingFrequency, bises: &[BroadcastBis]) -> BroadcastSubgroup {
+        let metadata = match frequency {
+            SamplingFrequency::Hz48000 => Metadata::StreamingAudioContexts(ContextType::Media),
+            _ => Metadata::ProgramInfo(heapless::String::try_from("News").unwrap()),
+        };
+        BroadcastSubgroup {
+            codec_id: codec_ids::LC3,
+            codec_specific_configuration: Vec::from_slice(&[
+                CodecSpecificConfiguration::SamplingFrequency(frequency),
+                CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+                CodecSpecificConfiguration::OctetsPerCodecFrame(
+                    OctetsPerCodecFrame::new(100, 100).unwrap(),
+                ),
+            ])
+            .unwrap(),
+            metadata: Vec::from_slice(&[metadata]).unwrap(),
+            bises: Vec::from_slice(bises).unwrap(),
+        }
+    }
+
+    fn source(subgroups: &[BroadcastSubgroup]) -> BapBroadcastSource<2> {
+        let big = BigParameters {
+            big_handle: 0,
+            advertising_handle: 0,
+            sdu_interval: 10_000,
+            max_sdu: 100,
+            max_transport_latency: 10,
+            retransmission_number: 2,
+            phy: PhySet::M2,
+            packing: 0,
+            framing: 0,
+            broadcast_code: None,
+        };
+        BapBroadcastSource::new(0x123456, 40_000, big, Vec::from_slice(subgroups).unwrap())
+    }
+
+    /// A BASE of a 48 kHz stereo subgroup and a 24 kHz mono subgroup
+    fn encoded_base(buf: &mut [u8]) -> usize {
+        source(&[
+            subgroup(
+                SamplingFrequency::Hz48000,
+                &[
+                    bis(1, AudioLocation::FrontLeft),
+                    bis(2, AudioLocation::FrontRight),
+                ],
+            ),
+            subgroup(
+                SamplingFrequency::Hz24000,
+                &[bis(3, AudioLocation::FrontLeft | AudioLocation::FrontRight)],
+            ),
+        ])
+        .encode_base(buf)
+        .unwrap()
+    }
+
+    #[test]
+    fn base_round_trips_every_subgroup_and_bis() {
+        let mut buf = [0; MAX_BASE_SIZE];
+        let len = encoded_base(&mut buf);
+
+        let base = Base::<2>::decode(&buf[..len]).unwrap();
+        assert_eq!(base.presentation_delay, 40_000);
+        assert_eq!(base.subgroups.len(), 2);
+        let indices: [&[u8]; 2] = [&[1, 2], &[3]];
+        for (subgroup, indices) in base.subgroups.iter().zip(indices) {
+            assert_eq!(subgroup.codec_id, codec_ids::LC3);
+            assert_eq!(subgroup.codec_specific_configuration.len(), 3);
+            assert_eq!(subgroup.metadata.len(), 1);
+            assert!(subgroup
+                .bises
+                .iter()
+                .map(|bis| bis.index)
+                .eq(indices.iter().copied()));
+        }
+        assert!(matches!(
+            &base.subgroups[1].metadata[0],
+            Metadata::ProgramInfo(info) if info == "News"
+        ));
+        assert_eq!(base.bis_location(2), AudioLocation::FrontRight);
+        assert_eq!(
+            base.bis_location(3),
+            AudioLocation::FrontLeft | AudioLocation::FrontRight
+        );
+        assert!(matches!(
+            base.bis_configuration(3).unwrap()[0],
+            CodecSpecificConfiguration::SamplingFrequency(SamplingFrequency::Hz24000)
+        ));
+
+        // Encoding the decoded BASE gives the same bytes
+        let mut reencoded = [0; MAX_BASE_SIZE];
+        let source = source(&base.subgroups);
+        assert_eq!(source.encode_base(&mut reencoded), Ok(len));
+        assert_eq!(reencoded[..len], buf[..len]);
+    }
+
+    #[test]
+    fn truncated_base_is_rejected() {
+        let mut buf = [0; MAX_BASE_SIZE];
+        let len = encoded_base(&mut buf);
+
+        for truncated in 0..len {
+            assert!(
+                Base::<2>::decode(&buf[..truncated]).is_err(),
+                "BASE truncated to {truncated} bytes was decoded"
+            );
+        }
+        assert_eq!(
+            Base::<1>::decode(&buf[..len]).err(),
+            Some(BapError::InvalidBase)
+        );
+    }
+
+    #[test]
+    fn malformed_base_is_rejected() {
+        let mut buf = [0; MAX_BASE_SIZE];
+        let len = encoded_base(&mut buf);
+        // Offsets of the BIS count and the configuration length of the first subgroup
+        let num_bis = 4;
+        let configuration_len = 10;
+
+        let mut no_subgroup = buf;
+        no_subgroup[3] = 0;
+        let mut no_bis = buf;
+        no_bis[num_bis] = 0;
+        let mut overrunning_configuration = buf;
+        overrunning_configuration[configuration_len] = 0xFF;
+        let metadata_len = configuration_len + 1 + buf[configuration_len] as usize;
+        let mut overrunning_metadata = buf;
+        overrunning_metadata[metadata_len] += 1;
+        let first_bis = metadata_len + 1 + buf[metadata_len] as usize;
+        let mut reserved_bis_index = buf;
+        reserved_bis_index[first_bis] = 0;
+
+        for malformed in [
+            no_subgroup,
+            no_bis,
+            overrunning_configuration,
+            overrunning_metadata,
+            reserved_bis_index,
+        ] {
+            assert!(Base::<2>::decode(&malformed[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn base_without_bis_or_with_invalid_index_is_not_encoded() {
+        let mut buf = [0; MAX_BASE_SIZE];
+        assert_eq!(
+            source(&[]).encode_base(&mut buf),
+            Err(BapError::InvalidBase)
+        );
+        let source_without_bis = source(&[subgroup(SamplingFrequency::Hz48000, &[])]);
+        assert_eq!(
+            source_without_bis.encode_base(&mut buf),
+            Err(BapError::InvalidBase)
+        );
+        let source_with_index_0 = source(&[subgroup(
+            SamplingFrequency::Hz48000,
+            &[bis(0, AudioLocation::FrontLeft)],
+        )]);
+        assert_eq!(
+            source_with_index_0.encode_base(&mut buf),
+            Err(BapError::InvalidBase)
+        );
+        let one = source(&[subgroup(
+            SamplingFrequency::Hz48000,
+            &[bis(1, AudioLocation::FrontLeft)],
+        )]);
+        assert_eq!(one.encode_base(&mut buf[..8]), Err(BapError::BaseTooLarge));
+    }
+}
@@ -0,0 +1,107 @@
+//! Content Control IDs
+//!
+//! Every service controlling audio content, like the media or telephony
+//! control services, is identified by a Content Control ID (CCID) unique on
+//! the device. Audio streams name the services controlling them with the
+//! CCID List metadata.
+
+use heapless::Vec;
+
+use crate::{
+    generic_audio::{Metadata, MAX_CCIDS},
+    ContentControlID,
+};
+
+/// Allocates the Content Control IDs of up to N services
+#[derive(Debug, Default)]
+pub struct CcidRegistry<const N: usize> {
+    ids: Vec<ContentControlID, N>,
+    service_handles: Vec<u16, N>,
+}
+
+impl<const N: usize> CcidRegistry<N> {
+    const CCID_LIST_CHECK: () = assert!(N <= MAX_CCIDS, "the CCID List holds up to 16 ids");
+
+    pub const fn new() -> Self {
+        let () = Self::CCID_LIST_CHECK;
+        Self {
+            ids: Vec::new(),
+            service_handles: Vec::new(),
+        }
+    }
+
+    /// Allocate the id of a service
+    ///
+    /// Registering a service again returns the id it was already given.
+    pub fn register(&mut self, service_handle: u16) -> Result<ContentControlID, CcidError> {
+        if let Some(id) = self.id_of(service_handle) {
+            return Ok(id);
+        }
+        let id = u8::try_from(self.ids.len()).map_err(|_| CcidError::Full)?;
+        self.ids.push(id).map_err(|_| CcidError::Full)?;
+        // Both vectors have the same length and capacity
+        let _ = self.service_handles.push(service_handle);
+        Ok(id)
+    }
+
+    /// The id of a registered service
+    pub fn id_of(&self, service_handle: u16) -> Option<ContentControlID> {
+        self.service_handles
+            .iter()
+            .position(|handle| *handle == service_handle)
+            .map(|index| self.ids[index])
+    }
+
+    /// The ids of every registered service
+    pub fn ids(&self) -> &[ContentControlID] {
+        &self.ids
+    }
+
+    /// CCID List metadata naming every registered service
+    pub fn ccid_list(&self) -> Metadata {
+        let () = Self::CCID_LIST_CHECK;
+        // Cannot fail, N is at most MAX_CCIDS
+        Metadata::CCIDList(Vec::from_slice(self.ids()).unwrap_or_default())
+    }
+}
+
+/// Errors returned by the [`CcidRegistry`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CcidError {
+    /// Every id of the registry is allocated
+    Full,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registering_more_than_n_services_fails() {
+        let mut registry = CcidRegistry::<2>::new();
+        assert_eq!(registry.register(0x0010), Ok(0));
+        assert_eq!(registry.register(0x0020), Ok(1));
+        assert_eq!(registry.register(0x0030), Err(CcidError::Full));
+        // A registered service keeps its id
+        assert_eq!(registry.register(0x0010), Ok(0));
+        assert_eq!(registry.ids(), &[0, 1]);
+        assert_eq!(registry.id_of(0x0030), None);
+    }
+
+    #[test]
+    fn ccid_list_names_every_registered_service() {
+        let mut registry = CcidRegistry::<4>::new();
+        registry.register(0x0010).unwrap();
+        registry.register(0x0020).unwrap();
+        let metadata = registry.ccid_list();
+        assert!(matches!(&metadata, Metadata::CCIDList(ccids) if ccids[..] == [0, 1]));
+
+        let mut buf = [0; 4];
+        assert_eq!(metadata.encode_ltv(&mut buf), 4);
+        assert_eq!(buf, [0x03, 0x05, 0x00, 0x01]);
+        let (decoded, len) = Metadata::decode_ltv(&buf).unwrap();
+        assert_eq!(len, 4);
+        assert!(matches!(decoded, Metadata::CCIDList(ccids) if ccids[..] == [0, 1]));
+    }
+}
@@ -0,0 +1,1349 @@
+//! Telephone Bearer Service
+//!
+//! This service exposes the calls of a telephone bearer, like a phone line or
+//! a VoIP application, and lets clients answer, place and hold them. The
+//! server keeps the state of every call, the application is told about the
+//! operations clients performed so it can drive the bearer.
+
+use bitflags::bitflags;
+use bt_hci::uuid::{characteristic, service};
+use core::{
+    cell::{Cell, RefCell},
+    slice,
+};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    channel::Channel,
+};
+use heapless::Vec;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::{info, warn};
+
+use crate::{ContentControlID, LeAudioError, LeAudioServerService, Utf8Text};
+
+/// Attributes added to the attribute table by the Ccs service
+pub const CCS_ATTRIBUTES: usize = 43;
+
+/// Max number of calls the bearer can hold at once
+pub const MAX_CALLS: usize = 2;
+
+/// Max length of the UTF-8 names and URIs of the bearer and its calls
+pub const MAX_BEARER_TEXT_SIZE: usize = 32;
+
+/// Size of a call in the Call State characteristic
+const CALL_STATE_SIZE: usize = 3;
+
+/// Size of the largest call in the Bearer List Current Calls characteristic
+const CURRENT_CALL_SIZE: usize = 4 + MAX_BEARER_TEXT_SIZE;
+
+/// Signal strength of a bearer that can not report it
+pub const SIGNAL_STRENGTH_UNAVAILABLE: u8 = 255;
+
+/// Number of events the Ccs event channel can queue
+pub const CCS_EVENT_QUEUE_SIZE: usize = 4;
+
+/// Channel the Ccs server publishes [`CcsEvent`]s on
+pub type CcsEventChannel = Channel<NoopRawMutex, CcsEvent, CCS_EVENT_QUEUE_SIZE>;
+
+/// Backing storage for the characteristics of a [`CcsServer`]
+pub struct CcsStorage {
+    provider_name: [u8; MAX_BEARER_TEXT_SIZE],
+    uci: [u8; MAX_BEARER_TEXT_SIZE],
+    technology: [u8; BearerTechnology::SIZE],
+    uri_schemes: [u8; MAX_BEARER_TEXT_SIZE],
+    signal_strength: [u8; 1],
+    current_calls: [u8; MAX_CALLS * CURRENT_CALL_SIZE],
+    content_control_id: [u8; 1],
+    status_flags: [u8; StatusFlags::SIZE],
+    incoming_call_target_uri: [u8; 1 + MAX_BEARER_TEXT_SIZE],
+    call_state: [u8; MAX_CALLS * CALL_STATE_SIZE],
+    call_control_point: [u8; 1 + MAX_BEARER_TEXT_SIZE],
+    optional_opcodes: [u8; CallControlOptionalOpcodes::SIZE],
+    termination_reason: [u8; CallTermination::SIZE],
+    incoming_call: [u8; 1 + MAX_BEARER_TEXT_SIZE],
+    call_friendly_name: [u8; 1 + MAX_BEARER_TEXT_SIZE],
+}
+
+impl CcsStorage {
+    pub const fn new() -> Self {
+        Self {
+            provider_name: [0; MAX_BEARER_TEXT_SIZE],
+            uci: [0; MAX_BEARER_TEXT_SIZE],
+            technology: [0; BearerTechnology::SIZE],
+            uri_schemes: [0; MAX_BEARER_TEXT_SIZE],
+            signal_strength: [0; 1],
+            current_calls: [0; MAX_CALLS * CURRENT_CALL_SIZE],
+            content_control_id: [0; 1],
+            status_flags: [0; StatusFlags::SIZE],
+            incoming_call_target_uri: [0; 1 + MAX_BEARER_TEXT_SIZE],
+            call_state: [0; MAX_CALLS * CALL_STATE_SIZE],
+            call_control_point: [0; 1 + MAX_BEARER_TEXT_SIZE],
+            optional_opcodes: [0; CallControlOptionalOpcodes::SIZE],
+            termination_reason: [0; CallTermination::SIZE],
+            incoming_call: [0; 1 + MAX_BEARER_TEXT_SIZE],
+            call_friendly_name: [0; 1 + MAX_BEARER_TEXT_SIZE],
+        }
+    }
+}
+
+impl Default for CcsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The telephone bearer exposed by a [`CcsServer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bearer {
+    /// Name of the provider of the bearer, like the name of a network operator
+    pub provider_name: BearerText,
+    /// Uniform Caller Identifier of the bearer, like `skype`
+    pub uci: BearerText,
+    pub technology: BearerTechnology,
+    /// Comma separated URI schemes the bearer can call, like `tel,sip`
+    pub uri_schemes: BearerText,
+}
+
+/// A call of the bearer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// Index identifying the call, never 0
+    pub index: u8,
+    pub state: CallState,
+    pub flags: CallFlags,
+    /// URI of the remote party, like `tel:+15551234`
+    pub uri: BearerText,
+}
+
+/// A Gatt service exposing the calls of a telephone bearer
+pub struct CcsServer {
+    handle: u16,
+    provider_name: Characteristic<BearerText>,
+    uci: Characteristic<BearerText>,
+    technology: Characteristic<BearerTechnology>,
+    uri_schemes: Characteristic<BearerText>,
+    signal_strength: Characteristic<u8>,
+    current_calls: Characteristic<CurrentCallList>,
+    content_control_id: Characteristic<ContentControlID>,
+    status_flags: Characteristic<StatusFlags>,
+    incoming_call_target_uri: Characteristic<CallText>,
+    call_state: Characteristic<CallStateList>,
+    call_control_point: Characteristic<CallControlPointResponse>,
+    optional_opcodes: Characteristic<CallControlOptionalOpcodes>,
+    termination_reason: Characteristic<CallTermination>,
+    incoming_call: Characteristic<CallText>,
+    call_friendly_name: Characteristic<CallText>,
+    opcodes: CallControlOptionalOpcodes,
+    calls: RefCell<Vec<Call, MAX_CALLS>>,
+    next_index: Cell<u8>,
+    signal: Cell<u8>,
+    status: Cell<StatusFlags>,
+    calls_changed: Cell<bool>,
+    signal_changed: Cell<bool>,
+    status_changed: Cell<bool>,
+    pending_incoming: RefCell<Option<CallText>>,
+    pending_target_uri: RefCell<Option<CallText>>,
+    pending_friendly_name: RefCell<Option<CallText>>,
+    pending_termination: Cell<Option<CallTermination>>,
+    pending_response: Cell<Option<CallControlPointResponse>>,
+    events: Option<&'static CcsEventChannel>,
+}
+
+impl CcsServer {
+    /// Create a new Ccs Gatt Service
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        bearer: Bearer,
+        optional_opcodes: CallControlOptionalOpcodes,
+        ccid: ContentControlID,
+        storage: &'a mut CcsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::TELEPHONE_BEARER));
+
+        let provider_name_char = service
+            .add_characteristic(
+                characteristic::BEARER_PROVIDER_NAME,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                bearer.provider_name,
+                &mut storage.provider_name,
+            )
+            .build();
+
+        let uci_char = service
+            .add_characteristic(
+                characteristic::BEARER_UCI,
+                &[CharacteristicProp::Read],
+                bearer.uci,
+                &mut storage.uci,
+            )
+            .build();
+
+        let technology_char = service
+            .add_characteristic(
+                characteristic::BEARER_TECHNOLOGY,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                bearer.technology,
+                &mut storage.technology,
+            )
+            .build();
+
+        let uri_schemes_char = service
+            .add_characteristic(
+                characteristic::BEARER_URI_SCHEMES_SUPPORTED_LIST,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                bearer.uri_schemes,
+                &mut storage.uri_schemes,
+            )
+            .build();
+
+        let signal_strength_char = service
+            .add_characteristic(
+                characteristic::BEARER_SIGNAL_STRENGTH,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                SIGNAL_STRENGTH_UNAVAILABLE,
+                &mut storage.signal_strength,
+            )
+            .build();
+
+        let current_calls_char = service
+            .add_characteristic(
+                characteristic::BEARER_LIST_CURRENT_CALLS,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                CurrentCallList::default(),
+                &mut storage.current_calls,
+            )
+            .build();
+
+        let content_control_id_char = service
+            .add_characteristic(
+                characteristic::CONTENT_CONTROL_ID,
+                &[CharacteristicProp::Read],
+                ccid,
+                &mut storage.content_control_id,
+            )
+            .build();
+
+        let status_flags_char = service
+            .add_characteristic(
+                characteristic::STATUS_FLAGS,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                StatusFlags::empty(),
+                &mut storage.status_flags,
+            )
+            .build();
+
+        let incoming_call_target_uri_char = service
+            .add_characteristic(
+                characteristic::INCOMING_CALL_TARGET_BEARER_URI,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                CallText::default(),
+                &mut storage.incoming_call_target_uri,
+            )
+            .build();
+
+        let call_state_char = service
+            .add_characteristic(
+                characteristic::CALL_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                CallStateList::default(),
+                &mut storage.call_state,
+            )
+            .build();
+
+        let call_control_point_char = service
+            .add_characteristic(
+                characteristic::CALL_CONTROL_POINT,
+                &[
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                CallControlPointResponse::default(),
+                &mut storage.call_control_point,
+            )
+            .build();
+
+        let optional_opcodes_char = service
+            .add_characteristic(
+                characteristic::CALL_CONTROL_POINT_OPTIONAL_OPCODES,
+                &[CharacteristicProp::Read],
+                optional_opcodes,
+                &mut storage.optional_opcodes,
+            )
+            .build();
+
+        let termination_reason_char = service
+            .add_characteristic(
+                characteristic::TERMINATION_REASON,
+                &[CharacteristicProp::Notify],
+                CallTermination::default(),
+                &mut storage.termination_reason,
+            )
+            .build();
+
+        let incoming_call_char = service
+            .add_characteristic(
+                characteristic::INCOMING_CALL,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                CallText::default(),
+                &mut storage.incoming_call,
+            )
+            .build();
+
+        let call_friendly_name_char = service
+            .add_characteristic(
+                characteristic::CALL_FRIENDLY_NAME,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                CallText::default(),
+                &mut storage.call_friendly_name,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            provider_name: provider_name_char,
+            uci: uci_char,
+            technology: technology_char,
+            uri_schemes: uri_schemes_char,
+            signal_strength: signal_strength_char,
+            current_calls: current_calls_char,
+            content_control_id: content_control_id_char,
+            status_flags: status_flags_char,
+            incoming_call_target_uri: incoming_call_target_uri_char,
+            call_state: call_state_char,
+            call_control_point: call_control_point_char,
+            optional_opcodes: optional_opcodes_char,
+            termination_reason: termination_reason_char,
+            incoming_call: incoming_call_char,
+            call_friendly_name: call_friendly_name_char,
+            opcodes: optional_opcodes,
+            calls: RefCell::new(Vec::new()),
+            next_index: Cell::new(1),
+            signal: Cell::new(SIGNAL_STRENGTH_UNAVAILABLE),
+            status: Cell::new(StatusFlags::empty()),
+            calls_changed: Cell::new(false),
+            signal_changed: Cell::new(false),
+            status_changed: Cell::new(false),
+            pending_incoming: RefCell::new(None),
+            pending_target_uri: RefCell::new(None),
+            pending_friendly_name: RefCell::new(None),
+            pending_termination: Cell::new(None),
+            pending_response: Cell::new(None),
+            events: None,
+        }
+    }
+
+    /// Surface the operations written by clients to the application
+    ///
+    /// The application task should receive from the channel and drive the
+    /// bearer, then report the progress of the calls with the methods of this service.
+    pub fn with_event_channel(mut self, channel: &'static CcsEventChannel) -> Self {
+        self.events = Some(channel);
+        self
+    }
+
+    fn emit(&self, event: CcsEvent) {
+        if let Some(events) = self.events {
+            if events.try_send(event).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[ccs] event channel is full, dropping event");
+            }
+        }
+    }
+
+    /// The handle of the service, used to register its Content Control ID
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Bearer Signal Strength characteristic
+    pub fn signal_strength_characteristic(&self) -> &Characteristic<u8> {
+        &self.signal_strength
+    }
+
+    /// The Bearer List Current Calls characteristic
+    pub fn current_calls_characteristic(&self) -> &Characteristic<CurrentCallList> {
+        &self.current_calls
+    }
+
+    /// The Status Flags characteristic
+    pub fn status_flags_characteristic(&self) -> &Characteristic<StatusFlags> {
+        &self.status_flags
+    }
+
+    /// The Incoming Call Target Bearer URI characteristic
+    pub fn incoming_call_target_uri_characteristic(&self) -> &Characteristic<CallText> {
+        &self.incoming_call_target_uri
+    }
+
+    /// The Call State characteristic
+    pub fn call_state_characteristic(&self) -> &Characteristic<CallStateList> {
+        &self.call_state
+    }
+
+    /// The Call Control Point characteristic
+    pub fn call_control_point_characteristic(&self) -> &Characteristic<CallControlPointResponse> {
+        &self.call_control_point
+    }
+
+    /// The Termination Reason characteristic
+    pub fn termination_reason_characteristic(&self) -> &Characteristic<CallTermination> {
+        &self.termination_reason
+    }
+
+    /// The Incoming Call characteristic
+    pub fn incoming_call_characteristic(&self) -> &Characteristic<CallText> {
+        &self.incoming_call
+    }
+
+    /// The Call Friendly Name characteristic
+    pub fn call_friendly_name_characteristic(&self) -> &Characteristic<CallText> {
+        &self.call_friendly_name
+    }
+
+    /// The calls of the bearer
+    pub fn calls(&self) -> Vec<Call, MAX_CALLS> {
+        self.calls.borrow().clone()
+    }
+
+    /// The state of the call `index`, `None` if there is no such call
+    pub fn call_state(&self, index: u8) -> Option<CallState> {
+        self.calls
+            .borrow()
+            .iter()
+            .find(|call| call.index == index)
+            .map(|call| call.state)
+    }
+
+    /// Report an incoming call from `uri`, returning its index
+    ///
+    /// `target_uri` is the URI the remote party called, `friendly_name` the
+    /// name of the caller, like a contact name.
+    pub fn incoming_call(
+        &self,
+        uri: BearerText,
+        target_uri: Option<BearerText>,
+        friendly_name: Option<BearerText>,
+    ) -> Result<u8, CcsError> {
+        let index = self.add_call(CallState::Incoming, CallFlags::empty(), uri.clone())?;
+        *self.pending_incoming.borrow_mut() = Some(CallText::new(index, &uri));
+        if let Some(target_uri) = target_uri {
+            *self.pending_target_uri.borrow_mut() = Some(CallText::new(index, &target_uri));
+        }
+        if let Some(name) = friendly_name {
+            *self.pending_friendly_name.borrow_mut() = Some(CallText::new(index, &name));
+        }
+        Ok(index)
+    }
+
+    /// Move the call `index` to `state`, as the remote party or the network did
+    ///
+    /// Use this when the remote party answers an outgoing call, puts it on hold
+    /// or retrieves it.
+    pub fn set_call_state(&self, index: u8, state: CallState) -> Result<(), CcsError> {
+        let mut calls = self.calls.borrow_mut();
+        let call = calls
+            .iter_mut()
+            .find(|call| call.index == index)
+            .ok_or(CcsError::InvalidCallIndex)?;
+        if call.state != state {
+            call.state = state;
+            self.calls_changed.set(true);
+        }
+        Ok(())
+    }
+
+    /// Remove the call `index`, ended by the bearer for `reason`
+    pub fn end_call(&self, index: u8, reason: TerminationReason) -> Result<(), CcsError> {
+        self.remove_call(index)?;
+        self.pending_termination
+            .set(Some(CallTermination { index, reason }));
+        Ok(())
+    }
+
+    /// Change the signal strength of the bearer, from 0 to 100
+    pub fn set_signal_strength(&self, strength: u8) {
+        let strength = if strength > 100 && strength != SIGNAL_STRENGTH_UNAVAILABLE {
+            100
+        } else {
+            strength
+        };
+        if self.signal.replace(strength) != strength {
+            self.signal_changed.set(true);
+        }
+    }
+
+    /// Change the status flags of the bearer
+    pub fn set_status_flags(&self, flags: StatusFlags) {
+        if self.status.replace(flags) != flags {
+            self.status_changed.set(true);
+        }
+    }
+
+    /// The call states to notify to clients, if a call changed since the last call
+    pub fn take_calls_change(&self) -> Option<(CallStateList, CurrentCallList)> {
+        self.calls_changed.replace(false).then(|| {
+            let calls = self.calls.borrow();
+            (
+                CallStateList::from_calls(&calls),
+                CurrentCallList::from_calls(&calls),
+            )
+        })
+    }
+
+    /// The incoming call to notify to clients, if a call came in since the last call
+    pub fn take_incoming_call(&self) -> Option<CallText> {
+        self.pending_incoming.borrow_mut().take()
+    }
+
+    /// The URI the caller called to notify to clients, if one was reported
+    pub fn take_incoming_call_target_uri(&self) -> Option<CallText> {
+        self.pending_target_uri.borrow_mut().take()
+    }
+
+    /// The friendly name of the caller to notify to clients, if one was reported
+    pub fn take_call_friendly_name(&self) -> Option<CallText> {
+        self.pending_friendly_name.borrow_mut().take()
+    }
+
+    /// The termination to notify to clients, if a call ended since the last call
+    pub fn take_termination(&self) -> Option<CallTermination> {
+        self.pending_termination.take()
+    }
+
+    /// The signal strength to notify to clients, if it changed since the last call
+    pub fn take_signal_strength_change(&self) -> Option<u8> {
+        self.signal_changed
+            .replace(false)
+            .then(|| self.signal.get())
+    }
+
+    /// The status flags to notify to clients, if they changed since the last call
+    pub fn take_status_flags_change(&self) -> Option<StatusFlags> {
+        self.status_changed
+            .replace(false)
+            .then(|| self.status.get())
+    }
+
+    /// The outcome of the last control point write, to notify after the write response
+    pub fn take_control_point_response(&self) -> Option<CallControlPointResponse> {
+        self.pending_response.take()
+    }
+
+    /// Add a call in `state`, with the next free index
+    fn add_call(
+        &self,
+        state: CallState,
+        flags: CallFlags,
+        uri: BearerText,
+    ) -> Result<u8, CcsError> {
+        let mut calls = self.calls.borrow_mut();
+        let index = self.next_index.get();
+        calls
+            .push(Call {
+                index,
+                state,
+                flags,
+                uri,
+            })
+            .map_err(|_| CcsError::NoCallSlot)?;
+        // Call indexes run from 1 to 255
+        self.next_index.set(index.checked_add(1).unwrap_or(1));
+        self.calls_changed.set(true);
+        Ok(index)
+    }
+
+    fn remove_call(&self, index: u8) -> Result<(), CcsError> {
+        let mut calls = self.calls.borrow_mut();
+        let position = calls
+            .iter()
+            .position(|call| call.index == index)
+            .ok_or(CcsError::InvalidCallIndex)?;
+        calls.remove(position);
+        self.calls_changed.set(true);
+        Ok(())
+    }
+
+    /// Move the call `index` with `transition`, failing if it is not in a state it applies to
+    fn transition(
+        &self,
+        index: u8,
+        transition: fn(CallState) -> Option<CallState>,
+    ) -> Result<(), CallControlResult> {
+        let mut calls = self.calls.borrow_mut();
+        let call = calls
+            .iter_mut()
+            .find(|call| call.index == index)
+            .ok_or(CallControlResult::InvalidCallIndex)?;
+        call.state = transition(call.state).ok_or(CallControlResult::StateMismatch)?;
+        self.calls_changed.set(true);
+        Ok(())
+    }
+
+    /// Perform a call control point operation on the calls
+    ///
+    /// The write is always accepted, its outcome is notified on the control point.
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, operand @ ..] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        let (index, result) = match CallControlOpcode::try_from(*opcode) {
+            Ok(op) => self.perform(op, operand),
+            Err(_) => (0, Err(CallControlResult::OpcodeNotSupported)),
+        };
+        let result = match result {
+            Ok(event) => {
+                #[cfg(feature = "defmt")]
+                info!("[ccs] {:?}", event);
+                self.emit(event);
+                CallControlResult::Success
+            }
+            Err(result) => result,
+        };
+        self.pending_response.set(Some(CallControlPointResponse {
+            opcode: *opcode,
+            call_index: index,
+            result: result as u8,
+        }));
+        Ok(())
+    }
+
+    /// Perform `op`, returning the index of the call it applies to and its outcome
+    fn perform(
+        &self,
+        op: CallControlOpcode,
+        operand: &[u8],
+    ) -> (u8, Result<CcsEvent, CallControlResult>) {
+        if let CallControlOpcode::Originate = op {
+            return self.originate(operand);
+        }
+        if let CallControlOpcode::Join = op {
+            return (0, self.join(operand));
+        }
+
+        let [index] = operand else {
+            return (0, Err(CallControlResult::OperationNotPossible));
+        };
+        let index = *index;
+        let result = match op {
+            CallControlOpcode::Accept => self
+                .transition(index, |state| {
+                    (state == CallState::Incoming).then_some(CallState::Active)
+                })
+                .map(|_| CcsEvent::Accepted(index)),
+            CallControlOpcode::Terminate => self
+                .remove_call(index)
+                .map_err(|_| CallControlResult::InvalidCallIndex)
+                .map(|_| {
+                    self.pending_termination.set(Some(CallTermination {
+                        index,
+                        reason: TerminationReason::ClientEndedCall,
+                    }));
+                    CcsEvent::Terminated(index)
+                }),
+            CallControlOpcode::LocalHold
+                if self.opcodes.contains(CallControlOptionalOpcodes::LocalHold) =>
+            {
+                self.transition(index, CallState::local_hold)
+                    .map(|_| CcsEvent::LocallyHeld(index))
+            }
+            CallControlOpcode::LocalRetrieve
+                if self.opcodes.contains(CallControlOptionalOpcodes::LocalHold) =>
+            {
+                self.transition(index, CallState::local_retrieve)
+                    .map(|_| CcsEvent::LocallyRetrieved(index))
+            }
+            _ => Err(CallControlResult::OpcodeNotSupported),
+        };
+        (index, result)
+    }
+
+    /// Place an outgoing call to the URI in `operand`
+    fn originate(&self, operand: &[u8]) -> (u8, Result<CcsEvent, CallControlResult>) {
+        let uri = core::str::from_utf8(operand)
+            .ok()
+            .filter(|uri| uri.contains(':'))
+            .and_then(|uri| BearerText::new(uri).ok());
+        let Some(uri) = uri else {
+            return (0, Err(CallControlResult::InvalidOutgoingUri));
+        };
+        match self.add_call(CallState::Dialing, CallFlags::Outgoing, uri.clone()) {
+            Ok(index) => (index, Ok(CcsEvent::Originated { index, uri })),
+            Err(_) => (0, Err(CallControlResult::LackOfResources)),
+        }
+    }
+
+    /// Join the calls whose indexes are in `operand` into a single active call
+    fn join(&self, operand: &[u8]) -> Result<CcsEvent, CallControlResult> {
+        if !self.opcodes.contains(CallControlOptionalOpcodes::Join) {
+            return Err(CallControlResult::OpcodeNotSupported);
+        }
+        let indexes = Vec::<u8, MAX_CALLS>::from_slice(operand)
+            .map_err(|_| CallControlResult::OperationNotPossible)?;
+        if indexes.len() < 2 {
+            return Err(CallControlResult::OperationNotPossible);
+        }
+
+        let mut calls = self.calls.borrow_mut();
+        for index in &indexes {
+            let call = calls
+                .iter()
+                .find(|call| call.index == *index)
+                .ok_or(CallControlResult::InvalidCallIndex)?;
+            if call.state.join().is_none() {
+                return Err(CallControlResult::StateMismatch);
+            }
+        }
+        for call in calls
+            .iter_mut()
+            .filter(|call| indexes.contains(&call.index))
+        {
+            if let Some(state) = call.state.join() {
+                call.state = state;
+            }
+        }
+        self.calls_changed.set(true);
+        Ok(CcsEvent::Joined(indexes))
+    }
+}
+
+impl LeAudioServerService for CcsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.call_control_point.handle || handle == self.termination_reason.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else if handle == self.provider_name.handle
+            || handle == self.uci.handle
+            || handle == self.technology.handle
+            || handle == self.uri_schemes.handle
+            || handle == self.signal_strength.handle
+            || handle == self.current_calls.handle
+            || handle == self.content_control_id.handle
+            || handle == self.status_flags.handle
+            || handle == self.incoming_call_target_uri.handle
+            || handle == self.call_state.handle
+            || handle == self.optional_opcodes.handle
+            || handle == self.incoming_call.handle
+            || handle == self.call_friendly_name.handle
+        {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.call_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.provider_name.handle
+            || handle == self.uci.handle
+            || handle == self.technology.handle
+            || handle == self.uri_schemes.handle
+            || handle == self.signal_strength.handle
+            || handle == self.current_calls.handle
+            || handle == self.content_control_id.handle
+            || handle == self.status_flags.handle
+            || handle == self.incoming_call_target_uri.handle
+            || handle == self.call_state.handle
+            || handle == self.optional_opcodes.handle
+            || handle == self.termination_reason.handle
+            || handle == self.incoming_call.handle
+            || handle == self.call_friendly_name.handle
+        {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Errors returned when the application reports the progress of a call
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CcsError {
+    /// The bearer already holds [`MAX_CALLS`] calls
+    NoCallSlot,
+    /// There is no call with this index
+    InvalidCallIndex,
+}
+
+/// Operations a client performed on the calls of the bearer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CcsEvent {
+    /// An incoming call was answered, it is now active
+    Accepted(u8),
+    /// A call was hung up, it is removed from the calls
+    Terminated(u8),
+    /// A call was put on hold
+    LocallyHeld(u8),
+    /// A call put on hold was resumed
+    LocallyRetrieved(u8),
+    /// An outgoing call was placed, it is dialing until the application reports its progress
+    Originated { index: u8, uri: BearerText },
+    /// Calls were joined into a conference
+    Joined(Vec<u8, MAX_CALLS>),
+}
+
+/// State of a call
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CallState {
+    /// A remote party is calling
+    Incoming = 0x00,
+    /// An outgoing call was placed, the remote party is not alerted yet
+    Dialing = 0x01,
+    /// The remote party of an outgoing call is being alerted
+    Alerting = 0x02,
+    Active = 0x03,
+    /// Held by the server or a client
+    LocallyHeld = 0x04,
+    /// Held by the remote party
+    RemotelyHeld = 0x05,
+    LocallyAndRemotelyHeld = 0x06,
+}
+
+impl CallState {
+    /// The state after a Local Hold operation
+    fn local_hold(self) -> Option<Self> {
+        match self {
+            Self::Incoming | Self::Active => Some(Self::LocallyHeld),
+            Self::RemotelyHeld => Some(Self::LocallyAndRemotelyHeld),
+            _ => None,
+        }
+    }
+
+    /// The state after a Local Retrieve operation
+    fn local_retrieve(self) -> Option<Self> {
+        match self {
+            Self::LocallyHeld => Some(Self::Active),
+            Self::LocallyAndRemotelyHeld => Some(Self::RemotelyHeld),
+            _ => None,
+        }
+    }
+
+    /// The state after a Join operation, only established calls can be joined
+    fn join(self) -> Option<Self> {
+        match self {
+            Self::Active | Self::LocallyHeld => Some(Self::Active),
+            Self::RemotelyHeld | Self::LocallyAndRemotelyHeld => Some(Self::RemotelyHeld),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// Properties of a call
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CallFlags: u8 {
+        /// The call was placed by the server, not received
+        const Outgoing = 0x01;
+        /// The server withholds the caller information
+        const WithheldByServer = 0x02;
+        /// The network does not provide the caller information
+        const WithheldByNetwork = 0x04;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CallFlags {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "CallFlags({=u8:#04x})", self.bits())
+    }
+}
+
+bitflags! {
+    /// Status of the bearer
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatusFlags: u16 {
+        /// Incoming calls ring on the server
+        const InbandRingtone = 0x0001;
+        /// The server is in silent mode
+        const SilentMode = 0x0002;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for StatusFlags {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "StatusFlags({=u16:#06x})", self.bits())
+    }
+}
+
+impl FixedGattValue for StatusFlags {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [low, high] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(u16::from_le_bytes([*low, *high])))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+bitflags! {
+    /// The optional Call Control Point operations supported by the bearer
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CallControlOptionalOpcodes: u16 {
+        /// Local Hold and Local Retrieve
+        const LocalHold = 0x0001;
+        const Join = 0x0002;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CallControlOptionalOpcodes {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "CallControlOptionalOpcodes({=u16:#06x})", self.bits())
+    }
+}
+
+impl FixedGattValue for CallControlOptionalOpcodes {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [low, high] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(u16::from_le_bytes([*low, *high])))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Technology of the bearer
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BearerTechnology {
+    ThreeG = 0x01,
+    FourG = 0x02,
+    Lte = 0x03,
+    WiFi = 0x04,
+    FiveG = 0x05,
+    Gsm = 0x06,
+    Cdma = 0x07,
+    TwoG = 0x08,
+    Wcdma = 0x09,
+}
+
+impl FixedGattValue for BearerTechnology {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [0x01] => Ok(Self::ThreeG),
+            [0x02] => Ok(Self::FourG),
+            [0x03] => Ok(Self::Lte),
+            [0x04] => Ok(Self::WiFi),
+            [0x05] => Ok(Self::FiveG),
+            [0x06] => Ok(Self::Gsm),
+            [0x07] => Ok(Self::Cdma),
+            [0x08] => Ok(Self::TwoG),
+            [0x09] => Ok(Self::Wcdma),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Operations of the Call Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CallControlOpcode {
+    Accept = 0x00,
+    Terminate = 0x01,
+    LocalHold = 0x02,
+    LocalRetrieve = 0x03,
+    Originate = 0x04,
+    Join = 0x05,
+}
+
+impl TryFrom<u8> for CallControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x00 => Self::Accept,
+            0x01 => Self::Terminate,
+            0x02 => Self::LocalHold,
+            0x03 => Self::LocalRetrieve,
+            0x04 => Self::Originate,
+            0x05 => Self::Join,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+/// Outcome of a Call Control Point operation
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CallControlResult {
+    Success = 0x00,
+    OpcodeNotSupported = 0x01,
+    OperationNotPossible = 0x02,
+    InvalidCallIndex = 0x03,
+    StateMismatch = 0x04,
+    LackOfResources = 0x05,
+    InvalidOutgoingUri = 0x06,
+}
+
+/// Notified on the Call Control Point after each write
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct CallControlPointResponse {
+    /// The opcode written by the client
+    pub opcode: u8,
+    /// The call the operation applied to, 0 when it applied to none
+    pub call_index: u8,
+    /// A [`CallControlResult`]
+    pub result: u8,
+}
+
+impl FixedGattValue for CallControlPointResponse {
+    const SIZE: usize = 3;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [opcode, call_index, result] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            opcode: *opcode,
+            call_index: *call_index,
+            result: *result,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Why a call ended
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TerminationReason {
+    /// The URI of the outgoing call is not valid
+    ImproperUri = 0x00,
+    CallFailed = 0x01,
+    RemoteEndedCall = 0x02,
+    ServerEndedCall = 0x03,
+    LineBusy = 0x04,
+    NetworkCongestion = 0x05,
+    ClientEndedCall = 0x06,
+    NoService = 0x07,
+    NoAnswer = 0x08,
+    #[default]
+    Unspecified = 0x09,
+}
+
+/// Notified on the Termination Reason characteristic when a call ends
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct CallTermination {
+    pub index: u8,
+    pub reason: TerminationReason,
+}
+
+impl FixedGattValue for CallTermination {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let reason = match data {
+            [_, 0x00] => TerminationReason::ImproperUri,
+            [_, 0x01] => TerminationReason::CallFailed,
+            [_, 0x02] => TerminationReason::RemoteEndedCall,
+            [_, 0x03] => TerminationReason::ServerEndedCall,
+            [_, 0x04] => TerminationReason::LineBusy,
+            [_, 0x05] => TerminationReason::NetworkCongestion,
+            [_, 0x06] => TerminationReason::ClientEndedCall,
+            [_, 0x07] => TerminationReason::NoService,
+            [_, 0x08] => TerminationReason::NoAnswer,
+            [_, 0x09] => TerminationReason::Unspecified,
+            _ => return Err(FromGattError::InvalidLength),
+        };
+        Ok(Self {
+            index: data[0],
+            reason,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// UTF-8 text of the bearer, like its provider name or the URI of a call
+pub type BearerText = Utf8Text<MAX_BEARER_TEXT_SIZE>;
+
+/// UTF-8 text of a call, like the URI of an incoming call
+///
+/// Encoded as the call index, then the text.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CallText(Vec<u8, { 1 + MAX_BEARER_TEXT_SIZE }>);
+
+impl CallText {
+    pub fn new(index: u8, text: &BearerText) -> Self {
+        let mut value = Vec::new();
+        let _ = value.push(index);
+        // Both fit, the text is at most MAX_BEARER_TEXT_SIZE long
+        let _ = value.extend_from_slice(text.as_bytes());
+        Self(value)
+    }
+
+    /// The call the text belongs to
+    pub fn index(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+
+    /// The text, `None` if it is not valid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(self.0.get(1..)?).ok()
+    }
+}
+
+impl AsGatt for CallText {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = 1 + MAX_BEARER_TEXT_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for CallText {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+/// Value of the Call State characteristic
+///
+/// Encoded as the index, state and flags of every call.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CallStateList(Vec<u8, { MAX_CALLS * CALL_STATE_SIZE }>);
+
+impl CallStateList {
+    fn from_calls(calls: &[Call]) -> Self {
+        let mut value = Vec::new();
+        for call in calls {
+            let _ = value.extend_from_slice(&[call.index, call.state as u8, call.flags.bits()]);
+        }
+        Self(value)
+    }
+}
+
+impl AsGatt for CallStateList {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = MAX_CALLS * CALL_STATE_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for CallStateList {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        if data.len() % CALL_STATE_SIZE != 0 {
+            return Err(FromGattError::InvalidLength);
+        }
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+/// Value of the Bearer List Current Calls characteristic
+///
+/// Encoded as the item length, index, state, flags and URI of every call.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CurrentCallList(Vec<u8, { MAX_CALLS * CURRENT_CALL_SIZE }>);
+
+impl CurrentCallList {
+    fn from_calls(calls: &[Call]) -> Self {
+        let mut value = Vec::new();
+        for call in calls {
+            let item_length = 3 + call.uri.as_bytes().len() as u8;
+            let _ = value.extend_from_slice(&[
+                item_length,
+                call.index,
+                call.state as u8,
+                call.flags.bits(),
+            ]);
+            let _ = value.extend_from_slice(call.uri.as_bytes());
+        }
+        Self(value)
+    }
+}
+
+impl AsGatt for CurrentCallList {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = MAX_CALLS * CURRENT_CALL_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for CurrentCallList {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trouble_host::attribute::AttributeTable;
+
+    fn bearer() -> Bearer {
+        Bearer {
+            provider_name: BearerText::new("Operator").unwrap(),
+            uci: BearerText::new("un000").unwrap(),
+            technology: BearerTechnology::Lte,
+            uri_schemes: BearerText::new("tel").unwrap(),
+        }
+    }
+
+    fn uri() -> BearerText {
+        BearerText::new("tel:+15551234").unwrap()
+    }
+
+    /// Write `opcode` on the call `index`, returning the notified result
+    fn write(ccs: &CcsServer, opcode: CallControlOpcode, index: u8) -> CallControlPointResponse {
+        assert!(matches!(
+            ccs.handle_write(ccs.call_control_point.handle, &[opcode as u8, index]),
+            Some(Ok(()))
+        ));
+        ccs.take_control_point_response().unwrap()
+    }
+
+    fn response(
+        opcode: CallControlOpcode,
+        call_index: u8,
+        result: CallControlResult,
+    ) -> CallControlPointResponse {
+        CallControlPointResponse {
+            opcode: opcode as u8,
+            call_index,
+            result: result as u8,
+        }
+    }
+
+    #[test]
+    fn incoming_call_is_accepted_held_and_terminated() {
+        let mut storage = CcsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CCS_ATTRIBUTES> = AttributeTable::new();
+        let ccs = CcsServer::new(
+            &mut table,
+            bearer(),
+            CallControlOptionalOpcodes::LocalHold,
+            1,
+            &mut storage,
+        );
+        let index = ccs.incoming_call(uri(), None, None).unwrap();
+
+        for (opcode, state) in [
+            (CallControlOpcode::Accept, Some(CallState::Active)),
+            (CallControlOpcode::LocalHold, Some(CallState::LocallyHeld)),
+            (CallControlOpcode::LocalRetrieve, Some(CallState::Active)),
+            (CallControlOpcode::Terminate, None),
+        ] {
+            assert_eq!(
+                write(&ccs, opcode, index),
+                response(opcode, index, CallControlResult::Success)
+            );
+            assert_eq!(ccs.call_state(index), state);
+        }
+        assert_eq!(
+            ccs.take_termination(),
+            Some(CallTermination {
+                index,
+                reason: TerminationReason::ClientEndedCall,
+            })
+        );
+    }
+
+    #[test]
+    fn operations_in_the_wrong_state_are_rejected() {
+        let mut storage = CcsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CCS_ATTRIBUTES> = AttributeTable::new();
+        let ccs = CcsServer::new(
+            &mut table,
+            bearer(),
+            CallControlOptionalOpcodes::LocalHold,
+            1,
+            &mut storage,
+        );
+        let index = ccs.incoming_call(uri(), None, None).unwrap();
+
+        assert_eq!(
+            write(&ccs, CallControlOpcode::LocalRetrieve, index),
+            response(
+                CallControlOpcode::LocalRetrieve,
+                index,
+                CallControlResult::StateMismatch
+            )
+        );
+        write(&ccs, CallControlOpcode::Accept, index);
+        assert_eq!(
+            write(&ccs, CallControlOpcode::Accept, index),
+            response(
+                CallControlOpcode::Accept,
+                index,
+                CallControlResult::StateMismatch
+            )
+        );
+        assert_eq!(ccs.call_state(index), Some(CallState::Active));
+    }
+
+    #[test]
+    fn invalid_call_index_is_rejected() {
+        let mut storage = CcsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CCS_ATTRIBUTES> = AttributeTable::new();
+        let ccs = CcsServer::new(
+            &mut table,
+            bearer(),
+            CallControlOptionalOpcodes::LocalHold,
+            1,
+            &mut storage,
+        );
+        let index = ccs.incoming_call(uri(), None, None).unwrap();
+        let unknown = index + 1;
+
+        for opcode in [
+            CallControlOpcode::Accept,
+            CallControlOpcode::Terminate,
+            CallControlOpcode::LocalHold,
+        ] {
+            assert_eq!(
+                write(&ccs, opcode, unknown),
+                response(opcode, unknown, CallControlResult::InvalidCallIndex)
+            );
+        }
+        assert_eq!(ccs.call_state(index), Some(CallState::Incoming));
+        assert_eq!(ccs.take_termination(), None);
+    }
+}
@@ -0,0 +1,491 @@
+//! Coordinated Set Identification Service
+//!
+//! This service lets a client discover that devices, like the left and right
+//! earbuds of a headset, form a set, and lock the set while configuring it.
+
+use bt_hci::uuid::{characteristic, service};
+use core::{cell::Cell, slice};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    signal::Signal,
+};
+use embassy_time::{Duration, Instant, Timer};
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::info;
+
+use crate::{LeAudioError, LeAudioServerService};
+
+/// Attributes added to the attribute table by the Csis service
+pub const CSIS_ATTRIBUTES: usize = 10;
+
+/// Time after which the lock of a client is released if it did not release it
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors of the Csis service, sent to clients as the application errors of the specification
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsisError {
+    /// The lock is held by another client
+    LockDenied,
+    /// The lock is not held by the client releasing it
+    LockReleaseNotAllowed,
+    /// The value written to the lock is not a [`MemberLock`]
+    InvalidLockValue,
+    /// The SIRK is only shared out of band
+    OobSirkOnly,
+    /// The client writing the lock already holds it
+    LockAlreadyGranted,
+}
+
+impl From<CsisError> for AttErrorCode {
+    fn from(err: CsisError) -> Self {
+        match err {
+            CsisError::LockDenied => AttErrorCode::APPLICATION_ERROR_0x80,
+            CsisError::LockReleaseNotAllowed => AttErrorCode::APPLICATION_ERROR_0x81,
+            CsisError::InvalidLockValue => AttErrorCode::APPLICATION_ERROR_0x82,
+            CsisError::OobSirkOnly => AttErrorCode::APPLICATION_ERROR_0x83,
+            CsisError::LockAlreadyGranted => AttErrorCode::APPLICATION_ERROR_0x84,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of a [`CsisServer`]
+pub struct CsisStorage {
+    sirk: [u8; Sirk::SIZE],
+    set_size: [u8; 1],
+    lock: [u8; MemberLock::SIZE],
+    rank: [u8; 1],
+}
+
+impl CsisStorage {
+    pub const fn new() -> Self {
+        Self {
+            sirk: [0; Sirk::SIZE],
+            set_size: [0; 1],
+            lock: [0; MemberLock::SIZE],
+            rank: [0; 1],
+        }
+    }
+}
+
+impl Default for CsisStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service identifying the device as a member of a coordinated set
+pub struct CsisServer {
+    handle: u16,
+    sirk: Characteristic<Sirk>,
+    set_size: Characteristic<u8>,
+    lock: Characteristic<MemberLock>,
+    rank: Characteristic<u8>,
+    /// Connection holding the lock
+    lock_owner: Cell<Option<u16>>,
+    locked_at: Cell<Option<Instant>>,
+    lock_changed: Cell<bool>,
+    /// Wakes [`Self::lock_timeout`] when a client takes the lock
+    lock_taken: Signal<NoopRawMutex, ()>,
+    /// Connection of the event being handled
+    active_connection: Cell<Option<u16>>,
+}
+
+impl CsisServer {
+    /// Create a new Csis Gatt Service
+    ///
+    /// `sirk` is shared by every member of the set, the application generates
+    /// it once and stores it. `rank` starts from 1 and is unique in the set.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        sirk: Sirk,
+        set_size: u8,
+        rank: u8,
+        storage: &'a mut CsisStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::COORDINATED_SET_IDENTIFICATION));
+
+        let sirk_char = service
+            .add_characteristic(
+                characteristic::SET_IDENTITY_RESOLVING_KEY,
+                &[CharacteristicProp::Read],
+                sirk,
+                &mut storage.sirk,
+            )
+            .build();
+
+        let set_size_char = service
+            .add_characteristic(
+                characteristic::COORDINATED_SET_SIZE,
+                &[CharacteristicProp::Read],
+                set_size,
+                &mut storage.set_size,
+            )
+            .build();
+
+        let lock_char = service
+            .add_characteristic(
+                characteristic::SET_MEMBER_LOCK,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::Notify,
+                ],
+                MemberLock::Unlocked,
+                &mut storage.lock,
+            )
+            .build();
+
+        let rank_char = service
+            .add_characteristic(
+                characteristic::SET_MEMBER_RANK,
+                &[CharacteristicProp::Read],
+                rank,
+                &mut storage.rank,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            sirk: sirk_char,
+            set_size: set_size_char,
+            lock: lock_char,
+            rank: rank_char,
+            lock_owner: Cell::new(None),
+            locked_at: Cell::new(None),
+            lock_changed: Cell::new(false),
+            lock_taken: Signal::new(),
+            active_connection: Cell::new(None),
+        }
+    }
+
+    /// The handle of the service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Set Identity Resolving Key characteristic
+    pub fn sirk_characteristic(&self) -> &Characteristic<Sirk> {
+        &self.sirk
+    }
+
+    /// The Coordinated Set Size characteristic
+    pub fn set_size_characteristic(&self) -> &Characteristic<u8> {
+        &self.set_size
+    }
+
+    /// The Set Member Lock characteristic
+    pub fn lock_characteristic(&self) -> &Characteristic<MemberLock> {
+        &self.lock
+    }
+
+    /// The Set Member Rank characteristic
+    pub fn rank_characteristic(&self) -> &Characteristic<u8> {
+        &self.rank
+    }
+
+    /// Select the client the next events are handled for
+    ///
+    /// [`crate::Server::process`] calls this before handling each event, the
+    /// lock is granted to this connection.
+    pub fn set_active_connection(&self, conn_handle: u16) {
+        self.active_connection.set(Some(conn_handle));
+    }
+
+    /// The connection holding the lock of the set, if any
+    pub fn lock_owner(&self) -> Option<u16> {
+        self.lock_owner.get()
+    }
+
+    /// The lock to notify to clients, if it changed since the last call
+    pub fn take_lock_change(&self) -> Option<MemberLock> {
+        self.lock_changed.replace(false).then(|| self.lock_state())
+    }
+
+    /// Release the lock held by a client whose connection dropped
+    pub fn handle_disconnect(&self, conn_handle: u16) {
+        if self.lock_owner.get() == Some(conn_handle) {
+            self.release_lock();
+        }
+    }
+
+    /// Wait until the lock of a client times out, then release it
+    ///
+    /// The lock is released [`LOCK_TIMEOUT`] after a client takes it. The
+    /// future can be dropped and created again, the timeout is kept.
+    pub async fn lock_timeout(&self) {
+        loop {
+            match self.locked_at.get() {
+                Some(locked_at) => {
+                    Timer::at(locked_at + LOCK_TIMEOUT).await;
+                    if self.locked_at.get() == Some(locked_at) {
+                        #[cfg(feature = "defmt")]
+                        info!("[csis] lock timed out");
+                        self.release_lock();
+                        return;
+                    }
+                }
+                None => self.lock_taken.wait().await,
+            }
+        }
+    }
+
+    fn lock_state(&self) -> MemberLock {
+        if self.lock_owner.get().is_some() {
+            MemberLock::Locked
+        } else {
+            MemberLock::Unlocked
+        }
+    }
+
+    fn release_lock(&self) {
+        self.lock_owner.set(None);
+        self.locked_at.set(None);
+        self.lock_changed.set(true);
+    }
+
+    /// Take or release the lock for the active connection
+    ///
+    /// Only one client holds the lock, the others are denied until it is
+    /// released or times out. Writes without an active connection can not be
+    /// attributed to a client and are rejected.
+    fn write_lock(&self, data: &[u8]) -> Result<(), CsisError> {
+        let lock = <MemberLock as FixedGattValue>::from_gatt(data)
+            .map_err(|_| CsisError::InvalidLockValue)?;
+        let Some(client) = self.active_connection.get() else {
+            return Err(match lock {
+                MemberLock::Locked => CsisError::LockDenied,
+                MemberLock::Unlocked => CsisError::LockReleaseNotAllowed,
+            });
+        };
+        match (lock, self.lock_owner.get()) {
+            (MemberLock::Locked, None) => {
+                #[cfg(feature = "defmt")]
+                info!("[csis] lock granted to {}", client);
+                self.lock_owner.set(Some(client));
+                self.locked_at.set(Some(Instant::now()));
+                self.lock_changed.set(true);
+                self.lock_taken.signal(());
+                Ok(())
+            }
+            (MemberLock::Locked, Some(owner)) if owner == client => {
+                Err(CsisError::LockAlreadyGranted)
+            }
+            (MemberLock::Locked, Some(_)) => Err(CsisError::LockDenied),
+            (MemberLock::Unlocked, Some(owner)) if owner == client => {
+                self.release_lock();
+                Ok(())
+            }
+            (MemberLock::Unlocked, _) => Err(CsisError::LockReleaseNotAllowed),
+        }
+    }
+}
+
+impl LeAudioServerService for CsisServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.sirk.handle
+            || handle == self.set_size.handle
+            || handle == self.lock.handle
+            || handle == self.rank.handle
+        {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.lock.handle {
+            Some(self.write_lock(data).map_err(Into::into))
+        } else if handle == self.sirk.handle
+            || handle == self.set_size.handle
+            || handle == self.rank.handle
+        {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// How the SIRK is exposed to clients
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SirkType {
+    /// The key is encrypted with the link key of the client
+    Encrypted = 0x00,
+    /// The key is sent as is
+    Plain = 0x01,
+}
+
+/// Set Identity Resolving Key, identifying the members of a set
+///
+/// An encrypted SIRK holds the key already encrypted by the application with
+/// the `sef` function of the specification, the host does not expose the link
+/// key to encrypt it here.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Sirk {
+    pub kind: SirkType,
+    pub key: [u8; 16],
+}
+
+impl Sirk {
+    pub const fn plain(key: [u8; 16]) -> Self {
+        Self {
+            kind: SirkType::Plain,
+            key,
+        }
+    }
+
+    pub const fn encrypted(key: [u8; 16]) -> Self {
+        Self {
+            kind: SirkType::Encrypted,
+            key,
+        }
+    }
+}
+
+impl FixedGattValue for Sirk {
+    const SIZE: usize = 17;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [kind, key @ ..] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        let kind = match kind {
+            0x00 => SirkType::Encrypted,
+            0x01 => SirkType::Plain,
+            _ => return Err(FromGattError::InvalidLength),
+        };
+        Ok(Self {
+            kind,
+            key: key.try_into().map_err(|_| FromGattError::InvalidLength)?,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// State of the lock of a set member
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MemberLock {
+    #[default]
+    Unlocked = 0x01,
+    Locked = 0x02,
+}
+
+impl TryFrom<u8> for MemberLock {
+    type Error = u8;
+
+    fn try_from(lock: u8) -> Result<Self, Self::Error> {
+        match lock {
+            0x01 => Ok(Self::Unlocked),
+            0x02 => Ok(Self::Locked),
+            _ => Err(lock),
+        }
+    }
+}
+
+impl FixedGattValue for MemberLock {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [lock] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*lock).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::{pin::pin, task::Poll};
+    use embassy_futures::{block_on, poll_once};
+    use embassy_time::MockDriver;
+    use trouble_host::attribute::AttributeTable;
+
+    fn write(csis: &CsisServer, conn: u16, lock: MemberLock) -> Result<(), CsisError> {
+        csis.set_active_connection(conn);
+        csis.write_lock(&[lock as u8])
+    }
+
+    #[test]
+    fn lock_is_denied_to_a_second_client() {
+        let mut storage = CsisStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CSIS_ATTRIBUTES> = AttributeTable::new();
+        let csis = CsisServer::new(&mut table, Sirk::plain([0; 16]), 2, 1, &mut storage);
+
+        assert_eq!(write(&csis, 1, MemberLock::Locked), Ok(()));
+        assert_eq!(
+            write(&csis, 1, MemberLock::Locked),
+            Err(CsisError::LockAlreadyGranted)
+        );
+        assert_eq!(
+            write(&csis, 2, MemberLock::Locked),
+            Err(CsisError::LockDenied)
+        );
+        assert_eq!(csis.lock_owner(), Some(1));
+        assert_eq!(csis.take_lock_change(), Some(MemberLock::Locked));
+    }
+
+    #[test]
+    fn only_the_owner_releases_the_lock() {
+        let mut storage = CsisStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CSIS_ATTRIBUTES> = AttributeTable::new();
+        let csis = CsisServer::new(&mut table, Sirk::plain([0; 16]), 2, 1, &mut storage);
+
+        write(&csis, 1, MemberLock::Locked).unwrap();
+        assert_eq!(
+            write(&csis, 2, MemberLock::Unlocked),
+            Err(CsisError::LockReleaseNotAllowed)
+        );
+        assert_eq!(csis.lock_owner(), Some(1));
+
+        assert_eq!(write(&csis, 1, MemberLock::Unlocked), Ok(()));
+        assert_eq!(csis.lock_owner(), None);
+    }
+
+    #[test]
+    fn lock_without_a_connection_is_rejected() {
+        let mut storage = CsisStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CSIS_ATTRIBUTES> = AttributeTable::new();
+        let csis = CsisServer::new(&mut table, Sirk::plain([0; 16]), 2, 1, &mut storage);
+
+        assert_eq!(
+            csis.write_lock(&[MemberLock::Locked as u8]),
+            Err(CsisError::LockDenied)
+        );
+        assert_eq!(csis.lock_owner(), None);
+        assert_eq!(csis.take_lock_change(), None);
+    }
+
+    #[test]
+    fn lock_is_released_on_timeout() {
+        let mut storage = CsisStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, CSIS_ATTRIBUTES> = AttributeTable::new();
+        let csis = CsisServer::new(&mut table, Sirk::plain([0; 16]), 2, 1, &mut storage);
+
+        write(&csis, 1, MemberLock::Locked).unwrap();
+        let mut timeout = pin!(csis.lock_timeout());
+        assert_eq!(poll_once(timeout.as_mut()), Poll::Pending);
+
+        MockDriver::get().advance(LOCK_TIMEOUT);
+        block_on(timeout);
+        assert_eq!(csis.lock_owner(), None);
+        assert_eq!(write(&csis, 2, MemberLock::Locked), Ok(()));
+    }
+}
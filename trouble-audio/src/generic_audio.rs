@@ -2,9 +2,11 @@
 //!
 use bitflags::bitflags;
 
-use core::{mem::transmute, slice};
+use core::slice;
 use trouble_host::{prelude::*, types::gatt_traits::*};
 
+use crate::{pacs::PACRecord, CodecId};
+
 mod metadata;
 pub use metadata::*;
 
@@ -14,8 +16,29 @@ pub use capabilities::*;
 mod configuration;
 pub use configuration::*;
 
+mod ltv;
+pub use ltv::*;
+
+/// Errors decoding the LTV structures of generic audio
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenericAudioError {
+    /// The data ends before the length announced by the structure
+    Truncated,
+    /// The value length does not match the type of the structure
+    InvalidLength { type_id: u8, len: u8 },
+    /// The type of the structure is not known
+    UnknownType(u8),
+    /// More structures than can be stored
+    TooMany,
+    /// The buffer can not hold the encoded structure
+    BufferTooSmall,
+    /// The value is out of the range of the type of the structure
+    InvalidValue { type_id: u8 },
+}
+
 bitflags! {
-    #[derive(Default, Debug, Clone, Copy)]
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
     pub struct AudioLocation: u32 {
         const Mono = 0x00000000; // Mono Audio (no specified Audio Location)
         const FrontLeft = 0x00000001;
@@ -50,25 +73,36 @@ bitflags! {
 }
 
 impl FixedGattValue for AudioLocation {
-    const SIZE: usize = size_of::<Self>();
+    /// Audio Location is a 4 octet bitfield
+    const SIZE: usize = 4;
 
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        #[cfg(feature = "defmt")]
-        defmt::info!("Gatt len: {}, data: {:?}", data.len(), data);
-        unsafe {
-            Ok(transmute::<u32, AudioLocation>(
-                <u32 as trouble_host::prelude::FixedGattValue>::from_gatt(data)?,
-            ))
-        }
+        let [a, b, c, d] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(u32::from_le_bytes([*a, *b, *c, *d])))
     }
 
     fn as_gatt(&self) -> &[u8] {
+        // Bitflags are a transparent u32, little endian targets lay it out as the
+        // characteristic value
         unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
     }
 }
 
+impl AudioLocation {
+    /// Audio channels of the location, one per location bit and one for [`Self::Mono`]
+    pub fn channel_count(&self) -> u8 {
+        match self.bits().count_ones() as u8 {
+            0 => 1,
+            count => count,
+        }
+    }
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
 pub enum AudioInputType {
     #[default]
     Unspecified = 0x00, // Unspecified Input
@@ -82,27 +116,65 @@ pub enum AudioInputType {
     Undefined,
 }
 
-/// A bitfield of values that, when set to 0b1 for a bit,
-/// describes audio data as being intended for the use case represented by that bit.
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug, Clone)]
-#[repr(u16)]
-pub enum ContextType {
-    #[default]
-    Prohibited = 0x0000,
-    Unspecified = 0x0001,
-    Conversational = 0x0002,
-    Media = 0x0004,
-    Game = 0x0008,
-    Instructional = 0x0010,
-    VoiceAssistants = 0x0020,
-    Live = 0x0040,
-    SoundEffects = 0x0080,
-    Notifications = 0x0100,
-    Ringtone = 0x0200,
-    Alerts = 0x0400,
-    Alarm = 0x0800,
-    Undefined,
+impl TryFrom<u8> for AudioInputType {
+    type Error = u8;
+
+    fn try_from(input_type: u8) -> Result<Self, Self::Error> {
+        Ok(match input_type {
+            0x00 => Self::Unspecified,
+            0x01 => Self::Bluetooth,
+            0x02 => Self::Microphone,
+            0x03 => Self::Analog,
+            0x04 => Self::Digital,
+            0x05 => Self::Radio,
+            0x06 => Self::Streaming,
+            0x07 => Self::Ambient,
+            _ => return Err(input_type),
+        })
+    }
+}
+
+impl FixedGattValue for AudioInputType {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [input_type] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*input_type).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+bitflags! {
+    /// A bitfield of values that, when set to 0b1 for a bit,
+    /// describes audio data as being intended for the use case represented by that bit.
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ContextType: u16 {
+        const Prohibited = 0x0000;
+        const Unspecified = 0x0001;
+        const Conversational = 0x0002;
+        const Media = 0x0004;
+        const Game = 0x0008;
+        const Instructional = 0x0010;
+        const VoiceAssistants = 0x0020;
+        const Live = 0x0040;
+        const SoundEffects = 0x0080;
+        const Notifications = 0x0100;
+        const Ringtone = 0x0200;
+        const Alerts = 0x0400;
+        const Alarm = 0x0800;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ContextType {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "ContextType({=u16:#06x})", self.bits())
+    }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -113,20 +185,252 @@ pub struct OctetsPerCodecFrame {
 }
 
 impl OctetsPerCodecFrame {
-    pub fn new(min_octets: u16, max_octets: u16) -> Self {
-        Self {
+    /// Create a range of octets per codec frame, failing if it is empty or allows 0 octets
+    ///
+    /// A codec frame always holds audio, so on top of [`Self::check_valid_range`]
+    /// the range must start at 1 octet.
+    pub fn new(min_octets: u16, max_octets: u16) -> Result<Self, CapabilitiesError> {
+        if min_octets == 0 || !Self::check_valid_range(min_octets, max_octets) {
+            return Err(CapabilitiesError::InvalidOctetsRange);
+        }
+        Ok(Self {
             min_octets,
             max_octets,
+        })
+    }
+
+    /// Create a range without checking it, for constants
+    ///
+    /// [`PACRecord::validate`](crate::pacs::PACRecord::validate) rejects invalid ranges.
+    pub const fn from_min_max(min: u16, max: u16) -> Self {
+        Self {
+            min_octets: min,
+            max_octets: max,
+        }
+    }
+
+    pub fn min_octets(&self) -> u16 {
+        self.min_octets
+    }
+
+    pub fn max_octets(&self) -> u16 {
+        self.max_octets
+    }
+
+    /// Whether `min` to `max` is a range, `min` not being above `max`
+    ///
+    /// Any pair of u16 is encodable, [`Self::new`] also rejects 0 octets.
+    pub const fn check_valid_range(min: u16, max: u16) -> bool {
+        min <= max
+    }
+
+    /// Write the Supported_Octets_per_Codec_Frame value, min then max, both little endian
+    pub(crate) fn encode(&self, buf: &mut [u8; 4]) {
+        buf[..2].copy_from_slice(&self.min_octets.to_le_bytes());
+        buf[2..].copy_from_slice(&self.max_octets.to_le_bytes());
+    }
+
+    /// Read a Supported_Octets_per_Codec_Frame value written by [`Self::encode`]
+    pub(crate) fn decode(data: &[u8; 4]) -> Self {
+        let [min_low, min_high, max_low, max_high] = *data;
+        Self::from_min_max(
+            u16::from_le_bytes([min_low, min_high]),
+            u16::from_le_bytes([max_low, max_high]),
+        )
+    }
+}
+
+/// Whether the codec configurations of a client and a server PAC record overlap
+///
+/// The records must share a codec id, a sampling frequency and a frame
+/// duration, and their ranges of octets per codec frame must overlap. BAP
+/// negotiation only configures a codec with compatible records.
+pub fn compatible_codec_configs(client: &PACRecord, server: &PACRecord) -> bool {
+    let client_caps = RecordCapabilities::of(client);
+    let server_caps = RecordCapabilities::of(server);
+    let supported_durations = FrameDurationMask::Supports7_5MS | FrameDurationMask::Supports10MS;
+    client
+        .codec_id
+        .iter()
+        .any(|id| server.codec_id.contains(id))
+        && client_caps.frequencies.intersects(server_caps.frequencies)
+        && (client_caps.durations & server_caps.durations).intersects(supported_durations)
+        && client_caps.max_octets >= server_caps.min_octets
+        && client_caps.min_octets <= server_caps.max_octets
+}
+
+/// Whether a server PAC record supports the codec configuration a client selected
+///
+/// Each configuration must be within the capabilities of the record, a stream
+/// without an audio channel allocation is mono. Codec frame blocks per SDU are
+/// only checked against the max codec frames per SDU of the record, if any.
+pub fn supports_codec_config(
+    record: &PACRecord,
+    codec_id: &CodecId,
+    config: &[CodecSpecificConfiguration],
+) -> bool {
+    if !record.codec_id.contains(codec_id) {
+        return false;
+    }
+    let caps = RecordCapabilities::of(record);
+    let max_frames = record
+        .codec_specific_capabilities
+        .iter()
+        .find_map(|capability| match capability {
+            CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(frames) => {
+                Some(frames.frames())
+            }
+            _ => None,
+        });
+    let channels = config
+        .iter()
+        .find_map(|configuration| match configuration {
+            CodecSpecificConfiguration::AudioChannelAllocation(location) => {
+                Some(location.channel_count())
+            }
+            _ => None,
+        })
+        .unwrap_or(1);
+
+    SupportedAudioChannelCounts::from_bitmask(caps.channel_counts).contains(channels)
+        && config.iter().all(|configuration| match configuration {
+            CodecSpecificConfiguration::SamplingFrequency(frequency) => caps
+                .frequencies
+                .contains(SamplingFrequencyMask::from_sampling_frequency(*frequency)),
+            CodecSpecificConfiguration::FrameDuration(duration) => {
+                SupportedFrameDurations::from_bitmask(caps.durations.bits()).supports(duration)
+            }
+            CodecSpecificConfiguration::AudioChannelAllocation(_) => true,
+            CodecSpecificConfiguration::OctetsPerCodecFrame(octets) => {
+                (caps.min_octets..=caps.max_octets).contains(&octets.min_octets())
+            }
+            CodecSpecificConfiguration::CodecFrameBlocksPerSdu(blocks) => max_frames
+                .is_none_or(|max| u16::from(*blocks) * u16::from(channels) <= u16::from(max)),
+        })
+}
+
+/// The capabilities of a record relevant to the QoS
+///
+/// A record without supported sampling frequencies or frame durations
+/// supports none, one without octets per codec frame supports any.
+pub(crate) struct RecordCapabilities {
+    pub(crate) frequencies: SamplingFrequencyMask,
+    pub(crate) durations: FrameDurationMask,
+    /// Bit n means n + 1 channels are supported
+    pub(crate) channel_counts: u8,
+    pub(crate) min_octets: u16,
+    pub(crate) max_octets: u16,
+}
+
+impl RecordCapabilities {
+    pub(crate) fn of(record: &PACRecord) -> Self {
+        let mut caps = Self {
+            frequencies: SamplingFrequencyMask::empty(),
+            durations: FrameDurationMask::empty(),
+            // A record without audio channel counts supports a single channel
+            channel_counts: 0b1,
+            min_octets: 0,
+            max_octets: u16::MAX,
+        };
+        for capability in record.codec_specific_capabilities.iter() {
+            match capability {
+                CodecSpecificCapabilities::SupportedSamplingFrequencies(frequencies) => {
+                    caps.frequencies = frequencies.mask()
+                }
+                CodecSpecificCapabilities::SupportedFrameDurations(durations) => {
+                    caps.durations = durations.mask()
+                }
+                CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(octets) => {
+                    caps.min_octets = octets.min_octets();
+                    caps.max_octets = octets.max_octets();
+                }
+                CodecSpecificCapabilities::SupportedAudioChannelCounts(counts) => {
+                    caps.channel_counts = counts.bitmask()
+                }
+                CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(_) => {}
+            }
         }
+        caps
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn encode(&self) -> u32 {
-        ((self.max_octets as u32) << 16) | self.min_octets as u32
+    #[test]
+    fn octets_per_codec_frame_round_trips_at_the_boundaries() {
+        for (min, max) in [(0, 65535), (1, 1), (65535, 65535), (40, 155)] {
+            let mut buf = [0; 4];
+            OctetsPerCodecFrame::from_min_max(min, max).encode(&mut buf);
+            let [min_low, min_high] = min.to_le_bytes();
+            let [max_low, max_high] = max.to_le_bytes();
+            assert_eq!(buf, [min_low, min_high, max_low, max_high]);
+            let decoded = OctetsPerCodecFrame::decode(&buf);
+            assert_eq!(decoded.min_octets(), min);
+            assert_eq!(decoded.max_octets(), max);
+        }
+    }
+
+    #[test]
+    fn octets_per_codec_frame_ranges_must_be_ordered() {
+        assert!(OctetsPerCodecFrame::check_valid_range(0, 65535));
+        assert!(OctetsPerCodecFrame::check_valid_range(65535, 65535));
+        assert!(!OctetsPerCodecFrame::check_valid_range(65535, 0));
+        assert!(!OctetsPerCodecFrame::check_valid_range(120, 100));
+    }
+
+    #[test]
+    fn octets_per_codec_frame_must_not_allow_0_octets() {
+        assert!(OctetsPerCodecFrame::new(1, 65535).is_ok());
+        assert_eq!(
+            OctetsPerCodecFrame::new(0, 65535).unwrap_err(),
+            CapabilitiesError::InvalidOctetsRange
+        );
+        assert_eq!(
+            OctetsPerCodecFrame::new(120, 100).unwrap_err(),
+            CapabilitiesError::InvalidOctetsRange
+        );
+    }
+
+    #[test]
+    fn context_type_bits_match_assigned_numbers() {
+        // Context Type of the Bluetooth Assigned Numbers, Table 3.7
+        let bits = [
+            (ContextType::Prohibited, 0x0000),
+            (ContextType::Unspecified, 0x0001),
+            (ContextType::Conversational, 0x0002),
+            (ContextType::Media, 0x0004),
+            (ContextType::Game, 0x0008),
+            (ContextType::Instructional, 0x0010),
+            (ContextType::VoiceAssistants, 0x0020),
+            (ContextType::Live, 0x0040),
+            (ContextType::SoundEffects, 0x0080),
+            (ContextType::Notifications, 0x0100),
+            (ContextType::Ringtone, 0x0200),
+            (ContextType::Alerts, 0x0400),
+            (ContextType::Alarm, 0x0800),
+        ];
+        for (context, bit) in bits {
+            assert_eq!(context.bits(), bit);
+        }
+        assert_eq!(ContextType::all().bits(), 0x0FFF);
     }
 
-    fn decode(encoded: u32) -> Self {
-        let min_octets = (encoded & 0xFFFF) as u16;
-        let max_octets = (encoded >> 16) as u16;
-        Self::new(min_octets, max_octets)
+    #[test]
+    fn audio_locations_count_their_channels() {
+        assert_eq!(AudioLocation::Mono.channel_count(), 1);
+        assert_eq!(AudioLocation::FrontLeft.channel_count(), 1);
+        assert_eq!(
+            (AudioLocation::FrontLeft | AudioLocation::FrontRight).channel_count(),
+            2
+        );
+        let surround_5_1 = AudioLocation::FrontLeft
+            | AudioLocation::FrontRight
+            | AudioLocation::FrontCenter
+            | AudioLocation::LowFrequencyEffects1
+            | AudioLocation::LeftSurround
+            | AudioLocation::RightSurround;
+        assert_eq!(surround_5_1.channel_count(), 6);
     }
 }
@@ -1,4 +1,9 @@
-use super::{OctetsPerCodecFrame, SamplingFrequency};
+use bitflags::bitflags;
+use heapless::Vec;
+
+use super::{
+    FrameDuration, GenericAudioError, LtvIterator, OctetsPerCodecFrame, SamplingFrequency,
+};
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
@@ -8,59 +13,351 @@ pub enum CodecSpecificCapabilities {
     SupportedFrameDurations(SupportedFrameDurations) = 2,
     SupportedAudioChannelCounts(SupportedAudioChannelCounts) = 3,
     SupportedOctetsPerCodecFrame(OctetsPerCodecFrame) = 4,
-    SupportedMaxCodecFramesPerSDU(u8) = 5,
+    SupportedMaxCodecFramesPerSDU(MaxCodecFramesPerSdu) = 5,
+}
+
+/// Largest LTV encoded capability, the octets per codec frame
+pub(crate) const MAX_CODEC_SPECIFIC_CAPABILITY_SIZE: usize = 6;
+
+/// Every capability LTV encoded once
+pub(crate) const MAX_CODEC_SPECIFIC_CAPABILITIES_SIZE: usize = 4 + 3 + 3 + 6 + 3;
+
+impl CodecSpecificCapabilities {
+    /// The type of the capability in its LTV encoding
+    fn type_id(&self) -> u8 {
+        match self {
+            CodecSpecificCapabilities::SupportedSamplingFrequencies(_) => 1,
+            CodecSpecificCapabilities::SupportedFrameDurations(_) => 2,
+            CodecSpecificCapabilities::SupportedAudioChannelCounts(_) => 3,
+            CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(_) => 4,
+            CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(_) => 5,
+        }
+    }
+
+    /// Write the capability as length, type and value into `buf`
+    ///
+    /// Returns the number of bytes written, 0 if `buf` is too small.
+    pub fn encode_ltv(&self, buf: &mut [u8]) -> usize {
+        let mut value = [0; MAX_CODEC_SPECIFIC_CAPABILITY_SIZE - 2];
+        let value = match self {
+            CodecSpecificCapabilities::SupportedSamplingFrequencies(frequencies) => {
+                value[..2].copy_from_slice(&frequencies.0.bits().to_le_bytes());
+                &value[..2]
+            }
+            CodecSpecificCapabilities::SupportedFrameDurations(durations) => {
+                value[0] = durations.0.bits();
+                &value[..1]
+            }
+            CodecSpecificCapabilities::SupportedAudioChannelCounts(counts) => {
+                value[0] = counts.0;
+                &value[..1]
+            }
+            CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(octets) => {
+                let mut encoded = [0; 4];
+                octets.encode(&mut encoded);
+                value[..4].copy_from_slice(&encoded);
+                &value[..4]
+            }
+            CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(frames) => {
+                value[0] = frames.0;
+                &value[..1]
+            }
+        };
+
+        let len = 2 + value.len();
+        let Some(buf) = buf.get_mut(..len) else {
+            return 0;
+        };
+        buf[0] = 1 + value.len() as u8;
+        buf[1] = self.type_id();
+        buf[2..].copy_from_slice(value);
+        len
+    }
+
+    /// Read a single LTV encoded capability from the start of `data`
+    ///
+    /// Returns the capability and the number of bytes consumed.
+    pub fn decode_ltv(data: &[u8]) -> Result<(Self, usize), GenericAudioError> {
+        let mut ltvs = LtvIterator::new(data);
+        let (type_id, value) = ltvs.next().ok_or(GenericAudioError::Truncated)?;
+        Ok((Self::decode_value(type_id, value)?, ltvs.position()))
+    }
+
+    /// Read the capability of type `type_id` from its LTV `value`
+    pub fn decode_value(type_id: u8, value: &[u8]) -> Result<Self, GenericAudioError> {
+        let capability = match (type_id, value) {
+            (1, [low, high]) => CodecSpecificCapabilities::SupportedSamplingFrequencies(
+                SupportedSamplingFrequencies(SamplingFrequencyMask::from_bits_retain(
+                    u16::from_le_bytes([*low, *high]),
+                )),
+            ),
+            (2, [durations]) => CodecSpecificCapabilities::SupportedFrameDurations(
+                SupportedFrameDurations(FrameDurationMask::from_bits_retain(*durations)),
+            ),
+            (3, [counts]) => CodecSpecificCapabilities::SupportedAudioChannelCounts(
+                SupportedAudioChannelCounts(*counts),
+            ),
+            (4, &[min_low, min_high, max_low, max_high]) => {
+                CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(
+                    OctetsPerCodecFrame::decode(&[min_low, min_high, max_low, max_high]),
+                )
+            }
+            (5, [frames]) => CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(
+                MaxCodecFramesPerSdu::new(*frames)
+                    .map_err(|_| GenericAudioError::InvalidValue { type_id })?,
+            ),
+            (1..=5, _) => {
+                return Err(GenericAudioError::InvalidLength {
+                    type_id,
+                    len: 1 + value.len() as u8,
+                })
+            }
+            _ => return Err(GenericAudioError::UnknownType(type_id)),
+        };
+        Ok(capability)
+    }
+
+    /// Write the capabilities one after another as LTV structures into `buf`
+    ///
+    /// Returns the number of bytes written, 0 if `buf` is too small.
+    pub fn encode_all(caps: &[CodecSpecificCapabilities], buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for capability in caps {
+            match capability.encode_ltv(&mut buf[len..]) {
+                0 => return 0,
+                written => len += written,
+            }
+        }
+        len
+    }
+
+    /// Read all the LTV encoded capabilities of `data`
+    ///
+    /// Capabilities of unknown types are skipped.
+    pub fn decode_all(data: &[u8]) -> Result<Vec<CodecSpecificCapabilities, 5>, GenericAudioError> {
+        let mut caps = Vec::new();
+        let mut ltvs = LtvIterator::new(data);
+        for (type_id, value) in ltvs.by_ref() {
+            match Self::decode_value(type_id, value) {
+                Ok(capability) => caps
+                    .push(capability)
+                    .map_err(|_| GenericAudioError::TooMany)?,
+                Err(GenericAudioError::UnknownType(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if !ltvs.remaining().is_empty() {
+            return Err(GenericAudioError::Truncated);
+        }
+        Ok(caps)
+    }
+}
+
+bitflags! {
+    /// The Supported_Sampling_Frequencies bitmask of PACS
+    ///
+    /// Bit n is the [`SamplingFrequency`] with ordinal n, unlike the codec
+    /// configuration which selects a single frequency by its ordinal.
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SamplingFrequencyMask: u16 {
+        const Hz8000 = 1 << 0;
+        const Hz11025 = 1 << 1;
+        const Hz16000 = 1 << 2;
+        const Hz22050 = 1 << 3;
+        const Hz24000 = 1 << 4;
+        const Hz32000 = 1 << 5;
+        const Hz44100 = 1 << 6;
+        const Hz48000 = 1 << 7;
+        const Hz88200 = 1 << 8;
+        const Hz96000 = 1 << 9;
+        const Hz176400 = 1 << 10;
+        const Hz192000 = 1 << 11;
+        const Hz384000 = 1 << 12;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for SamplingFrequencyMask {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "SamplingFrequencyMask({=u16:#06x})", self.bits())
+    }
+}
+
+impl SamplingFrequencyMask {
+    /// The bit of a single sampling frequency, empty for `Undefined`
+    pub fn from_sampling_frequency(frequency: SamplingFrequency) -> Self {
+        match frequency {
+            SamplingFrequency::Undefined => Self::empty(),
+            frequency => Self::from_bits_retain(1 << frequency as u8),
+        }
+    }
+
+    /// The highest sampling frequency in both masks
+    ///
+    /// Used to pick the configuration of a stream from the capabilities of
+    /// both ends.
+    pub fn highest_common(a: Self, b: Self) -> Option<SamplingFrequency> {
+        let common = (a & b).bits();
+        if common == 0 {
+            return None;
+        }
+        SamplingFrequency::try_from((u16::BITS - 1 - common.leading_zeros()) as u8).ok()
+    }
+
+    /// Every sampling frequency in the mask, from the lowest
+    pub fn frequencies(&self) -> Vec<SamplingFrequency, 13> {
+        (0..13u8)
+            .filter(|ordinal| self.bits() & (1 << ordinal) != 0)
+            .filter_map(|ordinal| SamplingFrequency::try_from(ordinal).ok())
+            .collect()
+    }
+}
+
+impl From<SamplingFrequency> for SamplingFrequencyMask {
+    fn from(frequency: SamplingFrequency) -> Self {
+        Self::from_sampling_frequency(frequency)
+    }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
-pub struct SupportedSamplingFrequencies(u8);
+pub struct SupportedSamplingFrequencies(SamplingFrequencyMask);
 
 impl Default for SupportedSamplingFrequencies {
     fn default() -> Self {
-        Self(1 << SamplingFrequency::default() as u8)
+        Self(SamplingFrequencyMask::from_sampling_frequency(
+            SamplingFrequency::default(),
+        ))
     }
 }
 
 impl SupportedSamplingFrequencies {
+    /// Create from a bitmask where bit n is the [`SamplingFrequency`] with ordinal n
+    ///
+    /// This is the Supported_Sampling_Frequencies bitmask of PACS, where bit 0
+    /// is 8 kHz up to bit 12 for 384 kHz.
+    pub const fn new_const(frequencies_bitmask: u16) -> Self {
+        Self(SamplingFrequencyMask::from_bits_retain(frequencies_bitmask))
+    }
+
     pub fn new(frequencies: &[SamplingFrequency]) -> Self {
-        let mut sampling_frequencies = 0;
+        let mut sampling_frequencies = SamplingFrequencyMask::empty();
         for frequency in frequencies {
             Self::add(&mut sampling_frequencies, *frequency)
         }
         SupportedSamplingFrequencies(sampling_frequencies)
     }
 
-    pub fn add(frequencies: &mut u8, sampling_frequency: SamplingFrequency) {
-        *frequencies += 1 << sampling_frequency as u8;
+    pub fn add(frequencies: &mut SamplingFrequencyMask, sampling_frequency: SamplingFrequency) {
+        *frequencies |= SamplingFrequencyMask::from_sampling_frequency(sampling_frequency);
+    }
+
+    /// The supported sampling frequencies as a bitmask
+    pub fn mask(&self) -> SamplingFrequencyMask {
+        self.0
+    }
+
+    /// No sampling frequency is supported
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The sampling frequency is supported
+    pub fn contains(&self, frequency: SamplingFrequency) -> bool {
+        frequency != SamplingFrequency::Undefined
+            && self
+                .0
+                .contains(SamplingFrequencyMask::from_sampling_frequency(frequency))
+    }
+
+    /// Every supported sampling frequency, from the lowest
+    pub fn supported_frequencies(&self) -> Vec<SamplingFrequency, 13> {
+        self.0.frequencies()
+    }
+}
+
+bitflags! {
+    /// The Supported_Frame_Durations bitmask of PACS
+    ///
+    /// Unlike the codec configuration, which selects a single [`FrameDuration`],
+    /// it lists every supported duration and at most one preferred.
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FrameDurationMask: u8 {
+        const Supports7_5MS = 0b0000_0001;
+        const Supports10MS = 0b0000_0010;
+        const Prefers7_5MS = 0b0001_0000;
+        const Prefers10MS = 0b0010_0000;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for FrameDurationMask {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "FrameDurationMask({=u8:#04x})", self.bits())
+    }
+}
+
+impl FrameDurationMask {
+    /// The preferred frame duration, if it is also supported
+    pub fn preferred(&self) -> Option<FrameDuration> {
+        if self.contains(Self::Prefers7_5MS | Self::Supports7_5MS) {
+            Some(FrameDuration::Duration7_5MS)
+        } else if self.contains(Self::Prefers10MS | Self::Supports10MS) {
+            Some(FrameDuration::Duration10MS)
+        } else {
+            None
+        }
     }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
-pub struct SupportedFrameDurations(u8);
+pub struct SupportedFrameDurations(FrameDurationMask);
 
 impl SupportedFrameDurations {
+    /// Create from the Supported_Frame_Durations bitmask
+    pub const fn from_bitmask(bitmask: u8) -> Self {
+        Self(FrameDurationMask::from_bits_retain(bitmask))
+    }
+
     pub fn new(
         support_7_5_ms: bool,
         support_10_ms: bool,
         prefer_7_5_ms: bool,
         prefer_10_ms: bool,
     ) -> Self {
-        let mut value = 0;
-        if support_7_5_ms {
-            value |= 0b0000_0001; // Set bit 0
-        }
-        if support_10_ms {
-            value |= 0b0000_0010; // Set bit 1
-        }
-        if support_7_5_ms && support_10_ms && prefer_7_5_ms {
-            value |= 0b0001_0000; // Set bit 4
-        }
-        if support_7_5_ms && support_10_ms && prefer_10_ms {
-            value |= 0b0010_0000; // Set bit 5
+        let both = support_7_5_ms && support_10_ms;
+        let mut value = FrameDurationMask::empty();
+        value.set(FrameDurationMask::Supports7_5MS, support_7_5_ms);
+        value.set(FrameDurationMask::Supports10MS, support_10_ms);
+        value.set(FrameDurationMask::Prefers7_5MS, both && prefer_7_5_ms);
+        value.set(FrameDurationMask::Prefers10MS, both && prefer_10_ms);
+        Self(value)
+    }
+
+    /// The supported frame durations as a bitmask
+    pub fn mask(&self) -> FrameDurationMask {
+        self.0
+    }
+
+    /// Neither the 7.5 ms nor the 10 ms frame duration is supported
+    pub fn is_empty(&self) -> bool {
+        !self
+            .0
+            .intersects(FrameDurationMask::Supports7_5MS | FrameDurationMask::Supports10MS)
+    }
+
+    /// The frame duration is supported
+    pub fn supports(&self, duration: &FrameDuration) -> bool {
+        match duration {
+            FrameDuration::Duration7_5MS => self.0.contains(FrameDurationMask::Supports7_5MS),
+            FrameDuration::Duration10MS => self.0.contains(FrameDurationMask::Supports10MS),
         }
+    }
 
-        Self(value)
+    /// The preferred frame duration, if any
+    pub fn preferred(&self) -> Option<FrameDuration> {
+        self.0.preferred()
     }
 }
 
@@ -75,13 +372,139 @@ impl Default for SupportedFrameDurations {
 pub struct SupportedAudioChannelCounts(u8);
 
 impl SupportedAudioChannelCounts {
-    pub fn new(count: u8) -> Self {
+    /// Create from a bitmask where bit n means n + 1 channels are supported
+    pub const fn from_bitmask(bitmask: u8) -> Self {
+        Self(bitmask)
+    }
+
+    /// Create from every supported channel count, counts outside 1 to 8 are ignored
+    pub fn new(counts: &[u8]) -> Self {
         let mut value = 0;
+        for &count in counts {
+            if (1..=8).contains(&count) {
+                value |= 1 << (count - 1);
+            }
+        }
+        Self(value)
+    }
 
-        if (1..=8).contains(&count) {
-            value |= 1 << (count - 1);
+    /// One and two channels are supported
+    pub fn mono_and_stereo() -> Self {
+        Self::new(&[1, 2])
+    }
+
+    /// Streams with `count` channels are supported
+    pub fn contains(&self, count: u8) -> bool {
+        (1..=8).contains(&count) && self.0 & (1 << (count - 1)) != 0
+    }
+
+    /// The bitmask where bit n means n + 1 channels are supported
+    pub fn bitmask(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Errors constructing a capability with an out of range value
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilitiesError {
+    /// A codec frame must be sent in each SDU
+    InvalidFrameCount,
+    /// The minimum octets per codec frame is 0 or above the maximum
+    InvalidOctetsRange,
+}
+
+/// The most codec frames per channel sent in a single SDU, from 1 to 255
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxCodecFramesPerSdu(u8);
+
+impl MaxCodecFramesPerSdu {
+    pub fn new(frames: u8) -> Result<Self, CapabilitiesError> {
+        match frames {
+            0 => Err(CapabilitiesError::InvalidFrameCount),
+            frames => Ok(Self(frames)),
         }
+    }
 
-        Self(value)
+    pub fn frames(&self) -> u8 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_sampling_frequency_has_its_pacs_bit() {
+        // Supported_Sampling_Frequencies of PACS, bit 0 is 8 kHz up to bit 12 for 384 kHz
+        let bits = [
+            (SamplingFrequency::Hz8000, 0x0001),
+            (SamplingFrequency::Hz11025, 0x0002),
+            (SamplingFrequency::Hz16000, 0x0004),
+            (SamplingFrequency::Hz22050, 0x0008),
+            (SamplingFrequency::Hz24000, 0x0010),
+            (SamplingFrequency::Hz32000, 0x0020),
+            (SamplingFrequency::Hz44100, 0x0040),
+            (SamplingFrequency::Hz48000, 0x0080),
+            (SamplingFrequency::Hz88200, 0x0100),
+            (SamplingFrequency::Hz96000, 0x0200),
+            (SamplingFrequency::Hz176400, 0x0400),
+            (SamplingFrequency::Hz192000, 0x0800),
+            (SamplingFrequency::Hz384000, 0x1000),
+        ];
+        for (frequency, bit) in bits {
+            let supported = SupportedSamplingFrequencies::new(&[frequency]);
+            assert_eq!(supported.mask().bits(), bit);
+            assert!(supported.contains(frequency));
+            assert_eq!(supported.supported_frequencies()[..], [frequency]);
+        }
+        let all = SupportedSamplingFrequencies::new(&bits.map(|(frequency, _)| frequency));
+        assert_eq!(all.mask().bits(), 0x1FFF);
+        assert_eq!(all.supported_frequencies().len(), 13);
+    }
+
+    #[test]
+    fn undefined_sampling_frequency_sets_no_bit() {
+        let supported = SupportedSamplingFrequencies::new(&[SamplingFrequency::Undefined]);
+        assert!(supported.is_empty());
+        assert!(!supported.contains(SamplingFrequency::Undefined));
+    }
+
+    #[test]
+    fn channel_counts_outside_1_to_8_are_ignored() {
+        let counts = SupportedAudioChannelCounts::new(&[0, 1, 9, 255]);
+        assert_eq!(counts.bitmask(), 0b0000_0001);
+        assert!(counts.contains(1));
+        assert!(!counts.contains(0));
+        assert!(!counts.contains(9));
+        assert_eq!(SupportedAudioChannelCounts::new(&[0, 9]).bitmask(), 0);
+    }
+
+    #[test]
+    fn channel_counts_are_encoded_as_pacs_table_4_4() {
+        // Bit n of Supported_Audio_Channel_Counts means n + 1 channels
+        for count in 1..=8u8 {
+            assert_eq!(
+                SupportedAudioChannelCounts::new(&[count]).bitmask(),
+                1 << (count - 1)
+            );
+        }
+        assert_eq!(
+            SupportedAudioChannelCounts::mono_and_stereo().bitmask(),
+            0x03
+        );
+        assert_eq!(
+            SupportedAudioChannelCounts::new(&[1, 2, 3, 4, 5, 6, 7, 8]).bitmask(),
+            0xFF
+        );
+
+        let capability = CodecSpecificCapabilities::SupportedAudioChannelCounts(
+            SupportedAudioChannelCounts::mono_and_stereo(),
+        );
+        let mut buf = [0; 3];
+        assert_eq!(capability.encode_ltv(&mut buf), 3);
+        assert_eq!(buf, [0x02, 0x03, 0x03]);
     }
 }
@@ -1,16 +1,255 @@
-use super::{AudioLocation, OctetsPerCodecFrame};
+use heapless::Vec;
 
-#[derive(Debug)]
+#[cfg(feature = "defmt")]
+use defmt::debug;
+
+use super::{AudioLocation, GenericAudioError, LtvIterator, OctetsPerCodecFrame};
+
+/// Max configurations of a codec, one of each type
+pub const MAX_CODEC_SPECIFIC_CONFIGURATIONS: usize = 5;
+
+#[derive(Debug, Clone)]
 #[repr(u8)]
 pub enum CodecSpecificConfiguration {
     SamplingFrequency(SamplingFrequency) = 1,
     FrameDuration(FrameDuration) = 2,
     AudioChannelAllocation(AudioLocation) = 3,
     OctetsPerCodecFrame(OctetsPerCodecFrame) = 4,
+    /// Blocks of codec frames, one frame per channel, sent in each SDU
+    CodecFrameBlocksPerSdu(u8) = 5,
+}
+
+impl CodecSpecificConfiguration {
+    /// Write the configuration as length, type and value into `buf`
+    ///
+    /// A configuration selects a single octets per codec frame, encoded on 2 bytes,
+    /// so a range of octets is rejected, as is an undefined sampling frequency.
+    /// Returns the number of bytes written.
+    pub fn encode_ltv(&self, buf: &mut [u8]) -> Result<usize, GenericAudioError> {
+        let mut value = [0; 4];
+        let (type_id, value) = match self {
+            CodecSpecificConfiguration::SamplingFrequency(SamplingFrequency::Undefined) => {
+                return Err(GenericAudioError::InvalidValue { type_id: 1 })
+            }
+            CodecSpecificConfiguration::SamplingFrequency(frequency) => {
+                // The configuration counts frequencies from 1
+                value[0] = *frequency as u8 + 1;
+                (1, &value[..1])
+            }
+            CodecSpecificConfiguration::FrameDuration(duration) => {
+                value[0] = match duration {
+                    FrameDuration::Duration7_5MS => 0,
+                    FrameDuration::Duration10MS => 1,
+                };
+                (2, &value[..1])
+            }
+            CodecSpecificConfiguration::AudioChannelAllocation(location) => {
+                value.copy_from_slice(&location.bits().to_le_bytes());
+                (3, &value[..])
+            }
+            CodecSpecificConfiguration::OctetsPerCodecFrame(octets)
+                if octets.min_octets() != octets.max_octets() =>
+            {
+                return Err(GenericAudioError::InvalidValue { type_id: 4 })
+            }
+            CodecSpecificConfiguration::OctetsPerCodecFrame(octets) => {
+                value[..2].copy_from_slice(&octets.min_octets().to_le_bytes());
+                (4, &value[..2])
+            }
+            CodecSpecificConfiguration::CodecFrameBlocksPerSdu(blocks) => {
+                value[0] = *blocks;
+                (5, &value[..1])
+            }
+        };
+
+        let len = 2 + value.len();
+        let buf = buf
+            .get_mut(..len)
+            .ok_or(GenericAudioError::BufferTooSmall)?;
+        buf[0] = 1 + value.len() as u8;
+        buf[1] = type_id;
+        buf[2..].copy_from_slice(value);
+        Ok(len)
+    }
+
+    /// Read a single LTV encoded configuration from the start of `data`
+    ///
+    /// Returns the configuration and the number of bytes consumed.
+    pub fn decode_ltv(data: &[u8]) -> Result<(Self, usize), GenericAudioError> {
+        let mut ltvs = LtvIterator::new(data);
+        let (type_id, value) = ltvs.next().ok_or(GenericAudioError::Truncated)?;
+        Ok((Self::decode_value(type_id, value)?, ltvs.position()))
+    }
+
+    /// Read the configuration of type `type_id` from its LTV `value`
+    pub fn decode_value(type_id: u8, value: &[u8]) -> Result<Self, GenericAudioError> {
+        let invalid = GenericAudioError::InvalidLength {
+            type_id,
+            len: 1 + value.len() as u8,
+        };
+
+        let configuration = match (type_id, value) {
+            // The configuration counts frequencies from 1
+            (1, [frequency]) => CodecSpecificConfiguration::SamplingFrequency(
+                frequency
+                    .checked_sub(1)
+                    .and_then(|ordinal| SamplingFrequency::try_from(ordinal).ok())
+                    .ok_or(invalid)?,
+            ),
+            (2, [0]) => CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration7_5MS),
+            (2, [1]) => CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+            (3, [a, b, c, d]) => CodecSpecificConfiguration::AudioChannelAllocation(
+                AudioLocation::from_bits_retain(u32::from_le_bytes([*a, *b, *c, *d])),
+            ),
+            (4, [low, high]) => {
+                let octets = u16::from_le_bytes([*low, *high]);
+                CodecSpecificConfiguration::OctetsPerCodecFrame(
+                    OctetsPerCodecFrame::new(octets, octets).map_err(|_| invalid)?,
+                )
+            }
+            (5, [blocks]) => CodecSpecificConfiguration::CodecFrameBlocksPerSdu(*blocks),
+            (1..=5, _) => return Err(invalid),
+            _ => return Err(GenericAudioError::UnknownType(type_id)),
+        };
+        Ok(configuration)
+    }
+
+    /// Read all the LTV encoded configurations of `data`
+    ///
+    /// Configurations of unknown types are skipped as the specification requires.
+    pub fn decode_all_ltv(
+        data: &[u8],
+    ) -> Result<Vec<Self, MAX_CODEC_SPECIFIC_CONFIGURATIONS>, GenericAudioError> {
+        let mut configurations = Vec::new();
+        let mut ltvs = LtvIterator::new(data);
+        for (type_id, value) in ltvs.by_ref() {
+            match Self::decode_value(type_id, value) {
+                Ok(configuration) => configurations
+                    .push(configuration)
+                    .map_err(|_| GenericAudioError::TooMany)?,
+                Err(GenericAudioError::UnknownType(_type_id)) => {
+                    #[cfg(feature = "defmt")]
+                    debug!(
+                        "[generic audio] skipping configuration of type {}",
+                        _type_id
+                    );
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !ltvs.remaining().is_empty() {
+            return Err(GenericAudioError::Truncated);
+        }
+        Ok(configurations)
+    }
+}
+
+/// Errors computing a QoS parameter from a codec configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeError {
+    /// The configuration does not set the octets per codec frame
+    MissingField,
+    /// The SDU does not fit the 12 bits of Max_SDU
+    Overflow,
+}
+
+/// The Max_SDU of a CIS or BIS carrying the stream configured by `config`
+///
+/// Each SDU holds Codec_Frame_Blocks_Per_SDU blocks of one codec frame per
+/// channel of the Audio_Channel_Allocation. As BAP specifies, a configuration
+/// without blocks per SDU sends one block and one without an allocation is mono.
+pub fn compute_max_sdu(config: &[CodecSpecificConfiguration]) -> Result<u16, ComputeError> {
+    let mut octets = None;
+    let mut channels = 1;
+    let mut blocks = 1;
+    for configuration in config {
+        match configuration {
+            CodecSpecificConfiguration::OctetsPerCodecFrame(frame) => {
+                octets = Some(frame.min_octets())
+            }
+            CodecSpecificConfiguration::AudioChannelAllocation(location) => {
+                channels = location.channel_count()
+            }
+            CodecSpecificConfiguration::CodecFrameBlocksPerSdu(count) => blocks = *count,
+            CodecSpecificConfiguration::SamplingFrequency(_)
+            | CodecSpecificConfiguration::FrameDuration(_) => {}
+        }
+    }
+    let octets = octets.ok_or(ComputeError::MissingField)?;
+    octets
+        .checked_mul(u16::from(channels) * u16::from(blocks))
+        .filter(|max_sdu| *max_sdu <= 0x0FFF)
+        .ok_or(ComputeError::Overflow)
+}
+
+/// Max length of a Codec_Specific_Configuration built by [`serialize_codec_config`]
+pub const MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE: usize = 64;
+
+/// Errors serializing a Codec_Specific_Configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerializeError {
+    /// The configurations exceed [`MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE`]
+    BufferFull,
+    /// A configuration has no single value, a range of octets per codec frame
+    /// or an undefined sampling frequency
+    InvalidValue { type_id: u8 },
+}
+
+/// Errors deserializing a Codec_Specific_Configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// A configuration is truncated or its value does not match its type
+    Malformed(GenericAudioError),
+    /// More configurations than [`MAX_CODEC_SPECIFIC_CONFIGURATIONS`]
+    TooMany,
+}
+
+/// Append the LTV encoding of each configuration of `config` to `buf`
+///
+/// This is the Codec_Specific_Configuration of a Config Codec operation or a
+/// BASE, without its length prefix. `buf` is left unchanged on error.
+pub fn serialize_codec_config(
+    config: &[CodecSpecificConfiguration],
+    buf: &mut Vec<u8, MAX_CODEC_SPECIFIC_CONFIGURATION_SIZE>,
+) -> Result<(), SerializeError> {
+    let start = buf.len();
+    for configuration in config {
+        let mut ltv = [0; 6];
+        // The largest configuration is 6 bytes long, only invalid values fail
+        let encoded = match configuration.encode_ltv(&mut ltv) {
+            Ok(len) => buf
+                .extend_from_slice(&ltv[..len])
+                .map_err(|_| SerializeError::BufferFull),
+            Err(GenericAudioError::InvalidValue { type_id }) => {
+                Err(SerializeError::InvalidValue { type_id })
+            }
+            Err(_) => Err(SerializeError::BufferFull),
+        };
+        if let Err(err) = encoded {
+            buf.truncate(start);
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Read the configurations of a Codec_Specific_Configuration
+///
+/// The inverse of [`serialize_codec_config`], configurations of unknown types are skipped.
+pub fn deserialize_codec_config(
+    data: &[u8],
+) -> Result<Vec<CodecSpecificConfiguration, MAX_CODEC_SPECIFIC_CONFIGURATIONS>, DeserializeError> {
+    CodecSpecificConfiguration::decode_all_ltv(data).map_err(|err| match err {
+        GenericAudioError::TooMany => DeserializeError::TooMany,
+        err => DeserializeError::Malformed(err),
+    })
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SamplingFrequency {
     #[default]
     Hz8000 = 0,
@@ -29,11 +268,267 @@ pub enum SamplingFrequency {
     Undefined,
 }
 
+/// Errors converting a value into a [`SamplingFrequency`]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingFrequencyError {
+    /// The value in Hz is not one of the defined sampling frequencies
+    UnsupportedFrequency(u32),
+    /// The ordinal is above the highest defined sampling frequency
+    InvalidOrdinal(u8),
+}
+
+impl SamplingFrequency {
+    /// The frequency in Hz, `Undefined` is 0
+    pub const fn to_hz(&self) -> u32 {
+        match self {
+            Self::Hz8000 => 8000,
+            Self::Hz11025 => 11025,
+            Self::Hz16000 => 16000,
+            Self::Hz22050 => 22050,
+            Self::Hz24000 => 24000,
+            Self::Hz32000 => 32000,
+            Self::Hz44100 => 44100,
+            Self::Hz48000 => 48000,
+            Self::Hz88200 => 88200,
+            Self::Hz96000 => 96000,
+            Self::Hz176400 => 176400,
+            Self::Hz192000 => 192000,
+            Self::Hz384000 => 384000,
+            Self::Undefined => 0,
+        }
+    }
+
+    /// The sampling frequency of `hz`, `None` if it is not one of the defined frequencies
+    pub const fn from_hz(hz: u32) -> Option<Self> {
+        Some(match hz {
+            8000 => Self::Hz8000,
+            11025 => Self::Hz11025,
+            16000 => Self::Hz16000,
+            22050 => Self::Hz22050,
+            24000 => Self::Hz24000,
+            32000 => Self::Hz32000,
+            44100 => Self::Hz44100,
+            48000 => Self::Hz48000,
+            88200 => Self::Hz88200,
+            96000 => Self::Hz96000,
+            176400 => Self::Hz176400,
+            192000 => Self::Hz192000,
+            384000 => Self::Hz384000,
+            _ => return None,
+        })
+    }
+
+    /// The sample period in nanoseconds, rounded down, as I2S peripherals are configured
+    ///
+    /// `Undefined` is 0.
+    pub const fn to_period_ns(&self) -> u32 {
+        match self.to_hz() {
+            0 => 0,
+            hz => 1_000_000_000 / hz,
+        }
+    }
+}
+
+impl TryFrom<u32> for SamplingFrequency {
+    type Error = SamplingFrequencyError;
+
+    /// Convert a frequency in Hz
+    fn try_from(hz: u32) -> Result<Self, Self::Error> {
+        Self::from_hz(hz).ok_or(SamplingFrequencyError::UnsupportedFrequency(hz))
+    }
+}
+
+impl From<SamplingFrequency> for u32 {
+    /// The frequency in Hz, `Undefined` is 0
+    fn from(frequency: SamplingFrequency) -> Self {
+        frequency.to_hz()
+    }
+}
+
+impl TryFrom<u8> for SamplingFrequency {
+    type Error = SamplingFrequencyError;
+
+    /// Convert the ordinal used as the bit position in the supported sampling frequencies
+    fn try_from(ordinal: u8) -> Result<Self, Self::Error> {
+        Ok(match ordinal {
+            0 => Self::Hz8000,
+            1 => Self::Hz11025,
+            2 => Self::Hz16000,
+            3 => Self::Hz22050,
+            4 => Self::Hz24000,
+            5 => Self::Hz32000,
+            6 => Self::Hz44100,
+            7 => Self::Hz48000,
+            8 => Self::Hz88200,
+            9 => Self::Hz96000,
+            10 => Self::Hz176400,
+            11 => Self::Hz192000,
+            12 => Self::Hz384000,
+            _ => return Err(SamplingFrequencyError::InvalidOrdinal(ordinal)),
+        })
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum FrameDuration {
     Duration7_5MS = 0,
     #[default]
     Duration10MS = 1,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialize(config: &[CodecSpecificConfiguration]) -> Vec<u8, 64> {
+        let mut buf = Vec::new();
+        serialize_codec_config(config, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let octets = OctetsPerCodecFrame::new(120, 120).unwrap();
+        let cases: [(CodecSpecificConfiguration, &[u8]); 6] = [
+            (
+                CodecSpecificConfiguration::SamplingFrequency(SamplingFrequency::Hz48000),
+                &[0x02, 0x01, 0x08],
+            ),
+            (
+                CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration7_5MS),
+                &[0x02, 0x02, 0x00],
+            ),
+            (
+                CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+                &[0x02, 0x02, 0x01],
+            ),
+            (
+                CodecSpecificConfiguration::AudioChannelAllocation(
+                    AudioLocation::FrontLeft | AudioLocation::FrontRight,
+                ),
+                &[0x05, 0x03, 0x03, 0x00, 0x00, 0x00],
+            ),
+            (
+                CodecSpecificConfiguration::OctetsPerCodecFrame(octets),
+                &[0x03, 0x04, 0x78, 0x00],
+            ),
+            (
+                CodecSpecificConfiguration::CodecFrameBlocksPerSdu(2),
+                &[0x02, 0x05, 0x02],
+            ),
+        ];
+        for (configuration, encoded) in cases {
+            let bytes = serialize(&[configuration]);
+            assert_eq!(&bytes[..], encoded);
+            let decoded = deserialize_codec_config(&bytes).unwrap();
+            assert_eq!(decoded.len(), 1);
+            assert_eq!(&serialize(&decoded)[..], encoded);
+        }
+    }
+
+    #[test]
+    fn every_sampling_frequency_round_trips() {
+        for ordinal in 0..=12 {
+            let frequency = SamplingFrequency::try_from(ordinal).unwrap();
+            let bytes = serialize(&[CodecSpecificConfiguration::SamplingFrequency(frequency)]);
+            assert_eq!(&bytes[..], &[0x02, 0x01, ordinal + 1]);
+            let decoded = deserialize_codec_config(&bytes).unwrap();
+            assert!(matches!(
+                decoded[..],
+                [CodecSpecificConfiguration::SamplingFrequency(decoded)] if decoded == frequency
+            ));
+        }
+    }
+
+    #[test]
+    fn a_full_configuration_round_trips() {
+        let config = [
+            CodecSpecificConfiguration::SamplingFrequency(SamplingFrequency::Hz16000),
+            CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+            CodecSpecificConfiguration::AudioChannelAllocation(AudioLocation::FrontLeft),
+            CodecSpecificConfiguration::OctetsPerCodecFrame(
+                OctetsPerCodecFrame::new(40, 40).unwrap(),
+            ),
+            CodecSpecificConfiguration::CodecFrameBlocksPerSdu(1),
+        ];
+        let bytes = serialize(&config);
+        assert_eq!(bytes.len(), 19);
+        let decoded = deserialize_codec_config(&bytes).unwrap();
+        assert_eq!(decoded.len(), config.len());
+        assert_eq!(serialize(&decoded), bytes);
+    }
+
+    #[test]
+    fn a_range_of_octets_is_rejected() {
+        let mut buf = Vec::new();
+        let config = [
+            CodecSpecificConfiguration::FrameDuration(FrameDuration::Duration10MS),
+            CodecSpecificConfiguration::OctetsPerCodecFrame(
+                OctetsPerCodecFrame::new(40, 60).unwrap(),
+            ),
+        ];
+        assert_eq!(
+            serialize_codec_config(&config, &mut buf),
+            Err(SerializeError::InvalidValue { type_id: 4 })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn an_undefined_sampling_frequency_is_rejected() {
+        let mut buf = Vec::new();
+        let config = [CodecSpecificConfiguration::SamplingFrequency(
+            SamplingFrequency::Undefined,
+        )];
+        assert_eq!(
+            serialize_codec_config(&config, &mut buf),
+            Err(SerializeError::InvalidValue { type_id: 1 })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn configurations_beyond_the_buffer_are_rejected() {
+        let config: [_; 11] = core::array::from_fn(|_| {
+            CodecSpecificConfiguration::AudioChannelAllocation(AudioLocation::FrontLeft)
+        });
+        let mut buf = Vec::new();
+        assert_eq!(
+            serialize_codec_config(&config, &mut buf),
+            Err(SerializeError::BufferFull)
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn max_sdu_holds_every_block_of_every_channel() {
+        let octets = CodecSpecificConfiguration::OctetsPerCodecFrame(
+            OctetsPerCodecFrame::new(100, 100).unwrap(),
+        );
+        assert_eq!(compute_max_sdu(&[octets.clone()]), Ok(100));
+        assert_eq!(
+            compute_max_sdu(&[
+                octets.clone(),
+                CodecSpecificConfiguration::AudioChannelAllocation(
+                    AudioLocation::FrontLeft | AudioLocation::FrontRight,
+                ),
+                CodecSpecificConfiguration::CodecFrameBlocksPerSdu(2),
+            ]),
+            Ok(400)
+        );
+        assert_eq!(
+            compute_max_sdu(&[
+                octets,
+                CodecSpecificConfiguration::CodecFrameBlocksPerSdu(41)
+            ]),
+            Err(ComputeError::Overflow)
+        );
+        assert_eq!(
+            compute_max_sdu(&[CodecSpecificConfiguration::CodecFrameBlocksPerSdu(1)]),
+            Err(ComputeError::MissingField)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+/// Iterates over the length, type and value structures of `data`
+///
+/// Each item is the type and the value of a structure. The iteration stops
+/// early on a structure whose length is 0 or runs past the end of `data`,
+/// [`Self::remaining`] then holds the malformed bytes.
+#[derive(Debug, Clone)]
+pub struct LtvIterator<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> LtvIterator<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// The number of bytes of the structures read so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// The bytes not read yet, not empty after a malformed structure
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    /// Skip the next structure without reading it, whatever its type
+    pub fn skip_unknown(&mut self) {
+        let _ = self.next();
+    }
+
+    /// The value of the next structure of type `type_tag`
+    ///
+    /// The structures before it are consumed.
+    pub fn find_type(&mut self, type_tag: u8) -> Option<&'a [u8]> {
+        self.find(|(type_id, _)| *type_id == type_tag)
+            .map(|(_, value)| value)
+    }
+}
+
+impl<'a> Iterator for LtvIterator<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let [len, type_id, ..] = self.remaining() else {
+            return None;
+        };
+        if *len == 0 {
+            return None;
+        }
+        let end = self.pos + 1 + *len as usize;
+        let value = self.data.get(self.pos + 2..end)?;
+        self.pos = end;
+        Some((*type_id, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_structure_is_read_in_order() {
+        let data = [2, 0x01, 0x08, 1, 0x05, 3, 0x02, 0xAA, 0xBB];
+        let mut ltvs = LtvIterator::new(&data);
+        assert_eq!(ltvs.next(), Some((0x01, &[0x08][..])));
+        assert_eq!(ltvs.next(), Some((0x05, &[][..])));
+        assert_eq!(ltvs.next(), Some((0x02, &[0xAA, 0xBB][..])));
+        assert_eq!(ltvs.next(), None);
+        assert_eq!(ltvs.position(), data.len());
+        assert!(ltvs.remaining().is_empty());
+    }
+
+    #[test]
+    fn iteration_stops_on_a_length_past_the_end() {
+        let data = [2, 0x01, 0x08, 4, 0x02, 0xAA, 0xBB];
+        let mut ltvs = LtvIterator::new(&data);
+        assert_eq!(ltvs.next(), Some((0x01, &[0x08][..])));
+        assert_eq!(ltvs.next(), None);
+        assert_eq!(ltvs.position(), 3);
+        assert_eq!(ltvs.remaining(), &data[3..]);
+        // The malformed structure is not skipped
+        assert_eq!(ltvs.next(), None);
+    }
+
+    #[test]
+    fn iteration_stops_on_a_zero_length() {
+        let data = [0, 2, 0x01, 0x08];
+        let mut ltvs = LtvIterator::new(&data);
+        assert_eq!(ltvs.next(), None);
+        assert_eq!(ltvs.remaining(), &data);
+
+        // A lone length octet is truncated too
+        assert_eq!(LtvIterator::new(&[2]).next(), None);
+    }
+
+    #[test]
+    fn find_type_consumes_the_structures_before_it() {
+        let data = [2, 0x01, 0x08, 3, 0x02, 0xAA, 0xBB, 2, 0x03, 0x01];
+        let mut ltvs = LtvIterator::new(&data);
+        assert_eq!(ltvs.find_type(0x02), Some(&[0xAA, 0xBB][..]));
+        assert_eq!(ltvs.next(), Some((0x03, &[0x01][..])));
+
+        let mut ltvs = LtvIterator::new(&data);
+        assert_eq!(ltvs.find_type(0x04), None);
+        assert!(ltvs.remaining().is_empty());
+
+        let mut ltvs = LtvIterator::new(&data);
+        ltvs.skip_unknown();
+        assert_eq!(ltvs.find_type(0x01), None);
+    }
+}
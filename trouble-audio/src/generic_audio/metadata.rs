@@ -1,6 +1,17 @@
-use super::ContextType;
+use heapless::{String, Vec};
+
+use super::{ContextType, LtvIterator};
 use crate::ContentControlID;
 
+/// Max length of the value of vender specific, extended and unrecognized metadata
+pub const MAX_METADATA_VALUE_SIZE: usize = 32;
+
+/// Max length in bytes of the UTF-8 program info and program info URI
+pub const MAX_PROGRAM_INFO_SIZE: usize = 64;
+
+/// Max Content Control IDs of a CCID List
+pub const MAX_CCIDS: usize = 16;
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
 #[repr(u8)]
@@ -8,22 +19,187 @@ pub enum Metadata {
     PreferredAudioContexts(ContextType) = 1,
     StreamingAudioContexts(ContextType) = 2,
     /// Title and/or summary of Audio Stream content: UTF-8 format
-    ProgramInfo(&'static str) = 3,
+    ProgramInfo(String<MAX_PROGRAM_INFO_SIZE>) = 3,
     /// 3-byte, lower case language code as defined in ISO 639-3
     Language([u8; 3]) = 4,
-    CCIDList(&'static [ContentControlID]) = 5,
+    /// Content Control IDs of the services controlling the content, built
+    /// with [`crate::ccid::CcidRegistry::ccid_list`] so every id is registered
+    CCIDList(Vec<ContentControlID, MAX_CCIDS>) = 5,
     ParentalRating(ParentalRating) = 6,
-    ProgramInfoURI(&'static str) = 7,
-    ExtendedMetadata() = 0xFE, // TODO
+    /// URI of additional program information: UTF-8 format
+    ProgramInfoURI(String<MAX_PROGRAM_INFO_SIZE>) = 7,
+    ExtendedMetadata(ExtendedMetadata) = 0xFE,
     VenderSpecific(VenderSpecific) = 0xFF,
     AudioActiveState(AudioActiveState) = 8,
     BroadcastAudioImmediateRenderingFlag = 9,
     AssistedListeningStream(AssistedListeningStream) = 10,
     BroadcastName(&'static str) = 11,
+    /// Metadata of an unknown type, or borrowing its value, as received
+    Unrecognized {
+        type_id: u8,
+        value: Vec<u8, MAX_METADATA_VALUE_SIZE>,
+    } = 0,
+}
+
+impl Metadata {
+    /// The type of the metadata in its LTV encoding
+    fn type_id(&self) -> u8 {
+        match self {
+            Metadata::PreferredAudioContexts(_) => 1,
+            Metadata::StreamingAudioContexts(_) => 2,
+            Metadata::ProgramInfo(_) => 3,
+            Metadata::Language(_) => 4,
+            Metadata::CCIDList(_) => 5,
+            Metadata::ParentalRating(_) => 6,
+            Metadata::ProgramInfoURI(_) => 7,
+            Metadata::AudioActiveState(_) => 8,
+            Metadata::BroadcastAudioImmediateRenderingFlag => 9,
+            Metadata::AssistedListeningStream(_) => 10,
+            Metadata::BroadcastName(_) => 11,
+            Metadata::ExtendedMetadata(_) => 0xFE,
+            Metadata::VenderSpecific(_) => 0xFF,
+            Metadata::Unrecognized { type_id, .. } => *type_id,
+        }
+    }
+
+    /// Write the metadata as length, type and value into `buf`
+    ///
+    /// Returns the number of bytes written, 0 if `buf` is too small.
+    pub fn encode_ltv(&self, buf: &mut [u8]) -> usize {
+        // Vender specific and extended metadata prefix their value with a 2 byte id
+        let mut prefix = [0; 2];
+        let (prefix, value): (&[u8], &[u8]) = match self {
+            Metadata::PreferredAudioContexts(context)
+            | Metadata::StreamingAudioContexts(context) => {
+                prefix.copy_from_slice(&context.bits().to_le_bytes());
+                (&prefix, &[])
+            }
+            Metadata::ProgramInfo(text) | Metadata::ProgramInfoURI(text) => (&[], text.as_bytes()),
+            Metadata::BroadcastName(text) => (&[], text.as_bytes()),
+            Metadata::Language(code) => (&[], code),
+            Metadata::CCIDList(ccids) => (&[], ccids),
+            Metadata::ParentalRating(rating) => {
+                prefix[0] = *rating as u8;
+                (&prefix[..1], &[])
+            }
+            Metadata::AudioActiveState(state) => {
+                prefix[0] = *state as u8;
+                (&prefix[..1], &[])
+            }
+            Metadata::AssistedListeningStream(stream) => {
+                prefix[0] = *stream as u8;
+                (&prefix[..1], &[])
+            }
+            Metadata::BroadcastAudioImmediateRenderingFlag => (&[], &[]),
+            Metadata::ExtendedMetadata(extended) => {
+                prefix.copy_from_slice(&extended.metadata_type.to_le_bytes());
+                (&prefix, &extended.metadata)
+            }
+            Metadata::VenderSpecific(vender) => {
+                prefix.copy_from_slice(&vender.company_id.to_le_bytes());
+                (&prefix, &vender.metadata)
+            }
+            Metadata::Unrecognized { value, .. } => (&[], value),
+        };
+
+        let value_len = prefix.len() + value.len();
+        let len = 2 + value_len;
+        let Some(buf) = buf.get_mut(..len).filter(|_| value_len < u8::MAX as usize) else {
+            return 0;
+        };
+        buf[0] = 1 + value_len as u8;
+        buf[1] = self.type_id();
+        buf[2..2 + prefix.len()].copy_from_slice(prefix);
+        buf[2 + prefix.len()..].copy_from_slice(value);
+        len
+    }
+
+    /// Read a single LTV encoded metadata from the start of `data`
+    ///
+    /// Returns the metadata and the number of bytes consumed. Metadata of
+    /// unknown types, or borrowing its value, is returned as [`Metadata::Unrecognized`].
+    pub fn decode_ltv(data: &[u8]) -> Result<(Self, usize), MetadataError> {
+        let mut ltvs = LtvIterator::new(data);
+        let (type_id, value) = ltvs.next().ok_or(MetadataError::Truncated)?;
+        Ok((Self::decode_value(type_id, value)?, ltvs.position()))
+    }
+
+    /// Read the metadata of type `type_id` from its LTV `value`
+    pub fn decode_value(type_id: u8, value: &[u8]) -> Result<Self, MetadataError> {
+        let invalid = MetadataError::InvalidValue { type_id };
+
+        let metadata = match (type_id, value) {
+            (1, [low, high]) => Metadata::PreferredAudioContexts(ContextType::from_bits_retain(
+                u16::from_le_bytes([*low, *high]),
+            )),
+            (2, [low, high]) => Metadata::StreamingAudioContexts(ContextType::from_bits_retain(
+                u16::from_le_bytes([*low, *high]),
+            )),
+            (3, text) => Metadata::ProgramInfo(program_info(type_id, text)?),
+            (4, [a, b, c]) => Metadata::Language([*a, *b, *c]),
+            (5, ccids) => {
+                Metadata::CCIDList(Vec::from_slice(ccids).map_err(|_| MetadataError::TooLarge)?)
+            }
+            (6, [rating]) => {
+                Metadata::ParentalRating(ParentalRating::try_from(*rating).map_err(|_| invalid)?)
+            }
+            (7, text) => Metadata::ProgramInfoURI(program_info(type_id, text)?),
+            (8, [state]) => {
+                Metadata::AudioActiveState(AudioActiveState::try_from(*state).map_err(|_| invalid)?)
+            }
+            (9, []) => Metadata::BroadcastAudioImmediateRenderingFlag,
+            (10, [stream]) => Metadata::AssistedListeningStream(
+                AssistedListeningStream::try_from(*stream).map_err(|_| invalid)?,
+            ),
+            (0xFE, [low, high, metadata @ ..]) => Metadata::ExtendedMetadata(ExtendedMetadata {
+                metadata_type: u16::from_le_bytes([*low, *high]),
+                metadata: Vec::from_slice(metadata).map_err(|_| MetadataError::TooLarge)?,
+            }),
+            (0xFF, [low, high, metadata @ ..]) => Metadata::VenderSpecific(VenderSpecific {
+                company_id: u16::from_le_bytes([*low, *high]),
+                metadata: Vec::from_slice(metadata).map_err(|_| MetadataError::TooLarge)?,
+            }),
+            (1 | 2 | 4 | 6 | 8 | 9 | 10 | 0xFE | 0xFF, _) => return Err(invalid),
+            _ => Metadata::Unrecognized {
+                type_id,
+                value: Vec::from_slice(value).map_err(|_| MetadataError::TooLarge)?,
+            },
+        };
+        Ok(metadata)
+    }
+
+    /// Replace the metadata with the program info `title`, like the current track
+    pub fn set_program_info(&mut self, title: &str) -> Result<(), MetadataError> {
+        *self = Metadata::ProgramInfo(
+            String::try_from(title).map_err(|_| MetadataError::StringTooLong)?,
+        );
+        Ok(())
+    }
 }
 
+/// Read the UTF-8 value of a program info or program info URI
+fn program_info(type_id: u8, value: &[u8]) -> Result<String<MAX_PROGRAM_INFO_SIZE>, MetadataError> {
+    let text = core::str::from_utf8(value).map_err(|_| MetadataError::InvalidValue { type_id })?;
+    String::try_from(text).map_err(|_| MetadataError::StringTooLong)
+}
+
+/// Errors decoding LTV encoded [`Metadata`]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataError {
+    /// The data ends before the length announced by the metadata
+    Truncated,
+    /// The value is not valid for the type of the metadata
+    InvalidValue { type_id: u8 },
+    /// The value is larger than [`MAX_METADATA_VALUE_SIZE`], or the CCID List
+    /// holds more than [`MAX_CCIDS`] ids
+    TooLarge,
+    /// The program info is longer than [`MAX_PROGRAM_INFO_SIZE`]
+    StringTooLong,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum ParentalRating {
     NoRating = 0x00,     // No rating
@@ -45,17 +221,23 @@ pub enum ParentalRating {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum ExtendedMetadata {}
+#[derive(Debug, Clone)]
+pub struct ExtendedMetadata {
+    /// Type of the metadata, assigned by the Bluetooth SIG
+    pub metadata_type: u16,
+    pub metadata: Vec<u8, MAX_METADATA_VALUE_SIZE>,
+}
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone)]
 pub struct VenderSpecific {
-    company_id: Option<u8>,
-    vender_specific_metadata: &'static [u8],
+    /// Company identifier assigned by the Bluetooth SIG
+    pub company_id: u16,
+    pub metadata: Vec<u8, MAX_METADATA_VALUE_SIZE>,
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum AudioActiveState {
     NotBeingTransmitted = 0,
@@ -63,8 +245,205 @@ pub enum AudioActiveState {
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum AssistedListeningStream {
     UnspecifiedAudioEnhancement = 0,
 }
+
+impl TryFrom<u8> for ParentalRating {
+    type Error = u8;
+
+    fn try_from(rating: u8) -> Result<Self, Self::Error> {
+        Ok(match rating {
+            0x00 => Self::NoRating,
+            0x01 => Self::AnyAge,
+            0x02 => Self::Age5orOlder,
+            0x03 => Self::Age6orOlder,
+            0x04 => Self::Age7orOlder,
+            0x05 => Self::Age8orOlder,
+            0x06 => Self::Age9orOlder,
+            0x07 => Self::Age10orOlder,
+            0x08 => Self::Age11orOlder,
+            0x09 => Self::Age12orOlder,
+            0x0A => Self::Age13orOlder,
+            0x0B => Self::Age14orOlder,
+            0x0C => Self::Age15orOlder,
+            0x0D => Self::Age16orOlder,
+            0x0E => Self::Age17orOlder,
+            0x0F => Self::Age18orOlder,
+            _ => return Err(rating),
+        })
+    }
+}
+
+impl TryFrom<u8> for AudioActiveState {
+    type Error = u8;
+
+    fn try_from(state: u8) -> Result<Self, Self::Error> {
+        match state {
+            0 => Ok(Self::NotBeingTransmitted),
+            1 => Ok(Self::BeingTransmitted),
+            _ => Err(state),
+        }
+    }
+}
+
+impl TryFrom<u8> for AssistedListeningStream {
+    type Error = u8;
+
+    fn try_from(stream: u8) -> Result<Self, Self::Error> {
+        match stream {
+            0 => Ok(Self::UnspecifiedAudioEnhancement),
+            _ => Err(stream),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_variant() -> [Metadata; 14] {
+        [
+            Metadata::PreferredAudioContexts(ContextType::Media | ContextType::Conversational),
+            Metadata::StreamingAudioContexts(ContextType::Media),
+            Metadata::ProgramInfo(String::try_from("Morning news").unwrap()),
+            Metadata::Language(*b"eng"),
+            Metadata::CCIDList(Vec::from_slice(&[1, 2]).unwrap()),
+            Metadata::ParentalRating(ParentalRating::Age12orOlder),
+            Metadata::ProgramInfoURI(String::try_from("https://example.com").unwrap()),
+            Metadata::AudioActiveState(AudioActiveState::BeingTransmitted),
+            Metadata::BroadcastAudioImmediateRenderingFlag,
+            Metadata::AssistedListeningStream(AssistedListeningStream::UnspecifiedAudioEnhancement),
+            Metadata::BroadcastName("Gate 4"),
+            Metadata::ExtendedMetadata(ExtendedMetadata {
+                metadata_type: 0x1234,
+                metadata: Vec::from_slice(&[0xAA]).unwrap(),
+            }),
+            Metadata::VenderSpecific(VenderSpecific {
+                company_id: 0x0059,
+                metadata: Vec::from_slice(&[0xBB, 0xCC]).unwrap(),
+            }),
+            Metadata::Unrecognized {
+                type_id: 0x42,
+                value: Vec::from_slice(&[1, 2, 3]).unwrap(),
+            },
+        ]
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        for metadata in every_variant() {
+            let mut buf = [0; 32];
+            let len = metadata.encode_ltv(&mut buf);
+            assert_eq!(buf[0] as usize, len - 1);
+            assert_eq!(buf[1], metadata.type_id());
+
+            let (decoded, consumed) = Metadata::decode_ltv(&buf[..len + 2]).unwrap();
+            assert_eq!(consumed, len);
+            assert_eq!(decoded.type_id(), metadata.type_id());
+            let mut reencoded = [0; 32];
+            assert_eq!(decoded.encode_ltv(&mut reencoded), len);
+            assert_eq!(reencoded[..len], buf[..len]);
+        }
+    }
+
+    #[test]
+    fn values_are_little_endian_after_the_type() {
+        let mut buf = [0; 8];
+        let contexts = Metadata::StreamingAudioContexts(ContextType::Media);
+        assert_eq!(contexts.encode_ltv(&mut buf), 4);
+        assert_eq!(buf[..4], [3, 2, 0x04, 0x00]);
+
+        let vender = &every_variant()[12];
+        assert_eq!(vender.encode_ltv(&mut buf), 6);
+        assert_eq!(buf[..6], [5, 0xFF, 0x59, 0x00, 0xBB, 0xCC]);
+
+        assert_eq!(
+            Metadata::BroadcastAudioImmediateRenderingFlag.encode_ltv(&mut buf),
+            2
+        );
+        assert_eq!(buf[..2], [1, 9]);
+    }
+
+    #[test]
+    fn unknown_and_borrowed_types_decode_as_unrecognized() {
+        let (metadata, _) = Metadata::decode_ltv(&[3, 0x42, 1, 2]).unwrap();
+        assert!(matches!(
+            metadata,
+            Metadata::Unrecognized { type_id: 0x42, ref value } if value == &[1, 2]
+        ));
+
+        // The broadcast name can not borrow from the decoded data
+        let (metadata, _) = Metadata::decode_ltv(&[3, 11, b'h', b'i']).unwrap();
+        assert!(matches!(
+            metadata,
+            Metadata::Unrecognized { type_id: 11, ref value } if value == b"hi"
+        ));
+    }
+
+    #[test]
+    fn malformed_metadata_is_rejected() {
+        assert_eq!(
+            Metadata::decode_ltv(&[]).err(),
+            Some(MetadataError::Truncated)
+        );
+        assert_eq!(
+            Metadata::decode_ltv(&[3, 2, 0x04]).err(),
+            Some(MetadataError::Truncated)
+        );
+        assert_eq!(
+            Metadata::decode_ltv(&[2, 2, 0x04]).err(),
+            Some(MetadataError::InvalidValue { type_id: 2 })
+        );
+        assert_eq!(
+            Metadata::decode_ltv(&[2, 6, 0x10]).err(),
+            Some(MetadataError::InvalidValue { type_id: 6 })
+        );
+        assert_eq!(
+            Metadata::decode_ltv(&[3, 3, 0xFF, 0xFE]).err(),
+            Some(MetadataError::InvalidValue { type_id: 3 })
+        );
+        let mut too_large = [0; 2 + MAX_METADATA_VALUE_SIZE + 1];
+        too_large[0] = 1 + MAX_METADATA_VALUE_SIZE as u8 + 1;
+        too_large[1] = 0x42;
+        assert_eq!(
+            Metadata::decode_ltv(&too_large).err(),
+            Some(MetadataError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn encoding_into_a_small_buffer_writes_nothing() {
+        let mut buf = [0xEE; 3];
+        let contexts = Metadata::StreamingAudioContexts(ContextType::Media);
+        assert_eq!(contexts.encode_ltv(&mut buf), 0);
+        assert_eq!(buf, [0xEE; 3]);
+    }
+
+    #[test]
+    fn program_info_holds_at_most_64_bytes() {
+        let mut metadata = Metadata::BroadcastAudioImmediateRenderingFlag;
+        let longest = "a".repeat(MAX_PROGRAM_INFO_SIZE);
+        assert_eq!(metadata.set_program_info(&longest), Ok(()));
+        assert!(matches!(&metadata, Metadata::ProgramInfo(title) if title.as_str() == longest));
+
+        let too_long = "a".repeat(MAX_PROGRAM_INFO_SIZE + 1);
+        assert_eq!(
+            metadata.set_program_info(&too_long),
+            Err(MetadataError::StringTooLong)
+        );
+        // The metadata is left unchanged
+        assert!(matches!(&metadata, Metadata::ProgramInfo(title) if title.as_str() == longest));
+
+        let mut buf = [0; 2 + MAX_PROGRAM_INFO_SIZE + 1];
+        buf[0] = 1 + MAX_PROGRAM_INFO_SIZE as u8 + 1;
+        buf[1] = 3;
+        buf[2..].fill(b'a');
+        assert_eq!(
+            Metadata::decode_ltv(&buf).err(),
+            Some(MetadataError::StringTooLong)
+        );
+    }
+}
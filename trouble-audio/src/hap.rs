@@ -0,0 +1,859 @@
+//! Hearing Access Profile
+//!
+//! This service exposes the features of a hearing aid and its presets, the
+//! hearing programs a user can switch between. Clients read the presets,
+//! rename the writable ones and select the active one.
+
+use bitflags::bitflags;
+use bt_hci::uuid::{characteristic, service};
+use core::{
+    cell::{Cell, RefCell},
+    slice,
+};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    channel::Channel,
+};
+use heapless::{Deque, Vec};
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::{info, warn};
+
+use crate::{LeAudioError, LeAudioServerService, Utf8Text};
+
+/// Attributes added to the attribute table by the Hap service
+pub const HAP_ATTRIBUTES: usize = 10;
+
+/// Max number of presets of a hearing aid
+///
+/// The specification allows up to 255, indexed from 1.
+pub const MAX_PRESETS: usize = 16;
+
+/// Max length of the UTF-8 name of a preset
+pub const MAX_PRESET_NAME_SIZE: usize = 40;
+
+/// Size of the largest Preset Control Point value, a Generic Update of a preset
+const PRESET_CONTROL_POINT_SIZE: usize = 6 + MAX_PRESET_NAME_SIZE;
+
+/// Number of events the Hap event channel can queue
+pub const HAP_EVENT_QUEUE_SIZE: usize = 4;
+
+/// Channel the Hap server publishes [`HapEvent`]s on
+pub type HapEventChannel = Channel<NoopRawMutex, HapEvent, HAP_EVENT_QUEUE_SIZE>;
+
+/// Errors of the Hap service, sent to clients as the application errors of the specification
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HapError {
+    /// The control point opcode is not one a client can write
+    InvalidOpcode,
+    /// The preset is not writable
+    WriteNameNotAllowed,
+    /// The hearing aid does not synchronize its presets with the other of the set
+    PresetSynchronizationNotSupported,
+    /// No preset allows the operation, like a preset that does not exist or is unavailable
+    PresetOperationNotPossible,
+    /// The operands of the control point operation have the wrong length
+    InvalidParametersLength,
+}
+
+impl From<HapError> for AttErrorCode {
+    fn from(err: HapError) -> Self {
+        match err {
+            HapError::InvalidOpcode => AttErrorCode::APPLICATION_ERROR_0x80,
+            HapError::WriteNameNotAllowed => AttErrorCode::APPLICATION_ERROR_0x81,
+            HapError::PresetSynchronizationNotSupported => AttErrorCode::APPLICATION_ERROR_0x82,
+            HapError::PresetOperationNotPossible => AttErrorCode::APPLICATION_ERROR_0x83,
+            HapError::InvalidParametersLength => AttErrorCode::APPLICATION_ERROR_0x84,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of a [`HapServer`]
+pub struct HapStorage {
+    features: [u8; HearingAidFeatures::SIZE],
+    preset_control_point: [u8; PRESET_CONTROL_POINT_SIZE],
+    active_preset_index: [u8; 1],
+}
+
+impl HapStorage {
+    pub const fn new() -> Self {
+        Self {
+            features: [0; HearingAidFeatures::SIZE],
+            preset_control_point: [0; PRESET_CONTROL_POINT_SIZE],
+            active_preset_index: [0; 1],
+        }
+    }
+}
+
+impl Default for HapStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A hearing program of the hearing aid
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preset {
+    /// Index identifying the preset, from 1
+    pub index: u8,
+    pub properties: PresetProperties,
+    pub name: PresetName,
+}
+
+/// A Gatt service exposing the presets of a hearing aid
+pub struct HapServer {
+    handle: u16,
+    features: Characteristic<HearingAidFeatures>,
+    preset_control_point: Characteristic<PresetControlPointValue>,
+    active_preset_index: Characteristic<u8>,
+    supported: HearingAidFeatures,
+    /// The presets, sorted by index
+    presets: RefCell<Vec<Preset, MAX_PRESETS>>,
+    active: Cell<u8>,
+    active_changed: Cell<bool>,
+    pending_values: RefCell<Deque<PresetControlPointValue, MAX_PRESETS>>,
+    events: Option<&'static HapEventChannel>,
+}
+
+impl HapServer {
+    /// Create a new Hap Gatt Service
+    ///
+    /// Presets with the index 0 or an index used by another preset are dropped.
+    /// `active_preset` is 0 when no preset is active.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        features: HearingAidFeatures,
+        mut presets: Vec<Preset, MAX_PRESETS>,
+        active_preset: u8,
+        storage: &'a mut HapStorage,
+    ) -> Self {
+        presets.sort_unstable_by_key(|preset| preset.index);
+        let mut previous = 0;
+        presets.retain(|preset| {
+            let valid = preset.index > previous;
+            previous = preset.index;
+            valid
+        });
+
+        let mut service = table.add_service(Service::new(service::HEARING_ACCESS));
+
+        let features_char = service
+            .add_characteristic(
+                characteristic::HEARING_AID_FEATURES,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                features,
+                &mut storage.features,
+            )
+            .build();
+
+        let preset_control_point_char = service
+            .add_characteristic(
+                characteristic::HEARING_AID_PRESET_CONTROL_POINT,
+                &[CharacteristicProp::Write, CharacteristicProp::Notify],
+                PresetControlPointValue::default(),
+                &mut storage.preset_control_point,
+            )
+            .build();
+
+        let active_preset_index_char = service
+            .add_characteristic(
+                characteristic::ACTIVE_PRESET_INDEX,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                active_preset,
+                &mut storage.active_preset_index,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            features: features_char,
+            preset_control_point: preset_control_point_char,
+            active_preset_index: active_preset_index_char,
+            supported: features,
+            presets: RefCell::new(presets),
+            active: Cell::new(active_preset),
+            active_changed: Cell::new(false),
+            pending_values: RefCell::new(Deque::new()),
+            events: None,
+        }
+    }
+
+    /// Surface the preset changes made by clients to the application
+    ///
+    /// The application task should receive from the channel and switch the
+    /// hearing program of the hearing aid.
+    pub fn with_event_channel(mut self, channel: &'static HapEventChannel) -> Self {
+        self.events = Some(channel);
+        self
+    }
+
+    fn emit(&self, event: HapEvent) {
+        if let Some(events) = self.events {
+            if events.try_send(event).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[hap] event channel is full, dropping event");
+            }
+        }
+    }
+
+    /// The handle of the service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Hearing Aid Features characteristic
+    pub fn features_characteristic(&self) -> &Characteristic<HearingAidFeatures> {
+        &self.features
+    }
+
+    /// The Hearing Aid Preset Control Point characteristic
+    pub fn preset_control_point_characteristic(&self) -> &Characteristic<PresetControlPointValue> {
+        &self.preset_control_point
+    }
+
+    /// The Active Preset Index characteristic
+    pub fn active_preset_index_characteristic(&self) -> &Characteristic<u8> {
+        &self.active_preset_index
+    }
+
+    /// The presets of the hearing aid, sorted by index
+    pub fn presets(&self) -> Vec<Preset, MAX_PRESETS> {
+        self.presets.borrow().clone()
+    }
+
+    /// The index of the active preset, 0 when none is active
+    pub fn active_preset(&self) -> u8 {
+        self.active.get()
+    }
+
+    /// Activate the preset `index`, as the user did on the hearing aid
+    pub fn set_active_preset(&self, index: u8) -> Result<(), HapError> {
+        let available = self
+            .presets
+            .borrow()
+            .iter()
+            .any(|preset| preset.index == index && preset.is_available());
+        if !available {
+            return Err(HapError::PresetOperationNotPossible);
+        }
+        self.activate(index);
+        Ok(())
+    }
+
+    /// Make the preset `index` available or not, like a program needing a streaming source
+    pub fn set_preset_available(&self, index: u8, available: bool) -> Result<(), HapError> {
+        let mut presets = self.presets.borrow_mut();
+        let preset = presets
+            .iter_mut()
+            .find(|preset| preset.index == index)
+            .ok_or(HapError::PresetOperationNotPossible)?;
+        if preset.is_available() == available {
+            return Ok(());
+        }
+        preset
+            .properties
+            .set(PresetProperties::Available, available);
+        let change = if available {
+            PresetChangeId::PresetAvailable
+        } else {
+            PresetChangeId::PresetUnavailable
+        };
+        self.queue(PresetControlPointValue::preset_index_changed(change, index));
+        Ok(())
+    }
+
+    /// Remove the preset `index` from the hearing aid
+    pub fn remove_preset(&self, index: u8) -> Result<(), HapError> {
+        let mut presets = self.presets.borrow_mut();
+        let position = presets
+            .iter()
+            .position(|preset| preset.index == index)
+            .ok_or(HapError::PresetOperationNotPossible)?;
+        presets.remove(position);
+        self.queue(PresetControlPointValue::preset_index_changed(
+            PresetChangeId::PresetDeleted,
+            index,
+        ));
+        if self.active.get() == index {
+            self.active.set(0);
+            self.active_changed.set(true);
+        }
+        Ok(())
+    }
+
+    /// The active preset to notify to clients, if it changed since the last call
+    pub fn take_active_preset_change(&self) -> Option<u8> {
+        self.active_changed
+            .replace(false)
+            .then(|| self.active.get())
+    }
+
+    /// The next value to notify on the control point, call it until it returns `None`
+    pub fn take_control_point_value(&self) -> Option<PresetControlPointValue> {
+        self.pending_values.borrow_mut().pop_front()
+    }
+
+    fn queue(&self, value: PresetControlPointValue) {
+        if self.pending_values.borrow_mut().push_back(value).is_err() {
+            #[cfg(feature = "defmt")]
+            warn!("[hap] control point queue is full, dropping value");
+        }
+    }
+
+    fn activate(&self, index: u8) {
+        if self.active.replace(index) != index {
+            self.active_changed.set(true);
+        }
+    }
+
+    /// Perform a preset control point operation
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, operand @ ..] = data else {
+            return Err(HapError::InvalidParametersLength.into());
+        };
+        let opcode = PresetControlOpcode::try_from(*opcode).map_err(|_| HapError::InvalidOpcode)?;
+
+        #[cfg(feature = "defmt")]
+        info!("[hap] {:?}", opcode);
+        match opcode {
+            PresetControlOpcode::ReadPresetsRequest => self.read_presets(operand),
+            PresetControlOpcode::WritePresetName => self.write_preset_name(operand),
+            PresetControlOpcode::SetActivePreset
+            | PresetControlOpcode::SetActivePresetSynchronized => {
+                let synchronize = opcode == PresetControlOpcode::SetActivePresetSynchronized;
+                self.check_synchronization(synchronize)?;
+                let [index] = operand else {
+                    return Err(HapError::InvalidParametersLength.into());
+                };
+                self.select(*index, synchronize)
+            }
+            PresetControlOpcode::SetNextPreset
+            | PresetControlOpcode::SetPreviousPreset
+            | PresetControlOpcode::SetNextPresetSynchronized
+            | PresetControlOpcode::SetPreviousPresetSynchronized => {
+                let synchronize = matches!(
+                    opcode,
+                    PresetControlOpcode::SetNextPresetSynchronized
+                        | PresetControlOpcode::SetPreviousPresetSynchronized
+                );
+                self.check_synchronization(synchronize)?;
+                if !operand.is_empty() {
+                    return Err(HapError::InvalidParametersLength.into());
+                }
+                let next = matches!(
+                    opcode,
+                    PresetControlOpcode::SetNextPreset
+                        | PresetControlOpcode::SetNextPresetSynchronized
+                );
+                let index = self
+                    .neighbour(next)
+                    .ok_or(HapError::PresetOperationNotPossible)?;
+                self.select(index, synchronize)
+            }
+            PresetControlOpcode::ReadPresetResponse | PresetControlOpcode::PresetChanged => {
+                Err(HapError::InvalidOpcode.into())
+            }
+        }
+    }
+
+    /// The synchronized operations need the presets to be synchronized with the set
+    fn check_synchronization(&self, synchronize: bool) -> Result<(), HapError> {
+        if synchronize
+            && !self
+                .supported
+                .flags()
+                .contains(HearingAidFeatureFlags::PresetSynchronizationSupport)
+        {
+            return Err(HapError::PresetSynchronizationNotSupported);
+        }
+        Ok(())
+    }
+
+    /// Queue a Read Preset Response for each preset requested
+    ///
+    /// A start index above every preset is reported as not possible.
+    fn read_presets(&self, operand: &[u8]) -> Result<(), LeAudioError> {
+        let [start_index, num_presets] = operand else {
+            return Err(HapError::InvalidParametersLength.into());
+        };
+        if *start_index == 0 || *num_presets == 0 {
+            return Err(HapError::PresetOperationNotPossible.into());
+        }
+        let presets = self.presets.borrow();
+        let mut requested = presets
+            .iter()
+            .filter(|preset| preset.index >= *start_index)
+            .take(*num_presets as usize)
+            .peekable();
+        if requested.peek().is_none() {
+            return Err(HapError::PresetOperationNotPossible.into());
+        }
+        while let Some(preset) = requested.next() {
+            let is_last = requested.peek().is_none();
+            self.queue(PresetControlPointValue::read_preset_response(
+                is_last, preset,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rename a writable preset and report it with a Generic Update
+    fn write_preset_name(&self, operand: &[u8]) -> Result<(), LeAudioError> {
+        let [index, name @ ..] = operand else {
+            return Err(HapError::InvalidParametersLength.into());
+        };
+        let name = core::str::from_utf8(name)
+            .ok()
+            .and_then(|name| PresetName::new(name).ok())
+            .filter(|name| !name.is_empty())
+            .ok_or(HapError::InvalidParametersLength)?;
+
+        let mut presets = self.presets.borrow_mut();
+        let position = presets
+            .iter()
+            .position(|preset| preset.index == *index)
+            .ok_or(HapError::PresetOperationNotPossible)?;
+        if !presets[position]
+            .properties
+            .contains(PresetProperties::Writable)
+        {
+            return Err(HapError::WriteNameNotAllowed.into());
+        }
+        presets[position].name = name.clone();
+
+        let previous_index = position
+            .checked_sub(1)
+            .map_or(0, |previous| presets[previous].index);
+        self.queue(PresetControlPointValue::generic_update(
+            previous_index,
+            &presets[position],
+        ));
+        self.emit(HapEvent::PresetRenamed {
+            index: *index,
+            name,
+        });
+        Ok(())
+    }
+
+    /// Activate an available preset at the request of a client
+    fn select(&self, index: u8, synchronize: bool) -> Result<(), LeAudioError> {
+        self.set_active_preset(index)?;
+        self.emit(HapEvent::ActivePresetChanged { index, synchronize });
+        Ok(())
+    }
+
+    /// The available preset after or before the active one, wrapping around
+    fn neighbour(&self, next: bool) -> Option<u8> {
+        let presets = self.presets.borrow();
+        let mut available = presets
+            .iter()
+            .filter(|preset| preset.is_available())
+            .map(|preset| preset.index);
+        let active = self.active.get();
+        if next {
+            available
+                .clone()
+                .find(|index| *index > active)
+                .or_else(|| available.next())
+        } else {
+            available
+                .clone()
+                .rev()
+                .find(|index| *index < active)
+                .or_else(|| available.next_back())
+        }
+    }
+}
+
+impl LeAudioServerService for HapServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.features.handle || handle == self.active_preset_index.handle {
+            Some(Ok(()))
+        } else if handle == self.preset_control_point.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.preset_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.features.handle || handle == self.active_preset_index.handle {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Preset changes a client asked for
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HapEvent {
+    /// The preset `index` is now active, `synchronize` when the other hearing
+    /// aid of the set should switch to it too
+    ActivePresetChanged { index: u8, synchronize: bool },
+    /// A writable preset was renamed
+    PresetRenamed { index: u8, name: PresetName },
+}
+
+/// Type of the hearing aid, held in the two lowest bits of its features
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HearingAidType {
+    /// One of a pair of hearing aids
+    #[default]
+    Binaural = 0x00,
+    /// A single hearing aid
+    Monaural = 0x01,
+    /// A single device for both ears
+    Banded = 0x02,
+}
+
+impl TryFrom<u8> for HearingAidType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x00 => Self::Binaural,
+            0x01 => Self::Monaural,
+            0x02 => Self::Banded,
+            _ => return Err(value),
+        })
+    }
+}
+
+bitflags! {
+    /// Features of the hearing aid besides its type
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct HearingAidFeatureFlags: u8 {
+        /// The presets are synchronized with the other hearing aid of the set
+        const PresetSynchronizationSupport = 0x04;
+        /// The presets differ from the other hearing aid of the set
+        const IndependentPresets = 0x08;
+        /// The list of presets can change
+        const DynamicPresets = 0x10;
+        /// Some presets can be renamed
+        const WritablePresetsSupport = 0x20;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for HearingAidFeatureFlags {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "HearingAidFeatureFlags({=u8:#04x})", self.bits())
+    }
+}
+
+/// Value of the Hearing Aid Features characteristic
+///
+/// Encoded as one octet, the [`HearingAidType`] in the two lowest bits and
+/// the [`HearingAidFeatureFlags`] above them.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct HearingAidFeatures(u8);
+
+impl HearingAidFeatures {
+    const TYPE_MASK: u8 = 0x03;
+
+    pub const fn new(hearing_aid_type: HearingAidType, flags: HearingAidFeatureFlags) -> Self {
+        Self(hearing_aid_type as u8 | (flags.bits() & !Self::TYPE_MASK))
+    }
+
+    pub fn hearing_aid_type(&self) -> HearingAidType {
+        // The reserved type is rejected when decoding
+        HearingAidType::try_from(self.0 & Self::TYPE_MASK).unwrap_or_default()
+    }
+
+    pub fn flags(&self) -> HearingAidFeatureFlags {
+        HearingAidFeatureFlags::from_bits_retain(self.0 & !Self::TYPE_MASK)
+    }
+}
+
+impl FixedGattValue for HearingAidFeatures {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [features] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        HearingAidType::try_from(features & Self::TYPE_MASK)
+            .map(|_| Self(*features))
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+bitflags! {
+    /// Properties of a preset
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PresetProperties: u8 {
+        /// Clients can rename the preset
+        const Writable = 0x01;
+        /// The preset can be activated
+        const Available = 0x02;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for PresetProperties {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "PresetProperties({=u8:#04x})", self.bits())
+    }
+}
+
+impl Preset {
+    fn is_available(&self) -> bool {
+        self.properties.contains(PresetProperties::Available)
+    }
+}
+
+/// UTF-8 name of a preset, from 1 to [`MAX_PRESET_NAME_SIZE`] bytes
+pub type PresetName = Utf8Text<MAX_PRESET_NAME_SIZE>;
+
+/// Operations of the Hearing Aid Preset Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PresetControlOpcode {
+    ReadPresetsRequest = 0x01,
+    /// Notified by the server for each preset requested
+    ReadPresetResponse = 0x02,
+    /// Notified by the server when the presets change
+    PresetChanged = 0x03,
+    WritePresetName = 0x04,
+    SetActivePreset = 0x05,
+    SetNextPreset = 0x06,
+    SetPreviousPreset = 0x07,
+    SetActivePresetSynchronized = 0x08,
+    SetNextPresetSynchronized = 0x09,
+    SetPreviousPresetSynchronized = 0x0A,
+}
+
+impl TryFrom<u8> for PresetControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x01 => Self::ReadPresetsRequest,
+            0x02 => Self::ReadPresetResponse,
+            0x03 => Self::PresetChanged,
+            0x04 => Self::WritePresetName,
+            0x05 => Self::SetActivePreset,
+            0x06 => Self::SetNextPreset,
+            0x07 => Self::SetPreviousPreset,
+            0x08 => Self::SetActivePresetSynchronized,
+            0x09 => Self::SetNextPresetSynchronized,
+            0x0A => Self::SetPreviousPresetSynchronized,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+/// Kind of change reported by a Preset Changed notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum PresetChangeId {
+    GenericUpdate = 0x00,
+    PresetDeleted = 0x01,
+    PresetAvailable = 0x02,
+    PresetUnavailable = 0x03,
+}
+
+/// Notified on the Hearing Aid Preset Control Point
+///
+/// Either a Read Preset Response, or a Preset Changed reporting a change of
+/// the presets to clients.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PresetControlPointValue(Vec<u8, PRESET_CONTROL_POINT_SIZE>);
+
+impl PresetControlPointValue {
+    /// Read Preset Response, encoded as IsLast then the preset record
+    fn read_preset_response(is_last: bool, preset: &Preset) -> Self {
+        let mut value = Vec::new();
+        let _ = value
+            .extend_from_slice(&[PresetControlOpcode::ReadPresetResponse as u8, is_last as u8]);
+        let _ = value.extend_from_slice(&Self::preset_record(preset));
+        Self(value)
+    }
+
+    /// Preset Changed with a Generic Update, encoded as the index of the
+    /// preceding preset then the preset record
+    fn generic_update(previous_index: u8, preset: &Preset) -> Self {
+        let mut value = Vec::new();
+        let _ = value.extend_from_slice(&[
+            PresetControlOpcode::PresetChanged as u8,
+            PresetChangeId::GenericUpdate as u8,
+            1,
+            previous_index,
+        ]);
+        let _ = value.extend_from_slice(&Self::preset_record(preset));
+        Self(value)
+    }
+
+    /// Preset Changed reporting a preset deleted, available or unavailable
+    fn preset_index_changed(change: PresetChangeId, index: u8) -> Self {
+        let mut value = Vec::new();
+        let _ = value.extend_from_slice(&[
+            PresetControlOpcode::PresetChanged as u8,
+            change as u8,
+            1,
+            index,
+        ]);
+        Self(value)
+    }
+
+    /// Index, properties and name of a preset
+    fn preset_record(preset: &Preset) -> Vec<u8, { 2 + MAX_PRESET_NAME_SIZE }> {
+        let mut record = Vec::new();
+        let _ = record.extend_from_slice(&[preset.index, preset.properties.bits()]);
+        let _ = record.extend_from_slice(preset.name.as_bytes());
+        record
+    }
+}
+
+impl AsGatt for PresetControlPointValue {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = PRESET_CONTROL_POINT_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for PresetControlPointValue {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trouble_host::attribute::AttributeTable;
+
+    /// Available presets 1 and 3, and the unavailable preset 5
+    fn presets() -> Vec<Preset, MAX_PRESETS> {
+        let mut presets = Vec::new();
+        for (index, name, properties) in [
+            (5, "Music", PresetProperties::Writable),
+            (1, "Universal", PresetProperties::Available),
+            (3, "Outdoor", PresetProperties::Available),
+        ] {
+            let _ = presets.push(Preset {
+                index,
+                properties,
+                name: PresetName::new(name).unwrap(),
+            });
+        }
+        presets
+    }
+
+    fn write(hap: &HapServer, data: &[u8]) -> Result<(), AttErrorCode> {
+        hap.handle_write(hap.preset_control_point.handle, data)
+            .unwrap()
+            .map_err(AttErrorCode::from)
+    }
+
+    #[test]
+    fn presets_are_read_from_the_start_index() {
+        let mut storage = HapStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, HAP_ATTRIBUTES> = AttributeTable::new();
+        let hap = HapServer::new(
+            &mut table,
+            HearingAidFeatures::default(),
+            presets(),
+            1,
+            &mut storage,
+        );
+
+        write(&hap, &[PresetControlOpcode::ReadPresetsRequest as u8, 2, 4]).unwrap();
+        let first = hap.take_control_point_value().unwrap();
+        assert_eq!(first.as_gatt()[..4], [0x02, 0, 3, 0x02]);
+        assert_eq!(&first.as_gatt()[4..], b"Outdoor");
+        let last = hap.take_control_point_value().unwrap();
+        assert_eq!(last.as_gatt()[..4], [0x02, 1, 5, 0x01]);
+        assert_eq!(hap.take_control_point_value(), None);
+
+        assert_eq!(
+            write(&hap, &[PresetControlOpcode::ReadPresetsRequest as u8, 6, 1]),
+            Err(AttErrorCode::APPLICATION_ERROR_0x83)
+        );
+    }
+
+    #[test]
+    fn next_and_previous_wrap_around_the_available_presets() {
+        let mut storage = HapStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, HAP_ATTRIBUTES> = AttributeTable::new();
+        let hap = HapServer::new(
+            &mut table,
+            HearingAidFeatures::default(),
+            presets(),
+            1,
+            &mut storage,
+        );
+
+        write(&hap, &[PresetControlOpcode::SetNextPreset as u8]).unwrap();
+        assert_eq!(hap.active_preset(), 3);
+        write(&hap, &[PresetControlOpcode::SetNextPreset as u8]).unwrap();
+        assert_eq!(hap.active_preset(), 1);
+        write(&hap, &[PresetControlOpcode::SetPreviousPreset as u8]).unwrap();
+        assert_eq!(hap.active_preset(), 3);
+        write(&hap, &[PresetControlOpcode::SetPreviousPreset as u8]).unwrap();
+        assert_eq!(hap.active_preset(), 1);
+    }
+
+    #[test]
+    fn invalid_preset_index_is_rejected() {
+        let mut storage = HapStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, HAP_ATTRIBUTES> = AttributeTable::new();
+        let hap = HapServer::new(
+            &mut table,
+            HearingAidFeatures::default(),
+            presets(),
+            1,
+            &mut storage,
+        );
+
+        for index in [0, 2, 5] {
+            assert_eq!(
+                write(&hap, &[PresetControlOpcode::SetActivePreset as u8, index]),
+                Err(AttErrorCode::APPLICATION_ERROR_0x83)
+            );
+        }
+        assert_eq!(
+            write(&hap, &[PresetControlOpcode::ReadPresetsRequest as u8, 0, 1]),
+            Err(AttErrorCode::APPLICATION_ERROR_0x83)
+        );
+        assert_eq!(hap.active_preset(), 1);
+    }
+
+    #[test]
+    fn features_hold_the_type_in_the_lowest_bits() {
+        let features = HearingAidFeatures::new(
+            HearingAidType::Banded,
+            HearingAidFeatureFlags::DynamicPresets,
+        );
+        assert_eq!(
+            <HearingAidFeatures as FixedGattValue>::as_gatt(&features),
+            &[0x12]
+        );
+        assert_eq!(features.hearing_aid_type(), HearingAidType::Banded);
+        assert_eq!(features.flags(), HearingAidFeatureFlags::DynamicPresets);
+
+        let binaural = <HearingAidFeatures as FixedGattValue>::from_gatt(&[0x04]).unwrap();
+        assert_eq!(binaural.hearing_aid_type(), HearingAidType::Binaural);
+        assert!(<HearingAidFeatures as FixedGattValue>::from_gatt(&[0x03]).is_err());
+    }
+}
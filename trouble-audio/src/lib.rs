@@ -2,25 +2,117 @@
 // #![warn(missing_docs)]
 #![feature(generic_const_exprs)]
 
+pub mod aics;
 #[allow(dead_code)]
 pub mod ascs;
 mod server;
 pub use server::*;
 mod client;
 pub use client::*;
-// pub mod bap;
+pub mod bap;
+pub mod ccid;
+pub mod ccs;
+pub mod csis;
 pub mod generic_audio;
+pub mod hap;
+pub mod mcs;
+pub mod mics;
 pub mod pacs;
+mod text;
+pub mod tmap;
+pub use text::*;
+pub mod vcs;
+pub mod vocs;
 
 pub type ContentControlID = u8;
 
+/// Codec_ID field identifying the codec of a stream
+///
+/// Transmitted as 5 octets: the coding format, then the company id and the
+/// vendor specific codec id, both little endian.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct CodecId(u64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecId {
+    pub coding_format: u8,
+    pub company_id: u16,
+    pub vendor_specific_codec_id: u16,
+}
+
+impl CodecId {
+    /// Size of the Codec_ID field
+    pub const SIZE: usize = 5;
+
+    /// Coding format of the codecs defined by a vendor
+    pub const VENDOR_SPECIFIC: u8 = 0xFF;
+
+    /// A coding format assigned by the Bluetooth SIG
+    pub const fn assigned(coding_format: u8) -> Self {
+        Self {
+            coding_format,
+            company_id: 0,
+            vendor_specific_codec_id: 0,
+        }
+    }
+
+    /// The LC3 codec
+    pub const fn lc3() -> Self {
+        codec_ids::LC3
+    }
+
+    /// A codec defined by the company `company_id`
+    pub const fn vendor(company_id: u16, vendor_id: u16) -> Self {
+        Self {
+            coding_format: Self::VENDOR_SPECIFIC,
+            company_id,
+            vendor_specific_codec_id: vendor_id,
+        }
+    }
+
+    /// The codec uses the LC3 coding format
+    pub fn is_lc3(&self) -> bool {
+        self.coding_format == codec_ids::LC3.coding_format
+    }
+
+    pub fn encode(&self, buf: &mut [u8; Self::SIZE]) {
+        buf[0] = self.coding_format;
+        buf[1..3].copy_from_slice(&self.company_id.to_le_bytes());
+        buf[3..5].copy_from_slice(&self.vendor_specific_codec_id.to_le_bytes());
+    }
+
+    pub fn decode(buf: &[u8; Self::SIZE]) -> Self {
+        Self {
+            coding_format: buf[0],
+            company_id: u16::from_le_bytes([buf[1], buf[2]]),
+            vendor_specific_codec_id: u16::from_le_bytes([buf[3], buf[4]]),
+        }
+    }
+
+    /// The Codec_ID field
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut buf = [0; Self::SIZE];
+        self.encode(&mut buf);
+        buf
+    }
+}
 
 impl Default for CodecId {
     fn default() -> Self {
-        Self(0x000000000D)
+        Self::lc3()
     }
 }
+
+/// Coding formats assigned by the Bluetooth SIG
+///
+/// The company and vendor specific codec ids are 0 for all of them.
+pub mod codec_ids {
+    use super::CodecId;
+
+    pub const ULAW: CodecId = CodecId::assigned(0x00);
+    pub const ALAW: CodecId = CodecId::assigned(0x01);
+    pub const CVSD: CodecId = CodecId::assigned(0x02);
+    pub const TRANSPARENT: CodecId = CodecId::assigned(0x03);
+    pub const LINEAR_PCM: CodecId = CodecId::assigned(0x04);
+    pub const MSBC: CodecId = CodecId::assigned(0x05);
+    pub const LC3: CodecId = CodecId::assigned(0x06);
+    pub const G729A: CodecId = CodecId::assigned(0x07);
+}
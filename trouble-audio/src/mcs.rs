@@ -0,0 +1,1015 @@
+//! Generic Media Control Service
+//!
+//! This service exposes the media player of a device, its current track and
+//! playback state, and lets clients control the playback. The operations
+//! written by clients are handed to the application, which owns the player.
+
+use bitflags::bitflags;
+use bt_hci::uuid::{characteristic, service};
+use core::{
+    cell::{Cell, RefCell},
+    slice,
+};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    channel::Channel,
+};
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::{info, warn};
+
+use crate::{ContentControlID, LeAudioError, LeAudioServerService, Utf8Text};
+
+/// Attributes added to the attribute table by the Mcs service
+pub const MCS_ATTRIBUTES: usize = 34;
+
+/// Max length of the UTF-8 player name, icon URL and track title
+pub const MAX_MEDIA_TEXT_SIZE: usize = 64;
+
+/// Track duration or position of a track whose length is unknown
+pub const TRACK_POSITION_UNAVAILABLE: i32 = -1;
+
+/// Number of events the Mcs event channel can queue
+pub const MCS_EVENT_QUEUE_SIZE: usize = 4;
+
+/// Channel the Mcs server publishes [`McsEvent`]s on
+pub type McsEventChannel = Channel<NoopRawMutex, McsEvent, MCS_EVENT_QUEUE_SIZE>;
+
+/// Backing storage for the characteristics of a [`McsServer`]
+pub struct McsStorage {
+    media_player_name: [u8; MAX_MEDIA_TEXT_SIZE],
+    media_player_icon_url: [u8; MAX_MEDIA_TEXT_SIZE],
+    track_changed: [u8; 1],
+    track_title: [u8; MAX_MEDIA_TEXT_SIZE],
+    track_duration: [u8; 4],
+    track_position: [u8; 4],
+    playback_speed: [u8; 1],
+    playing_order: [u8; PlayingOrder::SIZE],
+    media_state: [u8; MediaState::SIZE],
+    media_control_point: [u8; 5],
+    supported_opcodes: [u8; MediaControlOpcodes::SIZE],
+    content_control_id: [u8; 1],
+}
+
+impl McsStorage {
+    pub const fn new() -> Self {
+        Self {
+            media_player_name: [0; MAX_MEDIA_TEXT_SIZE],
+            media_player_icon_url: [0; MAX_MEDIA_TEXT_SIZE],
+            track_changed: [0; 1],
+            track_title: [0; MAX_MEDIA_TEXT_SIZE],
+            track_duration: [0; 4],
+            track_position: [0; 4],
+            playback_speed: [0; 1],
+            playing_order: [0; PlayingOrder::SIZE],
+            media_state: [0; MediaState::SIZE],
+            media_control_point: [0; 5],
+            supported_opcodes: [0; MediaControlOpcodes::SIZE],
+            content_control_id: [0; 1],
+        }
+    }
+}
+
+impl Default for McsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the media player of a device
+pub struct McsServer {
+    handle: u16,
+    media_player_name: Characteristic<MediaText>,
+    media_player_icon_url: Characteristic<MediaText>,
+    track_changed: Characteristic<TrackChanged>,
+    track_title: Characteristic<MediaText>,
+    track_duration: Characteristic<i32>,
+    track_position: Characteristic<i32>,
+    playback_speed: Characteristic<i8>,
+    playing_order: Characteristic<PlayingOrder>,
+    media_state: Characteristic<MediaState>,
+    media_control_point: Characteristic<MediaControlPointResponse>,
+    supported_opcodes: Characteristic<MediaControlOpcodes>,
+    content_control_id: Characteristic<ContentControlID>,
+    opcodes: MediaControlOpcodes,
+    title: RefCell<MediaText>,
+    duration: Cell<i32>,
+    position: Cell<i32>,
+    speed: Cell<i8>,
+    order: Cell<PlayingOrder>,
+    state: Cell<MediaState>,
+    title_changed: Cell<bool>,
+    duration_changed: Cell<bool>,
+    position_changed: Cell<bool>,
+    speed_changed: Cell<bool>,
+    order_changed: Cell<bool>,
+    state_changed: Cell<bool>,
+    track_changed_pending: Cell<bool>,
+    pending_response: Cell<Option<MediaControlPointResponse>>,
+    events: Option<&'static McsEventChannel>,
+}
+
+impl McsServer {
+    /// Create a new Mcs Gatt Service
+    ///
+    /// Only the operations in `supported_opcodes` are handed to the application,
+    /// the others are rejected on the control point.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        player_name: MediaText,
+        icon_url: MediaText,
+        supported_opcodes: MediaControlOpcodes,
+        ccid: ContentControlID,
+        storage: &'a mut McsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::GENERIC_MEDIA_CONTROL));
+
+        let media_player_name_char = service
+            .add_characteristic(
+                characteristic::MEDIA_PLAYER_NAME,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                player_name,
+                &mut storage.media_player_name,
+            )
+            .build();
+
+        let media_player_icon_url_char = service
+            .add_characteristic(
+                characteristic::MEDIA_PLAYER_ICON_URL,
+                &[CharacteristicProp::Read],
+                icon_url,
+                &mut storage.media_player_icon_url,
+            )
+            .build();
+
+        let track_changed_char = service
+            .add_characteristic(
+                characteristic::TRACK_CHANGED,
+                &[CharacteristicProp::Notify],
+                TrackChanged,
+                &mut storage.track_changed,
+            )
+            .build();
+
+        let track_title_char = service
+            .add_characteristic(
+                characteristic::TRACK_TITLE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                MediaText::default(),
+                &mut storage.track_title,
+            )
+            .build();
+
+        let track_duration_char = service
+            .add_characteristic(
+                characteristic::TRACK_DURATION,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                TRACK_POSITION_UNAVAILABLE,
+                &mut storage.track_duration,
+            )
+            .build();
+
+        let track_position_char = service
+            .add_characteristic(
+                characteristic::TRACK_POSITION,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                TRACK_POSITION_UNAVAILABLE,
+                &mut storage.track_position,
+            )
+            .build();
+
+        let playback_speed_char = service
+            .add_characteristic(
+                characteristic::PLAYBACK_SPEED,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                0i8,
+                &mut storage.playback_speed,
+            )
+            .build();
+
+        let playing_order_char = service
+            .add_characteristic(
+                characteristic::PLAYING_ORDER,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                PlayingOrder::default(),
+                &mut storage.playing_order,
+            )
+            .build();
+
+        let media_state_char = service
+            .add_characteristic(
+                characteristic::MEDIA_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                MediaState::Inactive,
+                &mut storage.media_state,
+            )
+            .build();
+
+        let media_control_point_char = service
+            .add_characteristic(
+                characteristic::MEDIA_CONTROL_POINT,
+                &[
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                MediaControlPointResponse::default(),
+                &mut storage.media_control_point,
+            )
+            .build();
+
+        let supported_opcodes_char = service
+            .add_characteristic(
+                characteristic::MEDIA_CONTROL_POINT_OPCODES_SUPPORTED,
+                &[CharacteristicProp::Read],
+                supported_opcodes,
+                &mut storage.supported_opcodes,
+            )
+            .build();
+
+        let content_control_id_char = service
+            .add_characteristic(
+                characteristic::CONTENT_CONTROL_ID,
+                &[CharacteristicProp::Read],
+                ccid,
+                &mut storage.content_control_id,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            media_player_name: media_player_name_char,
+            media_player_icon_url: media_player_icon_url_char,
+            track_changed: track_changed_char,
+            track_title: track_title_char,
+            track_duration: track_duration_char,
+            track_position: track_position_char,
+            playback_speed: playback_speed_char,
+            playing_order: playing_order_char,
+            media_state: media_state_char,
+            media_control_point: media_control_point_char,
+            supported_opcodes: supported_opcodes_char,
+            content_control_id: content_control_id_char,
+            opcodes: supported_opcodes,
+            title: RefCell::new(MediaText::default()),
+            duration: Cell::new(TRACK_POSITION_UNAVAILABLE),
+            position: Cell::new(TRACK_POSITION_UNAVAILABLE),
+            speed: Cell::new(0),
+            order: Cell::new(PlayingOrder::default()),
+            state: Cell::new(MediaState::Inactive),
+            title_changed: Cell::new(false),
+            duration_changed: Cell::new(false),
+            position_changed: Cell::new(false),
+            speed_changed: Cell::new(false),
+            order_changed: Cell::new(false),
+            state_changed: Cell::new(false),
+            track_changed_pending: Cell::new(false),
+            pending_response: Cell::new(None),
+            events: None,
+        }
+    }
+
+    /// Surface the operations written by clients to the application
+    ///
+    /// The application task should receive from the channel and drive its
+    /// media player, then report the outcome with the setters of this service.
+    pub fn with_event_channel(mut self, channel: &'static McsEventChannel) -> Self {
+        self.events = Some(channel);
+        self
+    }
+
+    fn emit(&self, event: McsEvent) {
+        if let Some(events) = self.events {
+            if events.try_send(event).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[mcs] event channel is full, dropping event");
+            }
+        }
+    }
+
+    /// The handle of the service, used to register its Content Control ID
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Media Player Name characteristic
+    pub fn media_player_name_characteristic(&self) -> &Characteristic<MediaText> {
+        &self.media_player_name
+    }
+
+    /// The Track Changed characteristic
+    pub fn track_changed_characteristic(&self) -> &Characteristic<TrackChanged> {
+        &self.track_changed
+    }
+
+    /// The Track Title characteristic
+    pub fn track_title_characteristic(&self) -> &Characteristic<MediaText> {
+        &self.track_title
+    }
+
+    /// The Track Duration characteristic
+    pub fn track_duration_characteristic(&self) -> &Characteristic<i32> {
+        &self.track_duration
+    }
+
+    /// The Track Position characteristic
+    pub fn track_position_characteristic(&self) -> &Characteristic<i32> {
+        &self.track_position
+    }
+
+    /// The Playback Speed characteristic
+    pub fn playback_speed_characteristic(&self) -> &Characteristic<i8> {
+        &self.playback_speed
+    }
+
+    /// The Playing Order characteristic
+    pub fn playing_order_characteristic(&self) -> &Characteristic<PlayingOrder> {
+        &self.playing_order
+    }
+
+    /// The Media State characteristic
+    pub fn media_state_characteristic(&self) -> &Characteristic<MediaState> {
+        &self.media_state
+    }
+
+    /// The Media Control Point characteristic
+    pub fn media_control_point_characteristic(&self) -> &Characteristic<MediaControlPointResponse> {
+        &self.media_control_point
+    }
+
+    /// The current state of the media player
+    pub fn media_state(&self) -> MediaState {
+        self.state.get()
+    }
+
+    /// Change the state of the media player, for example once a Play operation is done
+    pub fn set_media_state(&self, state: MediaState) {
+        if self.state.replace(state) != state {
+            self.state_changed.set(true);
+        }
+    }
+
+    /// Start a new track, its position is reset to the start
+    ///
+    /// `duration` is in hundredths of a second, [`TRACK_POSITION_UNAVAILABLE`]
+    /// if it is unknown.
+    pub fn set_track(&self, title: MediaText, duration: i32) {
+        *self.title.borrow_mut() = title;
+        self.duration.set(duration);
+        self.position.set(0);
+        self.title_changed.set(true);
+        self.duration_changed.set(true);
+        self.position_changed.set(true);
+        self.track_changed_pending.set(true);
+    }
+
+    /// Change the position in the current track, in hundredths of a second
+    pub fn set_track_position(&self, position: i32) {
+        if self.position.replace(position) != position {
+            self.position_changed.set(true);
+        }
+    }
+
+    /// Change the playback speed, as 64 times the base 2 logarithm of the speed
+    pub fn set_playback_speed(&self, speed: i8) {
+        if self.speed.replace(speed) != speed {
+            self.speed_changed.set(true);
+        }
+    }
+
+    /// Change the order the tracks are played in
+    pub fn set_playing_order(&self, order: PlayingOrder) {
+        if self.order.replace(order) != order {
+            self.order_changed.set(true);
+        }
+    }
+
+    /// Whether a new track started since the last call
+    pub fn take_track_changed(&self) -> bool {
+        self.track_changed_pending.replace(false)
+    }
+
+    /// The track title to notify to clients, if it changed since the last call
+    pub fn take_track_title_change(&self) -> Option<MediaText> {
+        self.title_changed
+            .replace(false)
+            .then(|| self.title.borrow().clone())
+    }
+
+    /// The track duration to notify to clients, if it changed since the last call
+    pub fn take_track_duration_change(&self) -> Option<i32> {
+        self.duration_changed
+            .replace(false)
+            .then(|| self.duration.get())
+    }
+
+    /// The track position to notify to clients, if it changed since the last call
+    pub fn take_track_position_change(&self) -> Option<i32> {
+        self.position_changed
+            .replace(false)
+            .then(|| self.position.get())
+    }
+
+    /// The playback speed to notify to clients, if it changed since the last call
+    pub fn take_playback_speed_change(&self) -> Option<i8> {
+        self.speed_changed.replace(false).then(|| self.speed.get())
+    }
+
+    /// The playing order to notify to clients, if it changed since the last call
+    pub fn take_playing_order_change(&self) -> Option<PlayingOrder> {
+        self.order_changed.replace(false).then(|| self.order.get())
+    }
+
+    /// The media state to notify to clients, if it changed since the last call
+    pub fn take_media_state_change(&self) -> Option<MediaState> {
+        self.state_changed.replace(false).then(|| self.state.get())
+    }
+
+    /// The outcome of the last control point write, to notify after the write response
+    pub fn take_control_point_response(&self) -> Option<MediaControlPointResponse> {
+        self.pending_response.take()
+    }
+
+    /// Hand a control point operation to the application
+    ///
+    /// The write is always accepted, its outcome is notified on the control point.
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, operand @ ..] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        let result = match MediaControlOpcode::try_from(*opcode) {
+            Ok(op) if self.opcodes.contains(op.as_flag()) => match (op.has_operand(), operand) {
+                (false, []) => Some(MediaControlCommand {
+                    opcode: op,
+                    operand: None,
+                }),
+                (true, [a, b, c, d]) => Some(MediaControlCommand {
+                    opcode: op,
+                    operand: Some(i32::from_le_bytes([*a, *b, *c, *d])),
+                }),
+                _ => return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into()),
+            },
+            _ => None,
+        };
+
+        let code = match result {
+            None => MediaControlResult::OpcodeNotSupported,
+            Some(_) if self.state.get() == MediaState::Inactive => {
+                MediaControlResult::MediaPlayerInactive
+            }
+            Some(command) => {
+                #[cfg(feature = "defmt")]
+                info!("[mcs] {:?}", command);
+                self.emit(McsEvent::Control(command));
+                MediaControlResult::Success
+            }
+        };
+        self.pending_response.set(Some(MediaControlPointResponse {
+            opcode: *opcode,
+            result: code as u8,
+        }));
+        Ok(())
+    }
+
+    /// Hand a track position written by a client to the application
+    fn write_track_position(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let position = <i32 as FixedGattValue>::from_gatt(data)
+            .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+        self.emit(McsEvent::TrackPosition(position));
+        Ok(())
+    }
+
+    /// Hand a playback speed written by a client to the application
+    fn write_playback_speed(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let speed = <i8 as FixedGattValue>::from_gatt(data)
+            .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+        self.emit(McsEvent::PlaybackSpeed(speed));
+        Ok(())
+    }
+
+    /// Hand a playing order written by a client to the application
+    ///
+    /// Unknown playing orders are ignored, as the specification requires.
+    fn write_playing_order(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [order] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        if let Ok(order) = PlayingOrder::try_from(*order) {
+            self.emit(McsEvent::PlayingOrder(order));
+        }
+        Ok(())
+    }
+}
+
+impl LeAudioServerService for McsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.track_changed.handle || handle == self.media_control_point.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else if handle == self.media_player_name.handle
+            || handle == self.media_player_icon_url.handle
+            || handle == self.track_title.handle
+            || handle == self.track_duration.handle
+            || handle == self.track_position.handle
+            || handle == self.playback_speed.handle
+            || handle == self.playing_order.handle
+            || handle == self.media_state.handle
+            || handle == self.supported_opcodes.handle
+            || handle == self.content_control_id.handle
+        {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.media_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.track_position.handle {
+            Some(self.write_track_position(data))
+        } else if handle == self.playback_speed.handle {
+            Some(self.write_playback_speed(data))
+        } else if handle == self.playing_order.handle {
+            Some(self.write_playing_order(data))
+        } else if handle == self.media_player_name.handle
+            || handle == self.media_player_icon_url.handle
+            || handle == self.track_changed.handle
+            || handle == self.track_title.handle
+            || handle == self.track_duration.handle
+            || handle == self.media_state.handle
+            || handle == self.supported_opcodes.handle
+            || handle == self.content_control_id.handle
+        {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Operations a client asked the media player to perform
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McsEvent {
+    /// A supported control point operation, written while the player was not inactive
+    Control(MediaControlCommand),
+    /// A client moved to a position of the current track
+    TrackPosition(i32),
+    /// A client changed the playback speed
+    PlaybackSpeed(i8),
+    /// A client changed the playing order
+    PlayingOrder(PlayingOrder),
+}
+
+/// A control point operation and its operand
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaControlCommand {
+    pub opcode: MediaControlOpcode,
+    /// The offset of Move Relative or the number of the Goto operations
+    pub operand: Option<i32>,
+}
+
+/// State of the media player
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MediaState {
+    #[default]
+    Inactive = 0x00,
+    Playing = 0x01,
+    Paused = 0x02,
+    Seeking = 0x03,
+}
+
+impl FixedGattValue for MediaState {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [0x00] => Ok(Self::Inactive),
+            [0x01] => Ok(Self::Playing),
+            [0x02] => Ok(Self::Paused),
+            [0x03] => Ok(Self::Seeking),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Order the tracks of the current group are played in
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PlayingOrder {
+    SingleOnce = 0x01,
+    SingleRepeat = 0x02,
+    #[default]
+    InOrderOnce = 0x03,
+    InOrderRepeat = 0x04,
+    OldestOnce = 0x05,
+    OldestRepeat = 0x06,
+    NewestOnce = 0x07,
+    NewestRepeat = 0x08,
+    ShuffleOnce = 0x09,
+    ShuffleRepeat = 0x0A,
+}
+
+impl TryFrom<u8> for PlayingOrder {
+    type Error = u8;
+
+    fn try_from(order: u8) -> Result<Self, Self::Error> {
+        Ok(match order {
+            0x01 => Self::SingleOnce,
+            0x02 => Self::SingleRepeat,
+            0x03 => Self::InOrderOnce,
+            0x04 => Self::InOrderRepeat,
+            0x05 => Self::OldestOnce,
+            0x06 => Self::OldestRepeat,
+            0x07 => Self::NewestOnce,
+            0x08 => Self::NewestRepeat,
+            0x09 => Self::ShuffleOnce,
+            0x0A => Self::ShuffleRepeat,
+            _ => return Err(order),
+        })
+    }
+}
+
+impl FixedGattValue for PlayingOrder {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [order] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*order).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Operations of the Media Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MediaControlOpcode {
+    Play = 0x01,
+    Pause = 0x02,
+    FastRewind = 0x03,
+    FastForward = 0x04,
+    Stop = 0x05,
+    MoveRelative = 0x10,
+    PreviousSegment = 0x20,
+    NextSegment = 0x21,
+    FirstSegment = 0x22,
+    LastSegment = 0x23,
+    GotoSegment = 0x24,
+    PreviousTrack = 0x30,
+    NextTrack = 0x31,
+    FirstTrack = 0x32,
+    LastTrack = 0x33,
+    GotoTrack = 0x34,
+    PreviousGroup = 0x40,
+    NextGroup = 0x41,
+    FirstGroup = 0x42,
+    LastGroup = 0x43,
+    GotoGroup = 0x44,
+}
+
+impl MediaControlOpcode {
+    /// Move Relative and the Goto operations are followed by an int32 operand
+    fn has_operand(self) -> bool {
+        matches!(
+            self,
+            Self::MoveRelative | Self::GotoSegment | Self::GotoTrack | Self::GotoGroup
+        )
+    }
+
+    /// The bit of the operation in the supported opcodes
+    fn as_flag(self) -> MediaControlOpcodes {
+        match self {
+            Self::Play => MediaControlOpcodes::Play,
+            Self::Pause => MediaControlOpcodes::Pause,
+            Self::FastRewind => MediaControlOpcodes::FastRewind,
+            Self::FastForward => MediaControlOpcodes::FastForward,
+            Self::Stop => MediaControlOpcodes::Stop,
+            Self::MoveRelative => MediaControlOpcodes::MoveRelative,
+            Self::PreviousSegment => MediaControlOpcodes::PreviousSegment,
+            Self::NextSegment => MediaControlOpcodes::NextSegment,
+            Self::FirstSegment => MediaControlOpcodes::FirstSegment,
+            Self::LastSegment => MediaControlOpcodes::LastSegment,
+            Self::GotoSegment => MediaControlOpcodes::GotoSegment,
+            Self::PreviousTrack => MediaControlOpcodes::PreviousTrack,
+            Self::NextTrack => MediaControlOpcodes::NextTrack,
+            Self::FirstTrack => MediaControlOpcodes::FirstTrack,
+            Self::LastTrack => MediaControlOpcodes::LastTrack,
+            Self::GotoTrack => MediaControlOpcodes::GotoTrack,
+            Self::PreviousGroup => MediaControlOpcodes::PreviousGroup,
+            Self::NextGroup => MediaControlOpcodes::NextGroup,
+            Self::FirstGroup => MediaControlOpcodes::FirstGroup,
+            Self::LastGroup => MediaControlOpcodes::LastGroup,
+            Self::GotoGroup => MediaControlOpcodes::GotoGroup,
+        }
+    }
+}
+
+impl TryFrom<u8> for MediaControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x01 => Self::Play,
+            0x02 => Self::Pause,
+            0x03 => Self::FastRewind,
+            0x04 => Self::FastForward,
+            0x05 => Self::Stop,
+            0x10 => Self::MoveRelative,
+            0x20 => Self::PreviousSegment,
+            0x21 => Self::NextSegment,
+            0x22 => Self::FirstSegment,
+            0x23 => Self::LastSegment,
+            0x24 => Self::GotoSegment,
+            0x30 => Self::PreviousTrack,
+            0x31 => Self::NextTrack,
+            0x32 => Self::FirstTrack,
+            0x33 => Self::LastTrack,
+            0x34 => Self::GotoTrack,
+            0x40 => Self::PreviousGroup,
+            0x41 => Self::NextGroup,
+            0x42 => Self::FirstGroup,
+            0x43 => Self::LastGroup,
+            0x44 => Self::GotoGroup,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+bitflags! {
+    /// The Media Control Point operations supported by the media player
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MediaControlOpcodes: u32 {
+        const Play = 1 << 0;
+        const Pause = 1 << 1;
+        const FastRewind = 1 << 2;
+        const FastForward = 1 << 3;
+        const Stop = 1 << 4;
+        const MoveRelative = 1 << 5;
+        const PreviousSegment = 1 << 6;
+        const NextSegment = 1 << 7;
+        const FirstSegment = 1 << 8;
+        const LastSegment = 1 << 9;
+        const GotoSegment = 1 << 10;
+        const PreviousTrack = 1 << 11;
+        const NextTrack = 1 << 12;
+        const FirstTrack = 1 << 13;
+        const LastTrack = 1 << 14;
+        const GotoTrack = 1 << 15;
+        const PreviousGroup = 1 << 16;
+        const NextGroup = 1 << 17;
+        const FirstGroup = 1 << 18;
+        const LastGroup = 1 << 19;
+        const GotoGroup = 1 << 20;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for MediaControlOpcodes {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "MediaControlOpcodes({=u32:#010x})", self.bits())
+    }
+}
+
+impl FixedGattValue for MediaControlOpcodes {
+    const SIZE: usize = 4;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [a, b, c, d] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(u32::from_le_bytes([*a, *b, *c, *d])))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Outcome of a Media Control Point operation
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MediaControlResult {
+    Success = 0x01,
+    OpcodeNotSupported = 0x02,
+    MediaPlayerInactive = 0x03,
+    CommandCannotBeCompleted = 0x04,
+}
+
+/// Notified on the Media Control Point after each write
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct MediaControlPointResponse {
+    /// The opcode written by the client
+    pub opcode: u8,
+    /// A [`MediaControlResult`]
+    pub result: u8,
+}
+
+impl FixedGattValue for MediaControlPointResponse {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [opcode, result] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            opcode: *opcode,
+            result: *result,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Value of the Track Changed characteristic, which is only ever notified
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TrackChanged;
+
+impl AsGatt for TrackChanged {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = 0;
+
+    fn as_gatt(&self) -> &[u8] {
+        &[]
+    }
+}
+
+impl FromGatt for TrackChanged {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        if data.is_empty() {
+            Ok(Self)
+        } else {
+            Err(FromGattError::InvalidLength)
+        }
+    }
+}
+
+/// UTF-8 text of the media player, like its name or the title of a track
+pub type MediaText = Utf8Text<MAX_MEDIA_TEXT_SIZE>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextTooLong;
+    use trouble_host::attribute::AttributeTable;
+
+    fn write(mcs: &McsServer, data: &[u8]) -> MediaControlPointResponse {
+        assert!(matches!(
+            mcs.handle_write(mcs.media_control_point.handle, data),
+            Some(Ok(()))
+        ));
+        mcs.take_control_point_response().unwrap()
+    }
+
+    fn response(
+        opcode: MediaControlOpcode,
+        result: MediaControlResult,
+    ) -> MediaControlPointResponse {
+        MediaControlPointResponse {
+            opcode: opcode as u8,
+            result: result as u8,
+        }
+    }
+
+    #[test]
+    fn control_point_operations_depend_on_the_media_state() {
+        let events: &'static McsEventChannel = Box::leak(Box::new(McsEventChannel::new()));
+        let mut storage = McsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, MCS_ATTRIBUTES> = AttributeTable::new();
+        let mcs = McsServer::new(
+            &mut table,
+            MediaText::new("Player").unwrap(),
+            MediaText::default(),
+            MediaControlOpcodes::Play | MediaControlOpcodes::Pause | MediaControlOpcodes::GotoTrack,
+            1,
+            &mut storage,
+        )
+        .with_event_channel(events);
+
+        for state in [
+            MediaState::Inactive,
+            MediaState::Playing,
+            MediaState::Paused,
+            MediaState::Seeking,
+        ] {
+            mcs.set_media_state(state);
+            let expected = if state == MediaState::Inactive {
+                MediaControlResult::MediaPlayerInactive
+            } else {
+                MediaControlResult::Success
+            };
+            for opcode in [MediaControlOpcode::Play, MediaControlOpcode::Pause] {
+                assert_eq!(write(&mcs, &[opcode as u8]), response(opcode, expected));
+                let event = events.try_receive().ok();
+                if state == MediaState::Inactive {
+                    assert_eq!(event, None);
+                } else {
+                    assert_eq!(
+                        event,
+                        Some(McsEvent::Control(MediaControlCommand {
+                            opcode,
+                            operand: None,
+                        }))
+                    );
+                }
+            }
+            assert_eq!(
+                write(&mcs, &[MediaControlOpcode::Stop as u8]),
+                response(
+                    MediaControlOpcode::Stop,
+                    MediaControlResult::OpcodeNotSupported
+                )
+            );
+            assert!(events.is_empty());
+        }
+    }
+
+    #[test]
+    fn operands_are_checked_against_the_opcode() {
+        let events: &'static McsEventChannel = Box::leak(Box::new(McsEventChannel::new()));
+        let mut storage = McsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, MCS_ATTRIBUTES> = AttributeTable::new();
+        let mcs = McsServer::new(
+            &mut table,
+            MediaText::new("Player").unwrap(),
+            MediaText::default(),
+            MediaControlOpcodes::Play | MediaControlOpcodes::GotoTrack,
+            1,
+            &mut storage,
+        )
+        .with_event_channel(events);
+        mcs.set_media_state(MediaState::Paused);
+
+        let mut goto = [MediaControlOpcode::GotoTrack as u8, 0, 0, 0, 0];
+        goto[1..].copy_from_slice(&(-2i32).to_le_bytes());
+        assert_eq!(
+            write(&mcs, &goto),
+            response(MediaControlOpcode::GotoTrack, MediaControlResult::Success)
+        );
+        assert_eq!(
+            events.try_receive().ok(),
+            Some(McsEvent::Control(MediaControlCommand {
+                opcode: MediaControlOpcode::GotoTrack,
+                operand: Some(-2),
+            }))
+        );
+
+        for data in [&goto[..3], &[MediaControlOpcode::Play as u8, 1][..]] {
+            assert!(matches!(
+                mcs.handle_write(mcs.media_control_point.handle, data),
+                Some(Err(LeAudioError::Att(
+                    AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH
+                )))
+            ));
+        }
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn media_text_longer_than_the_characteristic_is_rejected() {
+        let longest = [b'a'; MAX_MEDIA_TEXT_SIZE];
+        let longest = core::str::from_utf8(&longest).unwrap();
+        assert_eq!(MediaText::new(longest).unwrap().as_str(), Some(longest));
+
+        let too_long = [b'a'; MAX_MEDIA_TEXT_SIZE + 1];
+        assert_eq!(
+            MediaText::new(core::str::from_utf8(&too_long).unwrap()),
+            Err(TextTooLong)
+        );
+        assert!(MediaText::from_gatt(&too_long).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Microphone Control Service
+//!
+//! This service exposes the mute state of the microphones of an audio device,
+//! which clients can mute and unmute.
+
+use bt_hci::uuid::{characteristic, service};
+use core::{cell::Cell, slice};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::info;
+
+use crate::{LeAudioError, LeAudioServerService, NotifyError};
+
+/// Attributes added to the attribute table by the Mics service
+pub const MICS_ATTRIBUTES: usize = 4;
+
+/// Application errors of the Mics service
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MicsError {
+    /// The microphones are disabled and can not be muted or unmuted
+    MuteDisabled,
+}
+
+impl From<MicsError> for AttErrorCode {
+    fn from(err: MicsError) -> Self {
+        match err {
+            MicsError::MuteDisabled => AttErrorCode::APPLICATION_ERROR_0x80,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of a [`MicsServer`]
+pub struct MicsStorage {
+    mute: [u8; MuteState::SIZE],
+}
+
+impl MicsStorage {
+    pub const fn new() -> Self {
+        Self {
+            mute: [0; MuteState::SIZE],
+        }
+    }
+}
+
+impl Default for MicsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the mute state of the microphones of a device
+pub struct MicsServer {
+    handle: u16,
+    mute: Characteristic<MuteState>,
+    state: Cell<MuteState>,
+    changed: Cell<bool>,
+}
+
+impl MicsServer {
+    /// Create a new Mics Gatt Service
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        mute: MuteState,
+        storage: &'a mut MicsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::MICROPHONE_CONTROL));
+
+        let mute_char = service
+            .add_characteristic(
+                characteristic::MUTE,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::Notify,
+                ],
+                mute,
+                &mut storage.mute,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            mute: mute_char,
+            state: Cell::new(mute),
+            changed: Cell::new(false),
+        }
+    }
+
+    /// The handle of the service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Mute characteristic
+    pub fn mute_characteristic(&self) -> &Characteristic<MuteState> {
+        &self.mute
+    }
+
+    /// The current mute state
+    pub fn mute(&self) -> MuteState {
+        self.state.get()
+    }
+
+    /// Change the mute state from the server and notify it to the client of `conn`
+    ///
+    /// Use [`MuteState::Disabled`] when the microphones can not be used at all,
+    /// clients can not unmute them until the state is changed again.
+    pub async fn set_mute<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        mute: MuteState,
+    ) -> Result<(), NotifyError> {
+        self.state.set(mute);
+        self.changed.set(false);
+        self.mute
+            .notify(server, conn, &mute)
+            .await
+            .map_err(NotifyError::Host)
+    }
+
+    /// The mute state to notify to clients, if a client changed it since the last call
+    pub fn take_mute_change(&self) -> Option<MuteState> {
+        self.changed.replace(false).then(|| self.state.get())
+    }
+
+    /// Apply a mute state written by a client
+    fn write_mute(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [mute] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        if self.state.get() == MuteState::Disabled {
+            return Err(MicsError::MuteDisabled.into());
+        }
+        let mute = match mute {
+            0 => MuteState::NotMuted,
+            1 => MuteState::Muted,
+            _ => return Err(AttErrorCode::VALUE_NOT_ALLOWED.into()),
+        };
+
+        #[cfg(feature = "defmt")]
+        info!("[mics] {:?}", mute);
+        if mute != self.state.get() {
+            self.state.set(mute);
+            self.changed.set(true);
+        }
+        Ok(())
+    }
+}
+
+impl LeAudioServerService for MicsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.mute.handle {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.mute.handle {
+            Some(self.write_mute(data))
+        } else {
+            None
+        }
+    }
+}
+
+/// Mute state of the microphones
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MuteState {
+    #[default]
+    NotMuted = 0,
+    Muted = 1,
+    /// The microphones are disabled by the server, clients can not unmute them
+    Disabled = 2,
+}
+
+impl TryFrom<u8> for MuteState {
+    type Error = u8;
+
+    fn try_from(mute: u8) -> Result<Self, Self::Error> {
+        match mute {
+            0 => Ok(Self::NotMuted),
+            1 => Ok(Self::Muted),
+            2 => Ok(Self::Disabled),
+            _ => Err(mute),
+        }
+    }
+}
+
+impl FixedGattValue for MuteState {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [mute] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*mute).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use trouble_host::attribute::AttributeTable;
+
+    fn write(mics: &MicsServer, mute: u8) -> Result<(), AttErrorCode> {
+        mics.handle_write(mics.mute.handle, &[mute])
+            .unwrap()
+            .map_err(AttErrorCode::from)
+    }
+
+    #[test]
+    fn disabled_microphones_can_not_be_unmuted() {
+        let mut storage = MicsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, MICS_ATTRIBUTES> = AttributeTable::new();
+        let mics = MicsServer::new(&mut table, MuteState::Disabled, &mut storage);
+
+        for mute in [MuteState::NotMuted, MuteState::Muted, MuteState::Disabled] {
+            assert_eq!(
+                write(&mics, mute as u8),
+                Err(AttErrorCode::APPLICATION_ERROR_0x80)
+            );
+        }
+        assert_eq!(mics.mute(), MuteState::Disabled);
+        assert_eq!(mics.take_mute_change(), None);
+    }
+
+    #[test]
+    fn clients_can_not_disable_the_microphones() {
+        let mut storage = MicsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, MICS_ATTRIBUTES> = AttributeTable::new();
+        let mics = MicsServer::new(&mut table, MuteState::NotMuted, &mut storage);
+
+        assert_eq!(
+            write(&mics, MuteState::Disabled as u8),
+            Err(AttErrorCode::VALUE_NOT_ALLOWED)
+        );
+        assert_eq!(write(&mics, MuteState::Muted as u8), Ok(()));
+        assert_eq!(mics.take_mute_change(), Some(MuteState::Muted));
+    }
+}
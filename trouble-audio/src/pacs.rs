@@ -3,13 +3,23 @@
 //! The Published Audio Capabilities (PACS) service exposes
 //! server audio capabilities and audio availability, allowing discovery by clients.
 
-use super::{generic_audio::*, CodecId, LeAudioServerService, MAX_SERVICES};
+use super::{generic_audio::*, CodecId, LeAudioError, LeAudioServerService, NotifyError};
 use bt_hci::uuid::{characteristic, service};
-use core::slice;
-use embassy_sync::blocking_mutex::raw::RawMutex;
-use heapless::Vec;
+use core::{marker::PhantomData, ops::AsyncFnMut, slice};
+use embassy_sync::{
+    blocking_mutex::raw::{NoopRawMutex, RawMutex},
+    watch::Watch,
+};
+use embassy_time::{with_timeout, Duration};
+use heapless::{FnvIndexMap, Vec};
 use trouble_host::{prelude::*, types::gatt_traits::*};
 
+#[cfg(all(feature = "defmt", not(feature = "strict")))]
+use defmt::warn;
+
+/// How long a write of the audio locations waits for the server to notify them
+const AUDIO_LOCATIONS_CONFIRM_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// A Gatt service client for reading exposed Capabilities of an audio server
 pub struct PacsClient {
     handle: ServiceHandle,
@@ -22,16 +32,19 @@ pub struct PacsClient {
 }
 
 impl PacsClient {
-    pub async fn new<'a, T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
-        client: &'a mut GattClient<'a, T, MAX_SERVICES, L2CAP_MTU>,
-    ) -> Self {
+    /// Discover the Pacs service and its characteristics on the server
+    pub async fn new<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<Self, PacsDiscoveryError> {
         let services = client
             .services_by_uuid(&Uuid::new_short(
                 service::PUBLISHED_AUDIO_CAPABILITIES.into(),
             ))
             .await
-            .unwrap();
-        let handle = services.first().unwrap();
+            .map_err(|_| PacsDiscoveryError::ServiceNotFound)?;
+        let handle = services
+            .first()
+            .ok_or(PacsDiscoveryError::ServiceNotFound)?;
 
         let sink_pac = client
             .characteristic_by_uuid(&handle, &Uuid::new_short(characteristic::SINK_PAC.into()))
@@ -65,7 +78,11 @@ impl PacsClient {
                 &Uuid::new_short(characteristic::SUPPORTED_AUDIO_CONTEXTS.into()),
             )
             .await
-            .expect("The server Must support SUPPORTED_AUDIO_CONTEXTS");
+            .map_err(|_| {
+                PacsDiscoveryError::MandatoryCharacteristicMissing(
+                    characteristic::SUPPORTED_AUDIO_CONTEXTS.into(),
+                )
+            })?;
 
         let available_audio_contexts = client
             .characteristic_by_uuid(
@@ -73,9 +90,13 @@ impl PacsClient {
                 &Uuid::new_short(characteristic::AVAILABLE_AUDIO_CONTEXTS.into()),
             )
             .await
-            .expect("The server Must support AVAILABLE_AUDIO_CONTEXTS");
+            .map_err(|_| {
+                PacsDiscoveryError::MandatoryCharacteristicMissing(
+                    characteristic::AVAILABLE_AUDIO_CONTEXTS.into(),
+                )
+            })?;
 
-        Self {
+        Ok(Self {
             handle: handle.clone(),
             sink_pac,
             sink_audio_locations,
@@ -83,9 +104,435 @@ impl PacsClient {
             source_audio_locations,
             supported_audio_contexts,
             available_audio_contexts,
+        })
+    }
+
+    /// Write the preferred sink audio locations to the server
+    ///
+    /// The write is confirmed by the server notifying the new location, which is
+    /// only received while the `GattClient` task runs.
+    pub async fn write_sink_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        location: AudioLocation,
+    ) -> Result<(), PacsWriteError> {
+        let characteristic = self
+            .sink_audio_locations
+            .as_ref()
+            .ok_or(PacsWriteError::NotPresent)?;
+        Self::write_audio_locations(client, characteristic, location).await
+    }
+
+    /// Write the preferred source audio locations to the server
+    ///
+    /// The write is confirmed by the server notifying the new location, which is
+    /// only received while the `GattClient` task runs.
+    pub async fn write_source_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        location: AudioLocation,
+    ) -> Result<(), PacsWriteError> {
+        let characteristic = self
+            .source_audio_locations
+            .as_ref()
+            .ok_or(PacsWriteError::NotPresent)?;
+        Self::write_audio_locations(client, characteristic, location).await
+    }
+
+    /// Read the sink PAC, using `buf` to hold the raw characteristic value
+    ///
+    /// Fails with [`PacsReadError::BufferTooSmall`] if the PAC does not fit in `buf`,
+    /// which [`MAX_PAC_SIZE`] bytes always hold.
+    pub async fn read_sink_pac_into<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        buf: &mut [u8],
+    ) -> Result<PAC, PacsReadError> {
+        let characteristic = self.sink_pac.as_ref().ok_or(PacsReadError::NotPresent)?;
+        Self::read_pac_into(client, characteristic, buf).await
+    }
+
+    /// Read the source PAC, using `buf` to hold the raw characteristic value
+    ///
+    /// Fails with [`PacsReadError::BufferTooSmall`] if the PAC does not fit in `buf`,
+    /// which [`MAX_PAC_SIZE`] bytes always hold.
+    pub async fn read_source_pac_into<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        buf: &mut [u8],
+    ) -> Result<PAC, PacsReadError> {
+        let characteristic = self.source_pac.as_ref().ok_or(PacsReadError::NotPresent)?;
+        Self::read_pac_into(client, characteristic, buf).await
+    }
+
+    /// Read the sink PAC
+    pub async fn read_sink_pac<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PAC, PacsReadError> {
+        let mut buf = [0; MAX_PAC_SIZE];
+        self.read_sink_pac_into(client, &mut buf).await
+    }
+
+    /// Read the source PAC
+    pub async fn read_source_pac<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PAC, PacsReadError> {
+        let mut buf = [0; MAX_PAC_SIZE];
+        self.read_source_pac_into(client, &mut buf).await
+    }
+
+    /// Decode the `len` bytes of a PAC read into `buf`
+    ///
+    /// Reads are truncated to the buffer, so an invalid PAC filling `buf`
+    /// did not fit in it.
+    fn decode_pac(buf: &[u8], len: usize) -> Result<PAC, PacsReadError> {
+        PAC::decode_from(&buf[..len]).map_err(|_| {
+            if len == buf.len() {
+                PacsReadError::BufferTooSmall
+            } else {
+                PacsReadError::InvalidData
+            }
+        })
+    }
+
+    async fn read_pac_into<T: Controller, const MAX_SERVICES: usize, const L2CAP_MTU: usize>(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        characteristic: &Characteristic<PAC>,
+        buf: &mut [u8],
+    ) -> Result<PAC, PacsReadError> {
+        let len = client
+            .read_characteristic(characteristic, buf)
+            .await
+            .map_err(|_| PacsReadError::ReadFailed)?;
+        Self::decode_pac(buf, len)
+    }
+
+    /// Read the current sink audio locations of the server
+    pub async fn read_sink_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<AudioLocation, PacsReadError> {
+        let characteristic = self
+            .sink_audio_locations
+            .as_ref()
+            .ok_or(PacsReadError::NotPresent)?;
+        Self::read_audio_locations(client, characteristic).await
+    }
+
+    /// Read the current source audio locations of the server
+    pub async fn read_source_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<AudioLocation, PacsReadError> {
+        let characteristic = self
+            .source_audio_locations
+            .as_ref()
+            .ok_or(PacsReadError::NotPresent)?;
+        Self::read_audio_locations(client, characteristic).await
+    }
+
+    /// Read the audio contexts the server supports
+    pub async fn read_supported_audio_contexts<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<AudioContexts, PacsReadError> {
+        Self::read_audio_contexts(client, &self.supported_audio_contexts).await
+    }
+
+    /// Read the audio contexts the server is currently available for
+    pub async fn read_available_audio_contexts<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<AudioContexts, PacsReadError> {
+        Self::read_audio_contexts(client, &self.available_audio_contexts).await
+    }
+
+    async fn read_audio_contexts<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        characteristic: &Characteristic<AudioContexts>,
+    ) -> Result<AudioContexts, PacsReadError> {
+        let mut buf = [0; AudioContexts::SIZE];
+        let len = client
+            .read_characteristic(characteristic, &mut buf)
+            .await
+            .map_err(|_| PacsReadError::ReadFailed)?;
+        <AudioContexts as FixedGattValue>::from_gatt(&buf[..len])
+            .map_err(|_| PacsReadError::InvalidData)
+    }
+
+    async fn read_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        characteristic: &Characteristic<AudioLocation>,
+    ) -> Result<AudioLocation, PacsReadError> {
+        let mut buf = [0; 4];
+        let len = client
+            .read_characteristic(characteristic, &mut buf)
+            .await
+            .map_err(|_| PacsReadError::ReadFailed)?;
+        Self::decode_audio_locations(&buf[..len])
+    }
+
+    /// Encode `location` as the 4 octets little endian value of an audio locations characteristic
+    fn encode_audio_locations(location: AudioLocation) -> [u8; 4] {
+        location.bits().to_le_bytes()
+    }
+
+    /// Decode the 4 octets little endian value of an audio locations characteristic
+    fn decode_audio_locations(data: &[u8]) -> Result<AudioLocation, PacsReadError> {
+        let bits = data.try_into().map_err(|_| PacsReadError::InvalidData)?;
+        AudioLocation::from_bits(u32::from_le_bytes(bits)).ok_or(PacsReadError::InvalidData)
+    }
+
+    async fn write_audio_locations<
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        client: &GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        characteristic: &Characteristic<AudioLocation>,
+        location: AudioLocation,
+    ) -> Result<(), PacsWriteError> {
+        // Subscribe first so the notification of the new location is not missed
+        let mut notifications = Self::subscribe(client, characteristic)
+            .await
+            .map_err(|_| PacsWriteError::SubscribeFailed)?;
+        client
+            .write_characteristic(characteristic, &Self::encode_audio_locations(location))
+            .await
+            .map_err(|_| PacsWriteError::Rejected)?;
+
+        let notified =
+            Self::wait_for_audio_locations(async || notifications.next().await, location);
+        if with_timeout(AUDIO_LOCATIONS_CONFIRM_TIMEOUT, notified)
+            .await
+            .is_ok()
+        {
+            return Ok(());
+        }
+        // The server only notifies changes, so an unchanged location is read back
+        match Self::read_audio_locations(client, characteristic).await {
+            Ok(current) if current == location => Ok(()),
+            _ => Err(PacsWriteError::NotConfirmed),
         }
     }
-    // TODO: handle subscriptions
+
+    /// Wait for `next` to return `location`, skipping the other notified values
+    async fn wait_for_audio_locations(
+        mut next: impl AsyncFnMut() -> Result<AudioLocation, PacsReadError>,
+        location: AudioLocation,
+    ) {
+        while next().await != Ok(location) {}
+    }
+
+    /// Subscribe to the changes of the sink PAC
+    pub async fn subscribe_sink_pac<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, PAC, L2CAP_MTU>, PacsReadError> {
+        let characteristic = self.sink_pac.as_ref().ok_or(PacsReadError::NotPresent)?;
+        Self::subscribe(client, characteristic).await
+    }
+
+    /// Subscribe to the changes of the source PAC
+    pub async fn subscribe_source_pac<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, PAC, L2CAP_MTU>, PacsReadError> {
+        let characteristic = self.source_pac.as_ref().ok_or(PacsReadError::NotPresent)?;
+        Self::subscribe(client, characteristic).await
+    }
+
+    /// Subscribe to the changes of the sink audio locations
+    pub async fn subscribe_sink_audio_locations<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, AudioLocation, L2CAP_MTU>, PacsReadError> {
+        let characteristic = self
+            .sink_audio_locations
+            .as_ref()
+            .ok_or(PacsReadError::NotPresent)?;
+        Self::subscribe(client, characteristic).await
+    }
+
+    /// Subscribe to the changes of the source audio locations
+    pub async fn subscribe_source_audio_locations<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, AudioLocation, L2CAP_MTU>, PacsReadError> {
+        let characteristic = self
+            .source_audio_locations
+            .as_ref()
+            .ok_or(PacsReadError::NotPresent)?;
+        Self::subscribe(client, characteristic).await
+    }
+
+    /// Subscribe to the changes of the supported audio contexts
+    pub async fn subscribe_supported_audio_contexts<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, AudioContexts, L2CAP_MTU>, PacsReadError> {
+        Self::subscribe(client, &self.supported_audio_contexts).await
+    }
+
+    /// Subscribe to the changes of the available audio contexts
+    pub async fn subscribe_available_audio_contexts<
+        'c,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        &self,
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+    ) -> Result<PacsNotifications<'c, AudioContexts, L2CAP_MTU>, PacsReadError> {
+        Self::subscribe(client, &self.available_audio_contexts).await
+    }
+
+    async fn subscribe<
+        'c,
+        V: FromGatt,
+        T: Controller,
+        const MAX_SERVICES: usize,
+        const L2CAP_MTU: usize,
+    >(
+        client: &'c GattClient<'_, T, MAX_SERVICES, L2CAP_MTU>,
+        characteristic: &Characteristic<V>,
+    ) -> Result<PacsNotifications<'c, V, L2CAP_MTU>, PacsReadError> {
+        let listener = client
+            .subscribe(characteristic, false)
+            .await
+            .map_err(|_| PacsReadError::SubscribeFailed)?;
+        Ok(PacsNotifications {
+            listener,
+            _value: PhantomData,
+        })
+    }
+}
+
+/// The values notified by the server for a single PACS characteristic
+///
+/// The notifications are only received while the `GattClient` task runs.
+pub struct PacsNotifications<'c, V, const L2CAP_MTU: usize> {
+    listener: NotificationListener<'c, L2CAP_MTU>,
+    _value: PhantomData<V>,
+}
+
+impl<V: FromGatt, const L2CAP_MTU: usize> PacsNotifications<'_, V, L2CAP_MTU> {
+    /// Wait for the next value notified by the server
+    pub async fn next(&mut self) -> Result<V, PacsReadError> {
+        let notification = self.listener.next().await;
+        V::from_gatt(notification.as_ref()).map_err(|_| PacsReadError::InvalidData)
+    }
+}
+
+/// Errors returned while discovering the Pacs service on a server
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacsDiscoveryError {
+    /// The server does not expose the Published Audio Capabilities service
+    ServiceNotFound,
+    /// The mandatory characteristic with this uuid was not found
+    MandatoryCharacteristicMissing(u16),
+}
+
+/// Errors returned when reading a PACS characteristic from the client
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacsReadError {
+    /// The characteristic was not discovered on the server
+    NotPresent,
+    /// The provided buffer can not hold the characteristic value
+    BufferTooSmall,
+    /// The server rejected the read, or the link failed
+    ReadFailed,
+    /// Subscribing to the characteristic notifications failed
+    SubscribeFailed,
+    /// The value read is not a valid encoding
+    InvalidData,
+}
+
+/// Errors returned when writing a PACS characteristic from the client
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacsWriteError {
+    /// The characteristic was not discovered on the server
+    NotPresent,
+    /// The server rejected the write, or the link failed
+    Rejected,
+    /// Subscribing to the notifications confirming the write failed
+    SubscribeFailed,
+    /// The server did not notify the written value, nor returns it when read
+    NotConfirmed,
 }
 
 /// A Gatt service server exposing Capabilities of an audio device
@@ -97,29 +544,75 @@ pub struct PacsServer<const ATT_MTU: usize> {
     source_audio_locations: Option<Characteristic<AudioLocation>>,
     supported_audio_contexts: Characteristic<AudioContexts>,
     available_audio_contexts: Characteristic<AudioContexts>,
+    available_contexts_watch: AvailableContextsWatch,
+    supported_contexts: AudioContexts,
+    handle_map: HandleMap,
+}
+
+/// Number of receivers that can observe the available audio contexts
+pub const AVAILABLE_CONTEXTS_RECEIVERS: usize = 3;
+
+/// Source of truth for the available audio contexts of a [`PacsServer`]
+pub type AvailableContextsWatch = Watch<NoopRawMutex, AudioContexts, AVAILABLE_CONTEXTS_RECEIVERS>;
+
+/// Attributes added to the attribute table by a Pacs service exposing
+/// `characteristics` of the PAC and audio locations characteristics
+pub const fn pacs_attributes(characteristics: usize) -> usize {
+    // The service, the contexts and the optional characteristics, with their CCCD
+    1 + 2 + 3 + 3 * characteristics
 }
 
-pub const PACS_ATTRIBUTES: usize = 13;
+/// Attributes added to the attribute table by a Pacs service with a sink and a source PAC
+pub const PACS_ATTRIBUTES: usize = pacs_attributes(4);
 
 impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
+    const ATT_MTU_CHECK: () = assert!(
+        ATT_MTU >= 23,
+        "ATT_MTU must be at least 23 bytes per BLE spec"
+    );
+
     /// Create a new PAC Gatt Service
     ///
     /// If you enable a pac, you must also enable the corresponding location
-    pub fn new<'a, M: RawMutex>(
-        table: &mut trouble_host::attribute::AttributeTable<'a, M, MAX_SERVICES>,
-        sink_pac: Option<&'a PAC>,
+    ///
+    /// With the `strict` feature a configuration violating the mandatory Pacs
+    /// requirements panics, otherwise the violation is only logged.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        sink_pac: Option<(&'a PAC, &'a mut [u8])>,
         sink_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
-        source_pac: Option<&'a PAC>,
+        source_pac: Option<(&'a PAC, &'a mut [u8])>,
         source_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
         supported_audio_contexts: &'a AudioContexts,
-        available_audio_contexts: &'a AudioContexts,
+        available_audio_contexts: (&'a AudioContexts, &'a mut [u8]),
     ) -> Self {
+        let () = Self::ATT_MTU_CHECK;
+        let (available_audio_contexts, available_audio_contexts_store) = available_audio_contexts;
+        if let Err(_err) = Self::check_config(
+            sink_pac.as_ref().map(|(pac, _)| *pac),
+            sink_audio_locations.is_some(),
+            source_pac.as_ref().map(|(pac, _)| *pac),
+            source_audio_locations.is_some(),
+            supported_audio_contexts,
+            available_audio_contexts,
+        ) {
+            #[cfg(feature = "strict")]
+            panic!("Invalid Pacs configuration: {:?}", _err);
+            #[cfg(all(feature = "defmt", not(feature = "strict")))]
+            warn!("[pacs] invalid configuration: {:?}", _err);
+        }
+
         let mut service = table.add_service(Service::new(service::PUBLISHED_AUDIO_CAPABILITIES));
 
         let sink_pac_char = match sink_pac {
-            Some(sink_pac) => Some(
+            Some((sink_pac, store)) => Some(
                 service
-                    .add_characteristic_ro(characteristic::SINK_PAC, sink_pac)
+                    .add_characteristic(
+                        characteristic::SINK_PAC,
+                        &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                        sink_pac.clone(),
+                        store,
+                    )
                     .build(),
             ),
             None => None,
@@ -144,9 +637,14 @@ impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
         };
 
         let source_pac_char = match source_pac {
-            Some(source_pac) => Some(
+            Some((source_pac, store)) => Some(
                 service
-                    .add_characteristic_ro(characteristic::SOURCE_PAC, source_pac)
+                    .add_characteristic(
+                        characteristic::SOURCE_PAC,
+                        &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                        source_pac.clone(),
+                        store,
+                    )
                     .build(),
             ),
             None => None,
@@ -178,12 +676,41 @@ impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
             .build();
 
         let available_audio_contexts_char = service
-            .add_characteristic_ro(
+            .add_characteristic(
                 characteristic::AVAILABLE_AUDIO_CONTEXTS,
-                available_audio_contexts,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                available_audio_contexts.clone(),
+                available_audio_contexts_store,
             )
             .build();
 
+        let mut handle_map = HandleMap::new();
+        if let Some(sink_pac) = &sink_pac_char {
+            handle_map.insert(sink_pac, PacsHandleKind::SinkPac);
+        }
+        if let Some(sink_audio_locations) = &sink_audio_locations_char {
+            handle_map.insert(sink_audio_locations, PacsHandleKind::SinkAudioLocations);
+        }
+        if let Some(source_pac) = &source_pac_char {
+            handle_map.insert(source_pac, PacsHandleKind::SourcePac);
+        }
+        if let Some(source_audio_locations) = &source_audio_locations_char {
+            handle_map.insert(source_audio_locations, PacsHandleKind::SourceAudioLocations);
+        }
+        handle_map.insert(
+            &supported_audio_contexts_char,
+            PacsHandleKind::SupportedContexts,
+        );
+        handle_map.insert(
+            &available_audio_contexts_char,
+            PacsHandleKind::AvailableContexts,
+        );
+
+        let available_contexts_watch = Watch::new();
+        available_contexts_watch
+            .sender()
+            .send(available_audio_contexts.clone());
+
         Self {
             handle: service.build(),
             sink_pac: sink_pac_char,
@@ -192,97 +719,487 @@ impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
             source_audio_locations: source_audio_locations_char,
             supported_audio_contexts: supported_audio_contexts_char,
             available_audio_contexts: available_audio_contexts_char,
+            available_contexts_watch,
+            supported_contexts: supported_audio_contexts.clone(),
+            handle_map,
         }
     }
 }
 
-impl<const ATT_MTU: usize> LeAudioServerService for PacsServer<ATT_MTU> {
-    fn handle_read_event(
+impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
+    /// Start building a Pacs service, checking at compile time each PAC comes
+    /// with its audio locations
+    pub fn builder<'a>(
+        supported_audio_contexts: &'a AudioContexts,
+        available_audio_contexts: (&'a AudioContexts, &'a mut [u8]),
+    ) -> PacsBuilder<'a, SinkAbsent, SourceAbsent> {
+        PacsBuilder {
+            sink_pac: None,
+            sink_audio_locations: None,
+            source_pac: None,
+            source_audio_locations: None,
+            supported_audio_contexts,
+            available_audio_contexts,
+            _state: PhantomData,
+        }
+    }
+}
+
+/// The sink PAC and audio locations were not added to a [`PacsBuilder`]
+pub struct SinkAbsent;
+/// The sink PAC was added to a [`PacsBuilder`], its audio locations are missing
+pub struct SinkPacOnly;
+/// The sink PAC and audio locations were added to a [`PacsBuilder`]
+pub struct SinkPresent;
+/// The source PAC and audio locations were not added to a [`PacsBuilder`]
+pub struct SourceAbsent;
+/// The source PAC was added to a [`PacsBuilder`], its audio locations are missing
+pub struct SourcePacOnly;
+/// The source PAC and audio locations were added to a [`PacsBuilder`]
+pub struct SourcePresent;
+
+/// A direction of a [`PacsBuilder`] whose PAC and audio locations are both present or both absent
+pub trait PacsComplete {}
+
+impl PacsComplete for SinkAbsent {}
+impl PacsComplete for SinkPresent {}
+impl PacsComplete for SourceAbsent {}
+impl PacsComplete for SourcePresent {}
+
+/// Builder of a [`PacsServer`] tracking which characteristics were added in its type
+///
+/// The audio locations of a direction are added after its PAC, and
+/// [`Self::build`] only compiles once every PAC has its audio locations.
+pub struct PacsBuilder<'a, Sink, Source> {
+    sink_pac: Option<(&'a PAC, &'a mut [u8])>,
+    sink_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
+    source_pac: Option<(&'a PAC, &'a mut [u8])>,
+    source_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
+    supported_audio_contexts: &'a AudioContexts,
+    available_audio_contexts: (&'a AudioContexts, &'a mut [u8]),
+    _state: PhantomData<(Sink, Source)>,
+}
+
+impl<'a, Sink, Source> PacsBuilder<'a, Sink, Source> {
+    fn into_state<NewSink, NewSource>(self) -> PacsBuilder<'a, NewSink, NewSource> {
+        PacsBuilder {
+            sink_pac: self.sink_pac,
+            sink_audio_locations: self.sink_audio_locations,
+            source_pac: self.source_pac,
+            source_audio_locations: self.source_audio_locations,
+            supported_audio_contexts: self.supported_audio_contexts,
+            available_audio_contexts: self.available_audio_contexts,
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<'a, Source> PacsBuilder<'a, SinkAbsent, Source> {
+    /// Expose the sink PAC, to be followed by [`PacsBuilder::with_sink_audio_locations`]
+    pub fn with_sink_pac(
+        mut self,
+        pac: &'a PAC,
+        store: &'a mut [u8],
+    ) -> PacsBuilder<'a, SinkPacOnly, Source> {
+        self.sink_pac = Some((pac, store));
+        self.into_state()
+    }
+}
+
+impl<'a, Source> PacsBuilder<'a, SinkPacOnly, Source> {
+    pub fn with_sink_audio_locations(
+        mut self,
+        locations: &'a AudioLocation,
+        store: &'a mut [u8],
+    ) -> PacsBuilder<'a, SinkPresent, Source> {
+        self.sink_audio_locations = Some((locations, store));
+        self.into_state()
+    }
+}
+
+impl<'a, Sink> PacsBuilder<'a, Sink, SourceAbsent> {
+    /// Expose the source PAC, to be followed by [`PacsBuilder::with_source_audio_locations`]
+    pub fn with_source_pac(
+        mut self,
+        pac: &'a PAC,
+        store: &'a mut [u8],
+    ) -> PacsBuilder<'a, Sink, SourcePacOnly> {
+        self.source_pac = Some((pac, store));
+        self.into_state()
+    }
+}
+
+impl<'a, Sink> PacsBuilder<'a, Sink, SourcePacOnly> {
+    pub fn with_source_audio_locations(
+        mut self,
+        locations: &'a AudioLocation,
+        store: &'a mut [u8],
+    ) -> PacsBuilder<'a, Sink, SourcePresent> {
+        self.source_audio_locations = Some((locations, store));
+        self.into_state()
+    }
+}
+
+impl<'a, Sink: PacsComplete, Source: PacsComplete> PacsBuilder<'a, Sink, Source> {
+    /// Attributes the service adds to the attribute table
+    pub fn attributes(&self) -> usize {
+        pacs_attributes(
+            [
+                self.sink_pac.is_some(),
+                self.sink_audio_locations.is_some(),
+                self.source_pac.is_some(),
+                self.source_audio_locations.is_some(),
+            ]
+            .into_iter()
+            .filter(|present| *present)
+            .count(),
+        )
+    }
+
+    /// The sink and source PAC of the service
+    pub fn pacs(&self) -> (Option<&'a PAC>, Option<&'a PAC>) {
+        (
+            self.sink_pac.as_ref().map(|(pac, _)| *pac),
+            self.source_pac.as_ref().map(|(pac, _)| *pac),
+        )
+    }
+
+    /// Add the service to `table`
+    pub fn build<M: RawMutex, const N: usize, const ATT_MTU: usize>(
+        self,
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+    ) -> PacsServer<ATT_MTU> {
+        PacsServer::new(
+            table,
+            self.sink_pac,
+            self.sink_audio_locations,
+            self.source_pac,
+            self.source_audio_locations,
+            self.supported_audio_contexts,
+            self.available_audio_contexts,
+        )
+    }
+}
+
+impl<const ATT_MTU: usize> PacsServer<ATT_MTU> {
+    /// The watch holding the current available audio contexts
+    ///
+    /// Other modules can observe it to react to availability changes without
+    /// holding a reference to the attribute server.
+    pub fn available_contexts_watch(&self) -> &AvailableContextsWatch {
+        &self.available_contexts_watch
+    }
+
+    /// Read the available audio contexts stored in the attribute table
+    ///
+    /// This is the value last notified to clients.
+    pub fn available_audio_contexts<M: RawMutex, const N: usize>(
         &self,
-        event: &ReadEvent,
-    ) -> Option<Result<(), trouble_host::prelude::AttErrorCode>> {
-        if let Some(sink_pac) = &self.sink_pac {
-            if event.handle() == sink_pac.handle {
-                return Some(Ok(()));
-            }
-            if let Some(sink_audio_locations) = &self.sink_audio_locations {
-                if event.handle() == sink_audio_locations.handle {
-                    return Some(Ok(()));
-                }
-            }
+        server: &AttributeServer<'_, M, N>,
+    ) -> Result<AudioContexts, PacsError> {
+        self.available_audio_contexts
+            .get(server)
+            .map_err(PacsError::Host)
+    }
+
+    /// Read the supported audio contexts stored in the attribute table
+    pub fn supported_audio_contexts<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+    ) -> Result<AudioContexts, PacsError> {
+        self.supported_audio_contexts
+            .get(server)
+            .map_err(PacsError::Host)
+    }
+
+    /// Change the available audio contexts without notifying them
+    ///
+    /// The characteristic and every observer of [`Self::available_contexts_watch`]
+    /// get the new contexts, clients read them or are notified of them with
+    /// [`Self::update_available_contexts`].
+    pub fn set_available_audio_contexts<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        contexts: AudioContexts,
+    ) -> Result<(), PacsError> {
+        self.available_audio_contexts
+            .set(server, &contexts)
+            .map_err(PacsError::Host)?;
+        self.available_contexts_watch.sender().send(contexts);
+        Ok(())
+    }
+
+    /// Change the available audio contexts and notify them to the client of `conn`
+    ///
+    /// Clients only start streams for available contexts, so this should be called
+    /// whenever the device becomes busy or idle. Contexts equal to the current
+    /// ones are not notified again.
+    pub async fn update_available_contexts<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        contexts: AudioContexts,
+    ) -> Result<(), NotifyError> {
+        if self
+            .available_audio_contexts(server)
+            .is_ok_and(|current| current == contexts)
+        {
+            return Ok(());
         }
+        self.available_contexts_watch
+            .sender()
+            .send(contexts.clone());
+        self.available_audio_contexts
+            .notify(server, conn, &contexts)
+            .await
+            .map_err(NotifyError::Host)
+    }
 
-        if let Some(source_pac) = &self.source_pac {
-            if event.handle() == source_pac.handle {
-                return Some(Ok(()));
-            }
-            if let Some(source_audio_locations) = &self.source_audio_locations {
-                if event.handle() == source_audio_locations.handle {
-                    return Some(Ok(()));
-                }
-            }
+    /// Replace the sink PAC records and notify them to the client of `conn`
+    ///
+    /// The encoded PAC must fit the store given to [`Self::new`].
+    pub async fn update_sink_pac<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        new_pac: &PAC,
+    ) -> Result<(), PacsError> {
+        Self::update_pac(self.sink_pac.as_ref(), server, conn, new_pac).await
+    }
+
+    /// Replace the source PAC records and notify them to the client of `conn`
+    ///
+    /// The encoded PAC must fit the store given to [`Self::new`].
+    pub async fn update_source_pac<M: RawMutex, const N: usize>(
+        &self,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        new_pac: &PAC,
+    ) -> Result<(), PacsError> {
+        Self::update_pac(self.source_pac.as_ref(), server, conn, new_pac).await
+    }
+
+    async fn update_pac<M: RawMutex, const N: usize>(
+        characteristic: Option<&Characteristic<PAC>>,
+        server: &AttributeServer<'_, M, N>,
+        conn: &Connection<'_>,
+        new_pac: &PAC,
+    ) -> Result<(), PacsError> {
+        if new_pac.pac_records.is_empty() {
+            return Err(PacsError::EmptyPac);
         }
+        characteristic
+            .ok_or(PacsError::PacNotExposed)?
+            .notify(server, conn, new_pac)
+            .await
+            .map_err(PacsError::Host)
+    }
 
-        if event.handle() == self.supported_audio_contexts.handle {
-            return Some(Ok(()));
+    /// Check the service exposes what Pacs mandates
+    ///
+    /// Each PAC must come with its audio locations and at least one context
+    /// must be supported. [`ServerBuilder::build`](crate::ServerBuilder::build)
+    /// calls this so a non compliant service is reported at startup.
+    pub fn validate(&self) -> Result<(), PacsConfigError> {
+        Self::check_presence(
+            self.sink_pac.is_some(),
+            self.sink_audio_locations.is_some(),
+            self.source_pac.is_some(),
+            self.source_audio_locations.is_some(),
+        )?;
+        Self::check_supported_contexts(&self.supported_contexts)
+    }
+
+    /// Check every PAC comes with its audio locations, and the other way around
+    fn check_presence(
+        has_sink_pac: bool,
+        has_sink_audio_locations: bool,
+        has_source_pac: bool,
+        has_source_audio_locations: bool,
+    ) -> Result<(), PacsConfigError> {
+        match (has_sink_pac, has_sink_audio_locations) {
+            (true, false) => return Err(PacsConfigError::MissingSinkAudioLocations),
+            (false, true) => return Err(PacsConfigError::MissingSinkPac),
+            _ => {}
         }
+        match (has_source_pac, has_source_audio_locations) {
+            (true, false) => Err(PacsConfigError::MissingSourceAudioLocations),
+            (false, true) => Err(PacsConfigError::MissingSourcePac),
+            _ => Ok(()),
+        }
+    }
 
-        if event.handle() == self.available_audio_contexts.handle {
-            return Some(Ok(()));
+    /// Check a context is supported in at least one direction, and no RFU context is
+    fn check_supported_contexts(supported: &AudioContexts) -> Result<(), PacsConfigError> {
+        if supported.sink_contexts.bits() == 0 && supported.source_contexts.bits() == 0 {
+            return Err(PacsConfigError::NoSupportedContexts);
+        }
+        let rfu = !ContextType::all().bits();
+        if (supported.sink_contexts.bits() | supported.source_contexts.bits()) & rfu != 0 {
+            return Err(PacsConfigError::RfuContexts);
         }
+        Ok(())
+    }
 
-        None
+    /// Whether the service exposes a Sink PAC
+    pub fn has_sink_pac(&self) -> bool {
+        self.sink_pac.is_some()
     }
 
-    fn handle_write_event(
-        &self,
-        event: &WriteEvent,
-    ) -> Option<Result<(), trouble_host::prelude::AttErrorCode>> {
-        if let Some(sink_pac) = &self.sink_pac {
-            if event.handle() == sink_pac.handle {
-                return Some(Err(AttErrorCode::WRITE_NOT_PERMITTED));
+    /// Whether the service exposes a Source PAC
+    pub fn has_source_pac(&self) -> bool {
+        self.source_pac.is_some()
+    }
+
+    /// Check the mandatory Pacs requirements
+    fn check_config(
+        sink_pac: Option<&PAC>,
+        has_sink_audio_locations: bool,
+        source_pac: Option<&PAC>,
+        has_source_audio_locations: bool,
+        supported_audio_contexts: &AudioContexts,
+        available_audio_contexts: &AudioContexts,
+    ) -> Result<(), PacsConfigError> {
+        Self::check_presence(
+            sink_pac.is_some(),
+            has_sink_audio_locations,
+            source_pac.is_some(),
+            has_source_audio_locations,
+        )?;
+
+        for pac in [sink_pac, source_pac].into_iter().flatten() {
+            if pac.pac_records.is_empty() {
+                return Err(PacsConfigError::EmptyPac);
             }
-            if let Some(sink_audio_locations) = &self.sink_audio_locations {
-                if event.handle() == sink_audio_locations.handle {
-                    if event.data().len() == size_of::<AudioLocation>() {
-                        if let Ok(data) = event.value(sink_audio_locations) {
-                            if data.bits() <= AudioLocation::RightSurround.bits() {
-                                return Some(Ok(()));
-                            }
-                        }
-                    };
-                    return Some(Err(AttErrorCode::WRITE_REQUEST_REJECTED));
-                }
+            for record in pac.pac_records.iter() {
+                record.validate().map_err(PacsConfigError::InvalidRecord)?;
             }
         }
 
-        if let Some(source_pac) = &self.source_pac {
-            if event.handle() == source_pac.handle {
-                return Some(Err(AttErrorCode::WRITE_NOT_PERMITTED));
-            }
-            if let Some(source_audio_locations) = &self.source_audio_locations {
-                if event.handle() == source_audio_locations.handle {
-                    if event.data().len() == size_of::<AudioLocation>() {
-                        if let Ok(data) = event.value(source_audio_locations) {
-                            if data.bits() <= AudioLocation::RightSurround.bits() {
-                                return Some(Ok(()));
-                            }
-                        }
-                    };
-                    return Some(Err(AttErrorCode::WRITE_REQUEST_REJECTED));
+        Self::check_supported_contexts(supported_audio_contexts)?;
+        let supported_sink = supported_audio_contexts.sink_contexts.bits();
+        let supported_source = supported_audio_contexts.source_contexts.bits();
+        if available_audio_contexts.sink_contexts.bits() & !supported_sink != 0
+            || available_audio_contexts.source_contexts.bits() & !supported_source != 0
+        {
+            return Err(PacsConfigError::AvailableContextsNotSupported);
+        }
+
+        Ok(())
+    }
+
+    fn dispatch_read(&self, _kind: &PacsHandleKind) -> Result<(), LeAudioError> {
+        // Every Pacs characteristic is readable
+        Ok(())
+    }
+
+    fn dispatch_write(&self, kind: &PacsHandleKind, data: &[u8]) -> Result<(), LeAudioError> {
+        match kind {
+            PacsHandleKind::SinkAudioLocations | PacsHandleKind::SourceAudioLocations => {
+                // The value is the 4 octets of the bitfield, without any RFU bit set
+                match <AudioLocation as FixedGattValue>::from_gatt(data) {
+                    Ok(location) if location.bits() & !AudioLocation::all().bits() == 0 => Ok(()),
+                    _ => Err(PacsError::InvalidAudioLocation.into()),
                 }
             }
+            PacsHandleKind::SinkPac
+            | PacsHandleKind::SourcePac
+            | PacsHandleKind::SupportedContexts
+            | PacsHandleKind::AvailableContexts => Err(AttErrorCode::WRITE_NOT_PERMITTED.into()),
         }
+    }
+}
 
-        if event.handle() == self.supported_audio_contexts.handle {
-            return Some(Err(AttErrorCode::WRITE_NOT_PERMITTED));
-        }
+impl<const ATT_MTU: usize> LeAudioServerService for PacsServer<ATT_MTU> {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        self.handle_map
+            .get(handle)
+            .map(|kind| self.dispatch_read(kind))
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        self.handle_map
+            .get(handle)
+            .map(|kind| self.dispatch_write(kind, data))
+    }
+}
 
-        if event.handle() == self.available_audio_contexts.handle {
-            return Some(Err(AttErrorCode::WRITE_NOT_PERMITTED));
+/// Violations of the mandatory Pacs requirements
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacsConfigError {
+    /// A sink PAC was given without the sink audio locations
+    MissingSinkAudioLocations,
+    /// Sink audio locations were given without a sink PAC
+    MissingSinkPac,
+    /// A source PAC was given without the source audio locations
+    MissingSourceAudioLocations,
+    /// Source audio locations were given without a source PAC
+    MissingSourcePac,
+    /// A PAC has no records
+    EmptyPac,
+    /// A PAC record describes an unusable configuration
+    InvalidRecord(PacRecordValidationError),
+    /// No context is supported in either direction
+    NoSupportedContexts,
+    /// A context bit reserved for future use is set
+    RfuContexts,
+    /// A context is available that is not supported
+    AvailableContextsNotSupported,
+}
+
+/// Errors of the Pacs server
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum PacsError {
+    /// A client wrote an audio location outside of the defined ones
+    InvalidAudioLocation,
+    /// The PAC was not exposed when the service was created
+    PacNotExposed,
+    /// The new PAC has no records
+    EmptyPac,
+    /// The host failed to store or send the value
+    Host(trouble_host::Error),
+}
+
+impl From<PacsError> for AttErrorCode {
+    fn from(err: PacsError) -> Self {
+        match err {
+            PacsError::InvalidAudioLocation => AttErrorCode::WRITE_REQUEST_REJECTED,
+            PacsError::PacNotExposed | PacsError::EmptyPac | PacsError::Host(_) => {
+                AttErrorCode::UNLIKELY_ERROR
+            }
         }
+    }
+}
 
-        None
+/// The characteristics exposed by the Pacs service
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PacsHandleKind {
+    SinkPac,
+    SinkAudioLocations,
+    SourcePac,
+    SourceAudioLocations,
+    SupportedContexts,
+    AvailableContexts,
+}
+
+/// Maps attribute handles to the Pacs characteristic they belong to
+struct HandleMap(FnvIndexMap<u16, PacsHandleKind, 8>);
+
+impl HandleMap {
+    fn new() -> Self {
+        Self(FnvIndexMap::new())
+    }
+
+    fn insert<T: AsGatt>(&mut self, characteristic: &Characteristic<T>, kind: PacsHandleKind) {
+        // There are never more Pacs characteristics than the map can hold
+        let _ = self.0.insert(characteristic.handle, kind);
+    }
+
+    fn get(&self, handle: u16) -> Option<&PacsHandleKind> {
+        self.0.get(&handle)
     }
 }
 
@@ -295,46 +1212,285 @@ pub struct PACRecord {
     pub metadata: Vec<Metadata, 13>, // Metadata only has 13 elements
 }
 
+impl PACRecord {
+    /// Check the record describes a configuration a client can actually select
+    pub fn validate(&self) -> Result<(), PacRecordValidationError> {
+        for capability in self.codec_specific_capabilities.iter() {
+            match capability {
+                CodecSpecificCapabilities::SupportedSamplingFrequencies(frequencies) => {
+                    if frequencies.is_empty() {
+                        return Err(PacRecordValidationError::NoSamplingFrequencies);
+                    }
+                }
+                CodecSpecificCapabilities::SupportedFrameDurations(durations) => {
+                    if durations.is_empty() {
+                        return Err(PacRecordValidationError::NoFrameDurations);
+                    }
+                }
+                CodecSpecificCapabilities::SupportedAudioChannelCounts(_) => {}
+                CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(octets) => {
+                    if OctetsPerCodecFrame::new(octets.min_octets(), octets.max_octets()).is_err() {
+                        return Err(PacRecordValidationError::InvalidOctetsPerCodecFrame);
+                    }
+                }
+                // Checked when constructed
+                CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reasons a [`PACRecord`] describes an unusable configuration
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacRecordValidationError {
+    /// The supported sampling frequencies bitmask is zero
+    NoSamplingFrequencies,
+    /// Neither frame duration is supported
+    NoFrameDurations,
+    /// The minimum octets per codec frame is 0 or larger than the maximum
+    InvalidOctetsPerCodecFrame,
+    /// The record does not fit in the PAC characteristic value
+    TooLarge,
+}
+
+/// Errors changing the records of a [`PAC`]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacError {
+    /// The PAC already holds [`MAX_NUMBER_PAC_RECORDS`] records
+    Full,
+    /// The PAC has no record at this index
+    InvalidIndex,
+    /// The added record is not valid
+    InvalidRecord(PacRecordValidationError),
+}
+
+impl PACRecord {
+    /// Append the wire encoding of the record to `buf`
+    ///
+    /// Codec_ID, then the capabilities and the metadata, both LTV encoded
+    /// and prefixed with their length.
+    fn encode(&self, buf: &mut Vec<u8, MAX_PAC_SIZE>) -> Result<(), PacRecordValidationError> {
+        let codec_id = self.codec_id.first().copied().unwrap_or_default();
+        buf.extend_from_slice(&codec_id.to_bytes())
+            .map_err(|_| PacRecordValidationError::TooLarge)?;
+
+        let mut capabilities = [0; MAX_CODEC_SPECIFIC_CAPABILITIES_SIZE];
+        let len = CodecSpecificCapabilities::encode_all(
+            &self.codec_specific_capabilities,
+            &mut capabilities,
+        );
+        if len == 0 && !self.codec_specific_capabilities.is_empty() {
+            return Err(PacRecordValidationError::TooLarge);
+        }
+        buf.push(len as u8)
+            .map_err(|_| PacRecordValidationError::TooLarge)?;
+        buf.extend_from_slice(&capabilities[..len])
+            .map_err(|_| PacRecordValidationError::TooLarge)?;
+
+        let mut metadata = [0; MAX_PAC_RECORD_METADATA_SIZE];
+        let mut len = 0;
+        for entry in self.metadata.iter() {
+            match entry.encode_ltv(&mut metadata[len..]) {
+                0 => return Err(PacRecordValidationError::TooLarge),
+                written => len += written,
+            }
+        }
+        buf.push(len as u8)
+            .map_err(|_| PacRecordValidationError::TooLarge)?;
+        buf.extend_from_slice(&metadata[..len])
+            .map_err(|_| PacRecordValidationError::TooLarge)
+    }
+
+    /// Decode a record from the start of `data`, returning it and the number of bytes consumed
+    ///
+    /// Capabilities of unknown types are skipped.
+    fn decode(data: &[u8]) -> Result<(Self, usize), FromGattError> {
+        let [codec_id @ .., capabilities_len] = data
+            .get(..6)
+            .and_then(|header| <&[u8; 6]>::try_from(header).ok())
+            .ok_or(FromGattError::InvalidLength)?;
+        let mut record = PACRecord::default();
+        let _ = record.codec_id.push(CodecId::decode(codec_id));
+
+        let capabilities_end = 6 + *capabilities_len as usize;
+        let capabilities = data
+            .get(6..capabilities_end)
+            .ok_or(FromGattError::InvalidLength)?;
+        record.codec_specific_capabilities = CodecSpecificCapabilities::decode_all(capabilities)
+            .map_err(|_| FromGattError::InvalidLength)?;
+
+        let metadata_len = *data
+            .get(capabilities_end)
+            .ok_or(FromGattError::InvalidLength)? as usize;
+        let end = capabilities_end + 1 + metadata_len;
+        let metadata = data
+            .get(capabilities_end + 1..end)
+            .ok_or(FromGattError::InvalidLength)?;
+        let mut ltvs = LtvIterator::new(metadata);
+        for (type_id, value) in ltvs.by_ref() {
+            let entry =
+                Metadata::decode_value(type_id, value).map_err(|_| FromGattError::InvalidLength)?;
+            record
+                .metadata
+                .push(entry)
+                .map_err(|_| FromGattError::InvalidLength)?;
+        }
+        if !ltvs.remaining().is_empty() {
+            return Err(FromGattError::InvalidLength);
+        }
+
+        Ok((record, end))
+    }
+}
+
 // 5 may be too small
-const MAX_NUMBER_PAC_RECORDS: usize = 5;
+pub const MAX_NUMBER_PAC_RECORDS: usize = 5;
+
+/// Max length of the LTV encoded metadata of a PAC record
+const MAX_PAC_RECORD_METADATA_SIZE: usize = 32;
+
+/// Max length of an encoded PAC characteristic value
+pub const MAX_PAC_SIZE: usize = 1 + MAX_NUMBER_PAC_RECORDS
+    * (5 + 1 + MAX_CODEC_SPECIFIC_CAPABILITIES_SIZE + 1 + MAX_PAC_RECORD_METADATA_SIZE);
 
 /// The Sink Audio Locations characteristic i
 /// The Source PAC characteristic is used to expose PAC records when the server supports transmission of audio data.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug)]
+#[derive(Debug, Clone)]
 pub struct PAC {
     number_of_pac_records: u8,
     pac_records: Vec<PACRecord, MAX_NUMBER_PAC_RECORDS>,
+    /// The characteristic value, encoded as the records are added
+    encoded: Vec<u8, MAX_PAC_SIZE>,
 }
 
-impl PAC {
-    pub fn new(records: Vec<PACRecord, MAX_NUMBER_PAC_RECORDS>) -> Self {
+impl Default for PAC {
+    fn default() -> Self {
+        let mut encoded = Vec::new();
+        // Cannot fail, the capacity is larger than the record count
+        let _ = encoded.push(0);
         Self {
+            number_of_pac_records: 0,
+            pac_records: Vec::new(),
+            encoded,
+        }
+    }
+}
+
+impl PAC {
+    /// Create a new PAC, validating each of its records
+    pub fn new(
+        records: Vec<PACRecord, MAX_NUMBER_PAC_RECORDS>,
+    ) -> Result<Self, PacRecordValidationError> {
+        let mut encoded = Vec::new();
+        // Cannot fail, the capacity is larger than the record count
+        let _ = encoded.push(records.len() as u8);
+        for record in records.iter() {
+            record.validate()?;
+            record.encode(&mut encoded)?;
+        }
+        Ok(Self {
             number_of_pac_records: records.len() as u8,
             pac_records: records,
+            encoded,
+        })
+    }
+
+    /// The records of the PAC
+    pub fn records(&self) -> &[PACRecord] {
+        &self.pac_records
+    }
+
+    /// Add a record to the PAC, like a codec supported at runtime
+    ///
+    /// Update the PAC characteristic with the new PAC to notify the clients.
+    pub fn add_record(&mut self, record: PACRecord) -> Result<(), PacError> {
+        if self.pac_records.is_full() {
+            return Err(PacError::Full);
         }
+        record.validate().map_err(PacError::InvalidRecord)?;
+        let len = self.encoded.len();
+        if let Err(err) = record.encode(&mut self.encoded) {
+            self.encoded.truncate(len);
+            return Err(PacError::InvalidRecord(err));
+        }
+        // Cannot fail, the PAC is not full
+        let _ = self.pac_records.push(record);
+        self.set_number_of_pac_records();
+        Ok(())
+    }
+
+    /// Remove the record at `index` from the PAC
+    ///
+    /// Update the PAC characteristic with the new PAC to notify the clients.
+    pub fn remove_record(&mut self, index: usize) -> Result<PACRecord, PacError> {
+        if index >= self.pac_records.len() {
+            return Err(PacError::InvalidIndex);
+        }
+        let record = self.pac_records.remove(index);
+        self.encoded.truncate(1);
+        for record in self.pac_records.iter() {
+            // Cannot fail, the remaining records fitted with the removed one
+            let _ = record.encode(&mut self.encoded);
+        }
+        self.set_number_of_pac_records();
+        Ok(record)
+    }
+
+    fn set_number_of_pac_records(&mut self) {
+        self.number_of_pac_records = self.pac_records.len() as u8;
+        self.encoded[0] = self.number_of_pac_records;
+    }
+
+    /// Decode a PAC from its characteristic value
+    pub fn decode_from(data: &[u8]) -> Result<Self, FromGattError> {
+        <Self as FromGatt>::from_gatt(data)
     }
 }
 
 impl FromGatt for PAC {
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        if data.len() < Self::MIN_SIZE || data.len() > Self::MAX_SIZE {
-            Err(FromGattError::InvalidLength)
-        } else {
-            unsafe { Ok((data.as_ptr() as *const Self).read_unaligned()) }
+        let [number_of_pac_records, records @ ..] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        let encoded = Vec::from_slice(data).map_err(|_| FromGattError::InvalidLength)?;
+
+        let mut pac_records = Vec::new();
+        let mut records = records;
+        for _ in 0..*number_of_pac_records {
+            let (record, len) = PACRecord::decode(records)?;
+            pac_records
+                .push(record)
+                .map_err(|_| FromGattError::InvalidLength)?;
+            records = &records[len..];
+        }
+        if !records.is_empty() {
+            return Err(FromGattError::InvalidLength);
         }
+
+        Ok(Self {
+            number_of_pac_records: *number_of_pac_records,
+            pac_records,
+            encoded,
+        })
     }
 }
+
 impl AsGatt for PAC {
-    const MIN_SIZE: usize = size_of::<PACRecord>() + 1;
-    const MAX_SIZE: usize = size_of::<PAC>();
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = MAX_PAC_SIZE;
     fn as_gatt(&self) -> &[u8] {
-        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::MAX_SIZE) }
+        &self.encoded
     }
 }
 
+/// Sink and source contexts, laid out as their characteristic value
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Default, Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(C)]
 pub struct AudioContexts {
     /// Bitmask of audio data Context Type values for reception.
     pub sink_contexts: ContextType,
@@ -342,18 +1498,587 @@ pub struct AudioContexts {
     pub source_contexts: ContextType,
 }
 
+impl AudioContexts {
+    /// A device that receives audio of an unspecified context, but never transmits
+    pub fn sink_only_default() -> Self {
+        Self {
+            sink_contexts: ContextType::Unspecified,
+            source_contexts: ContextType::Prohibited,
+        }
+    }
+
+    /// A device that transmits audio of an unspecified context, but never receives
+    pub fn source_only_default() -> Self {
+        Self {
+            sink_contexts: ContextType::Prohibited,
+            source_contexts: ContextType::Unspecified,
+        }
+    }
+
+    /// Whether no context is set in either direction
+    pub fn is_empty(&self) -> bool {
+        self.sink_contexts.is_empty() && self.source_contexts.is_empty()
+    }
+
+    /// The contexts set in both `self` and `other`, like the supported contexts
+    /// of a server the client prefers
+    pub fn intersect(&self, other: &Self) -> Self {
+        Self {
+            sink_contexts: self.sink_contexts & other.sink_contexts,
+            source_contexts: self.source_contexts & other.source_contexts,
+        }
+    }
+
+    /// The contexts set in either `self` or `other`
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            sink_contexts: self.sink_contexts | other.sink_contexts,
+            source_contexts: self.source_contexts | other.source_contexts,
+        }
+    }
+}
+
+impl Default for AudioContexts {
+    /// A device must support at least the unspecified context, so the default is
+    /// [`Self::sink_only_default`]
+    fn default() -> Self {
+        Self::sink_only_default()
+    }
+}
+
 impl FixedGattValue for AudioContexts {
     const SIZE: usize = size_of::<Self>();
 
     fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
-        if data.len() != Self::SIZE {
-            Err(FromGattError::InvalidLength)
-        } else {
-            unsafe { Ok((data.as_ptr() as *const Self).read_unaligned()) }
-        }
+        let [sink_low, sink_high, source_low, source_high] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            sink_contexts: ContextType::from_bits_retain(u16::from_le_bytes([
+                *sink_low, *sink_high,
+            ])),
+            source_contexts: ContextType::from_bits_retain(u16::from_le_bytes([
+                *source_low,
+                *source_high,
+            ])),
+        })
     }
 
     fn as_gatt(&self) -> &[u8] {
+        // Both contexts are transparent u16 in a repr(C) struct, little endian targets
+        // lay them out as the characteristic value
         unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codec_ids, GattStorage, Server, ServerBuilder, MIN_ATTRIBUTES};
+    use embassy_futures::block_on;
+    use trouble_host::attribute::AttributeTable;
+
+    static NAME: [u8; 4] = *b"test";
+    static APPEARANCE: [u8; 2] = [0; 2];
+
+    /// Values and backing stores of a Pacs service with a sink and a source PAC
+    struct Fixture {
+        pac: PAC,
+        locations: AudioLocation,
+        supported: AudioContexts,
+        available: AudioContexts,
+        gatt: GattStorage,
+        pac_stores: [[u8; MAX_PAC_SIZE]; 2],
+        location_stores: [[u8; 4]; 2],
+        available_store: [u8; 4],
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut records = Vec::new();
+            let _ = records.push(PACRecord {
+                codec_id: Vec::from_slice(&[codec_ids::LC3]).unwrap(),
+                ..Default::default()
+            });
+            let contexts = AudioContexts {
+                sink_contexts: ContextType::Unspecified | ContextType::Media,
+                source_contexts: ContextType::Conversational,
+            };
+            Self {
+                pac: PAC::new(records).unwrap(),
+                locations: AudioLocation::FrontLeft | AudioLocation::FrontRight,
+                supported: contexts.clone(),
+                available: contexts,
+                gatt: GattStorage::new(),
+                pac_stores: [[0; MAX_PAC_SIZE]; 2],
+                location_stores: [[0; 4]; 2],
+                available_store: [0; 4],
+            }
+        }
+
+        fn server(&mut self) -> Server<'_, 23, 1, 1, MIN_ATTRIBUTES, NoopRawMutex> {
+            let [sink_pac_store, source_pac_store] = &mut self.pac_stores;
+            let [sink_locations_store, source_locations_store] = &mut self.location_stores;
+            ServerBuilder::new(&NAME, &APPEARANCE, &mut self.gatt)
+                .add_pacs(
+                    Some((&self.pac, sink_pac_store)),
+                    Some((&self.locations, sink_locations_store)),
+                    Some((&self.pac, source_pac_store)),
+                    Some((&self.locations, source_locations_store)),
+                    &self.supported,
+                    (&self.available, &mut self.available_store),
+                )
+                .build()
+                .unwrap()
+        }
+    }
+
+    fn att_error(result: Option<Result<(), LeAudioError>>) -> Option<AttErrorCode> {
+        match result {
+            Some(Err(err)) => Some(err.into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn every_characteristic_is_readable() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let pacs = server.pacs();
+        for handle in [
+            pacs.sink_pac.unwrap().handle,
+            pacs.sink_audio_locations.unwrap().handle,
+            pacs.source_pac.unwrap().handle,
+            pacs.source_audio_locations.unwrap().handle,
+            pacs.supported_audio_contexts.handle,
+            pacs.available_audio_contexts.handle,
+        ] {
+            assert!(matches!(server.handle_read(handle), Some(Ok(()))));
+        }
+    }
+
+    #[test]
+    fn read_only_characteristics_reject_writes() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let pacs = server.pacs();
+        for handle in [
+            pacs.sink_pac.unwrap().handle,
+            pacs.source_pac.unwrap().handle,
+            pacs.supported_audio_contexts.handle,
+            pacs.available_audio_contexts.handle,
+        ] {
+            assert_eq!(
+                att_error(server.handle_write(handle, &[0x01, 0x00, 0x00, 0x00])),
+                Some(AttErrorCode::WRITE_NOT_PERMITTED)
+            );
+        }
+    }
+
+    #[test]
+    fn audio_locations_are_writable() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let pacs = server.pacs();
+        for handle in [
+            pacs.sink_audio_locations.unwrap().handle,
+            pacs.source_audio_locations.unwrap().handle,
+        ] {
+            let location = AudioLocation::FrontLeft | AudioLocation::RightSurround;
+            let result = server.handle_write(handle, &location.bits().to_le_bytes());
+            assert!(matches!(result, Some(Ok(()))));
+        }
+    }
+
+    #[test]
+    fn rfu_audio_locations_are_rejected() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let handle = server.pacs().sink_audio_locations.unwrap().handle;
+        for rfu in [0x1000_0000u32, 0x8000_0001] {
+            assert_eq!(
+                att_error(server.handle_write(handle, &rfu.to_le_bytes())),
+                Some(AttErrorCode::WRITE_REQUEST_REJECTED)
+            );
+        }
+        // The audio locations are 4 octets
+        assert_eq!(
+            att_error(server.handle_write(handle, &[0x01, 0x00])),
+            Some(AttErrorCode::WRITE_REQUEST_REJECTED)
+        );
+    }
+
+    #[test]
+    fn rfu_contexts_are_rejected() {
+        let rfu = AudioContexts {
+            sink_contexts: ContextType::Unspecified | ContextType::from_bits_retain(0x1000),
+            source_contexts: ContextType::Prohibited,
+        };
+        assert_eq!(
+            PacsServer::<23>::check_supported_contexts(&rfu),
+            Err(PacsConfigError::RfuContexts)
+        );
+        let defined = AudioContexts {
+            sink_contexts: ContextType::all(),
+            source_contexts: ContextType::all(),
+        };
+        assert_eq!(PacsServer::<23>::check_supported_contexts(&defined), Ok(()));
+    }
+
+    #[test]
+    fn unknown_handles_are_not_handled() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        assert!(server.handle_read(0xfff0).is_none());
+        assert!(server.handle_write(0xfff0, &[0]).is_none());
+    }
+
+    #[test]
+    fn contexts_are_encoded_little_endian() {
+        let contexts = AudioContexts {
+            sink_contexts: ContextType::Unspecified | ContextType::Media | ContextType::Alarm,
+            source_contexts: ContextType::Conversational | ContextType::Ringtone,
+        };
+        // Sink contexts then source contexts, 2 octets each
+        assert_eq!(
+            FixedGattValue::as_gatt(&contexts),
+            &[0x05, 0x08, 0x02, 0x02]
+        );
+        assert_eq!(
+            <AudioContexts as FixedGattValue>::from_gatt(&[0x05, 0x08, 0x02, 0x02]).unwrap(),
+            contexts
+        );
+
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let available = server.available_audio_contexts().unwrap();
+        assert_eq!(
+            FixedGattValue::as_gatt(&available),
+            &[0x05, 0x00, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn set_available_contexts_updates_the_characteristic() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let busy = AudioContexts {
+            sink_contexts: ContextType::Unspecified,
+            source_contexts: ContextType::Prohibited,
+        };
+        server.set_available_audio_contexts(busy.clone()).unwrap();
+        assert_eq!(server.available_audio_contexts().unwrap(), busy);
+        assert_eq!(
+            server.pacs().available_contexts_watch().try_get(),
+            Some(busy)
+        );
+    }
+
+    #[test]
+    fn each_handle_dispatches_to_its_characteristic() {
+        let mut fixture = Fixture::new();
+        let server = fixture.server();
+        let pacs = server.pacs();
+        for (handle, kind) in [
+            (pacs.sink_pac.unwrap().handle, PacsHandleKind::SinkPac),
+            (
+                pacs.sink_audio_locations.unwrap().handle,
+                PacsHandleKind::SinkAudioLocations,
+            ),
+            (pacs.source_pac.unwrap().handle, PacsHandleKind::SourcePac),
+            (
+                pacs.source_audio_locations.unwrap().handle,
+                PacsHandleKind::SourceAudioLocations,
+            ),
+            (
+                pacs.supported_audio_contexts.handle,
+                PacsHandleKind::SupportedContexts,
+            ),
+            (
+                pacs.available_audio_contexts.handle,
+                PacsHandleKind::AvailableContexts,
+            ),
+        ] {
+            assert_eq!(pacs.handle_map.get(handle), Some(&kind));
+        }
+        assert_eq!(pacs.handle_map.0.len(), 6);
+    }
+
+    #[test]
+    fn absent_characteristics_are_not_mapped() {
+        let supported = AudioContexts::sink_only_default();
+        let available = AudioContexts::sink_only_default();
+        let mut available_store = [0; 4];
+        let mut gatt = GattStorage::new();
+        let server = ServerBuilder::<23, 1, 1, MIN_ATTRIBUTES, NoopRawMutex>::new(
+            &NAME,
+            &APPEARANCE,
+            &mut gatt,
+        )
+        .add_pacs(
+            None,
+            None,
+            None,
+            None,
+            &supported,
+            (&available, &mut available_store),
+        )
+        .build()
+        .unwrap();
+        let pacs = server.pacs();
+        assert_eq!(pacs.handle_map.0.len(), 2);
+        assert_eq!(
+            pacs.handle_map.get(pacs.supported_audio_contexts.handle),
+            Some(&PacsHandleKind::SupportedContexts)
+        );
+    }
+
+    /// An LC3 record with the single capability `capability`
+    fn record_with(capability: CodecSpecificCapabilities) -> PACRecord {
+        let mut record = PACRecord {
+            codec_id: Vec::from_slice(&[codec_ids::LC3]).unwrap(),
+            ..Default::default()
+        };
+        record.codec_specific_capabilities.push(capability).unwrap();
+        record
+    }
+
+    fn pac_of(record: PACRecord) -> Result<PAC, PacRecordValidationError> {
+        let mut records = Vec::new();
+        records.push(record).unwrap();
+        PAC::new(records)
+    }
+
+    #[test]
+    fn records_without_sampling_frequencies_are_rejected() {
+        let record = record_with(CodecSpecificCapabilities::SupportedSamplingFrequencies(
+            SupportedSamplingFrequencies::new_const(0),
+        ));
+        assert_eq!(
+            record.validate(),
+            Err(PacRecordValidationError::NoSamplingFrequencies)
+        );
+        assert_eq!(
+            pac_of(record).err(),
+            Some(PacRecordValidationError::NoSamplingFrequencies)
+        );
+
+        let record = record_with(CodecSpecificCapabilities::SupportedSamplingFrequencies(
+            SupportedSamplingFrequencies::new(&[SamplingFrequency::Hz16000]),
+        ));
+        assert_eq!(record.validate(), Ok(()));
+    }
+
+    #[test]
+    fn records_without_frame_durations_are_rejected() {
+        let record = record_with(CodecSpecificCapabilities::SupportedFrameDurations(
+            SupportedFrameDurations::new(false, false, false, false),
+        ));
+        assert_eq!(
+            record.validate(),
+            Err(PacRecordValidationError::NoFrameDurations)
+        );
+        assert_eq!(
+            pac_of(record).err(),
+            Some(PacRecordValidationError::NoFrameDurations)
+        );
+    }
+
+    #[test]
+    fn records_with_an_invalid_octets_range_are_rejected() {
+        for (min, max) in [(120, 100), (0, 100)] {
+            let record = record_with(CodecSpecificCapabilities::SupportedOctetsPerCodecFrame(
+                OctetsPerCodecFrame::from_min_max(min, max),
+            ));
+            assert_eq!(
+                record.validate(),
+                Err(PacRecordValidationError::InvalidOctetsPerCodecFrame)
+            );
+            assert_eq!(
+                pac_of(record).err(),
+                Some(PacRecordValidationError::InvalidOctetsPerCodecFrame)
+            );
+        }
+    }
+
+    #[test]
+    fn max_codec_frames_per_sdu_must_be_at_least_1() {
+        assert!(MaxCodecFramesPerSdu::new(0).is_err());
+        // A record received with 0 frames is not decoded
+        assert!(CodecSpecificCapabilities::decode_value(5, &[0]).is_err());
+        let record = record_with(CodecSpecificCapabilities::SupportedMaxCodecFramesPerSDU(
+            MaxCodecFramesPerSdu::new(1).unwrap(),
+        ));
+        assert_eq!(record.validate(), Ok(()));
+    }
+
+    #[test]
+    fn invalid_configurations_fail_the_check() {
+        let pac = pac_of(record_with(
+            CodecSpecificCapabilities::SupportedSamplingFrequencies(
+                SupportedSamplingFrequencies::new(&[SamplingFrequency::Hz16000]),
+            ),
+        ))
+        .unwrap();
+        let empty_pac = PAC::new(Vec::new()).unwrap();
+        let invalid_pac = PAC {
+            number_of_pac_records: 1,
+            pac_records: Vec::from_slice(&[record_with(
+                CodecSpecificCapabilities::SupportedSamplingFrequencies(
+                    SupportedSamplingFrequencies::new_const(0),
+                ),
+            )])
+            .unwrap(),
+            encoded: Vec::new(),
+        };
+        let supported = AudioContexts::sink_only_default();
+        let none = AudioContexts {
+            sink_contexts: ContextType::Prohibited,
+            source_contexts: ContextType::Prohibited,
+        };
+        let unsupported = AudioContexts {
+            sink_contexts: ContextType::Alarm,
+            source_contexts: ContextType::Prohibited,
+        };
+
+        let check = |sink_pac, sink_locations, supported, available| {
+            PacsServer::<23>::check_config(
+                sink_pac,
+                sink_locations,
+                None,
+                false,
+                supported,
+                available,
+            )
+        };
+        assert_eq!(check(Some(&pac), true, &supported, &supported), Ok(()));
+        assert_eq!(
+            check(Some(&pac), false, &supported, &supported),
+            Err(PacsConfigError::MissingSinkAudioLocations)
+        );
+        assert_eq!(
+            check(None, true, &supported, &supported),
+            Err(PacsConfigError::MissingSinkPac)
+        );
+        assert_eq!(
+            check(Some(&empty_pac), true, &supported, &supported),
+            Err(PacsConfigError::EmptyPac)
+        );
+        assert_eq!(
+            check(Some(&invalid_pac), true, &supported, &supported),
+            Err(PacsConfigError::InvalidRecord(
+                PacRecordValidationError::NoSamplingFrequencies
+            ))
+        );
+        assert_eq!(
+            check(Some(&pac), true, &none, &none),
+            Err(PacsConfigError::NoSupportedContexts)
+        );
+        assert_eq!(
+            check(Some(&pac), true, &supported, &unsupported),
+            Err(PacsConfigError::AvailableContextsNotSupported)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        feature = "strict",
+        should_panic(expected = "Invalid Pacs configuration")
+    )]
+    fn invalid_configurations_are_only_rejected_when_strict() {
+        let supported = AudioContexts::sink_only_default();
+        let available = AudioContexts {
+            sink_contexts: ContextType::Alarm,
+            source_contexts: ContextType::Prohibited,
+        };
+        let mut available_store = [0; 4];
+        let mut table = AttributeTable::<NoopRawMutex, MIN_ATTRIBUTES>::new();
+        let pacs = PacsServer::<23>::new(
+            &mut table,
+            None,
+            None,
+            None,
+            None,
+            &supported,
+            (&available, &mut available_store),
+        );
+        assert!(!pacs.has_sink_pac());
+    }
+
+    #[test]
+    fn pac_reads_fail_only_if_the_pac_does_not_fit() {
+        let fixture = Fixture::new();
+        let encoded = fixture.pac.as_gatt();
+
+        // A buffer just large enough for the PAC read from the server
+        let mut buf = [0; MAX_PAC_SIZE];
+        let exact = &mut buf[..encoded.len()];
+        exact.copy_from_slice(encoded);
+        let pac = PacsClient::decode_pac(exact, encoded.len()).unwrap();
+        assert_eq!(pac.records().len(), 1);
+        assert_eq!(pac.as_gatt(), encoded);
+
+        // The read is truncated to a smaller buffer
+        let truncated = &buf[..encoded.len() - 1];
+        assert_eq!(
+            PacsClient::decode_pac(truncated, truncated.len()).err(),
+            Some(PacsReadError::BufferTooSmall)
+        );
+        assert_eq!(
+            PacsClient::decode_pac(&[], 0).err(),
+            Some(PacsReadError::BufferTooSmall)
+        );
+
+        // An invalid PAC not filling the buffer was not truncated
+        assert_eq!(
+            PacsClient::decode_pac(&buf, encoded.len() - 1).err(),
+            Some(PacsReadError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn audio_locations_write_waits_for_the_written_location() {
+        let written = AudioLocation::FrontLeft;
+        let mut notified = [
+            Err(PacsReadError::InvalidData),
+            Ok(AudioLocation::FrontRight),
+            Ok(written),
+        ]
+        .into_iter();
+        let mut received = 0;
+        block_on(PacsClient::wait_for_audio_locations(
+            async || {
+                received += 1;
+                notified.next().unwrap()
+            },
+            written,
+        ));
+        assert_eq!(received, 3);
+        assert_eq!(notified.next(), None);
+    }
+
+    #[test]
+    fn audio_locations_read_back_as_written() {
+        let stereo = AudioLocation::FrontLeft | AudioLocation::FrontRight;
+        for location in [AudioLocation::Mono, AudioLocation::FrontLeft, stereo] {
+            let written = PacsClient::encode_audio_locations(location);
+            assert_eq!(PacsClient::decode_audio_locations(&written), Ok(location));
+        }
+        assert_eq!(
+            PacsClient::encode_audio_locations(stereo),
+            [0x03, 0x00, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn invalid_audio_locations_are_rejected() {
+        assert_eq!(
+            PacsClient::decode_audio_locations(&[0x01, 0x00, 0x00]),
+            Err(PacsReadError::InvalidData)
+        );
+        assert_eq!(
+            PacsClient::decode_audio_locations(&[0x00, 0x00, 0x00, 0xf0]),
+            Err(PacsReadError::InvalidData)
+        );
+    }
+}
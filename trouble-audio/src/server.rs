@@ -1,110 +1,438 @@
-use core::slice::ChunksExactMut;
+use embassy_futures::select::{select, Either};
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use heapless::Vec;
 use trouble_host::{
     gatt::{GattData, GattEvent, ReadEvent, WriteEvent},
-    prelude::{AsGatt, AttErrorCode, AttributeServer, AttributeTable},
+    prelude::{
+        Advertisement, AsGatt, AttErrorCode, AttributeServer, AttributeTable, Characteristic,
+        CharacteristicProp, Connection, ConnectionEvent, Controller, FixedGattValue, FromGattError,
+        Peripheral,
+    },
 };
 
 #[cfg(feature = "defmt")]
-use defmt::*;
+use defmt::{info, warn, Debug2Format};
 
 use crate::{
-    ascs::{AscsServer, AseType},
-    generic_audio::AudioLocation,
-    pacs::{AudioContexts, PacsServer, PAC, PACS_ATTRIBUTES},
+    aics::{
+        AicsError, AicsServer, AicsStorage, AudioInputDescription, AudioInputState,
+        GainSettingProperties, AICS_ATTRIBUTES,
+    },
+    ascs::{
+        ascs_attributes, AscsError, AscsEventChannel, AscsServer, AscsStorage, AseType,
+        StreamEventChannel,
+    },
+    ccs::{
+        Bearer, CallControlOptionalOpcodes, CcsEventChannel, CcsServer, CcsStorage, CCS_ATTRIBUTES,
+    },
+    csis::{CsisError, CsisServer, CsisStorage, Sirk, CSIS_ATTRIBUTES},
+    generic_audio::{AudioInputType, AudioLocation},
+    hap::{
+        HapError, HapEventChannel, HapServer, HapStorage, HearingAidFeatures, Preset,
+        HAP_ATTRIBUTES, MAX_PRESETS,
+    },
+    mcs::{
+        McsEventChannel, McsServer, McsStorage, MediaControlOpcodes, MediaText, TrackChanged,
+        MCS_ATTRIBUTES,
+    },
+    mics::{MicsError, MicsServer, MicsStorage, MuteState, MICS_ATTRIBUTES},
+    pacs::{
+        pacs_attributes, AudioContexts, PacsBuilder, PacsComplete, PacsConfigError, PacsError,
+        PacsServer, PAC, PACS_ATTRIBUTES,
+    },
+    tmap::{TmapRole, TmapServer, TmapStorage, TMAP_ATTRIBUTES},
+    vcs::{VcsError, VcsServer, VcsStorage, VolumeFlags, VolumeState, MAX_VOCS, VCS_ATTRIBUTES},
+    vocs::{AudioOutputDescription, VocsError, VocsServer, VocsStorage, VOCS_ATTRIBUTES},
+    ContentControlID,
 };
 
-pub const MAX_SERVICES: usize = 4 // att
-     + PACS_ATTRIBUTES  
-     + 15 // ascs
-     ;
+/// Attributes of the Gap and Gatt services, always added by [`ServerBuilder::new`]
+const GAP_GATT_ATTRIBUTES: usize = 9;
+
+/// Attributes of the Gap, Gatt and Pacs services, the services every server has
+pub const MIN_ATTRIBUTES: usize = GAP_GATT_ATTRIBUTES + PACS_ATTRIBUTES;
+
+/// Attributes of a server exposing every service, with a sink and a source
+/// Ase for a single connection
+///
+/// Servers only adding some services should size their attribute table
+/// with the sum of the `*_ATTRIBUTES` of those instead.
+pub const MAX_SERVICES: usize = GAP_GATT_ATTRIBUTES
+    + PACS_ATTRIBUTES
+    + ascs_attributes(2, 1)
+    + VCS_ATTRIBUTES
+    + MICS_ATTRIBUTES
+    + AICS_ATTRIBUTES
+    + MAX_VOCS * VOCS_ATTRIBUTES
+    + TMAP_ATTRIBUTES
+    + MCS_ATTRIBUTES
+    + CCS_ATTRIBUTES
+    + HAP_ATTRIBUTES
+    + CSIS_ATTRIBUTES;
 
 pub trait LeAudioServerService {
-    fn handle_read_event(&self, event: &ReadEvent) -> Option<Result<(), AttErrorCode>>;
-    fn handle_write_event(&self, event: &WriteEvent) -> Option<Result<(), AttErrorCode>>;
-}
-
-// pub struct ServerStorage<'a, const ATT_MTU: usize, const MAX_SERVICES: usize> {
-//     storage: [u8],
-//     count: usize,
-// }
-
-// impl<'a, const ATT_MTU: usize> ServerStorage<'a, ATT_MTU> {
-//     pub fn new(storage: &'a mut [u8]) -> Self {
-//         Self {
-//             storage: storage.chunks_exact_mut(ATT_MTU),
-//             count: 0,
-//         }
-//     }
-//     fn next(&mut self) -> Option<&'a mut [u8]> {
-//         let chunk = self.storage.nth(self.count);
-//         self.count += 1;
-//         chunk
-//     }
-// }
+    /// Check a read of the attribute `handle`, `None` when the service does not own it
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>>;
+    /// Check and apply a write of `data` to the attribute `handle`, `None` when
+    /// the service does not own it
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>>;
+
+    fn handle_read_event(&self, event: &ReadEvent) -> Option<Result<(), LeAudioError>> {
+        self.handle_read(event.handle())
+    }
+
+    fn handle_write_event(&self, event: &WriteEvent) -> Option<Result<(), LeAudioError>> {
+        self.handle_write(event.handle(), event.data())
+    }
+}
+
+/// Errors of the service handlers
+///
+/// Each is sent to the client as an ATT error, the errors of a service with
+/// the application error codes, from 0x80, its specification defines.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum LeAudioError {
+    /// An error defined by the ATT protocol, like a value of the wrong length
+    Att(AttErrorCode),
+    Pacs(PacsError),
+    Ascs(AscsError),
+    Vcs(VcsError),
+    Vocs(VocsError),
+    Aics(AicsError),
+    Mics(MicsError),
+    Hap(HapError),
+    Csis(CsisError),
+}
+
+impl From<LeAudioError> for AttErrorCode {
+    fn from(err: LeAudioError) -> Self {
+        match err {
+            LeAudioError::Att(code) => code,
+            LeAudioError::Pacs(err) => err.into(),
+            LeAudioError::Ascs(err) => err.into(),
+            LeAudioError::Vcs(err) => err.into(),
+            LeAudioError::Vocs(err) => err.into(),
+            LeAudioError::Aics(err) => err.into(),
+            LeAudioError::Mics(err) => err.into(),
+            LeAudioError::Hap(err) => err.into(),
+            LeAudioError::Csis(err) => err.into(),
+        }
+    }
+}
+
+impl From<AttErrorCode> for LeAudioError {
+    fn from(code: AttErrorCode) -> Self {
+        LeAudioError::Att(code)
+    }
+}
+
+impl From<PacsError> for LeAudioError {
+    fn from(err: PacsError) -> Self {
+        LeAudioError::Pacs(err)
+    }
+}
+
+impl From<AscsError> for LeAudioError {
+    fn from(err: AscsError) -> Self {
+        LeAudioError::Ascs(err)
+    }
+}
 
+impl From<VcsError> for LeAudioError {
+    fn from(err: VcsError) -> Self {
+        LeAudioError::Vcs(err)
+    }
+}
+
+impl From<VocsError> for LeAudioError {
+    fn from(err: VocsError) -> Self {
+        LeAudioError::Vocs(err)
+    }
+}
+
+impl From<AicsError> for LeAudioError {
+    fn from(err: AicsError) -> Self {
+        LeAudioError::Aics(err)
+    }
+}
+
+impl From<MicsError> for LeAudioError {
+    fn from(err: MicsError) -> Self {
+        LeAudioError::Mics(err)
+    }
+}
+
+impl From<HapError> for LeAudioError {
+    fn from(err: HapError) -> Self {
+        LeAudioError::Hap(err)
+    }
+}
+
+impl From<CsisError> for LeAudioError {
+    fn from(err: CsisError) -> Self {
+        LeAudioError::Csis(err)
+    }
+}
+
+/// Backing storage for the Service Changed characteristic of the Gatt service
+pub struct GattStorage {
+    service_changed: [u8; ServiceChangedRange::SIZE],
+}
+
+impl GattStorage {
+    pub const fn new() -> Self {
+        Self {
+            service_changed: [0; ServiceChangedRange::SIZE],
+        }
+    }
+}
+
+impl Default for GattStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The attribute handles affected by a change of the attribute table
+///
+/// The value of the Service Changed characteristic, the start then the end
+/// handle, both little endian.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceChangedRange([u8; 4]);
+
+impl ServiceChangedRange {
+    pub fn new(start_handle: u16, end_handle: u16) -> Self {
+        let [start_low, start_high] = start_handle.to_le_bytes();
+        let [end_low, end_high] = end_handle.to_le_bytes();
+        Self([start_low, start_high, end_low, end_high])
+    }
+
+    pub fn start_handle(&self) -> u16 {
+        u16::from_le_bytes([self.0[0], self.0[1]])
+    }
+
+    pub fn end_handle(&self) -> u16 {
+        u16::from_le_bytes([self.0[2], self.0[3]])
+    }
+}
+
+impl FixedGattValue for ServiceChangedRange {
+    const SIZE: usize = 4;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        data.try_into()
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Adds the services of the device to the attribute table of a [`Server`]
+///
+/// Builder methods take the builder by value and return it, so discarding
+/// the returned builder is linted:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// use trouble_audio::{pacs::AudioContexts, GattStorage, ServerBuilder, MIN_ATTRIBUTES};
+///
+/// static NAME: [u8; 4] = *b"test";
+/// static APPEARANCE: [u8; 2] = [0; 2];
+///
+/// let contexts = AudioContexts::source_only_default();
+/// let mut available_store = [0; 4];
+/// let mut gatt_storage = GattStorage::new();
+/// let builder = ServerBuilder::<23, 1, 1, { MIN_ATTRIBUTES + 8 }, NoopRawMutex>::new(
+///     &NAME,
+///     &APPEARANCE,
+///     &mut gatt_storage,
+/// );
+/// builder.add_pacs(None, None, None, None, &contexts, (&contexts, &mut available_store));
+/// ```
 pub struct ServerBuilder<
     'a,
     const ATT_MTU: usize,
     const MAX_ASES: usize,
     const MAX_CONNECTIONS: usize,
+    const N: usize,
     M,
 > where
     M: RawMutex,
 {
-    table: AttributeTable<'a, M, MAX_SERVICES>,
-    // storage: &'a mut ServerStorage<'a, ATT_MTU>,
+    table: AttributeTable<'a, M, N>,
+    /// Attributes of the services added so far
+    attributes: usize,
+    service_changed: Characteristic<ServiceChangedRange>,
     pacs: Option<PacsServer<ATT_MTU>>,
+    /// The sink and source PAC of Pacs, Config Codec of Ascs is checked against
+    pacs_capabilities: (Option<&'a PAC>, Option<&'a PAC>),
     ascs: Option<AscsServer<MAX_ASES, MAX_CONNECTIONS>>,
+    vcs: Option<VcsServer>,
+    mics: Option<MicsServer>,
+    tmap: Option<TmapServer>,
+    mcs: Option<McsServer>,
+    ccs: Option<CcsServer>,
+    hap: Option<HapServer>,
+    csis: Option<CsisServer>,
 }
 
-impl<'a, const ATT_MTU: usize, const MAX_ASES: usize, const MAX_CONNECTIONS: usize, M>
-    ServerBuilder<'a, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, M>
+impl<
+        'a,
+        const ATT_MTU: usize,
+        const MAX_ASES: usize,
+        const MAX_CONNECTIONS: usize,
+        const N: usize,
+        M,
+    > ServerBuilder<'a, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, N, M>
 where
     M: RawMutex,
 {
-    const STORAGE_SIZE: usize = MAX_SERVICES * ATT_MTU;
+    // Ascs is not generic over the MTU, the server checks it for every service
+    const ATT_MTU_CHECK: () = assert!(
+        ATT_MTU >= 23,
+        "ATT_MTU must be at least 23 bytes per BLE spec"
+    );
+
+    const ATTRIBUTES_CHECK: () = assert!(
+        N >= GAP_GATT_ATTRIBUTES,
+        "the attribute table can not hold the Gap and Gatt services"
+    );
 
     pub fn new(
         name_id: &'a impl AsGatt,
         appearance: &'a impl AsGatt,
-        // storage: &'a mut ServerStorage<'a, ATT_MTU>,
+        gatt_storage: &'a mut GattStorage,
     ) -> Self {
-        let mut table: AttributeTable<'_, M, MAX_SERVICES> = AttributeTable::new();
+        let () = Self::ATT_MTU_CHECK;
+        let () = Self::ATTRIBUTES_CHECK;
+        let mut table: AttributeTable<'_, M, N> = AttributeTable::new();
         let mut svc = table.add_service(trouble_host::attribute::Service::new(0x1800u16));
         let _ = svc.add_characteristic_ro(0x2a00u16, name_id);
         let _ = svc.add_characteristic_ro(0x2a01u16, appearance);
         svc.build();
 
         // Generic attribute service (mandatory)
-        table.add_service(trouble_host::attribute::Service::new(0x1801u16));
+        let mut svc = table.add_service(trouble_host::attribute::Service::new(0x1801u16));
+        let service_changed = svc
+            .add_characteristic(
+                0x2a05u16,
+                &[CharacteristicProp::Indicate],
+                ServiceChangedRange::default(),
+                &mut gatt_storage.service_changed,
+            )
+            .build();
+        svc.build();
 
         Self {
             table,
-            // storage,
+            attributes: GAP_GATT_ATTRIBUTES,
+            service_changed,
             pacs: None,
+            pacs_capabilities: (None, None),
             ascs: None,
+            vcs: None,
+            mics: None,
+            tmap: None,
+            mcs: None,
+            ccs: None,
+            hap: None,
+            csis: None,
         }
     }
 
-    pub fn build(self) -> Server<'a, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, M> {
-        Server {
-            server: AttributeServer::<M, MAX_SERVICES>::new(self.table),
-            pacs: self.pacs.expect("Pacs is a mandatory service"),
-            ascs: self.ascs,
+    /// Build the server, panicking if the services are not compliant
+    ///
+    /// Returns an error if the PACs, audio locations or contexts given to Pacs
+    /// violate its spec.
+    ///
+    /// # Panics
+    ///
+    /// If Pacs was not added, or Ascs exposes sink or source Ases without the
+    /// matching PAC to describe their capabilities.
+    pub fn build(
+        self,
+    ) -> Result<Server<'a, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, N, M>, PacsConfigError> {
+        if self.ascs.is_some() && self.pacs.is_none() {
+            panic!("Ascs requires Pacs, add it with add_pacs or with_pacs");
+        }
+        let pacs = self.pacs.expect("Pacs is a mandatory service");
+        if let Some(ascs) = &self.ascs {
+            assert!(
+                !ascs.has_sink_ases() || pacs.has_sink_pac(),
+                "Ascs has sink Ases but Pacs has no Sink PAC"
+            );
+            assert!(
+                !ascs.has_source_ases() || pacs.has_source_pac(),
+                "Ascs has source Ases but Pacs has no Source PAC"
+            );
         }
+        pacs.validate()?;
+        let (sink_pac, source_pac) = self.pacs_capabilities;
+        Ok(Server {
+            server: AttributeServer::<M, N>::new(self.table),
+            service_changed: self.service_changed,
+            pacs,
+            ascs: self
+                .ascs
+                .map(|ascs| ascs.with_capabilities(sink_pac, source_pac)),
+            vcs: self.vcs,
+            mics: self.mics,
+            tmap: self.tmap,
+            mcs: self.mcs,
+            ccs: self.ccs,
+            hap: self.hap,
+            csis: self.csis,
+        })
+    }
+
+    /// Account for the `attributes` of a service about to be added
+    ///
+    /// # Panics
+    ///
+    /// If the attribute table can not hold them with the services already added.
+    fn reserve(&mut self, attributes: usize) {
+        self.attributes += attributes;
+        assert!(
+            self.attributes <= N,
+            "the attribute table can not hold the services, N must be at least {}",
+            self.attributes
+        );
     }
 
+    /// Expose the capabilities of the device with the Pacs service
+    ///
+    /// [`Self::with_pacs`] checks at compile time each PAC comes with its audio locations.
+    #[must_use = "builder method returns new Self, old value is discarded"]
     pub fn add_pacs(
         mut self,
-        sink_pac: Option<&'a PAC>,
+        sink_pac: Option<(&'a PAC, &'a mut [u8])>,
         sink_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
-        source_pac: Option<&'a PAC>,
+        source_pac: Option<(&'a PAC, &'a mut [u8])>,
         source_audio_locations: Option<(&'a AudioLocation, &'a mut [u8])>,
         supported_audio_contexts: &'a AudioContexts,
-        available_audio_contexts: &'a AudioContexts,
+        available_audio_contexts: (&'a AudioContexts, &'a mut [u8]),
     ) -> Self {
+        self.pacs_capabilities = (
+            sink_pac.as_ref().map(|(pac, _)| *pac),
+            source_pac.as_ref().map(|(pac, _)| *pac),
+        );
+        self.reserve(pacs_attributes(
+            [
+                sink_pac.is_some(),
+                sink_audio_locations.is_some(),
+                source_pac.is_some(),
+                source_audio_locations.is_some(),
+            ]
+            .into_iter()
+            .filter(|present| *present)
+            .count(),
+        ));
         let pacs = PacsServer::<ATT_MTU>::new(
             &mut self.table,
             sink_pac,
@@ -118,38 +446,313 @@ where
         self
     }
 
-    pub fn add_ascs(mut self, ases: Vec<AseType, MAX_ASES>) -> Self
-    {
-        let ascs = AscsServer::new(&mut self.table, ases);
+    /// Expose the capabilities of the device with a Pacs service built by `pacs`
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_pacs<Sink: PacsComplete, Source: PacsComplete>(
+        mut self,
+        pacs: PacsBuilder<'a, Sink, Source>,
+    ) -> Self {
+        self.reserve(pacs.attributes());
+        self.pacs_capabilities = pacs.pacs();
+        self.pacs = Some(pacs.build(&mut self.table));
+        self
+    }
+
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_ascs(
+        mut self,
+        ases: Vec<AseType, MAX_ASES>,
+        stores: &'a mut AscsStorage<MAX_ASES, MAX_CONNECTIONS>,
+    ) -> Self {
+        self.reserve(ascs_attributes(ases.len(), MAX_CONNECTIONS));
+        let ascs = AscsServer::new(&mut self.table, ases, stores);
         self.ascs = Some(ascs);
         self
     }
+
+    /// Expose the volume of the device with the Volume Control service
+    ///
+    /// Relative volume operations change the volume setting by `step_size`.
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_vcs(
+        mut self,
+        volume_state: VolumeState,
+        volume_flags: VolumeFlags,
+        step_size: u8,
+        storage: &'a mut VcsStorage,
+    ) -> Self {
+        self.reserve(VCS_ATTRIBUTES);
+        let vcs = VcsServer::new(
+            &mut self.table,
+            volume_state,
+            volume_flags,
+            step_size,
+            storage,
+        );
+        self.vcs = Some(vcs);
+        self
+    }
+
+    /// Expose an audio input with the Audio Input Control service, included by Vcs
+    ///
+    /// Must be called after [`Self::add_vcs`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_aics(
+        mut self,
+        audio_input_state: AudioInputState,
+        properties: GainSettingProperties,
+        audio_input_type: AudioInputType,
+        description: AudioInputDescription,
+        storage: &'a mut AicsStorage,
+    ) -> Self {
+        self.reserve(AICS_ATTRIBUTES);
+        let aics = AicsServer::new(
+            &mut self.table,
+            audio_input_state,
+            properties,
+            audio_input_type,
+            description,
+            storage,
+        );
+        self.vcs
+            .as_mut()
+            .expect("Aics is included by Vcs, add it first")
+            .include_aics(aics);
+        self
+    }
+
+    /// Expose the volume offset of an audio output with the Volume Offset Control
+    /// service, included by Vcs
+    ///
+    /// Must be called after [`Self::add_vcs`], at most [`MAX_VOCS`] times
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_vocs(
+        mut self,
+        volume_offset: i16,
+        audio_location: AudioLocation,
+        description: AudioOutputDescription,
+        storage: &'a mut VocsStorage,
+    ) -> Self {
+        self.reserve(VOCS_ATTRIBUTES);
+        self.vcs
+            .as_mut()
+            .expect("Vocs is included by Vcs, add it first")
+            .add_vocs(
+                &mut self.table,
+                volume_offset,
+                audio_location,
+                description,
+                storage,
+            )
+            .expect("too many Vocs services");
+        self
+    }
+
+    /// Expose the mute state of the microphones with the Microphone Control service
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_mics(mut self, mute: MuteState, storage: &'a mut MicsStorage) -> Self {
+        self.reserve(MICS_ATTRIBUTES);
+        let mics = MicsServer::new(&mut self.table, mute, storage);
+        self.mics = Some(mics);
+        self
+    }
+
+    /// Expose the TMAP roles of the device with the Telephony and Media Audio service
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_tmap(mut self, role: TmapRole, storage: &'a mut TmapStorage) -> Self {
+        self.reserve(TMAP_ATTRIBUTES);
+        let tmap = TmapServer::new(&mut self.table, role, storage);
+        self.tmap = Some(tmap);
+        self
+    }
+
+    /// Expose the media player of the device with the Generic Media Control service
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_mcs(
+        mut self,
+        player_name: MediaText,
+        icon_url: MediaText,
+        supported_opcodes: MediaControlOpcodes,
+        ccid: ContentControlID,
+        storage: &'a mut McsStorage,
+    ) -> Self {
+        self.reserve(MCS_ATTRIBUTES);
+        let mcs = McsServer::new(
+            &mut self.table,
+            player_name,
+            icon_url,
+            supported_opcodes,
+            ccid,
+            storage,
+        );
+        self.mcs = Some(mcs);
+        self
+    }
+
+    /// Publish the operations written to the Mcs service on `channel`
+    ///
+    /// Must be called after [`Self::add_mcs`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_mcs_event_channel(mut self, channel: &'static McsEventChannel) -> Self {
+        self.mcs = self.mcs.map(|mcs| mcs.with_event_channel(channel));
+        self
+    }
+
+    /// Expose the calls of a telephone bearer with the Telephone Bearer service
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_ccs(
+        mut self,
+        bearer: Bearer,
+        optional_opcodes: CallControlOptionalOpcodes,
+        ccid: ContentControlID,
+        storage: &'a mut CcsStorage,
+    ) -> Self {
+        self.reserve(CCS_ATTRIBUTES);
+        let ccs = CcsServer::new(&mut self.table, bearer, optional_opcodes, ccid, storage);
+        self.ccs = Some(ccs);
+        self
+    }
+
+    /// Publish the operations written to the Ccs service on `channel`
+    ///
+    /// Must be called after [`Self::add_ccs`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_ccs_event_channel(mut self, channel: &'static CcsEventChannel) -> Self {
+        self.ccs = self.ccs.map(|ccs| ccs.with_event_channel(channel));
+        self
+    }
+
+    /// Expose the presets of a hearing aid with the Hearing Access service
+    ///
+    /// `active_preset` is the index of the active preset, 0 when none is.
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_hap(
+        mut self,
+        features: HearingAidFeatures,
+        presets: Vec<Preset, MAX_PRESETS>,
+        active_preset: u8,
+        storage: &'a mut HapStorage,
+    ) -> Self {
+        self.reserve(HAP_ATTRIBUTES);
+        let hap = HapServer::new(&mut self.table, features, presets, active_preset, storage);
+        self.hap = Some(hap);
+        self
+    }
+
+    /// Publish the preset changes made by clients of the Hap service on `channel`
+    ///
+    /// Must be called after [`Self::add_hap`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_hap_event_channel(mut self, channel: &'static HapEventChannel) -> Self {
+        self.hap = self.hap.map(|hap| hap.with_event_channel(channel));
+        self
+    }
+
+    /// Identify the device as a member of a coordinated set with the Csis service
+    ///
+    /// `rank` starts from 1 and is unique among the `set_size` members of the set.
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn add_csis(
+        mut self,
+        sirk: Sirk,
+        set_size: u8,
+        rank: u8,
+        storage: &'a mut CsisStorage,
+    ) -> Self {
+        self.reserve(CSIS_ATTRIBUTES);
+        let csis = CsisServer::new(&mut self.table, sirk, set_size, rank, storage);
+        self.csis = Some(csis);
+        self
+    }
+
+    /// Publish the Ascs control point operations on `channel`
+    ///
+    /// Must be called after [`Self::add_ascs`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_ascs_event_channel(mut self, channel: &'static AscsEventChannel) -> Self {
+        self.ascs = self.ascs.map(|ascs| ascs.with_event_channel(channel));
+        self
+    }
+
+    /// Publish the streams of the Ascs server on `channel`, to set up their ISO data paths
+    ///
+    /// Must be called after [`Self::add_ascs`]
+    #[must_use = "builder method returns new Self, old value is discarded"]
+    pub fn with_ascs_stream_channel(mut self, channel: &'static StreamEventChannel) -> Self {
+        self.ascs = self
+            .ascs
+            .map(|ascs| ascs.with_stream_event_channel(channel));
+        self
+    }
 }
 
-pub struct Server<'a, const ATT_MTU: usize, const MAX_ASES: usize, const MAX_CONNECTIONS: usize, M>
-where
+/// Errors returned when sending a notification
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug)]
+pub enum NotifyError {
+    /// The host failed to store or send the value
+    Host(trouble_host::Error),
+    /// The Ascs server has no characteristic to notify
+    Ascs(AscsError),
+}
+
+pub struct Server<
+    'a,
+    const ATT_MTU: usize,
+    const MAX_ASES: usize,
+    const MAX_CONNECTIONS: usize,
+    const N: usize,
+    M,
+> where
     M: RawMutex,
 {
-    server: AttributeServer<'a, M, MAX_SERVICES>,
+    server: AttributeServer<'a, M, N>,
+    service_changed: Characteristic<ServiceChangedRange>,
     pacs: PacsServer<ATT_MTU>,
     ascs: Option<AscsServer<MAX_ASES, MAX_CONNECTIONS>>,
+    vcs: Option<VcsServer>,
+    mics: Option<MicsServer>,
+    tmap: Option<TmapServer>,
+    mcs: Option<McsServer>,
+    ccs: Option<CcsServer>,
+    hap: Option<HapServer>,
+    csis: Option<CsisServer>,
 }
 
-impl<const ATT_MTU: usize, const MAX_ASES: usize, const MAX_CONNECTIONS: usize, M>
-    Server<'_, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, M>
+impl<
+        const ATT_MTU: usize,
+        const MAX_ASES: usize,
+        const MAX_CONNECTIONS: usize,
+        const N: usize,
+        M,
+    > Server<'_, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, N, M>
 where
     M: RawMutex,
 {
-    pub async fn process(&self, gatt_data: GattData<'_>) {
+    /// Process a GATT event of `conn`
+    ///
+    /// Responses of the ASE control point, followed by the new state of every
+    /// Ase it changed, are notified to `conn` once the write is accepted.
+    pub async fn process(&self, conn: &Connection<'_>, gatt_data: GattData<'_>) {
+        if let Some(ascs) = &self.ascs {
+            ascs.set_active_connection(conn.handle().raw());
+        }
+        if let Some(csis) = &self.csis {
+            csis.set_active_connection(conn.handle().raw());
+        }
         match gatt_data.process(&self.server).await {
             Ok(data) => {
                 if let Some(event) = data {
                     if let Some(resp) = match event {
-                        GattEvent::Read(ref event) => self.handle_read(event),
-                        GattEvent::Write(ref event) => self.handle_write(event),
+                        GattEvent::Read(ref event) => self.handle_read(event.handle()),
+                        GattEvent::Write(ref event) => {
+                            self.handle_write(event.handle(), event.data())
+                        }
                     } {
                         if let Err(err) = resp {
-                            event.reject(err).unwrap().send().await
+                            #[cfg(feature = "defmt")]
+                            warn!("[le audio] rejecting request: {:?}", err);
+                            event.reject(err.into()).unwrap().send().await
                         } else {
                             event.accept().unwrap().send().await
                         };
@@ -163,6 +766,64 @@ where
                             .await;
                     }
                 }
+
+                if let Some(ascs) = &self.ascs {
+                    if let Some(response) = ascs.take_control_point_response() {
+                        if let Err(_e) = ascs
+                            .control_point()
+                            .notify(&self.server, conn, &response)
+                            .await
+                        {
+                            #[cfg(feature = "defmt")]
+                            warn!("[le audio] error notifying ase control point: {:?}", _e);
+                        }
+                    }
+                }
+                self.notify_ase_state_changes(conn).await;
+
+                if let Some(vcs) = &self.vcs {
+                    if let Some(state) = vcs.take_volume_state_change() {
+                        if let Err(_e) = self
+                            .notify(conn, vcs.volume_state_characteristic(), &state)
+                            .await
+                        {
+                            #[cfg(feature = "defmt")]
+                            warn!("[le audio] error notifying volume state: {:?}", _e);
+                        }
+                    }
+                    if let Some(aics) = vcs.aics() {
+                        self.notify_aics_changes(conn, aics).await;
+                    }
+                    for vocs in vcs.vocs() {
+                        self.notify_vocs_changes(conn, vocs).await;
+                    }
+                }
+
+                if let Some(mics) = &self.mics {
+                    if let Some(mute) = mics.take_mute_change() {
+                        if let Err(_e) = self.notify(conn, mics.mute_characteristic(), &mute).await
+                        {
+                            #[cfg(feature = "defmt")]
+                            warn!("[le audio] error notifying mute: {:?}", _e);
+                        }
+                    }
+                }
+
+                if let Some(mcs) = &self.mcs {
+                    self.notify_mcs_changes(conn, mcs).await;
+                }
+
+                if let Some(ccs) = &self.ccs {
+                    self.notify_ccs_changes(conn, ccs).await;
+                }
+
+                if let Some(hap) = &self.hap {
+                    self.notify_hap_changes(conn, hap).await;
+                }
+
+                if let Some(csis) = &self.csis {
+                    self.notify_csis_lock(conn, csis).await;
+                }
             }
             Err(e) => {
                 #[cfg(feature = "defmt")]
@@ -171,31 +832,750 @@ where
         }
     }
 
-    fn handle_read(&self, event: &ReadEvent) -> Option<Result<(), AttErrorCode>> {
-        if let Some(res) = self.pacs.handle_read_event(event) {
-            Some(res)
-        } else if let Some(ascs) = &self.ascs {
-            if let Some(res) = ascs.handle_read_event(event) {
-                Some(res)
-            } else {
-                None
+    /// Prepare the services for a newly connected client
+    pub fn handle_connect(&mut self, conn: &Connection<'_>) {
+        if let Some(ascs) = &mut self.ascs {
+            if ascs.handle_connect(conn.handle().raw()).is_err() {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] no ascs connection slot left for this client");
+            }
+        }
+    }
+
+    /// Clean up the state a client left behind
+    ///
+    /// Call this from the `ConnectionEvent::Disconnected` arm of the connection
+    /// event loop, so Ases of a dropped connection return to idle.
+    pub fn handle_disconnect(&mut self, conn: &Connection<'_>) {
+        if let Some(ascs) = &mut self.ascs {
+            ascs.handle_disconnect(conn.handle().raw());
+        }
+        if let Some(csis) = &self.csis {
+            csis.handle_disconnect(conn.handle().raw());
+        }
+    }
+
+    /// Notify `conn` of every Ase state change not notified yet
+    ///
+    /// [`Self::process`] calls this after each event, call it after driving a
+    /// transition from the server, like [`AscsServer::start_streaming`].
+    /// Released Ases without a CIS are notified `Releasing`, then `Idle`.
+    pub async fn notify_ase_state_changes(&self, conn: &Connection<'_>) {
+        if let Some(ascs) = &self.ascs {
+            loop {
+                let changed = ascs.take_changed_ases(conn.handle().raw());
+                if changed.is_empty() {
+                    break;
+                }
+                for ase_id in changed {
+                    if let Err(_e) = ascs
+                        .notify_ase_state_change(&self.server, conn, ase_id)
+                        .await
+                    {
+                        #[cfg(feature = "defmt")]
+                        warn!("[le audio] error notifying ase {}: {:?}", ase_id, _e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The available audio contexts last notified to clients
+    pub fn available_audio_contexts(&self) -> Result<AudioContexts, PacsError> {
+        self.pacs.available_audio_contexts(&self.server)
+    }
+
+    /// Change the available audio contexts without notifying them
+    ///
+    /// Ascs rejects enabling streams of contexts no longer available.
+    pub fn set_available_audio_contexts(&self, contexts: AudioContexts) -> Result<(), PacsError> {
+        self.pacs
+            .set_available_audio_contexts(&self.server, contexts)
+    }
+
+    /// Indicate to `conn` the attributes from `start_handle` to `end_handle` changed
+    ///
+    /// Must be called when a bonded client reconnects after the attribute
+    /// table changed since its last connection, for example once a firmware
+    /// update added a service, so the client discovers the handles again. Use
+    /// `0x0001` to `0xFFFF` when the changed handles are not known.
+    pub async fn notify_service_changed(
+        &self,
+        conn: &Connection<'_>,
+        start_handle: u16,
+        end_handle: u16,
+    ) -> Result<(), NotifyError> {
+        let range = ServiceChangedRange::new(start_handle, end_handle);
+        self.service_changed
+            .indicate(&self.server, conn, &range)
+            .await
+            .map_err(NotifyError::Host)
+    }
+
+    /// Change the available audio contexts and notify them to `conn`
+    pub async fn update_available_contexts(
+        &self,
+        conn: &Connection<'_>,
+        contexts: AudioContexts,
+    ) -> Result<(), NotifyError> {
+        self.pacs
+            .update_available_contexts(&self.server, conn, contexts)
+            .await
+    }
+
+    /// Replace the sink PAC records and notify them to `conn`
+    pub async fn update_sink_pac(&self, conn: &Connection<'_>, pac: &PAC) -> Result<(), PacsError> {
+        self.pacs.update_sink_pac(&self.server, conn, pac).await
+    }
+
+    /// Replace the source PAC records and notify them to `conn`
+    pub async fn update_source_pac(
+        &self,
+        conn: &Connection<'_>,
+        pac: &PAC,
+    ) -> Result<(), PacsError> {
+        self.pacs.update_source_pac(&self.server, conn, pac).await
+    }
+
+    /// Notify `conn` of the audio input changes not notified yet
+    async fn notify_aics_changes(&self, conn: &Connection<'_>, aics: &AicsServer) {
+        if let Some(state) = aics.take_audio_input_state_change() {
+            if let Err(_e) = self
+                .notify(conn, aics.audio_input_state_characteristic(), &state)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying audio input state: {:?}", _e);
+            }
+        }
+        if let Some(status) = aics.take_audio_input_status_change() {
+            if let Err(_e) = self
+                .notify(conn, aics.audio_input_status_characteristic(), &status)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying audio input status: {:?}", _e);
+            }
+        }
+        if let Some(description) = aics.take_audio_input_description_change() {
+            if let Err(_e) = aics
+                .audio_input_description_characteristic()
+                .notify(&self.server, conn, &description)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!(
+                    "[le audio] error notifying audio input description: {:?}",
+                    _e
+                );
+            }
+        }
+    }
+
+    /// Notify `conn` of the audio output changes not notified yet
+    async fn notify_vocs_changes(&self, conn: &Connection<'_>, vocs: &VocsServer) {
+        if let Some(state) = vocs.take_volume_offset_state_change() {
+            if let Err(_e) = self
+                .notify(conn, vocs.volume_offset_state_characteristic(), &state)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying volume offset state: {:?}", _e);
+            }
+        }
+        if let Some(location) = vocs.take_audio_location_change() {
+            if let Err(_e) = self
+                .notify(conn, vocs.audio_location_characteristic(), &location)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying audio location: {:?}", _e);
+            }
+        }
+        if let Some(description) = vocs.take_audio_output_description_change() {
+            if let Err(_e) = vocs
+                .audio_output_description_characteristic()
+                .notify(&self.server, conn, &description)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!(
+                    "[le audio] error notifying audio output description: {:?}",
+                    _e
+                );
+            }
+        }
+    }
+
+    /// Notify `conn` of the media player changes not notified yet
+    ///
+    /// [`Self::process`] calls this after each event, call it after changing the
+    /// state of the player from the application, like [`McsServer::set_track`].
+    pub async fn notify_mcs_changes(&self, conn: &Connection<'_>, mcs: &McsServer) {
+        if let Some(response) = mcs.take_control_point_response() {
+            if let Err(_e) = self
+                .notify(conn, mcs.media_control_point_characteristic(), &response)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying media control point: {:?}", _e);
+            }
+        }
+        if mcs.take_track_changed() {
+            if let Err(_e) = mcs
+                .track_changed_characteristic()
+                .notify(&self.server, conn, &TrackChanged)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying track changed: {:?}", _e);
+            }
+        }
+        if let Some(title) = mcs.take_track_title_change() {
+            if let Err(_e) = mcs
+                .track_title_characteristic()
+                .notify(&self.server, conn, &title)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying track title: {:?}", _e);
+            }
+        }
+        if let Some(duration) = mcs.take_track_duration_change() {
+            if let Err(_e) = self
+                .notify(conn, mcs.track_duration_characteristic(), &duration)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying track duration: {:?}", _e);
+            }
+        }
+        if let Some(position) = mcs.take_track_position_change() {
+            if let Err(_e) = self
+                .notify(conn, mcs.track_position_characteristic(), &position)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying track position: {:?}", _e);
+            }
+        }
+        if let Some(speed) = mcs.take_playback_speed_change() {
+            if let Err(_e) = self
+                .notify(conn, mcs.playback_speed_characteristic(), &speed)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying playback speed: {:?}", _e);
+            }
+        }
+        if let Some(order) = mcs.take_playing_order_change() {
+            if let Err(_e) = self
+                .notify(conn, mcs.playing_order_characteristic(), &order)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying playing order: {:?}", _e);
+            }
+        }
+        if let Some(state) = mcs.take_media_state_change() {
+            if let Err(_e) = self
+                .notify(conn, mcs.media_state_characteristic(), &state)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying media state: {:?}", _e);
+            }
+        }
+    }
+
+    /// The Pacs server, to read the capabilities it exposes
+    pub fn pacs(&self) -> &PacsServer<ATT_MTU> {
+        &self.pacs
+    }
+
+    /// The Ascs server, to drive the streams with a [`BapUnicastServer`](crate::bap::BapUnicastServer)
+    pub fn ascs(&self) -> Option<&AscsServer<MAX_ASES, MAX_CONNECTIONS>> {
+        self.ascs.as_ref()
+    }
+
+    /// The Ascs server, to restore the Ases of a reconnected client with
+    /// [`AscsServer::restore_state`]
+    pub fn ascs_mut(&mut self) -> Option<&mut AscsServer<MAX_ASES, MAX_CONNECTIONS>> {
+        self.ascs.as_mut()
+    }
+
+    /// Notify `conn` of the call changes not notified yet
+    ///
+    /// [`Self::process`] calls this after each event, call it after reporting the
+    /// progress of a call from the application, like [`CcsServer::incoming_call`].
+    pub async fn notify_ccs_changes(&self, conn: &Connection<'_>, ccs: &CcsServer) {
+        if let Some(response) = ccs.take_control_point_response() {
+            if let Err(_e) = self
+                .notify(conn, ccs.call_control_point_characteristic(), &response)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying call control point: {:?}", _e);
+            }
+        }
+        if let Some(target_uri) = ccs.take_incoming_call_target_uri() {
+            if let Err(_e) = ccs
+                .incoming_call_target_uri_characteristic()
+                .notify(&self.server, conn, &target_uri)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!(
+                    "[le audio] error notifying incoming call target uri: {:?}",
+                    _e
+                );
+            }
+        }
+        if let Some(incoming) = ccs.take_incoming_call() {
+            if let Err(_e) = ccs
+                .incoming_call_characteristic()
+                .notify(&self.server, conn, &incoming)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying incoming call: {:?}", _e);
+            }
+        }
+        if let Some(name) = ccs.take_call_friendly_name() {
+            if let Err(_e) = ccs
+                .call_friendly_name_characteristic()
+                .notify(&self.server, conn, &name)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying call friendly name: {:?}", _e);
+            }
+        }
+        if let Some((states, calls)) = ccs.take_calls_change() {
+            if let Err(_e) = ccs
+                .call_state_characteristic()
+                .notify(&self.server, conn, &states)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying call state: {:?}", _e);
+            }
+            if let Err(_e) = ccs
+                .current_calls_characteristic()
+                .notify(&self.server, conn, &calls)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying current calls: {:?}", _e);
+            }
+        }
+        if let Some(termination) = ccs.take_termination() {
+            if let Err(_e) = self
+                .notify(conn, ccs.termination_reason_characteristic(), &termination)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying termination reason: {:?}", _e);
+            }
+        }
+        if let Some(strength) = ccs.take_signal_strength_change() {
+            if let Err(_e) = self
+                .notify(conn, ccs.signal_strength_characteristic(), &strength)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying signal strength: {:?}", _e);
+            }
+        }
+        if let Some(flags) = ccs.take_status_flags_change() {
+            if let Err(_e) = self
+                .notify(conn, ccs.status_flags_characteristic(), &flags)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying status flags: {:?}", _e);
             }
-        } else {
-            None
         }
     }
 
-    fn handle_write(&self, event: &WriteEvent) -> Option<Result<(), AttErrorCode>> {
-        if let Some(res) = self.pacs.handle_write_event(event) {
-            Some(res)
-        } else if let Some(ascs) = &self.ascs {
-            if let Some(res) = ascs.handle_write_event(event) {
-                Some(res)
-            } else {
-                None
+    /// Notify `conn` of the preset changes not notified yet
+    ///
+    /// [`Self::process`] calls this after each event, call it after changing the
+    /// presets from the application, like [`HapServer::set_active_preset`].
+    pub async fn notify_hap_changes(&self, conn: &Connection<'_>, hap: &HapServer) {
+        while let Some(value) = hap.take_control_point_value() {
+            if let Err(_e) = hap
+                .preset_control_point_characteristic()
+                .notify(&self.server, conn, &value)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying preset control point: {:?}", _e);
             }
+        }
+        if let Some(index) = hap.take_active_preset_change() {
+            if let Err(_e) = self
+                .notify(conn, hap.active_preset_index_characteristic(), &index)
+                .await
+            {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying active preset: {:?}", _e);
+            }
+        }
+    }
+
+    /// Notify `conn` of the lock of the set, if it changed
+    pub async fn notify_csis_lock(&self, conn: &Connection<'_>, csis: &CsisServer) {
+        if let Some(lock) = csis.take_lock_change() {
+            if let Err(_e) = self.notify(conn, csis.lock_characteristic(), &lock).await {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error notifying set member lock: {:?}", _e);
+            }
+        }
+    }
+
+    /// Release the lock of the set once it times out, and notify it to `conn`
+    ///
+    /// Select on this next to the events of `conn` in the connection event loop.
+    /// Never completes when the Csis service was not added.
+    pub async fn csis_lock_timeout(&self, conn: &Connection<'_>) {
+        let Some(csis) = &self.csis else {
+            return core::future::pending().await;
+        };
+        csis.lock_timeout().await;
+        self.notify_csis_lock(conn, csis).await;
+    }
+
+    /// The Csis server, to know which client holds the lock of the set
+    pub fn csis(&self) -> Option<&CsisServer> {
+        self.csis.as_ref()
+    }
+
+    /// The Hap server, to change the presets from the application
+    pub fn hap(&self) -> Option<&HapServer> {
+        self.hap.as_ref()
+    }
+
+    /// The Ccs server, to report the progress of the calls from the application
+    pub fn ccs(&self) -> Option<&CcsServer> {
+        self.ccs.as_ref()
+    }
+
+    /// The Mcs server, to drive the media player state from the application
+    pub fn mcs(&self) -> Option<&McsServer> {
+        self.mcs.as_ref()
+    }
+
+    /// Change the mute state of the microphones and notify it to `conn`
+    ///
+    /// Does nothing when the Microphone Control service was not added.
+    pub async fn set_mute(
+        &self,
+        conn: &Connection<'_>,
+        mute: MuteState,
+    ) -> Result<(), NotifyError> {
+        if let Some(mics) = &self.mics {
+            mics.set_mute(&self.server, conn, mute).await
         } else {
-            None
+            Ok(())
+        }
+    }
+
+    /// Store `value` as the value of a characteristic of this server, without notifying it
+    ///
+    /// Clients read the new value, use [`Self::notify`] to also send it to a client.
+    pub fn set<T: FixedGattValue>(
+        &self,
+        characteristic: &Characteristic<T>,
+        value: &T,
+    ) -> Result<(), NotifyError> {
+        characteristic
+            .set(&self.server, value)
+            .map_err(NotifyError::Host)
+    }
+
+    /// Send a notification of `value` on a characteristic of this server
+    ///
+    /// The value is also stored as the new value of the characteristic.
+    pub async fn notify<T: FixedGattValue>(
+        &self,
+        conn: &Connection<'_>,
+        characteristic: &Characteristic<T>,
+        value: &T,
+    ) -> Result<(), NotifyError> {
+        characteristic
+            .notify(&self.server, conn, value)
+            .await
+            .map_err(NotifyError::Host)
+    }
+
+    /// Dispatch a read of the attribute `handle` to the service owning it
+    ///
+    /// Returns `None` when no service owns the handle. [`Self::process`] calls this
+    /// for every read, it is public so the dispatch can be driven without a GATT stack.
+    /// Ase accesses are checked against the client selected with
+    /// [`AscsServer::set_active_connection`].
+    pub fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        self.pacs
+            .handle_read(handle)
+            .or_else(|| self.ascs.as_ref().and_then(|ascs| ascs.handle_read(handle)))
+            .or_else(|| self.vcs.as_ref().and_then(|vcs| vcs.handle_read(handle)))
+            .or_else(|| self.mics.as_ref().and_then(|mics| mics.handle_read(handle)))
+            .or_else(|| self.tmap.as_ref().and_then(|tmap| tmap.handle_read(handle)))
+            .or_else(|| self.mcs.as_ref().and_then(|mcs| mcs.handle_read(handle)))
+            .or_else(|| self.ccs.as_ref().and_then(|ccs| ccs.handle_read(handle)))
+            .or_else(|| self.hap.as_ref().and_then(|hap| hap.handle_read(handle)))
+            .or_else(|| self.csis.as_ref().and_then(|csis| csis.handle_read(handle)))
+    }
+
+    /// Dispatch a write of `data` to the attribute `handle` to the service owning it
+    ///
+    /// Returns `None` when no service owns the handle. [`Self::process`] calls this
+    /// for every write, it is public so the dispatch can be driven without a GATT stack.
+    pub fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if let (Some(ascs), Some(contexts)) =
+            (&self.ascs, self.pacs.available_contexts_watch().try_get())
+        {
+            ascs.set_available_contexts(contexts);
+        }
+        self.pacs
+            .handle_write(handle, data)
+            .or_else(|| {
+                self.ascs
+                    .as_ref()
+                    .and_then(|ascs| ascs.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.vcs
+                    .as_ref()
+                    .and_then(|vcs| vcs.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.mics
+                    .as_ref()
+                    .and_then(|mics| mics.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.tmap
+                    .as_ref()
+                    .and_then(|tmap| tmap.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.mcs
+                    .as_ref()
+                    .and_then(|mcs| mcs.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.ccs
+                    .as_ref()
+                    .and_then(|ccs| ccs.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.hap
+                    .as_ref()
+                    .and_then(|hap| hap.handle_write(handle, data))
+            })
+            .or_else(|| {
+                self.csis
+                    .as_ref()
+                    .and_then(|csis| csis.handle_write(handle, data))
+            })
+    }
+}
+
+/// Advertise `server` and serve the clients connecting to it, one at a time
+///
+/// Each connection is prepared with [`Server::handle_connect`], its Gatt events
+/// processed until it drops, then cleaned up with [`Server::handle_disconnect`]
+/// before advertising again. `adv_data` and `scan_data` are the AD structures
+/// encoded with `AdStructure::encode_slice`. Applications handling other events
+/// of the connection, like Ascs events, should write their own loop instead.
+pub async fn run_server<
+    C: Controller,
+    const ATT_MTU: usize,
+    const MAX_ASES: usize,
+    const MAX_CONNECTIONS: usize,
+    const N: usize,
+    M: RawMutex,
+>(
+    peripheral: &mut Peripheral<'_, C>,
+    server: &mut Server<'_, ATT_MTU, MAX_ASES, MAX_CONNECTIONS, N, M>,
+    adv_data: &[u8],
+    scan_data: &[u8],
+) -> ! {
+    loop {
+        let advertisement = Advertisement::ConnectableScannableUndirected {
+            adv_data,
+            scan_data,
+        };
+        let conn = match peripheral
+            .advertise(&Default::default(), advertisement)
+            .await
+        {
+            Ok(advertiser) => advertiser.accept().await.map_err(Into::into),
+            Err(err) => Err(err),
+        };
+        let conn = match conn {
+            Ok(conn) => conn,
+            Err(_e) => {
+                #[cfg(feature = "defmt")]
+                warn!("[le audio] error advertising: {:?}", Debug2Format(&_e));
+                continue;
+            }
+        };
+
+        #[cfg(feature = "defmt")]
+        info!("[le audio] client connected");
+        server.handle_connect(&conn);
+        loop {
+            match select(conn.next(), server.csis_lock_timeout(&conn)).await {
+                Either::First(ConnectionEvent::Disconnected { reason: _reason }) => {
+                    #[cfg(feature = "defmt")]
+                    info!("[le audio] client disconnected: {:?}", _reason);
+                    break;
+                }
+                Either::First(ConnectionEvent::Gatt { data }) => server.process(&conn, data).await,
+                Either::Second(()) => {}
+            }
         }
+        server.handle_disconnect(&conn);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ascs::{Ase, AseId},
+        pacs::{PACRecord, MAX_PAC_SIZE},
+    };
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    const ATTRIBUTES: usize = MIN_ATTRIBUTES + ascs_attributes(1, 1) + VCS_ATTRIBUTES;
+
+    static NAME: [u8; 4] = *b"test";
+    static APPEARANCE: [u8; 2] = [0; 2];
+
+    #[test]
+    fn service_changed_range_is_both_handles_little_endian() {
+        let range = ServiceChangedRange::new(0x0001, 0xABCD);
+        assert_eq!(FixedGattValue::as_gatt(&range), &[0x01, 0x00, 0xCD, 0xAB]);
+        assert_eq!(
+            <ServiceChangedRange as FixedGattValue>::from_gatt(&[0x01, 0x00, 0xCD, 0xAB]),
+            Ok(range)
+        );
+        assert_eq!(range.start_handle(), 0x0001);
+        assert_eq!(range.end_handle(), 0xABCD);
+        assert!(<ServiceChangedRange as FixedGattValue>::from_gatt(&[0x01, 0x00, 0xCD]).is_err());
+    }
+
+    #[test]
+    fn each_handle_is_handled_by_its_service_only() {
+        let mut records = Vec::new();
+        let _ = records.push(PACRecord {
+            codec_id: Vec::from_slice(&[crate::codec_ids::LC3]).unwrap(),
+            ..Default::default()
+        });
+        let pac = PAC::new(records).unwrap();
+        let locations = AudioLocation::FrontLeft;
+        let contexts = AudioContexts::sink_only_default();
+        let mut pac_store = [0; MAX_PAC_SIZE];
+        let mut locations_store = [0; 4];
+        let mut available_store = [0; 4];
+        let mut ascs_storage = AscsStorage::<1, 1>::new();
+        let mut vcs_storage = VcsStorage::new();
+        let mut gatt_storage = GattStorage::new();
+        let mut ases = Vec::new();
+        let _ = ases.push(AseType::Sink(Ase::new(AseId::new(1).unwrap())));
+        let server = ServerBuilder::<23, 1, 1, ATTRIBUTES, NoopRawMutex>::new(
+            &NAME,
+            &APPEARANCE,
+            &mut gatt_storage,
+        )
+        .add_pacs(
+            Some((&pac, &mut pac_store)),
+            Some((&locations, &mut locations_store)),
+            None,
+            None,
+            &contexts,
+            (&contexts, &mut available_store),
+        )
+        .add_ascs(ases, &mut ascs_storage)
+        .add_vcs(
+            VolumeState::default(),
+            VolumeFlags::empty(),
+            1,
+            &mut vcs_storage,
+        )
+        .build()
+        .unwrap();
+        let ascs = server.ascs().unwrap();
+        let vcs = server.vcs.as_ref().unwrap();
+
+        let pacs_handle = (1..=u16::MAX)
+            .find(|handle| server.pacs().handle_read(*handle).is_some())
+            .unwrap();
+        let handles = [
+            pacs_handle,
+            ascs.control_point().handle,
+            vcs.volume_state_characteristic().handle,
+        ];
+        for (owner, handle) in handles.into_iter().enumerate() {
+            let handled = [
+                server.pacs().handle_read(handle).is_some(),
+                ascs.handle_read(handle).is_some(),
+                vcs.handle_read(handle).is_some(),
+            ];
+            for (service, handled) in handled.into_iter().enumerate() {
+                assert_eq!(handled, service == owner, "handle {:#06x}", handle);
+            }
+            assert!(server.handle_read(handle).is_some());
+        }
+        assert!(server.handle_read(0xfff0).is_none());
+        assert!(server.handle_write(0xfff0, &[0]).is_none());
+
+        // Writes reach the owning service, which rejects read only characteristics
+        let result = server.handle_write(handles[0], &[0; 4]);
+        assert!(matches!(result, Some(Err(_))));
+        let result = server.handle_write(handles[2], &[0; 3]);
+        assert!(matches!(result, Some(Err(_))));
+    }
+
+    #[test]
+    fn set_stores_the_value_of_a_characteristic() {
+        let contexts = AudioContexts::source_only_default();
+        let mut available_store = [0; 4];
+        let mut vcs_storage = VcsStorage::new();
+        let mut gatt_storage = GattStorage::new();
+        let server = ServerBuilder::<23, 1, 1, ATTRIBUTES, NoopRawMutex>::new(
+            &NAME,
+            &APPEARANCE,
+            &mut gatt_storage,
+        )
+        .add_pacs(
+            None,
+            None,
+            None,
+            None,
+            &contexts,
+            (&contexts, &mut available_store),
+        )
+        .add_vcs(
+            VolumeState::default(),
+            VolumeFlags::empty(),
+            1,
+            &mut vcs_storage,
+        )
+        .build()
+        .unwrap();
+        let volume_state = server.vcs.as_ref().unwrap().volume_state_characteristic();
+        let value = VolumeState {
+            volume_setting: 200,
+            mute: true,
+            change_counter: 3,
+        };
+        server.set(volume_state, &value).unwrap();
+        assert_eq!(volume_state.get(&server.server).unwrap(), value);
+        assert!(server.handle_read(volume_state.handle).is_some());
     }
 }
@@ -0,0 +1,73 @@
+use heapless::Vec;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+/// The text does not fit in the characteristic it is written to
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextTooLong;
+
+/// UTF-8 text characteristic of up to `N` bytes, like a description or a name
+///
+/// Clients can write any bytes, so the text is only checked when read with
+/// [`Utf8Text::as_str`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Utf8Text<const N: usize>(Vec<u8, N>);
+
+impl<const N: usize> Utf8Text<N> {
+    /// Fails if the text is longer than `N` bytes
+    pub fn new(text: &str) -> Result<Self, TextTooLong> {
+        Vec::from_slice(text.as_bytes())
+            .map(Self)
+            .map_err(|_| TextTooLong)
+    }
+
+    /// The text, `None` if a client wrote invalid UTF-8
+    pub fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(&self.0).ok()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<const N: usize> AsGatt for Utf8Text<N> {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = N;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> FromGatt for Utf8Text<N> {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_longer_than_the_characteristic_is_rejected() {
+        assert_eq!(Utf8Text::<4>::new("Left").unwrap().as_str(), Some("Left"));
+        assert_eq!(Utf8Text::<4>::new("Right"), Err(TextTooLong));
+    }
+
+    #[test]
+    fn written_text_is_checked_when_read() {
+        let text = Utf8Text::<4>::from_gatt(&[0xFF, 0xFE]).unwrap();
+        assert_eq!(text.as_gatt(), &[0xFF, 0xFE]);
+        assert_eq!(text.as_str(), None);
+        assert!(Utf8Text::<1>::from_gatt(b"ab").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Telephony and Media Audio Profile
+//!
+//! This service exposes the TMAP roles of a device, which clients read to
+//! find out which telephony and media use cases it supports.
+
+use bitflags::bitflags;
+use bt_hci::uuid::{characteristic, service};
+use core::slice;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+use crate::{LeAudioError, LeAudioServerService};
+
+/// Attributes added to the attribute table by the Tmap service
+pub const TMAP_ATTRIBUTES: usize = 3;
+
+/// Backing storage for the characteristics of a [`TmapServer`]
+pub struct TmapStorage {
+    role: [u8; TmapRole::SIZE],
+}
+
+impl TmapStorage {
+    pub const fn new() -> Self {
+        Self {
+            role: [0; TmapRole::SIZE],
+        }
+    }
+}
+
+impl Default for TmapStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the TMAP roles of a device
+pub struct TmapServer {
+    handle: u16,
+    role: Characteristic<TmapRole>,
+}
+
+impl TmapServer {
+    /// Create a new Tmap Gatt Service
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        role: TmapRole,
+        storage: &'a mut TmapStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::TELEPHONY_AND_MEDIA_AUDIO));
+
+        let role_char = service
+            .add_characteristic(
+                characteristic::TMAP_ROLE,
+                &[CharacteristicProp::Read],
+                role,
+                &mut storage.role,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            role: role_char,
+        }
+    }
+
+    /// The handle of the service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The TMAP Role characteristic
+    pub fn role_characteristic(&self) -> &Characteristic<TmapRole> {
+        &self.role
+    }
+}
+
+impl LeAudioServerService for TmapServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        (handle == self.role.handle).then_some(Ok(()))
+    }
+
+    fn handle_write(&self, handle: u16, _data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        (handle == self.role.handle).then_some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+    }
+}
+
+bitflags! {
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct TmapRole: u16 {
+        const CallGateway = 0x0001;
+        const CallTerminal = 0x0002;
+        const UnicastMediaSender = 0x0004;
+        const UnicastMediaReceiver = 0x0008;
+        const BroadcastMediaSender = 0x0010;
+        const BroadcastMediaReceiver = 0x0020;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for TmapRole {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "TmapRole({=u16:#06x})", self.bits())
+    }
+}
+
+impl FixedGattValue for TmapRole {
+    const SIZE: usize = 2;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [low, high] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(u16::from_le_bytes([*low, *high])))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_are_encoded_little_endian() {
+        let role = TmapRole::UnicastMediaReceiver | TmapRole::BroadcastMediaReceiver;
+        assert_eq!(<TmapRole as FixedGattValue>::as_gatt(&role), &[0x28, 0x00]);
+
+        let role = <TmapRole as FixedGattValue>::from_gatt(&[0x01, 0x01]).unwrap();
+        assert_eq!(role.bits(), 0x0101);
+        assert!(role.contains(TmapRole::CallGateway));
+    }
+}
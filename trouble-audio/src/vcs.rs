@@ -0,0 +1,479 @@
+//! Volume Control Service
+//!
+//! This service exposes the volume of an audio device, which clients
+//! can change in steps, set directly and mute.
+
+use bitflags::bitflags;
+use bt_hci::uuid::{characteristic, service};
+use core::{cell::Cell, slice};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::info;
+
+use crate::{
+    aics::AicsServer,
+    generic_audio::AudioLocation,
+    vocs::{AudioOutputDescription, VocsServer, VocsStorage},
+    LeAudioError, LeAudioServerService,
+};
+
+/// Attributes added to the attribute table by the Vcs service
+pub const VCS_ATTRIBUTES: usize = 9;
+
+/// Max audio outputs whose volume offset is exposed with Vocs
+pub const MAX_VOCS: usize = 2;
+
+/// Length of the longest Volume Control Point write, Set Absolute Volume
+pub const VOLUME_CONTROL_POINT_SIZE: usize = 3;
+
+/// Application errors of the Vcs service
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcsError {
+    /// A write named a stale change counter
+    InvalidChangeCounter,
+    /// The opcode belongs to a later version of the service
+    OpcodeNotSupported,
+}
+
+impl From<VcsError> for AttErrorCode {
+    fn from(err: VcsError) -> Self {
+        match err {
+            VcsError::InvalidChangeCounter => AttErrorCode::APPLICATION_ERROR_0x80,
+            VcsError::OpcodeNotSupported => AttErrorCode::APPLICATION_ERROR_0x81,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of a [`VcsServer`]
+pub struct VcsStorage {
+    volume_state: [u8; VolumeState::SIZE],
+    volume_control_point: [u8; VOLUME_CONTROL_POINT_SIZE],
+    volume_flags: [u8; VolumeFlags::SIZE],
+}
+
+impl VcsStorage {
+    pub const fn new() -> Self {
+        Self {
+            volume_state: [0; VolumeState::SIZE],
+            volume_control_point: [0; VOLUME_CONTROL_POINT_SIZE],
+            volume_flags: [0; VolumeFlags::SIZE],
+        }
+    }
+}
+
+impl Default for VcsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the volume of an audio device
+pub struct VcsServer {
+    handle: u16,
+    volume_state: Characteristic<VolumeState>,
+    volume_control_point: Characteristic<VolumeControlPointValue>,
+    volume_flags: Characteristic<VolumeFlags>,
+    state: Cell<VolumeState>,
+    step_size: u8,
+    changed: Cell<bool>,
+    aics: Option<AicsServer>,
+    vocs: Vec<VocsServer, MAX_VOCS>,
+}
+
+impl VcsServer {
+    /// Create a new Vcs Gatt Service
+    ///
+    /// Relative volume operations change the volume setting by `step_size`.
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        volume_state: VolumeState,
+        volume_flags: VolumeFlags,
+        step_size: u8,
+        storage: &'a mut VcsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::VOLUME_CONTROL));
+
+        let volume_state_char = service
+            .add_characteristic(
+                characteristic::VOLUME_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                volume_state,
+                &mut storage.volume_state,
+            )
+            .build();
+
+        let volume_control_point_char = service
+            .add_characteristic(
+                characteristic::VOLUME_CONTROL_POINT,
+                &[CharacteristicProp::Write],
+                VolumeControlPointValue::default(),
+                &mut storage.volume_control_point,
+            )
+            .build();
+
+        let volume_flags_char = service
+            .add_characteristic(
+                characteristic::VOLUME_FLAGS,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                volume_flags,
+                &mut storage.volume_flags,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            volume_state: volume_state_char,
+            volume_control_point: volume_control_point_char,
+            volume_flags: volume_flags_char,
+            state: Cell::new(volume_state),
+            step_size,
+            changed: Cell::new(false),
+            aics: None,
+            vocs: Vec::new(),
+        }
+    }
+
+    /// The handle of the service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Volume State characteristic
+    pub fn volume_state_characteristic(&self) -> &Characteristic<VolumeState> {
+        &self.volume_state
+    }
+
+    /// The current volume state
+    pub fn volume_state(&self) -> VolumeState {
+        self.state.get()
+    }
+
+    /// Change the volume from the server, for example from a button on the device
+    pub fn set_volume(&self, volume_setting: u8, mute: bool) {
+        self.update(VolumeState {
+            volume_setting,
+            mute,
+            ..self.state.get()
+        });
+    }
+
+    /// Include an audio input whose gain is controlled along the volume
+    ///
+    /// The events of the Aics service are dispatched through this service.
+    pub fn include_aics(&mut self, aics: AicsServer) {
+        self.aics = Some(aics);
+    }
+
+    /// The included audio input
+    pub fn aics(&self) -> Option<&AicsServer> {
+        self.aics.as_ref()
+    }
+
+    /// Expose the volume offset of an audio output with a Vocs service
+    ///
+    /// Returns the handle of the included service, `None` when [`MAX_VOCS`]
+    /// outputs were already added. The events of the Vocs service are
+    /// dispatched through this service.
+    pub fn add_vocs<'a, M: RawMutex, const N: usize>(
+        &mut self,
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        volume_offset: i16,
+        audio_location: AudioLocation,
+        description: AudioOutputDescription,
+        storage: &'a mut VocsStorage,
+    ) -> Option<u16> {
+        if self.vocs.is_full() {
+            return None;
+        }
+        let vocs = VocsServer::new(table, volume_offset, audio_location, description, storage);
+        let handle = vocs.handle();
+        self.vocs.push(vocs).ok()?;
+        Some(handle)
+    }
+
+    /// The included audio outputs
+    pub fn vocs(&self) -> &[VocsServer] {
+        &self.vocs
+    }
+
+    /// The volume state to notify to clients, if it changed since the last call
+    pub fn take_volume_state_change(&self) -> Option<VolumeState> {
+        self.changed.replace(false).then(|| self.state.get())
+    }
+
+    /// Store a new volume state, counting the change if any field differs
+    fn update(&self, new: VolumeState) {
+        let current = self.state.get();
+        if new.volume_setting != current.volume_setting || new.mute != current.mute {
+            self.state.set(VolumeState {
+                change_counter: current.change_counter.wrapping_add(1),
+                ..new
+            });
+            self.changed.set(true);
+        }
+    }
+
+    /// Apply a volume control point operation
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, change_counter, operand @ ..] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        let opcode =
+            VolumeControlOpcode::try_from(*opcode).map_err(|_| VcsError::OpcodeNotSupported)?;
+        let expected_len = match opcode {
+            VolumeControlOpcode::SetAbsoluteVolume => 1,
+            _ => 0,
+        };
+        if operand.len() != expected_len {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        }
+
+        let state = self.state.get();
+        if *change_counter != state.change_counter {
+            return Err(VcsError::InvalidChangeCounter.into());
+        }
+
+        #[cfg(feature = "defmt")]
+        info!("[vcs] {:?}", opcode);
+        let down = state.volume_setting.saturating_sub(self.step_size);
+        let up = state.volume_setting.saturating_add(self.step_size);
+        let (volume_setting, mute) = match opcode {
+            VolumeControlOpcode::RelativeVolumeDown => (down, state.mute),
+            VolumeControlOpcode::RelativeVolumeUp => (up, state.mute),
+            VolumeControlOpcode::UnmuteRelativeVolumeDown => (down, false),
+            VolumeControlOpcode::UnmuteRelativeVolumeUp => (up, false),
+            VolumeControlOpcode::SetAbsoluteVolume => (operand[0], state.mute),
+            VolumeControlOpcode::Unmute => (state.volume_setting, false),
+            VolumeControlOpcode::Mute => (state.volume_setting, true),
+        };
+        self.update(VolumeState {
+            volume_setting,
+            mute,
+            ..state
+        });
+        Ok(())
+    }
+}
+
+impl LeAudioServerService for VcsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.volume_state.handle || handle == self.volume_flags.handle {
+            Some(Ok(()))
+        } else if handle == self.volume_control_point.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else {
+            self.aics
+                .as_ref()
+                .and_then(|aics| aics.handle_read(handle))
+                .or_else(|| self.vocs.iter().find_map(|vocs| vocs.handle_read(handle)))
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.volume_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.volume_state.handle || handle == self.volume_flags.handle {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            self.aics
+                .as_ref()
+                .and_then(|aics| aics.handle_write(handle, data))
+                .or_else(|| {
+                    self.vocs
+                        .iter()
+                        .find_map(|vocs| vocs.handle_write(handle, data))
+                })
+        }
+    }
+}
+
+/// The volume setting, mute state and the number of changes made to them
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct VolumeState {
+    pub volume_setting: u8,
+    pub mute: bool,
+    /// Incremented on every change, a client must name it to change the volume
+    pub change_counter: u8,
+}
+
+impl FixedGattValue for VolumeState {
+    const SIZE: usize = 3;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [volume_setting, mute @ (0 | 1), change_counter] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            volume_setting: *volume_setting,
+            mute: *mute == 1,
+            change_counter: *change_counter,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+bitflags! {
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct VolumeFlags: u8 {
+        /// The volume setting was restored from a previous session instead of reset
+        const VolumeSettingPersisted = 0x01;
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for VolumeFlags {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "VolumeFlags({=u8:#04x})", self.bits())
+    }
+}
+
+impl FixedGattValue for VolumeFlags {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [flags] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self::from_bits_retain(*flags))
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Operations of the Volume Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum VolumeControlOpcode {
+    RelativeVolumeDown = 0x00,
+    RelativeVolumeUp = 0x01,
+    UnmuteRelativeVolumeDown = 0x02,
+    UnmuteRelativeVolumeUp = 0x03,
+    SetAbsoluteVolume = 0x04,
+    Unmute = 0x05,
+    Mute = 0x06,
+}
+
+impl TryFrom<u8> for VolumeControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        Ok(match opcode {
+            0x00 => Self::RelativeVolumeDown,
+            0x01 => Self::RelativeVolumeUp,
+            0x02 => Self::UnmuteRelativeVolumeDown,
+            0x03 => Self::UnmuteRelativeVolumeUp,
+            0x04 => Self::SetAbsoluteVolume,
+            0x05 => Self::Unmute,
+            0x06 => Self::Mute,
+            _ => return Err(opcode),
+        })
+    }
+}
+
+/// A write to the Volume Control Point
+///
+/// The opcode and the change counter, followed by the volume setting for
+/// Set Absolute Volume.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VolumeControlPointValue(Vec<u8, VOLUME_CONTROL_POINT_SIZE>);
+
+impl AsGatt for VolumeControlPointValue {
+    const MIN_SIZE: usize = 0;
+    const MAX_SIZE: usize = VOLUME_CONTROL_POINT_SIZE;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromGatt for VolumeControlPointValue {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Vec::from_slice(data)
+            .map(Self)
+            .map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use trouble_host::attribute::AttributeTable;
+
+    const ATTRIBUTES: usize = VCS_ATTRIBUTES;
+
+    fn vcs<'a>(
+        table: &mut AttributeTable<'a, NoopRawMutex, ATTRIBUTES>,
+        storage: &'a mut VcsStorage,
+        volume_setting: u8,
+    ) -> VcsServer {
+        let state = VolumeState {
+            volume_setting,
+            ..Default::default()
+        };
+        VcsServer::new(table, state, VolumeFlags::empty(), 16, storage)
+    }
+
+    fn write(vcs: &VcsServer, data: &[u8]) -> Result<(), AttErrorCode> {
+        vcs.handle_write(vcs.volume_control_point.handle, data)
+            .unwrap()
+            .map_err(AttErrorCode::from)
+    }
+
+    #[test]
+    fn stale_change_counter_is_rejected() {
+        let mut storage = VcsStorage::new();
+        let mut table = AttributeTable::new();
+        let vcs = vcs(&mut table, &mut storage, 100);
+
+        write(&vcs, &[VolumeControlOpcode::Mute as u8, 0]).unwrap();
+        assert_eq!(
+            write(&vcs, &[VolumeControlOpcode::Unmute as u8, 0]),
+            Err(AttErrorCode::APPLICATION_ERROR_0x80)
+        );
+        assert!(vcs.volume_state().mute);
+    }
+
+    #[test]
+    fn relative_volume_saturates() {
+        let mut storage = VcsStorage::new();
+        let mut table = AttributeTable::new();
+        let vcs = vcs(&mut table, &mut storage, 250);
+
+        write(&vcs, &[VolumeControlOpcode::RelativeVolumeUp as u8, 0]).unwrap();
+        assert_eq!(vcs.volume_state().volume_setting, 255);
+
+        write(&vcs, &[VolumeControlOpcode::SetAbsoluteVolume as u8, 1, 10]).unwrap();
+        write(&vcs, &[VolumeControlOpcode::RelativeVolumeDown as u8, 2]).unwrap();
+        assert_eq!(vcs.volume_state().volume_setting, 0);
+    }
+
+    #[test]
+    fn change_counter_wraps() {
+        let mut storage = VcsStorage::new();
+        let mut table = AttributeTable::new();
+        let vcs = vcs(&mut table, &mut storage, 0);
+
+        for volume_setting in 1..=256u16 {
+            vcs.set_volume(volume_setting as u8, false);
+        }
+        assert_eq!(vcs.volume_state().change_counter, 0);
+        assert_eq!(vcs.take_volume_state_change().unwrap().volume_setting, 0);
+
+        vcs.set_volume(0, false);
+        assert_eq!(vcs.take_volume_state_change(), None);
+    }
+}
@@ -0,0 +1,471 @@
+//! Volume Offset Control Service
+//!
+//! This service exposes the volume offset, location and description of an
+//! audio output, usually included by the Volume Control service once for
+//! each output of the device.
+
+use bt_hci::uuid::{characteristic, service};
+use core::{
+    cell::{Cell, RefCell},
+    slice,
+};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use trouble_host::{prelude::*, types::gatt_traits::*};
+
+#[cfg(feature = "defmt")]
+use defmt::info;
+
+use crate::{generic_audio::AudioLocation, LeAudioError, LeAudioServerService, Utf8Text};
+
+/// Attributes added to the attribute table by the Vocs service
+pub const VOCS_ATTRIBUTES: usize = 12;
+
+/// Max length of the UTF-8 audio output description
+pub const MAX_AUDIO_OUTPUT_DESCRIPTION_SIZE: usize = 32;
+
+/// Smallest volume offset, muting the output
+pub const MIN_VOLUME_OFFSET: i16 = -255;
+/// Largest volume offset
+pub const MAX_VOLUME_OFFSET: i16 = 255;
+
+/// Application errors of the Vocs service
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VocsError {
+    /// A write named a stale change counter
+    InvalidChangeCounter,
+    /// The opcode belongs to a later version of the service
+    OpcodeNotSupported,
+    /// The volume offset is outside of -255 to 255
+    ValueOutOfRange,
+}
+
+impl From<VocsError> for AttErrorCode {
+    fn from(err: VocsError) -> Self {
+        match err {
+            VocsError::InvalidChangeCounter => AttErrorCode::APPLICATION_ERROR_0x80,
+            VocsError::OpcodeNotSupported => AttErrorCode::APPLICATION_ERROR_0x81,
+            VocsError::ValueOutOfRange => AttErrorCode::APPLICATION_ERROR_0x82,
+        }
+    }
+}
+
+/// Backing storage for the characteristics of a [`VocsServer`]
+///
+/// Every Vocs instance needs its own storage.
+pub struct VocsStorage {
+    volume_offset_state: [u8; VolumeOffsetState::SIZE],
+    audio_location: [u8; AudioLocation::SIZE],
+    volume_offset_control_point: [u8; 4],
+    audio_output_description: [u8; MAX_AUDIO_OUTPUT_DESCRIPTION_SIZE],
+}
+
+impl VocsStorage {
+    pub const fn new() -> Self {
+        Self {
+            volume_offset_state: [0; VolumeOffsetState::SIZE],
+            audio_location: [0; AudioLocation::SIZE],
+            volume_offset_control_point: [0; 4],
+            audio_output_description: [0; MAX_AUDIO_OUTPUT_DESCRIPTION_SIZE],
+        }
+    }
+}
+
+impl Default for VocsStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Gatt service exposing the volume offset of an audio output
+pub struct VocsServer {
+    handle: u16,
+    volume_offset_state: Characteristic<VolumeOffsetState>,
+    audio_location: Characteristic<AudioLocation>,
+    volume_offset_control_point: Characteristic<VolumeOffsetControlOpcode>,
+    audio_output_description: Characteristic<AudioOutputDescription>,
+    state: Cell<VolumeOffsetState>,
+    location: Cell<AudioLocation>,
+    description: RefCell<AudioOutputDescription>,
+    state_changed: Cell<bool>,
+    location_changed: Cell<bool>,
+    description_changed: Cell<bool>,
+}
+
+impl VocsServer {
+    /// Create a new Vocs Gatt Service
+    pub fn new<'a, M: RawMutex, const N: usize>(
+        table: &mut trouble_host::attribute::AttributeTable<'a, M, N>,
+        volume_offset: i16,
+        audio_location: AudioLocation,
+        description: AudioOutputDescription,
+        storage: &'a mut VocsStorage,
+    ) -> Self {
+        let mut service = table.add_service(Service::new(service::VOLUME_OFFSET_CONTROL));
+
+        let state = VolumeOffsetState {
+            volume_offset: volume_offset.clamp(MIN_VOLUME_OFFSET, MAX_VOLUME_OFFSET),
+            change_counter: 0,
+        };
+        let volume_offset_state_char = service
+            .add_characteristic(
+                characteristic::VOLUME_OFFSET_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                state,
+                &mut storage.volume_offset_state,
+            )
+            .build();
+
+        let audio_location_char = service
+            .add_characteristic(
+                characteristic::AUDIO_LOCATION,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::Notify,
+                ],
+                audio_location,
+                &mut storage.audio_location,
+            )
+            .build();
+
+        let volume_offset_control_point_char = service
+            .add_characteristic(
+                characteristic::VOLUME_OFFSET_CONTROL_POINT,
+                &[CharacteristicProp::Write],
+                VolumeOffsetControlOpcode::SetVolumeOffset,
+                &mut storage.volume_offset_control_point,
+            )
+            .build();
+
+        let audio_output_description_char = service
+            .add_characteristic(
+                characteristic::AUDIO_OUTPUT_DESCRIPTION,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                description.clone(),
+                &mut storage.audio_output_description,
+            )
+            .build();
+
+        Self {
+            handle: service.build(),
+            volume_offset_state: volume_offset_state_char,
+            audio_location: audio_location_char,
+            volume_offset_control_point: volume_offset_control_point_char,
+            audio_output_description: audio_output_description_char,
+            state: Cell::new(state),
+            location: Cell::new(audio_location),
+            description: RefCell::new(description),
+            state_changed: Cell::new(false),
+            location_changed: Cell::new(false),
+            description_changed: Cell::new(false),
+        }
+    }
+
+    /// The handle of the service, to be included by another service
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// The Volume Offset State characteristic
+    pub fn volume_offset_state_characteristic(&self) -> &Characteristic<VolumeOffsetState> {
+        &self.volume_offset_state
+    }
+
+    /// The Audio Location characteristic
+    pub fn audio_location_characteristic(&self) -> &Characteristic<AudioLocation> {
+        &self.audio_location
+    }
+
+    /// The Audio Output Description characteristic
+    pub fn audio_output_description_characteristic(
+        &self,
+    ) -> &Characteristic<AudioOutputDescription> {
+        &self.audio_output_description
+    }
+
+    /// The current volume offset and change counter
+    pub fn volume_offset_state(&self) -> VolumeOffsetState {
+        self.state.get()
+    }
+
+    /// The location of the audio output
+    pub fn audio_location(&self) -> AudioLocation {
+        self.location.get()
+    }
+
+    /// Change the volume offset from the server, clamped to -255 to 255
+    pub fn set_volume_offset(&self, volume_offset: i16) {
+        self.update(volume_offset.clamp(MIN_VOLUME_OFFSET, MAX_VOLUME_OFFSET));
+    }
+
+    /// Change the location of the audio output from the server
+    pub fn set_audio_location(&self, location: AudioLocation) {
+        if self.location.replace(location) != location {
+            self.location_changed.set(true);
+        }
+    }
+
+    /// The volume offset state to notify to clients, if it changed since the last call
+    pub fn take_volume_offset_state_change(&self) -> Option<VolumeOffsetState> {
+        self.state_changed.replace(false).then(|| self.state.get())
+    }
+
+    /// The audio location to notify to clients, if it changed since the last call
+    pub fn take_audio_location_change(&self) -> Option<AudioLocation> {
+        self.location_changed
+            .replace(false)
+            .then(|| self.location.get())
+    }
+
+    /// The audio output description to notify to clients, if a client changed it
+    pub fn take_audio_output_description_change(&self) -> Option<AudioOutputDescription> {
+        self.description_changed
+            .replace(false)
+            .then(|| self.description.borrow().clone())
+    }
+
+    /// Store a new volume offset, counting the change if it differs
+    fn update(&self, volume_offset: i16) {
+        let current = self.state.get();
+        if volume_offset != current.volume_offset {
+            self.state.set(VolumeOffsetState {
+                volume_offset,
+                change_counter: current.change_counter.wrapping_add(1),
+            });
+            self.state_changed.set(true);
+        }
+    }
+
+    /// Apply a volume offset control point operation
+    fn process_control_point(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let [opcode, change_counter, operand @ ..] = data else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+        let opcode = VolumeOffsetControlOpcode::try_from(*opcode)
+            .map_err(|_| VocsError::OpcodeNotSupported)?;
+        let [low, high] = operand else {
+            return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH.into());
+        };
+
+        if *change_counter != self.state.get().change_counter {
+            return Err(VocsError::InvalidChangeCounter.into());
+        }
+        let volume_offset = i16::from_le_bytes([*low, *high]);
+        if !(MIN_VOLUME_OFFSET..=MAX_VOLUME_OFFSET).contains(&volume_offset) {
+            return Err(VocsError::ValueOutOfRange.into());
+        }
+
+        #[cfg(feature = "defmt")]
+        info!("[vocs] {:?} {}", opcode, volume_offset);
+        match opcode {
+            VolumeOffsetControlOpcode::SetVolumeOffset => self.update(volume_offset),
+        }
+        Ok(())
+    }
+
+    /// Store an audio location written by a client
+    fn write_audio_location(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let location = <AudioLocation as FixedGattValue>::from_gatt(data)
+            .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+        self.set_audio_location(location);
+        Ok(())
+    }
+
+    /// Store a description written by a client
+    fn write_description(&self, data: &[u8]) -> Result<(), LeAudioError> {
+        let description = AudioOutputDescription::from_gatt(data)
+            .map_err(|_| AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)?;
+        *self.description.borrow_mut() = description;
+        self.description_changed.set(true);
+        Ok(())
+    }
+}
+
+impl LeAudioServerService for VocsServer {
+    fn handle_read(&self, handle: u16) -> Option<Result<(), LeAudioError>> {
+        if handle == self.volume_offset_state.handle
+            || handle == self.audio_location.handle
+            || handle == self.audio_output_description.handle
+        {
+            Some(Ok(()))
+        } else if handle == self.volume_offset_control_point.handle {
+            Some(Err(AttErrorCode::READ_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+
+    fn handle_write(&self, handle: u16, data: &[u8]) -> Option<Result<(), LeAudioError>> {
+        if handle == self.volume_offset_control_point.handle {
+            Some(self.process_control_point(data))
+        } else if handle == self.audio_location.handle {
+            Some(self.write_audio_location(data))
+        } else if handle == self.audio_output_description.handle {
+            Some(self.write_description(data))
+        } else if handle == self.volume_offset_state.handle {
+            Some(Err(AttErrorCode::WRITE_NOT_PERMITTED.into()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The volume offset and the number of changes made to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C, packed)]
+pub struct VolumeOffsetState {
+    /// Offset added to the volume setting of the output, from -255 to 255
+    pub volume_offset: i16,
+    /// Incremented on every change, a client must name it to change the offset
+    pub change_counter: u8,
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for VolumeOffsetState {
+    fn format(&self, f: defmt::Formatter) {
+        let Self {
+            volume_offset,
+            change_counter,
+        } = *self;
+        defmt::write!(
+            f,
+            "VolumeOffsetState {{ volume_offset: {}, change_counter: {} }}",
+            volume_offset,
+            change_counter
+        )
+    }
+}
+
+impl FixedGattValue for VolumeOffsetState {
+    const SIZE: usize = 3;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [low, high, change_counter] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Ok(Self {
+            volume_offset: i16::from_le_bytes([*low, *high]),
+            change_counter: *change_counter,
+        })
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        // Packed without padding, little endian targets lay it out as the
+        // characteristic value
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// Operations of the Volume Offset Control Point
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum VolumeOffsetControlOpcode {
+    SetVolumeOffset = 0x01,
+}
+
+impl TryFrom<u8> for VolumeOffsetControlOpcode {
+    type Error = u8;
+
+    fn try_from(opcode: u8) -> Result<Self, Self::Error> {
+        match opcode {
+            0x01 => Ok(Self::SetVolumeOffset),
+            _ => Err(opcode),
+        }
+    }
+}
+
+impl FixedGattValue for VolumeOffsetControlOpcode {
+    const SIZE: usize = 1;
+
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let [opcode] = data else {
+            return Err(FromGattError::InvalidLength);
+        };
+        Self::try_from(*opcode).map_err(|_| FromGattError::InvalidLength)
+    }
+
+    fn as_gatt(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, Self::SIZE) }
+    }
+}
+
+/// UTF-8 description of an audio output, like "Left Speaker"
+pub type AudioOutputDescription = Utf8Text<MAX_AUDIO_OUTPUT_DESCRIPTION_SIZE>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use trouble_host::attribute::AttributeTable;
+
+    fn set_volume_offset(
+        vocs: &VocsServer,
+        change_counter: u8,
+        volume_offset: i16,
+    ) -> Result<(), AttErrorCode> {
+        let [low, high] = volume_offset.to_le_bytes();
+        let data = [
+            VolumeOffsetControlOpcode::SetVolumeOffset as u8,
+            change_counter,
+            low,
+            high,
+        ];
+        vocs.handle_write(vocs.volume_offset_control_point.handle, &data)
+            .unwrap()
+            .map_err(AttErrorCode::from)
+    }
+
+    #[test]
+    fn stale_change_counter_is_rejected() {
+        let mut storage = VocsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, VOCS_ATTRIBUTES> = AttributeTable::new();
+        let vocs = VocsServer::new(
+            &mut table,
+            0,
+            AudioLocation::FrontLeft,
+            AudioOutputDescription::default(),
+            &mut storage,
+        );
+
+        assert_eq!(set_volume_offset(&vocs, 0, -10), Ok(()));
+        assert_eq!(
+            set_volume_offset(&vocs, 0, 10),
+            Err(AttErrorCode::APPLICATION_ERROR_0x80)
+        );
+        let state = vocs.volume_offset_state();
+        assert_eq!({ state.volume_offset }, -10);
+        assert_eq!(state.change_counter, 1);
+    }
+
+    #[test]
+    fn volume_offset_is_limited() {
+        let mut storage = VocsStorage::new();
+        let mut table: AttributeTable<'_, NoopRawMutex, VOCS_ATTRIBUTES> = AttributeTable::new();
+        let vocs = VocsServer::new(
+            &mut table,
+            0,
+            AudioLocation::FrontLeft,
+            AudioOutputDescription::default(),
+            &mut storage,
+        );
+
+        for volume_offset in [MIN_VOLUME_OFFSET - 1, MAX_VOLUME_OFFSET + 1] {
+            assert_eq!(
+                set_volume_offset(&vocs, 0, volume_offset),
+                Err(AttErrorCode::APPLICATION_ERROR_0x82)
+            );
+        }
+        assert_eq!(set_volume_offset(&vocs, 0, MIN_VOLUME_OFFSET), Ok(()));
+        assert_eq!(set_volume_offset(&vocs, 1, MAX_VOLUME_OFFSET), Ok(()));
+        assert_eq!(
+            { vocs.volume_offset_state().volume_offset },
+            MAX_VOLUME_OFFSET
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Development tasks for trouble-audio
+//!
+//! `cargo xtask gen-adv-data` prints the `AdStructure` initialization for a
+//! LE Audio peripheral, ready to be pasted into application code.
+
+use std::{env, fmt::Write, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: cargo xtask gen-adv-data --name <NAME> [OPTIONS]
+
+Options:
+  --name <NAME>                Complete local name of the device
+  --services <LIST>            Comma separated services to advertise:
+                               pacs, ascs, bass, vcs, mics, csis, tmas, has, gmcs, tbs, cas
+  --sink-contexts <LIST>       Comma separated available sink audio contexts
+  --source-contexts <LIST>     Comma separated available source audio contexts
+  --targeted                   Send a targeted instead of a general announcement
+
+Contexts: unspecified, conversational, media, game, instructional, voice-assistants,
+          live, sound-effects, notifications, ringtone, alerts, alarm";
+
+/// Maximum size of the legacy advertising and scan response data
+const MAX_ADV_DATA: usize = 31;
+
+struct Service {
+    arg: &'static str,
+    /// Name of the uuid in `bt_hci::uuid::service`
+    constant: &'static str,
+}
+
+const SERVICES: &[Service] = &[
+    Service {
+        arg: "pacs",
+        constant: "PUBLISHED_AUDIO_CAPABILITIES",
+    },
+    Service {
+        arg: "ascs",
+        constant: "AUDIO_STREAM_CONTROL",
+    },
+    Service {
+        arg: "bass",
+        constant: "BROADCAST_AUDIO_SCAN",
+    },
+    Service {
+        arg: "vcs",
+        constant: "VOLUME_CONTROL",
+    },
+    Service {
+        arg: "mics",
+        constant: "MICROPHONE_CONTROL",
+    },
+    Service {
+        arg: "csis",
+        constant: "COORDINATED_SET_IDENTIFICATION",
+    },
+    Service {
+        arg: "tmas",
+        constant: "TELEPHONY_AND_MEDIA_AUDIO",
+    },
+    Service {
+        arg: "has",
+        constant: "HEARING_ACCESS",
+    },
+    Service {
+        arg: "gmcs",
+        constant: "GENERIC_MEDIA_CONTROL",
+    },
+    Service {
+        arg: "tbs",
+        constant: "GENERIC_TELEPHONE_BEARER",
+    },
+    Service {
+        arg: "cas",
+        constant: "COMMON_AUDIO",
+    },
+];
+
+/// Context type bits, matching `trouble_audio::generic_audio::ContextType`
+const CONTEXTS: &[(&str, u16)] = &[
+    ("unspecified", 0x0001),
+    ("conversational", 0x0002),
+    ("media", 0x0004),
+    ("game", 0x0008),
+    ("instructional", 0x0010),
+    ("voice-assistants", 0x0020),
+    ("live", 0x0040),
+    ("sound-effects", 0x0080),
+    ("notifications", 0x0100),
+    ("ringtone", 0x0200),
+    ("alerts", 0x0400),
+    ("alarm", 0x0800),
+];
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("gen-adv-data") => match GenAdvData::parse(&args[1..]).and_then(|task| task.run()) {
+            Ok(code) => {
+                print!("{code}");
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("error: {err}\n\n{USAGE}");
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+struct GenAdvData {
+    name: String,
+    services: Vec<&'static Service>,
+    sink_contexts: u16,
+    source_contexts: u16,
+    targeted: bool,
+}
+
+impl GenAdvData {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut name = None;
+        let mut services = Vec::new();
+        let mut sink_contexts = 0;
+        let mut source_contexts = 0;
+        let mut targeted = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--name" => name = Some(value()?.clone()),
+                "--services" => {
+                    for service in list(value()?) {
+                        services.push(
+                            SERVICES
+                                .iter()
+                                .find(|known| known.arg == service)
+                                .ok_or_else(|| format!("unknown service {service}"))?,
+                        );
+                    }
+                }
+                "--sink-contexts" => sink_contexts = parse_contexts(value()?)?,
+                "--source-contexts" => source_contexts = parse_contexts(value()?)?,
+                "--targeted" => targeted = true,
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+
+        Ok(Self {
+            name: name.ok_or("--name is required")?,
+            services,
+            sink_contexts,
+            source_contexts,
+            targeted,
+        })
+    }
+
+    /// Split the structures between the advertising and scan response data
+    fn run(&self) -> Result<String, String> {
+        let mut adv_data = vec![AdStructure::Flags];
+        let mut scan_data = Vec::new();
+
+        if self.services.iter().any(|service| service.arg == "ascs") {
+            if self.sink_contexts == 0 && self.source_contexts == 0 {
+                eprintln!("warning: no audio context is available, clients will not start streams");
+            }
+            adv_data.push(AdStructure::Announcement {
+                targeted: self.targeted,
+                sink_contexts: self.sink_contexts,
+                source_contexts: self.source_contexts,
+            });
+        }
+
+        if !self.services.is_empty() {
+            let uuids = AdStructure::ServiceUuids16(self.services.clone());
+            if size(&adv_data) + uuids.size() <= MAX_ADV_DATA {
+                adv_data.push(uuids);
+            } else {
+                eprintln!(
+                    "warning: service uuids do not fit the advertising data, moved to the scan response"
+                );
+                scan_data.push(uuids);
+            }
+        }
+
+        let name = AdStructure::CompleteLocalName(self.name.clone());
+        if size(&adv_data) + name.size() <= MAX_ADV_DATA {
+            adv_data.push(name);
+        } else if size(&scan_data) + name.size() <= MAX_ADV_DATA {
+            eprintln!(
+                "warning: name does not fit the advertising data, moved to the scan response"
+            );
+            scan_data.push(name);
+        } else {
+            return Err(format!(
+                "name `{}` does not fit in the advertising or scan response data",
+                self.name
+            ));
+        }
+
+        let mut code = String::new();
+        generate(&mut code, "adv_data", &adv_data);
+        if !scan_data.is_empty() {
+            code.push('\n');
+            generate(&mut code, "scan_data", &scan_data);
+        }
+        Ok(code)
+    }
+}
+
+enum AdStructure {
+    Flags,
+    /// BAP announcement, service data of the Audio Stream Control service
+    Announcement {
+        targeted: bool,
+        sink_contexts: u16,
+        source_contexts: u16,
+    },
+    ServiceUuids16(Vec<&'static Service>),
+    CompleteLocalName(String),
+}
+
+impl AdStructure {
+    /// Encoded size including the length and type bytes
+    fn size(&self) -> usize {
+        2 + match self {
+            AdStructure::Flags => 1,
+            // Uuid, announcement type, available audio contexts, metadata length
+            AdStructure::Announcement { .. } => 2 + 1 + 4 + 1,
+            AdStructure::ServiceUuids16(services) => 2 * services.len(),
+            AdStructure::CompleteLocalName(name) => name.len(),
+        }
+    }
+
+    fn generate(&self, code: &mut String) {
+        match self {
+            AdStructure::Flags => {
+                code.push_str("AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED)")
+            }
+            AdStructure::Announcement {
+                targeted,
+                sink_contexts,
+                source_contexts,
+            } => {
+                let [sink_low, sink_high] = sink_contexts.to_le_bytes();
+                let [source_low, source_high] = source_contexts.to_le_bytes();
+                let _ = write!(
+                    code,
+                    "AdStructure::ServiceData16 {{\n            \
+                     uuid: service::AUDIO_STREAM_CONTROL.into(),\n            \
+                     // {} announcement, sink contexts, source contexts, no metadata\n            \
+                     data: &[{:#04x}, {sink_low:#04x}, {sink_high:#04x}, {source_low:#04x}, {source_high:#04x}, 0x00],\n        \
+                     }}",
+                    if *targeted { "Targeted" } else { "General" },
+                    u8::from(*targeted),
+                );
+            }
+            AdStructure::ServiceUuids16(services) => {
+                code.push_str("AdStructure::ServiceUuids16(&[\n");
+                for service in services {
+                    let _ = writeln!(code, "            service::{}.into(),", service.constant);
+                }
+                code.push_str("        ])");
+            }
+            AdStructure::CompleteLocalName(name) => {
+                let _ = write!(code, "AdStructure::CompleteLocalName(b{name:?})");
+            }
+        }
+    }
+}
+
+fn size(structures: &[AdStructure]) -> usize {
+    structures.iter().map(AdStructure::size).sum()
+}
+
+fn generate(code: &mut String, buffer: &str, structures: &[AdStructure]) {
+    let _ = writeln!(
+        code,
+        "// {} of {MAX_ADV_DATA} bytes\nlet mut {buffer} = [0; {MAX_ADV_DATA}];",
+        size(structures)
+    );
+    code.push_str("AdStructure::encode_slice(\n    &[\n");
+    for structure in structures {
+        code.push_str("        ");
+        structure.generate(code);
+        code.push_str(",\n");
+    }
+    let _ = writeln!(code, "    ],\n    &mut {buffer}[..],\n)?;");
+}
+
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_contexts(value: &str) -> Result<u16, String> {
+    list(value).try_fold(0, |contexts, context| {
+        CONTEXTS
+            .iter()
+            .find(|(name, _)| *name == context)
+            .map(|(_, bit)| contexts | bit)
+            .ok_or_else(|| format!("unknown context {context}"))
+    })
+}